@@ -1,3 +1,8 @@
+use apca::api::v2::asset::{Class, Symbol};
+use apca::ApiError;
+use http_endpoint::{EndpointDef, Str};
+use serde::Deserialize;
+
 pub const ALPACA_API_PAPER_URL: &str = "https://paper-api.alpaca.markets";
 pub const ALPACA_API_LIVE_URL: &str = "https://api.alpaca.markets";
 /// Data API endpoints (paper or live)
@@ -22,3 +27,44 @@ impl ToString for Stocks {
         format!("{DATA_WS_URL}/v1beta3/crypto/us")
     }
 }
+
+/// Alpaca asset attributes orders are rounded with.
+/// `apca`'s `Asset` drops the increments Alpaca sets on crypto assets.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AssetIncrements {
+    pub class: Class,
+    pub fractionable: bool,
+    /// Smallest step of an order quantity, set for crypto
+    #[serde(default)]
+    pub min_trade_increment: Option<String>,
+    /// Smallest step of an order price, set for crypto
+    #[serde(default)]
+    pub price_increment: Option<String>,
+}
+
+EndpointDef! {
+    /// GET /v2/assets/{symbol}, keeping the increments of the asset
+    pub GetAssetIncrements(Symbol),
+    Ok => AssetIncrements, [
+        /* 200 */ OK,
+    ],
+    Err => GetAssetIncrementsError, [
+        /* 401 */ UNAUTHORIZED => AuthenticationFailed,
+        /* 404 */ NOT_FOUND => NotFound,
+        /* 429 */ TOO_MANY_REQUESTS => RateLimitExceeded,
+    ],
+    ConversionErr => serde_json::Error,
+    ApiErr => ApiError,
+
+    fn path(input: &Self::Input) -> Str {
+        format!("/v2/assets/{}", input).into()
+    }
+
+    fn parse(body: &[u8]) -> Result<Self::Output, Self::ConversionError> {
+        serde_json::from_slice::<Self::Output>(body)
+    }
+
+    fn parse_err(body: &[u8]) -> Result<Self::ApiError, Vec<u8>> {
+        serde_json::from_slice::<Self::ApiError>(body).map_err(|_| body.to_vec())
+    }
+}
@@ -1,3 +1,4 @@
+use crate::endpoints::GetAssetIncrements;
 use crate::error::*;
 use crate::utils::*;
 use apca::api::v2::account::{Account, Get as GetAccount};
@@ -56,6 +57,8 @@ pub struct Engine {
     pub trailing_take_profit: ExitType,
     pub stop_loss: ExitType,
     pub active_order: ActiveOrder,
+    /// Rounding of order quantity and price for the ticker
+    pub precision: AssetPrecision,
 }
 
 impl Engine {
@@ -76,9 +79,22 @@ impl Engine {
             trailing_take_profit,
             stop_loss,
             active_order,
+            precision: AssetPrecision::default(),
         }
     }
 
+    /// Set order precision from the ticker's Alpaca asset attributes
+    pub async fn load_asset_precision(&mut self) -> Result<AssetPrecision> {
+        let symbol = Symbol::Sym(self.ticker.to_string());
+        let asset = self.client.issue::<GetAssetIncrements>(&symbol).await?;
+        self.precision = AssetPrecision::from_asset(&asset);
+        info!(
+            "{} precision, quantity increment: {}, price increment: {}",
+            self.ticker, self.precision.quantity_increment, self.precision.price_increment
+        );
+        Ok(self.precision)
+    }
+
     async fn account(&self) -> Result<Account> {
         let res = self.client.issue::<GetAccount>(&()).await;
         trace!("Get account: {:?}", res);
@@ -163,14 +179,14 @@ impl Engine {
                 let quote_balance = num_to_f64!(account.cash)? / price;
 
                 let sum = quote_balance + base_balance;
-                let equal = precise_round!(sum / 2_f64, 5);
-                let quote_diff = precise_round!(quote_balance - equal, 5);
-                let base_diff = precise_round!(base_balance - equal, 5);
+                let equal = precise_round(sum / 2_f64, 5);
+                let quote_diff = precise_round(quote_balance - equal, 5);
+                let base_diff = precise_round(base_balance - equal, 5);
                 let min_notional = 0.001;
 
                 // buy base asset
                 if quote_diff > 0_f64 && quote_diff > min_notional {
-                    let long_qty = precise_round!(quote_diff, 5);
+                    let long_qty = precise_round(quote_diff, 5);
                     let long = OrderReqInit {
                         type_: Type::Limit,
                         limit_price: Some(f64_to_num!(price)),
@@ -205,7 +221,7 @@ impl Engine {
                 }
                 // sell base asset
                 else if base_diff > 0_f64 && base_diff > min_notional {
-                    let short_qty = precise_round!(base_diff, 5);
+                    let short_qty = precise_round(base_diff, 5);
                     let short = OrderReqInit {
                         type_: Type::Limit,
                         limit_price: Some(f64_to_num!(price)),
@@ -239,7 +255,7 @@ impl Engine {
             }
             None => {
                 let cash = num_to_f64!(account.cash)?;
-                let long_notional = precise_round!(cash / 2.0, 2);
+                let long_notional = precise_round(cash / 2.0, 2);
                 let long = OrderReqInit {
                     type_: Type::Market,
                     client_order_id: Some(format!(
@@ -280,16 +296,12 @@ impl Engine {
             (Some(entry), Some(take_profit)) => {
                 let entry_price = num_unwrap_f64!(entry.clone().average_fill_price)?;
                 let take_profit_price = num_unwrap_f64!(take_profit.clone().average_fill_price)?;
-                let pnl = precise_round!(
+                let pnl = precise_round(
                     match entry.side {
-                        Side::Buy => {
-                            (take_profit_price - entry_price) / entry_price * 100_f64
-                        }
-                        Side::Sell => {
-                            (entry_price - take_profit_price) / entry_price * 100_f64
-                        }
+                        Side::Buy => (take_profit_price - entry_price) / entry_price * 100_f64,
+                        Side::Sell => (entry_price - take_profit_price) / entry_price * 100_f64,
                     },
-                    5
+                    5,
                 );
                 Ok(pnl)
             }
@@ -302,16 +314,12 @@ impl Engine {
             (Some(entry), Some(stop_loss)) => {
                 let entry_price = num_unwrap_f64!(entry.clone().average_fill_price)?;
                 let stop_loss_price = num_unwrap_f64!(stop_loss.clone().average_fill_price)?;
-                let pnl = precise_round!(
+                let pnl = precise_round(
                     match entry.side {
-                        Side::Buy => {
-                            (stop_loss_price - entry_price) / entry_price * 100_f64
-                        }
-                        Side::Sell => {
-                            (entry_price - stop_loss_price) / entry_price * 100_f64
-                        }
+                        Side::Buy => (stop_loss_price - entry_price) / entry_price * 100_f64,
+                        Side::Sell => (entry_price - stop_loss_price) / entry_price * 100_f64,
                     },
-                    5
+                    5,
                 );
                 Ok(pnl)
             }
@@ -476,19 +484,16 @@ impl Engine {
         side: Side,
         cash: f64,
     ) -> Result<Order> {
+        let quantity = self.precision.round_quantity(cash / 3.0 / candle.close);
         let entry = OrderReqInit {
             type_: Type::Limit,
-            limit_price: Some(f64_to_num!(candle.close)),
+            limit_price: Some(f64_to_num!(self.precision.round_price(candle.close))),
             client_order_id: Some(format!("{}-{}", timestamp, "ENTRY")),
             time_in_force: TimeInForce::UntilCanceled,
             extended_hours: true,
             ..Default::default()
         }
-        .init(
-            &self.ticker,
-            side,
-            Amount::quantity(f64_to_num!(precise_round!(cash / 3.0 / candle.close, 0))),
-        );
+        .init(&self.ticker, side, Amount::quantity(f64_to_num!(quantity)));
         debug!("Entry order: {:?}", entry);
         match self.client.issue::<Post>(&entry).await {
             Ok(res) => {
@@ -555,10 +560,11 @@ impl Engine {
                     Side::Sell => Side::Buy,
                 };
                 let entry_price = num_unwrap_f64!(entry.limit_price.clone())?;
-                let (stop_price, limit_price) = self
-                    .active_order
-                    .stop_loss_handler
-                    .build(entry_price, entry.side)?;
+                let (stop_price, limit_price) = self.active_order.stop_loss_handler.build(
+                    entry_price,
+                    entry.side,
+                    &self.precision,
+                )?;
                 let sl = OrderReqInit {
                     class: Class::Simple,
                     type_: Type::Limit,
@@ -1,3 +1,4 @@
+use crate::endpoints::GetAssetIncrementsError;
 use crate::utils::WebSocketEvent;
 use apca::api::v2::{account, assets, order, orders, position};
 use apca::data::v2::bars;
@@ -9,6 +10,7 @@ use std::fmt::Debug;
 use std::sync::PoisonError;
 
 #[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum AlpacaError {
     PLPL(ephemeris::PLPLError),
     Apca(apca::Error),
@@ -35,6 +37,7 @@ pub enum AlpacaError {
     ApcaGetOrder(RequestError<order::GetError>),
     ApcaDeleteOrder(RequestError<order::DeleteError>),
     ApcaGetAccount(RequestError<account::GetError>),
+    ApcaGetAsset(RequestError<GetAssetIncrementsError>),
     ApcaGetAssets(RequestError<assets::GetError>),
     ApcaGetPosition(RequestError<position::GetError>),
     ApcaDeletePosition(RequestError<position::DeleteError>),
@@ -145,6 +148,10 @@ impl std::fmt::Display for AlpacaError {
                 error!("Apca get account error: {:?}", e);
                 write!(f, "Apca get account error: {:?}", e)
             }
+            AlpacaError::ApcaGetAsset(e) => {
+                error!("Apca get asset error: {:?}", e);
+                write!(f, "Apca get asset error: {:?}", e)
+            }
             AlpacaError::ApcaGetAssets(e) => {
                 error!("Apca get assets error: {:?}", e);
                 write!(f, "Apca get assets error: {:?}", e)
@@ -288,6 +295,12 @@ impl From<RequestError<account::GetError>> for AlpacaError {
     }
 }
 
+impl From<RequestError<GetAssetIncrementsError>> for AlpacaError {
+    fn from(e: RequestError<GetAssetIncrementsError>) -> Self {
+        AlpacaError::ApcaGetAsset(e)
+    }
+}
+
 impl From<RequestError<assets::GetError>> for AlpacaError {
     fn from(e: RequestError<assets::GetError>) -> Self {
         AlpacaError::ApcaGetAssets(e)
@@ -7,12 +7,12 @@ mod error;
 mod utils;
 
 use apca::api::v2::updates::OrderUpdates;
+use apca::data::v2::stream::MarketData;
 use apca::data::v2::stream::{drive, Data};
-use apca::data::v2::stream::{CustomUrl, MarketData};
 use apca::data::v2::stream::{RealtimeData, IEX};
 use apca::ApiInfo;
 use apca::Client;
-use crossbeam::channel::unbounded;
+use crossbeam::channel::{unbounded, Sender};
 use endpoints::*;
 use engine::*;
 use ephemeris::*;
@@ -23,7 +23,7 @@ use lazy_static::lazy_static;
 use log::*;
 use std::path::PathBuf;
 use std::sync::Mutex;
-use time_series::{Candle, Day, Month, Time};
+use time_series::{CandleCache, Day, Month, Time};
 use utils::*;
 
 /// Paper trading API credentials
@@ -52,12 +52,12 @@ lazy_static! {
             }
         };
     // cache previous and current Kline/Candle to assess PLPL trade signal
-    static ref PREV_CANDLE: Mutex<Option<Candle>> = Mutex::new(None);
-    static ref CURR_CANDLE: Mutex<Option<Candle>> = Mutex::new(None);
+    static ref CANDLES: Mutex<CandleCache> = Mutex::new(CandleCache::Empty);
     static ref TICKER: String = "SPY".to_string();
 }
 
 #[tokio::main]
+#[allow(clippy::await_holding_lock)]
 async fn main() -> Result<()> {
     init_logger(&PathBuf::from("alpaca.log"))?;
 
@@ -80,136 +80,66 @@ async fn main() -> Result<()> {
         plpl_price,
         num_plpls,
         cross_margin_pct,
+        price_range: None,
     })?;
 
     let client = Client::new(API_INFO.clone());
 
     // PLPL engine
-    let engine = Engine::new(
+    let mut engine = Engine::new(
         client,
         TICKER.clone(),
         plpl_system,
         trailing_take_profit,
         stop_loss,
     );
+    // round order sizes to the asset's precision, or whole shares if unavailable
+    if let Err(e) = engine.load_asset_precision().await {
+        warn!(
+            "Failed to get asset precision, default to whole shares: {}",
+            e
+        );
+    }
 
     // before starting to process messages, reset open orders and equalize assets
     engine.cancel_open_orders().await?;
     engine.equalize_assets().await?;
 
-    // Subscribe to websocket bar updates.
-    let (mut stream, mut subscription) = engine.client.subscribe::<RealtimeData<IEX>>().await?;
-    let mut data = MarketData::default();
-    data.set_bars([TICKER.as_str()]);
-    let subscribe = subscription.subscribe(&data).boxed();
-    let () = drive(subscribe, &mut stream).await?.unwrap()?;
-
-    // Subscribe to websocket order updates.
-    let (stream_orders, _subscription_orders) = engine.client.subscribe::<OrderUpdates>().await?;
-
+    // queued events are handled one at a time, so order operations never interleave
     let engine = Mutex::new(engine);
     let (tx, rx) = unbounded::<WebSocketEvent>();
+    // resubscribe to bars and order updates if either stream disconnects
+    let reconnect = ReconnectPolicy::default();
 
     // handle websocket order updates
     let order_sender = tx.clone();
-    tokio::spawn(async move {
-        match stream_orders
-            .map_err(AlpacaError::WebSocket)
-            .try_for_each(|result| async {
-                let data = result.map_err(AlpacaError::Json)?;
-                let suffix = order_id_suffix(&data.order);
-                if suffix == "ENTRY" || suffix == "TAKE_PROFIT" || suffix == "STOP_LOSS" {
-                    order_sender.send(WebSocketEvent::OrderUpdate(data))?;
-                }
-                Ok(())
-            })
-            .await
-        {
-            Ok(()) => Ok(()),
-            Err(e) => {
-                error!("Error in order stream: {:?}", e);
-                Err(e)
-            }
-        }
-    });
+    tokio::spawn(reconnect_stream("order", reconnect, move || {
+        stream_orders(order_sender.clone())
+    }));
 
     // handle websocket bar updates
     let bar_sender = tx.clone();
-    tokio::spawn(async move {
-        match stream
-            .map_err(AlpacaError::WebSocket)
-            .try_for_each(|result| async {
-                let data = result.map_err(AlpacaError::Json)?;
-
-                match data {
-                    Data::Quote(quote) => {
-                        trace!("quote: {:?}", quote);
-                    }
-                    Data::Trade(trade) => {
-                        trace!("{:?}", trade);
-                    }
-                    Data::Bar(bar) => {
-                        bar_sender.send(WebSocketEvent::Bar(bar))?;
-                    }
-                    _ => {
-                        trace!("Other websocket data: {:?}", data);
-                    }
-                }
-                Ok(())
-            })
-            .await
-        {
-            Ok(()) => Ok(()),
-            Err(e) => {
-                error!("Error in bar stream: {:?}", e);
-                Err(e)
-            }
-        }
-    });
+    tokio::spawn(reconnect_stream("bar", reconnect, move || {
+        stream_bars(bar_sender.clone())
+    }));
 
     // handle queue messages
-    while let Ok(event) = rx.recv() {
+    let engine = &engine;
+    handle_queue(&rx, |event| async move {
         match event {
             WebSocketEvent::Bar(bar) => {
                 trace!("bar: {:?}", bar);
 
-                let mut prev = PREV_CANDLE.lock()?;
-                let mut curr = CURR_CANDLE.lock()?;
+                let mut candles = CANDLES.lock()?;
                 let mut engine = engine.lock()?;
 
                 // compute closest PLPL to current Candle
                 let candle = bar_to_candle(bar)?;
                 // compare previous candle to current candle to check crossover of PLPL signal threshold
-                match (&*prev, &*curr) {
-                    (None, None) => *prev = Some(candle),
-                    (Some(prev_candle), None) => {
-                        *curr = Some(candle.clone());
-
-                        engine
-                            .process_candle(
-                                prev_candle,
-                                &candle,
-                                candle.date.to_unix_ms().to_string(),
-                            )
-                            .await?;
-                    }
-                    (None, Some(_)) => {
-                        error!(
-                            "🛑 Previous candle is None and current candle is Some. Should never occur."
-                        );
-                    }
-                    (Some(_prev_candle), Some(curr_candle)) => {
-                        engine
-                            .process_candle(
-                                curr_candle,
-                                &candle,
-                                candle.date.to_unix_ms().to_string(),
-                            )
-                            .await?;
-
-                        *prev = Some(curr_candle.clone());
-                        *curr = Some(candle);
-                    }
+                if let Some((prev_candle, candle)) = candles.push(candle) {
+                    engine
+                        .process_candle(&prev_candle, &candle, candle.date.to_unix_ms().to_string())
+                        .await?;
                 }
             }
             WebSocketEvent::OrderUpdate(order_update) => {
@@ -219,7 +149,57 @@ async fn main() -> Result<()> {
                 engine.check_active_order().await?;
             }
         }
-    }
+        Ok(())
+    })
+    .await
+}
 
-    Ok(())
+/// Subscribe to websocket order updates and queue entry and exit updates until the stream ends.
+async fn stream_orders(order_sender: Sender<WebSocketEvent>) -> Result<()> {
+    let client = Client::new(API_INFO.clone());
+    let (stream, _subscription) = client.subscribe::<OrderUpdates>().await?;
+    stream
+        .map_err(AlpacaError::WebSocket)
+        .try_for_each(|result| async {
+            let data = result.map_err(AlpacaError::Json)?;
+            let suffix = order_id_suffix(&data.order);
+            if suffix == "ENTRY" || suffix == "TAKE_PROFIT" || suffix == "STOP_LOSS" {
+                order_sender.send(WebSocketEvent::OrderUpdate(data))?;
+            }
+            Ok(())
+        })
+        .await
+}
+
+/// Subscribe to websocket bar updates and queue bars until the stream ends.
+async fn stream_bars(bar_sender: Sender<WebSocketEvent>) -> Result<()> {
+    let client = Client::new(API_INFO.clone());
+    let (mut stream, mut subscription) = client.subscribe::<RealtimeData<IEX>>().await?;
+    let mut data = MarketData::default();
+    data.set_bars([TICKER.as_str()]);
+    let subscribe = subscription.subscribe(&data).boxed();
+    let () = drive(subscribe, &mut stream).await?.unwrap()?;
+
+    stream
+        .map_err(AlpacaError::WebSocket)
+        .try_for_each(|result| async {
+            let data = result.map_err(AlpacaError::Json)?;
+
+            match data {
+                Data::Quote(quote) => {
+                    trace!("quote: {:?}", quote);
+                }
+                Data::Trade(trade) => {
+                    trace!("{:?}", trade);
+                }
+                Data::Bar(bar) => {
+                    bar_sender.send(WebSocketEvent::Bar(bar))?;
+                }
+                _ => {
+                    trace!("Other websocket data: {:?}", data);
+                }
+            }
+            Ok(())
+        })
+        .await
 }
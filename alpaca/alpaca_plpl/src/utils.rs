@@ -1,7 +1,10 @@
+use crate::endpoints::AssetIncrements;
 use crate::{AlpacaError, Result};
+use apca::api::v2::asset::Class;
 use apca::api::v2::order::{Order, Side, Status};
 use apca::api::v2::updates::OrderUpdate;
 use apca::data::v2::stream::Bar;
+use crossbeam::channel::Receiver;
 use log::*;
 use num_decimal::Num;
 use simplelog::{
@@ -9,8 +12,10 @@ use simplelog::{
     TerminalMode, WriteLogger,
 };
 use std::fs::File;
+use std::future::Future;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 use time_series::{f64_to_num, num_to_f64, precise_round, Candle, Time};
 
 pub fn init_logger(log_file: &PathBuf) -> Result<()> {
@@ -49,6 +54,82 @@ pub fn is_testnet() -> Result<bool> {
         .map_err(AlpacaError::ParseBool)
 }
 
+/// Increments an asset's order quantity and price are rounded to a multiple of
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AssetPrecision {
+    /// One for assets that only trade whole shares
+    pub quantity_increment: f64,
+    pub price_increment: f64,
+}
+
+impl Default for AssetPrecision {
+    /// Whole shares with cent prices, which every US equity supports
+    fn default() -> Self {
+        Self {
+            quantity_increment: 1.0,
+            price_increment: 0.01,
+        }
+    }
+}
+
+impl AssetPrecision {
+    /// Alpaca accepts fractional quantities up to 9 decimal places
+    pub const FRACTIONAL_QUANTITY_INCREMENT: f64 = 1e-9;
+
+    /// Precision from Alpaca's asset attributes.
+    /// Crypto rounds to the asset's quantity and price increments, fractionable equities
+    /// keep fractional quantities, and other equities round to whole shares.
+    /// Equity prices are in cents.
+    pub fn from_asset(asset: &AssetIncrements) -> Self {
+        let mut precision = Self::new(asset.fractionable || asset.class == Class::Crypto);
+        if let Some(increment) = asset
+            .min_trade_increment
+            .as_deref()
+            .and_then(Self::increment)
+        {
+            precision.quantity_increment = increment;
+        }
+        if let Some(increment) = asset.price_increment.as_deref().and_then(Self::increment) {
+            precision.price_increment = increment;
+        }
+        precision
+    }
+
+    pub fn new(fractionable: bool) -> Self {
+        let mut precision = Self::default();
+        if fractionable {
+            precision.quantity_increment = Self::FRACTIONAL_QUANTITY_INCREMENT;
+        }
+        precision
+    }
+
+    /// Increment like `0.001` or `0.05`, None if it is not a positive number
+    fn increment(increment: &str) -> Option<f64> {
+        let increment = increment.parse::<f64>().ok()?;
+        (increment.is_finite() && increment > 0.0).then_some(increment)
+    }
+
+    /// Nearest multiple of `increment` to `value`, without the float error of the multiplication
+    fn round_to_increment(value: f64, increment: f64) -> f64 {
+        // decimal places of the increment, e.g. 2 for 0.05 and 0 for 5
+        let decimals = (0..=12)
+            .find(|decimals| {
+                let scaled = increment * 10_f64.powi(*decimals);
+                (scaled - scaled.round()).abs() < 1e-9 * scaled.max(1.0)
+            })
+            .unwrap_or(12) as u32;
+        precise_round((value / increment).round() * increment, decimals)
+    }
+
+    pub fn round_quantity(&self, quantity: f64) -> f64 {
+        Self::round_to_increment(quantity, self.quantity_increment)
+    }
+
+    pub fn round_price(&self, price: f64) -> f64 {
+        Self::round_to_increment(price, self.price_increment)
+    }
+}
+
 pub fn order_id_prefix(order: &Order) -> String {
     order.client_order_id.split('-').next().unwrap().to_string()
 }
@@ -64,37 +145,37 @@ pub enum ExitType {
 }
 
 impl ExitType {
-    pub fn calc_stop_loss_exit(&self, entry_side: &Side, origin: f64) -> f64 {
+    /// Stop loss price `self` away from `origin` against the entry, rounded to the asset's price increment
+    pub fn calc_stop_loss_exit(
+        &self,
+        entry_side: &Side,
+        origin: f64,
+        precision: &AssetPrecision,
+    ) -> f64 {
+        let offset = match self {
+            ExitType::Percent(pct) => origin * (*pct) / 100.0,
+            ExitType::Price(dollars) => *dollars,
+        };
         match entry_side {
-            Side::Buy => match self {
-                ExitType::Percent(pct) => {
-                    precise_round!(origin - (origin * (*pct) / 100.0), 2)
-                }
-                ExitType::Price(dollars) => precise_round!(origin - dollars, 2),
-            },
-            Side::Sell => match self {
-                ExitType::Percent(pct) => {
-                    precise_round!(origin + (origin * (*pct) / 100.0), 2)
-                }
-                ExitType::Price(dollars) => precise_round!(origin + dollars, 2),
-            },
+            Side::Buy => precision.round_price(origin - offset),
+            Side::Sell => precision.round_price(origin + offset),
         }
     }
 
-    pub fn calc_take_profit_exit(&self, entry_side: &Side, origin: f64) -> f64 {
+    /// Take profit price `self` away from `origin` with the entry, rounded to the asset's price increment
+    pub fn calc_take_profit_exit(
+        &self,
+        entry_side: &Side,
+        origin: f64,
+        precision: &AssetPrecision,
+    ) -> f64 {
+        let offset = match self {
+            ExitType::Percent(pct) => origin * (*pct) / 100.0,
+            ExitType::Price(dollars) => *dollars,
+        };
         match entry_side {
-            Side::Sell => match self {
-                ExitType::Percent(pct) => {
-                    precise_round!(origin - (origin * (*pct) / 100.0), 2)
-                }
-                ExitType::Price(dollars) => precise_round!(origin - dollars, 2),
-            },
-            Side::Buy => match self {
-                ExitType::Percent(pct) => {
-                    precise_round!(origin + (origin * (*pct) / 100.0), 2)
-                }
-                ExitType::Price(dollars) => precise_round!(origin + dollars, 2),
-            },
+            Side::Sell => precision.round_price(origin - offset),
+            Side::Buy => precision.round_price(origin + offset),
         }
     }
 }
@@ -109,25 +190,23 @@ impl StopLossHandler {
         Self { stop_type }
     }
 
-    pub fn build(&self, entry_price: f64, entry_side: Side) -> Result<(Num, Num)> {
-        let (stop_price, limit_price) = match entry_side {
+    pub fn build(
+        &self,
+        entry_price: f64,
+        entry_side: Side,
+        precision: &AssetPrecision,
+    ) -> Result<(Num, Num)> {
+        let limit_price = self
+            .stop_type
+            .calc_stop_loss_exit(&entry_side, entry_price, precision);
+        // stop price is 75% of the way from entry to limit price
+        let stop_price = match entry_side {
             // entry is buy, so stop loss is sell
-            Side::Buy => {
-                let limit_price = self.stop_type.calc_stop_loss_exit(&entry_side, entry_price);
-                // stop price is 75% of the way from entry to limit price
-                let stop_price =
-                    precise_round!(limit_price + ((limit_price - entry_price).abs() / 4.0), 2);
-                (limit_price, stop_price)
-            }
+            Side::Buy => limit_price + ((limit_price - entry_price).abs() / 4.0),
             // entry is sell, so stop loss is buy
-            Side::Sell => {
-                let limit_price = self.stop_type.calc_stop_loss_exit(&entry_side, entry_price);
-                // stop price is 75% of the way from entry to limit price
-                let stop_price =
-                    precise_round!(limit_price - ((limit_price - entry_price).abs() / 4.0), 2);
-                (limit_price, stop_price)
-            }
+            Side::Sell => limit_price - ((limit_price - entry_price).abs() / 4.0),
         };
+        let stop_price = precision.round_price(stop_price);
         Ok((f64_to_num!(stop_price), f64_to_num!(limit_price)))
     }
 }
@@ -163,11 +242,110 @@ impl TakeProfitHandler {
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum WebSocketEvent {
     OrderUpdate(OrderUpdate),
     Bar(Bar),
 }
 
+/// Backoff between resubscribing a websocket stream after it ends
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// Consecutive reconnects before giving up, or retry forever if None
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay doubles each attempt from the initial delay up to the max delay
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.initial_delay
+            .saturating_mul(2_u32.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+
+    /// Delay before reconnecting a stream that ended with `result` after `attempt` reconnects,
+    /// or None if the stream should stay down.
+    pub fn next_delay(&self, result: &Result<()>, attempt: u32) -> Option<Duration> {
+        if let Some(max_attempts) = self.max_attempts {
+            if attempt >= max_attempts {
+                return None;
+            }
+        }
+        is_retryable(result).then(|| self.delay(attempt))
+    }
+}
+
+/// A stream that ended or failed on the socket, a message or the subscription can be resubscribed.
+/// Other errors, like a closed event queue, would fail again on reconnect.
+pub fn is_retryable(result: &Result<()>) -> bool {
+    matches!(
+        result,
+        Ok(())
+            | Err(AlpacaError::WebSocket(_))
+            | Err(AlpacaError::Json(_))
+            | Err(AlpacaError::Apca(_))
+            | Err(AlpacaError::Custom(_))
+    )
+}
+
+/// Run `connect` until its stream ends, then reconnect with backoff while the policy allows.
+/// A connection that stayed up longer than the max delay resets the backoff.
+pub async fn reconnect_stream<F, Fut>(
+    name: &str,
+    policy: ReconnectPolicy,
+    mut connect: F,
+) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut attempt = 0;
+    loop {
+        let connected = Instant::now();
+        let result = connect().await;
+        match &result {
+            Ok(()) => warn!("{} stream ended", name),
+            Err(e) => error!("Error in {} stream: {:?}", name, e),
+        }
+        if connected.elapsed() > policy.max_delay {
+            attempt = 0;
+        }
+        match policy.next_delay(&result, attempt) {
+            Some(delay) => {
+                warn!("Reconnecting {} stream in {:?}", name, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            None => return result,
+        }
+    }
+}
+
+/// Handle each queued event to completion before receiving the next, so order operations never interleave.
+/// Returns once every sender is dropped, or with the first error of `handle`.
+pub async fn handle_queue<T, F, Fut>(rx: &Receiver<T>, mut handle: F) -> Result<()>
+where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    while let Ok(event) = rx.recv() {
+        handle(event).await?;
+    }
+    Ok(())
+}
+
 pub fn status_to_string(status: Status) -> String {
     match status {
         Status::New => "New".to_string(),
@@ -190,3 +368,164 @@ pub fn status_to_string(status: Status) -> String {
         Status::Unknown => "Unknown".to_string(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn asset_quantity_precision() {
+        let quantity = 1000.0 / 3.0 / 437.15;
+        let fractionable = AssetPrecision::new(true);
+        assert_eq!(
+            fractionable.round_quantity(quantity),
+            precise_round(quantity, 9)
+        );
+        assert!(fractionable.round_quantity(quantity).fract() > 0.0);
+
+        let whole_shares = AssetPrecision::new(false);
+        assert_eq!(whole_shares.round_quantity(quantity), 1.0);
+        assert_eq!(whole_shares.round_quantity(2.5), 3.0);
+        assert_eq!(whole_shares.round_price(437.156), 437.16);
+    }
+
+    #[test]
+    fn crypto_price_increment() -> Result<()> {
+        let asset = serde_json::from_str::<AssetIncrements>(
+            r#"{
+                "class": "crypto",
+                "symbol": "BTC/USD",
+                "fractionable": true,
+                "min_trade_increment": "0.0001",
+                "price_increment": "1"
+            }"#,
+        )?;
+        let precision = AssetPrecision::from_asset(&asset);
+        assert_eq!(precision.quantity_increment, 0.0001);
+        assert_eq!(precision.price_increment, 1.0);
+        assert_eq!(precision.round_quantity(0.123456), 0.1235);
+        assert_eq!(precision.round_price(26123.57), 26124.0);
+
+        let asset = serde_json::from_str::<AssetIncrements>(
+            r#"{"class": "crypto", "fractionable": true, "price_increment": "0.000001"}"#,
+        )?;
+        let precision = AssetPrecision::from_asset(&asset);
+        assert_eq!(precision.price_increment, 0.000001);
+        assert_eq!(precision.round_price(0.12345678), 0.123457);
+
+        // increments that are not a power of ten round to a multiple rather than to decimal places
+        let asset = serde_json::from_str::<AssetIncrements>(
+            r#"{"class": "crypto", "fractionable": true, "price_increment": "0.05"}"#,
+        )?;
+        let precision = AssetPrecision::from_asset(&asset);
+        assert_eq!(precision.round_price(1.23), 1.25);
+        assert_eq!(precision.round_price(1.22), 1.2);
+        assert_eq!(precision.round_price(17.07), 17.05);
+
+        // equities have no increments and trade in cents
+        let asset = serde_json::from_str::<AssetIncrements>(
+            r#"{"class": "us_equity", "fractionable": false}"#,
+        )?;
+        assert_eq!(
+            AssetPrecision::from_asset(&asset),
+            AssetPrecision::default()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn exit_prices_round_to_price_increment() -> Result<()> {
+        let precision = AssetPrecision {
+            quantity_increment: 0.0001,
+            price_increment: 0.05,
+        };
+        // 1% of 101.3 is 1.013
+        let exit = ExitType::Percent(1.0);
+        assert_eq!(
+            exit.calc_stop_loss_exit(&Side::Buy, 101.3, &precision),
+            100.3
+        );
+        assert_eq!(
+            exit.calc_take_profit_exit(&Side::Buy, 101.3, &precision),
+            102.3
+        );
+        assert_eq!(
+            exit.calc_stop_loss_exit(&Side::Sell, 101.3, &precision),
+            102.3
+        );
+        assert_eq!(
+            exit.calc_take_profit_exit(&Side::Sell, 101.3, &precision),
+            100.3
+        );
+
+        // stop 75% of the way from 101.3 to the 100.3 limit is 100.55
+        let (stop_price, limit_price) =
+            StopLossHandler::new(exit).build(101.3, Side::Buy, &precision)?;
+        assert_eq!(stop_price, f64_to_num!(100.55));
+        assert_eq!(limit_price, f64_to_num!(100.3));
+
+        // cent prices of equities are unchanged
+        let exit = ExitType::Price(2.5);
+        let cents = AssetPrecision::default();
+        assert_eq!(
+            exit.calc_stop_loss_exit(&Side::Buy, 437.153, &cents),
+            434.65
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn symbol_events_handled_in_order() -> Result<()> {
+        let (tx, rx) = crossbeam::channel::unbounded::<(&str, &str)>();
+        for event in [("SPY", "ENTRY"), ("SPY", "STOP_LOSS")] {
+            tx.send(event).expect("Receiver is open");
+        }
+        drop(tx);
+
+        // the first event yields mid way, which would let the second start if they were not sequential
+        let handled = std::sync::Mutex::new(Vec::<String>::new());
+        handle_queue(&rx, |(symbol, event)| {
+            let handled = &handled;
+            async move {
+                handled.lock()?.push(format!("{} {} start", symbol, event));
+                if event == "ENTRY" {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+                handled.lock()?.push(format!("{} {} end", symbol, event));
+                Ok(())
+            }
+        })
+        .await?;
+        assert_eq!(
+            *handled.lock()?,
+            vec![
+                "SPY ENTRY start",
+                "SPY ENTRY end",
+                "SPY STOP_LOSS start",
+                "SPY STOP_LOSS end"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reconnect_decision() {
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            max_attempts: Some(5),
+        };
+        // server closed the stream
+        assert_eq!(policy.next_delay(&Ok(()), 0), Some(Duration::from_secs(1)));
+        let closed = Err(AlpacaError::WebSocket(tungstenite::Error::ConnectionClosed));
+        assert_eq!(policy.next_delay(&closed, 2), Some(Duration::from_secs(4)));
+        // backoff is capped, then gives up
+        assert_eq!(policy.next_delay(&Ok(()), 4), Some(Duration::from_secs(10)));
+        assert_eq!(policy.next_delay(&Ok(()), 5), None);
+        // errors unrelated to the socket are not retried
+        assert_eq!(policy.next_delay(&Err(AlpacaError::BarsEmpty), 0), None);
+        assert!(ReconnectPolicy::default()
+            .next_delay(&Ok(()), 1000)
+            .is_some());
+    }
+}
@@ -13,21 +13,8 @@ fn init_logger() {
     .expect("Failed to initialize logger");
 }
 
-fn median(data: &[f32]) -> CycleResult<f32> {
-    let mut data = data.to_vec();
-    data.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    let count = data.len();
-    if count > 0 {
-        let mid = count / 2;
-        if count % 2 == 0 {
-            Ok((data[mid - 1] + data[mid]) / 2.0)
-        } else {
-            Ok(data[mid])
-        }
-    } else {
-        Err(CycleError::EmptyMedian)
-    }
-}
+/// Cycle lows further than this many standard deviations from the median period are outliers
+const CYCLE_LOW_Z_THRESHOLD: f64 = 1.01;
 
 fn mean(data: &[f32]) -> CycleResult<f32> {
     let sum = data.iter().sum::<f32>();
@@ -39,112 +26,14 @@ fn mean(data: &[f32]) -> CycleResult<f32> {
     }
 }
 
-/// Mean is replaced by median to avoid outliers
-fn std_dev(data: &[f32]) -> CycleResult<f32> {
-    let mean = match median(data) {
-        Ok(mean) => mean,
-        Err(e) => return Err(e),
-    };
-    let count = data.len();
-    if count > 0 {
-        let variance = data
-            .iter()
-            .map(|value| {
-                let diff = mean - *value;
-
-                diff * diff
-            })
-            .sum::<f32>()
-            / count as f32;
-
-        Ok(variance.sqrt())
-    } else {
-        Err(CycleError::EmptyStdDev)
-    }
-}
-
-/// Use this to compute cycle averages
-/// Median is used instead of mean for datasets with outliers
-/// Median of a range of cycle deviations is a better metric than mean which is skewed by outliers
-fn zscore_median(data: &[f32], index: usize) -> CycleResult<f32> {
-    let median = match median(data) {
-        Ok(median) => median,
-        Err(e) => return Err(e),
-    };
-    let std_dev = match std_dev(data) {
-        Ok(std_deviation) => std_deviation,
-        Err(e) => return Err(e),
-    };
-    let diff = data[index] - median;
-    Ok(diff / std_dev)
-}
-
-#[derive(Debug, Clone)]
-pub struct CycleLow {
-    pub first: Candle,
-    pub second: Candle,
-    pub period_minutes: u64,
-    pub timeframe: CycleTimeframe,
-    pub period_timeframe: f32,
-}
-
-fn cycle_lows(
-    ticker: &TickerData,
-    candle_range: usize,
-    timeframe: CycleTimeframe,
-) -> CycleResult<Vec<CycleLow>> {
-    let lows = ticker.pivot_lows(candle_range, candle_range);
-    // get difference in days between each low
-    let low_periods = lows
-        .windows(2)
-        .map(|window| {
-            let low1 = &window[0];
-            let low2 = &window[1];
-            let period = low1
-                .date
-                .diff_minutes(&low2.date)
-                .map_err(CycleError::TimeError)? as u64;
-            let period_timeframe = timeframe.period_minutes_to_timeframe(period);
-            let candle1 = low1.clone();
-            let candle2 = low2.clone();
-            Ok(CycleLow {
-                first: candle1,
-                second: candle2,
-                period_minutes: period,
-                timeframe: timeframe.clone(),
-                period_timeframe,
-            })
-        })
-        .collect::<CycleResult<Vec<CycleLow>>>();
-    low_periods
-}
-
-fn filter_cycle_lows(cycle_lows: Vec<CycleLow>) -> CycleResult<Vec<CycleLow>> {
-    let mut filtered = Vec::new();
-    let periods = cycle_lows
-        .iter()
-        .map(|cycle_low| cycle_low.period_timeframe)
-        .collect::<Vec<f32>>();
-    for (index, value) in cycle_lows.iter().enumerate() {
-        let zscore = zscore_median(&periods, index)?;
-        if zscore.abs() < 1.01 {
-            filtered.push(value.clone());
-        }
-    }
-    Ok(filtered)
-}
-
 fn cycle_period(
     ticker: &TickerData,
     candle_range: usize,
     timeframe: CycleTimeframe,
 ) -> CycleResult<f32> {
-    let low_periods = cycle_lows(ticker, candle_range, timeframe)?;
-    let filtered = filter_cycle_lows(low_periods)?;
-    let filtered_periods = filtered
-        .iter()
-        .map(|cycle_low| cycle_low.period_timeframe)
-        .collect::<Vec<f32>>();
+    let market_structure = MarketStructure::new(ticker, candle_range);
+    let filtered_periods =
+        market_structure.filtered_cycle_periods(timeframe, CYCLE_LOW_Z_THRESHOLD)?;
 
     let filtered_mean = mean(&filtered_periods)?;
     Ok(filtered_mean)
@@ -190,6 +79,13 @@ fn main() -> CycleResult<()> {
     dji_weekly_ticker
         .add_csv_series(&PathBuf::from(dji_weekly))
         .expect("Failed to add DJI weekly CSV to TickerData");
+    info!("DJI weekly: {}", dji_weekly_ticker.stats());
+    for (period, power) in dji_weekly_ticker.dominant_cycles(5)? {
+        info!(
+            "DJI weekly dominant cycle = {} weeks, power = {}",
+            period, power
+        );
+    }
 
     // start sine cycles at first major low in time series (candle range should be high to get high timeframe low)
     let market_structure = MarketStructure::new(&dji_weekly_ticker, 100);
@@ -219,8 +115,11 @@ fn main() -> CycleResult<()> {
         // convert to SineCycle
         let sine_cycle =
             cycle_period_to_sine_cycle(dji_weekly_cycle, &dji_weekly_ticker, weekly_timeframe);
-        let correlation = sine_cycle.series_correlation(&market_structure)?;
-        info!("Correlation = {}", correlation.correlation);
+        let (phase, correlation) = sine_cycle.best_phase_correlation(&market_structure, 12)?;
+        info!(
+            "Correlation = {}, Phase = {}",
+            correlation.correlation, phase
+        );
     }
 
     // // DJI monthly cycle
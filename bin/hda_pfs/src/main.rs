@@ -139,7 +139,9 @@ async fn main() -> PFSResult<()> {
                                 if let Some(mut trade) = open_trade {
                                     if trade.order == Order::Short {
                                         trade.exit(date, candle.close);
-                                        backtest.add_trade(trade);
+                                        backtest
+                                            .add_trade(trade)
+                                            .map_err(PFSError::BacktestError)?;
                                     }
                                 }
                                 // enter long
@@ -161,7 +163,9 @@ async fn main() -> PFSResult<()> {
                                 if let Some(mut trade) = open_trade {
                                     if trade.order == Order::Long {
                                         trade.exit(date, candle.close);
-                                        backtest.add_trade(trade);
+                                        backtest
+                                            .add_trade(trade)
+                                            .map_err(PFSError::BacktestError)?;
                                     }
                                 }
                                 // enter short
@@ -238,6 +242,8 @@ async fn spx_pfs_confluent_direction(
         pfs_confluent_years,
         timeframe,
         &pfs_confluence_file,
+        0.0,
+        0,
     )
 }
 
@@ -256,33 +262,19 @@ fn write_hda_pfs_backtest_csv(backtests: Vec<Backtest>, out_file: &str) -> PFSRe
         if backtest.trades.is_empty() {
             continue;
         }
-        let start_date = backtest
-            .start_date
-            .expect("No start date found")
-            .to_string_daily();
-        let end_date = backtest
-            .end_date
-            .expect("No end date found")
-            .to_string_daily();
-        let pnl = backtest.pnl.unwrap_or(0.0);
-        let avg_trade = backtest.avg_trade_pnl.unwrap_or(0.0);
-        let avg_win = backtest.avg_win_trade_pnl.unwrap_or(0.0);
-        let avg_loss = backtest.avg_loss_trade_pnl.unwrap_or(0.0);
-        let win_trades = backtest.num_win_trades();
-        let loss_trades = backtest.num_loss_trades();
-        let trades = backtest.trades.len();
+        let report = backtest.report();
         writeln!(
             file,
             "{},{},{},{},{},{},{},{},{}",
-            start_date,
-            end_date,
-            pnl,
-            avg_trade,
-            avg_win,
-            avg_loss,
-            win_trades,
-            loss_trades,
-            trades
+            report.start_date,
+            report.end_date,
+            report.pnl,
+            report.avg_trade_pnl,
+            report.avg_win_trade_pnl,
+            report.avg_loss_trade_pnl,
+            report.win_trades,
+            report.loss_trades,
+            report.trades
         )
         .map_err(PFSError::CustomError)?;
     }
@@ -6,6 +6,9 @@ use std::io::Write;
 use std::path::PathBuf;
 use time_series::*;
 
+/// Most sine cycles summed into a composite, which keeps the combination search tractable
+const MAX_COMPOSITE_CYCLES: usize = 4;
+
 #[tokio::main]
 async fn main() -> CycleResult<()> {
     init_logger();
@@ -13,12 +16,24 @@ async fn main() -> CycleResult<()> {
     let path_to_dir = env::var("PATH_TO_DIR").expect("PATH_TO_DIR not set");
 
     // BTCUSD
-    #[allow(unused_variables)]
     let btc_daily = path_to_dir.clone() + "/data/BTCUSD/input/BTC_daily.csv";
     #[allow(unused_variables)]
     let btc_1h = path_to_dir.clone() + "/data/BTCUSD/input/BTC_1h.csv";
     #[allow(unused_variables)]
-    let btc_5min = path_to_dir.clone() + "/data/BTCUSD/input/BTC_5min.csv";
+    let btc_5min = path_to_dir + "/data/BTCUSD/input/BTC_5min.csv";
+
+    // above 0.5 BTC trends, below 0.5 it mean reverts and cycle reversals are favored
+    let mut btc_daily_ticker = TickerData::new();
+    match btc_daily_ticker.add_csv_series(&PathBuf::from(&btc_daily)) {
+        Ok(_) => info!(
+            "BTC daily Hurst exponent: {}",
+            btc_daily_ticker.hurst_exponent(256)?
+        ),
+        Err(e) => warn!(
+            "Skip BTC daily Hurst exponent, failed to load {}: {}",
+            btc_daily, e
+        ),
+    }
 
     // let out_file = PathBuf::from(path_to_dir + "/data/BTCUSD/output/BTC_cycle_results.txt");
 
@@ -73,6 +88,7 @@ fn single_sine_harmonics_composite(
     ticker_data_daily
         .add_csv_series(&PathBuf::from(btc_daily))
         .expect("Failed to add BTC daily CSV to TickerData");
+    info!("BTC daily: {}", ticker_data_daily.stats());
 
     // start sine cycles at first major low in time series (candle range should be high to get high timeframe low)
     let market_structure = MarketStructure::new(&ticker_data_daily, 200);
@@ -103,7 +119,7 @@ fn single_sine_harmonics_composite(
         .iter()
         .map(|x| {
             let period = cycle_timeframe.timeframe_to_period_minutes(*x as f32);
-            SineCycle::new(start_date, end_date, period, cycle_timeframe.clone())
+            SineCycle::new(start_date, end_date, period, cycle_timeframe)
         })
         .collect::<Vec<SineCycle>>();
 
@@ -113,8 +129,12 @@ fn single_sine_harmonics_composite(
         let correlation = cycle.series_correlation(&market_structure)?;
         correlations.push((cycle, correlation));
     }
-    // sort by highest correlation
-    correlations.sort_by(|a, b| b.1.correlation.partial_cmp(&a.1.correlation).unwrap());
+    // sort by highest lower bound of the 95% confidence interval of correlation
+    correlations.sort_by(|a, b| {
+        b.1.lower_bound(1.96)
+            .partial_cmp(&a.1.lower_bound(1.96))
+            .unwrap()
+    });
 
     let mut best_composites: Vec<(CompositeCycle, CycleCorrelation)> = Vec::new();
     // for each highly correlated sine wave, build a composite based on its harmonics (1/2, 1/3, etc)
@@ -128,7 +148,9 @@ fn single_sine_harmonics_composite(
             &start_date,
             &end_date,
             harmonic_cycles,
-            cycle_timeframe.clone(),
+            cycle_timeframe,
+            1,
+            MAX_COMPOSITE_CYCLES,
         )?;
 
         // for each composite wave compute correlation to time series
@@ -197,6 +219,7 @@ fn multi_sine_composite(
     ticker_data_daily
         .add_csv_series(&PathBuf::from(btc_daily))
         .expect("Failed to add BTC daily CSV to TickerData");
+    info!("BTC daily: {}", ticker_data_daily.stats());
 
     // start sine cycles at first major low in time series (candle range should be high to get high timeframe low)
     let market_structure = MarketStructure::new(&ticker_data_daily, 200);
@@ -229,6 +252,8 @@ fn multi_sine_composite(
         &end_date,
         cycles_to_test,
         cycle_timeframe,
+        1,
+        MAX_COMPOSITE_CYCLES,
     )?;
 
     // for each composite wave compute correlation to time series
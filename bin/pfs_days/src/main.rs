@@ -1,12 +1,12 @@
 use log::*;
 use plotters::prelude::full_palette::GREEN;
 use simplelog::{ColorChoice, Config, TermLogger, TerminalMode};
+use std::cmp::Ordering;
 use std::env;
-use std::error::Error;
 use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use time_series::*;
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 10)]
@@ -75,6 +75,16 @@ async fn main() -> PFSResult<()> {
         .parse::<f64>()
         .expect("STOP_LOSS_PCT not a number");
 
+    // units held at once when adding to a position on repeated signals, 1 to never add
+    let max_units = env::var("MAX_UNITS")
+        .map(|units| units.parse::<usize>().expect("MAX_UNITS not a number"))
+        .unwrap_or(1);
+
+    // days a trade is held before exiting at the close, unset to hold until a signal or stop
+    let max_hold = env::var("MAX_HOLD_DAYS").ok().map(|days| {
+        Duration::from_secs(days.parse::<u64>().expect("MAX_HOLD_DAYS not a number") * 86_400)
+    });
+
     // BTCUSD
     let btc_daily = path_to_dir.clone() + "/data/BTCUSD/input/BTC_daily.csv";
     #[allow(unused_variables)]
@@ -123,6 +133,7 @@ async fn main() -> PFSResult<()> {
     btc_daily_ticker
         .add_csv_series(&PathBuf::from(btc_daily))
         .expect("Failed to add BTC 5 minute csv series");
+    info!("BTC daily: {}", btc_daily_ticker.stats());
 
     // let mut spx_daily_ticker = TickerData::new();
     // spx_daily_ticker.build_series("SPX", Interval::Daily, &PathBuf::from(spx_daily))
@@ -197,7 +208,9 @@ async fn main() -> PFSResult<()> {
         trailing_stop_type,
         trailing_stop,
         stop_loss_pct,
-    );
+        max_units,
+        max_hold,
+    )?;
     println!(
         "Confluent PFS direction backtest results have been saved to {}",
         btc_conf_dir_backtest_file
@@ -227,8 +240,7 @@ async fn spx(
     let pfs = PlotPFS::new(start_date, end_date);
     let daily_pfs = PlotPFS::pfs_days(pfs.start_date, pfs.end_date, ticker_data, pfs_cycle)?;
     let title = format!("SPX - PFS Days {}", pfs_cycle);
-    pfs.plot_pfs(&daily_pfs, &pfs_file, &title, &GREEN);
-    Ok(())
+    pfs.plot_pfs(&daily_pfs, &pfs_file, &title, &GREEN)
 }
 
 #[allow(dead_code)]
@@ -243,8 +255,7 @@ async fn btcusd(
     let pfs = PlotPFS::new(start_date, end_date);
     let daily_pfs = PlotPFS::pfs_days(pfs.start_date, pfs.end_date, ticker_data, pfs_cycle)?;
     let title = format!("BTCUSD - PFS Days {}", pfs_cycle);
-    pfs.plot_pfs(&daily_pfs, &pfs_file, &title, &GREEN);
-    Ok(())
+    pfs.plot_pfs(&daily_pfs, &pfs_file, &title, &GREEN)
 }
 
 #[allow(dead_code)]
@@ -297,6 +308,8 @@ async fn btcusd_pfs_confluent_direction(
         pfs_confluent_years,
         timeframe,
         &pfs_confluence_file,
+        0.0,
+        0,
     )?;
     println!("##### Confluent PFS Direction Correlation #####");
     for corr in backtest_corr.iter() {
@@ -331,6 +344,7 @@ async fn btcusd_pfs_confluent_reversal(
         pfs_confluent_cycles,
         timeframe,
         &pfs_confluence_file,
+        0,
     )?;
     println!("##### Confluent PFS Reversal Correlation #####");
     for corr in backtest_corr.iter() {
@@ -342,19 +356,17 @@ async fn btcusd_pfs_confluent_reversal(
     Ok(backtest_corr)
 }
 
-fn write_backtest_csv(
-    backtests: Vec<(Backtest, Vec<u32>)>,
-    out_file: &str,
-) -> Result<(), Box<dyn Error>> {
+fn write_backtest_csv(backtests: Vec<(Backtest, Vec<u32>)>, out_file: &str) -> PFSResult<()> {
     if backtests.is_empty() {
-        return Err("No backtests found".into());
+        return Err(PFSError::BacktestEmpty);
     }
-    let mut file = File::create(out_file)?;
+    let mut file = File::create(out_file).map_err(PFSError::CustomError)?;
 
     writeln!(
         file,
-        "start_date,end_date,pnl,avg_trade,avg_win,avg_loss,win_trades,loss_trades,trades,cycles"
-    )?;
+        "start_date,end_date,pnl,avg_trade,avg_win,avg_loss,sharpe,sortino,win_trades,loss_trades,trades,cycles"
+    )
+    .map_err(PFSError::CustomError)?;
     for backtest in backtests.iter() {
         if backtest.0.trades.is_empty() {
             continue;
@@ -365,73 +377,29 @@ fn write_backtest_csv(
             .map(|cycle| cycle.to_string())
             .collect::<Vec<String>>()
             .join(",");
-        let start_date = backtest
-            .0
-            .start_date
-            .expect("No start date found")
-            .to_string_daily();
-        let end_date = backtest
-            .0
-            .end_date
-            .expect("No end date found")
-            .to_string_daily();
-        let pnl = backtest.0.pnl.unwrap_or(0.0);
-        let avg_trade = backtest.0.avg_trade_pnl.unwrap_or(0.0);
-        let avg_win = backtest.0.avg_win_trade_pnl.unwrap_or(0.0);
-        let avg_loss = backtest.0.avg_loss_trade_pnl.unwrap_or(0.0);
-        let win_trades = backtest.0.num_win_trades();
-        let loss_trades = backtest.0.num_loss_trades();
-        let trades = backtest.0.trades.len();
+        let report = backtest.0.report();
         writeln!(
             file,
-            "{},{},{},{},{},{},{},{},{},[{}]",
-            start_date,
-            end_date,
-            pnl,
-            avg_trade,
-            avg_win,
-            avg_loss,
-            win_trades,
-            loss_trades,
-            trades,
+            "{},{},{},{},{},{},{},{},{},{},{},[{}]",
+            report.start_date,
+            report.end_date,
+            report.pnl,
+            report.avg_trade_pnl,
+            report.avg_win_trade_pnl,
+            report.avg_loss_trade_pnl,
+            report.sharpe_ratio,
+            report.sortino_ratio,
+            report.win_trades,
+            report.loss_trades,
+            report.trades,
             cycles
-        )?;
+        )
+        .map_err(PFSError::CustomError)?;
     }
     Ok(())
 }
 
-fn stop_triggered(
-    order: &Order,
-    trailing_stop: &Option<f64>,
-    stop_loss: &Option<f64>,
-    candle: &Candle,
-) -> bool {
-    match order {
-        Order::Long => {
-            if trailing_stop.is_some() && stop_loss.is_some() {
-                candle.close < trailing_stop.unwrap() || candle.close < stop_loss.unwrap()
-            } else if trailing_stop.is_some() && stop_loss.is_none() {
-                candle.close < trailing_stop.unwrap()
-            } else if trailing_stop.is_none() && stop_loss.is_some() {
-                candle.close < stop_loss.unwrap()
-            } else {
-                false
-            }
-        }
-        Order::Short => {
-            if trailing_stop.is_some() && stop_loss.is_some() {
-                candle.close > trailing_stop.unwrap() || candle.close > stop_loss.unwrap()
-            } else if trailing_stop.is_some() && stop_loss.is_none() {
-                candle.close > trailing_stop.unwrap()
-            } else if trailing_stop.is_none() && stop_loss.is_some() {
-                candle.close > stop_loss.unwrap()
-            } else {
-                false
-            }
-        }
-    }
-}
-
+#[allow(clippy::too_many_arguments)]
 fn btcusd_confluent_direction_backtest(
     conf_pfs_dir: Vec<ConfluentPFSCorrelation>,
     ticker_data: &TickerData,
@@ -439,194 +407,21 @@ fn btcusd_confluent_direction_backtest(
     trailing_stop_type: TrailingStopType,
     trailing_stop: f64,
     stop_loss_pct: f64,
-) -> Vec<(Backtest, Vec<u32>)> {
-    let capital = 1000.0;
-    let mut backtests = Vec::<(Backtest, Vec<u32>)>::new();
-    // iterate through PFS cycle combinations
-    let mut threads = vec![];
-    for corr in conf_pfs_dir.into_iter() {
-        let ticker_data = ticker_data.clone();
-        let thread = std::thread::spawn(move || {
-            let open_trade_mutex: Arc<Mutex<Option<Trade>>> = Arc::new(Mutex::new(None));
-            let mut backtest = Backtest::new(capital);
-
-            // iterate time series
-            for candle in ticker_data.get_candles().iter() {
-                let date = candle.date;
-                // find confluent PFS event on this candle date
-                let pfs_event = corr.events.iter().find(|&x| x.date == date);
-                let mut open_trade = open_trade_mutex
-                    .lock()
-                    .expect("Failed to lock open trade mutex");
-
-                // confluent PFS direction on this date
-                match pfs_event {
-                    Some(pfs_event) => {
-                        if let Some(direction) = &pfs_event.direction {
-                            match direction {
-                                // exit short, enter long
-                                Direction::Up => {
-                                    // exit short
-                                    if let Some(trade) = &*open_trade {
-                                        let mut trade = trade.clone();
-                                        if trade.order == Order::Short
-                                            || stop_triggered(
-                                                &trade.order,
-                                                &trade.trailing_stop,
-                                                &trade.stop_loss,
-                                                candle,
-                                            )
-                                        {
-                                            trade.exit(date, candle.close);
-                                            backtest.add_trade(trade);
-                                            *open_trade = None;
-                                        }
-                                    }
-                                    // enter long
-                                    let qty = Trade::trade_quantity(capital, candle.close);
-                                    let trailing_stop = Trade::calc_trailing_stop(
-                                        Order::Long,
-                                        candle.close,
-                                        trailing_stop_type,
-                                        trailing_stop,
-                                    );
-                                    let stop_loss = Trade::calc_stop_loss(
-                                        Order::Long,
-                                        candle.close,
-                                        stop_loss_pct,
-                                    );
-                                    *open_trade = Some(Trade::new(
-                                        date,
-                                        Order::Long,
-                                        qty,
-                                        candle.close,
-                                        capital,
-                                        Some(trailing_stop),
-                                        Some(stop_loss),
-                                    ));
-                                }
-                                // exit long, enter short
-                                Direction::Down => {
-                                    // exit long
-                                    if let Some(trade) = &*open_trade {
-                                        // clone is ok because value is overwritten after this block
-                                        let mut trade = trade.clone();
-                                        if trade.order == Order::Long
-                                            || stop_triggered(
-                                                &trade.order,
-                                                &trade.trailing_stop,
-                                                &trade.stop_loss,
-                                                candle,
-                                            )
-                                        {
-                                            trade.exit(date, candle.close);
-                                            backtest.add_trade(trade);
-                                            *open_trade = None;
-                                        }
-                                    }
-                                    // enter short
-                                    let qty = Trade::trade_quantity(capital, candle.close);
-                                    let trailing_stop = Trade::calc_trailing_stop(
-                                        Order::Short,
-                                        candle.close,
-                                        trailing_stop_type,
-                                        trailing_stop,
-                                    );
-                                    let stop_loss = Trade::calc_stop_loss(
-                                        Order::Short,
-                                        candle.close,
-                                        stop_loss_pct,
-                                    );
-                                    *open_trade = Some(Trade::new(
-                                        date,
-                                        Order::Short,
-                                        qty,
-                                        candle.close,
-                                        capital,
-                                        Some(trailing_stop),
-                                        Some(stop_loss),
-                                    ));
-                                }
-                            }
-                        }
-                    }
-                    // if no event, check trailing stop
-                    // if trailing stop is hit, exit trade
-                    // other update trailing stop
-                    None => {
-                        debug!("No PFS Direction: {}", date.to_string_daily());
-                        if let Some(trade) = &*open_trade {
-                            match trade.order {
-                                Order::Long => {
-                                    // Long trailing stop is hit, exit trade
-                                    if stop_triggered(
-                                        &Order::Long,
-                                        &trade.trailing_stop,
-                                        &trade.stop_loss,
-                                        candle,
-                                    ) {
-                                        let mut trade = trade.clone();
-                                        trade.exit(date, candle.close);
-                                        backtest.add_trade(trade);
-                                        *open_trade = None;
-                                    }
-                                    // Long trailing stop is not hit, update trailing stop
-                                    else {
-                                        let mut trade = trade.clone();
-                                        trade.trailing_stop = Some(Trade::calc_trailing_stop(
-                                            Order::Long,
-                                            candle.close,
-                                            trailing_stop_type,
-                                            trailing_stop,
-                                        ));
-                                        *open_trade = Some(trade);
-                                    }
-                                }
-                                Order::Short => {
-                                    // Short trailing stop is hit, exit trade
-                                    if stop_triggered(
-                                        &Order::Short,
-                                        &trade.trailing_stop,
-                                        &trade.stop_loss,
-                                        candle,
-                                    ) {
-                                        let mut trade = trade.clone();
-                                        trade.exit(date, candle.close);
-                                        backtest.add_trade(trade);
-                                        *open_trade = None;
-                                    }
-                                    // Short trailing stop is not hit, update trailing stop
-                                    else {
-                                        let mut trade = trade.clone();
-                                        trade.trailing_stop = Some(Trade::calc_trailing_stop(
-                                            Order::Short,
-                                            candle.close,
-                                            trailing_stop_type,
-                                            trailing_stop,
-                                        ));
-                                        *open_trade = Some(trade);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            backtest.summarize();
-            (backtest, corr.cycles)
-        });
-        threads.push(thread);
-    }
-    for thread in threads {
-        let backtest = thread
-            .join()
-            .expect("Failed to join PFS confluent direction backtest thread");
-        backtests.push(backtest);
-    }
-    backtests.sort_by(|a, b| b.0.pnl.partial_cmp(&a.0.pnl).unwrap());
-    write_backtest_csv(backtests.clone(), backtest_file)
-        .expect("Failed to write PFS confluent direction backtest to CSV");
-    backtests
+    max_units: usize,
+    max_hold: Option<Duration>,
+) -> PFSResult<Vec<(Backtest, Vec<u32>)>> {
+    // Up exits shorts and enters long, Down exits longs and enters short
+    btcusd_confluent_backtest(
+        conf_pfs_dir,
+        ticker_data,
+        backtest_file,
+        |pfs_event| pfs_event.direction.clone().map(Order::from),
+        trailing_stop_type,
+        trailing_stop,
+        stop_loss_pct,
+        max_units,
+        max_hold,
+    )
 }
 
 #[allow(dead_code)]
@@ -637,192 +432,86 @@ fn btcusd_confluent_reversal_backtest(
     trailing_stop_type: TrailingStopType,
     trailing_stop: f64,
     stop_loss_pct: f64,
-) -> Vec<(Backtest, Vec<u32>)> {
+) -> PFSResult<Vec<(Backtest, Vec<u32>)>> {
+    // Low exits shorts and enters long, High exits longs and enters short
+    btcusd_confluent_backtest(
+        conf_pfs_rev,
+        ticker_data,
+        backtest_file,
+        |pfs_event| pfs_event.reversal.clone().map(Order::from),
+        trailing_stop_type,
+        trailing_stop,
+        stop_loss_pct,
+        1,
+        None,
+    )
+}
+
+/// Backtest each confluent PFS correlation on the order `signal` reads from its event on each candle date
+#[allow(clippy::too_many_arguments)]
+fn btcusd_confluent_backtest(
+    conf_pfs: Vec<ConfluentPFSCorrelation>,
+    ticker_data: &TickerData,
+    backtest_file: &str,
+    signal: fn(&ConfluentPFSEvent) -> Option<Order>,
+    trailing_stop_type: TrailingStopType,
+    trailing_stop: f64,
+    stop_loss_pct: f64,
+    max_units: usize,
+    max_hold: Option<Duration>,
+) -> PFSResult<Vec<(Backtest, Vec<u32>)>> {
     let capital = 1000.0;
     let mut backtests = Vec::<(Backtest, Vec<u32>)>::new();
     // iterate through PFS cycle combinations
     let mut threads = vec![];
-    for corr in conf_pfs_rev.into_iter() {
+    for corr in conf_pfs.into_iter() {
         let ticker_data = ticker_data.clone();
         let thread = std::thread::spawn(move || {
-            let open_trade_mutex: Arc<Mutex<Option<Trade>>> = Arc::new(Mutex::new(None));
+            let mut portfolio = PortfolioState::new(max_units).with_max_hold(max_hold);
             let mut backtest = Backtest::new(capital);
+            // pyramided units are open at the same time
+            backtest.max_open_trades = portfolio.max_units;
 
             // iterate time series
             for candle in ticker_data.get_candles().iter() {
                 let date = candle.date;
-                // find confluent PFS event on this candle date
-                let pfs_event = corr.events.iter().find(|&x| x.date == date);
-                let mut open_trade = open_trade_mutex
-                    .lock()
-                    .expect("Failed to lock open trade mutex");
-
-                // confluent PFS direction on this date
-                match pfs_event {
-                    Some(pfs_event) => {
-                        if let Some(reversal) = &pfs_event.reversal {
-                            match reversal {
-                                // exit short, enter long
-                                ReversalType::Low => {
-                                    // exit short
-                                    if let Some(trade) = &*open_trade {
-                                        let mut trade = trade.clone();
-                                        if trade.order == Order::Short
-                                            || stop_triggered(
-                                                &trade.order,
-                                                &trade.trailing_stop,
-                                                &trade.stop_loss,
-                                                candle,
-                                            )
-                                        {
-                                            trade.exit(date, candle.close);
-                                            backtest.add_trade(trade);
-                                            *open_trade = None;
-                                        }
-                                    }
-                                    // enter long
-                                    let qty = Trade::trade_quantity(capital, candle.close);
-                                    let trailing_stop = Trade::calc_trailing_stop(
-                                        Order::Long,
-                                        candle.close,
-                                        trailing_stop_type,
-                                        trailing_stop,
-                                    );
-                                    let stop_loss = Trade::calc_stop_loss(
-                                        Order::Long,
-                                        candle.close,
-                                        stop_loss_pct,
-                                    );
-                                    *open_trade = Some(Trade::new(
-                                        date,
-                                        Order::Long,
-                                        qty,
-                                        candle.close,
-                                        capital,
-                                        Some(trailing_stop),
-                                        Some(stop_loss),
-                                    ));
-                                }
-                                // exit long, enter short
-                                ReversalType::High => {
-                                    // exit long
-                                    if let Some(trade) = &*open_trade {
-                                        // clone is ok because value is overwritten after this block
-                                        let mut trade = trade.clone();
-                                        if trade.order == Order::Long
-                                            || stop_triggered(
-                                                &trade.order,
-                                                &trade.trailing_stop,
-                                                &trade.stop_loss,
-                                                candle,
-                                            )
-                                        {
-                                            trade.exit(date, candle.close);
-                                            backtest.add_trade(trade);
-                                            *open_trade = None;
-                                        }
-                                    }
-                                    // enter short
-                                    let qty = Trade::trade_quantity(capital, candle.close);
-                                    let trailing_stop = Trade::calc_trailing_stop(
-                                        Order::Short,
-                                        candle.close,
-                                        trailing_stop_type,
-                                        trailing_stop,
-                                    );
-                                    let stop_loss = Trade::calc_stop_loss(
-                                        Order::Short,
-                                        candle.close,
-                                        stop_loss_pct,
-                                    );
-                                    *open_trade = Some(Trade::new(
-                                        date,
-                                        Order::Short,
-                                        qty,
-                                        candle.close,
-                                        capital,
-                                        Some(trailing_stop),
-                                        Some(stop_loss),
-                                    ));
-                                }
-                            }
-                        }
-                    }
-                    // if no event, check trailing stop
-                    // if trailing stop is hit, exit trade
-                    // other update trailing stop
+                // confluent PFS event on this candle date
+                let order = match corr.events.iter().find(|&x| x.date == date) {
+                    // an event without a signal leaves the position as is
+                    Some(pfs_event) => match signal(pfs_event) {
+                        Some(order) => Some(order),
+                        None => continue,
+                    },
+                    // without an event, stops are checked and trailed
                     None => {
                         debug!("No PFS Direction: {}", date.to_string_daily());
-                        if let Some(trade) = &*open_trade {
-                            match trade.order {
-                                Order::Long => {
-                                    // Long trailing stop is hit, exit trade
-                                    if stop_triggered(
-                                        &Order::Long,
-                                        &trade.trailing_stop,
-                                        &trade.stop_loss,
-                                        candle,
-                                    ) {
-                                        let mut trade = trade.clone();
-                                        trade.exit(date, candle.close);
-                                        backtest.add_trade(trade);
-                                        *open_trade = None;
-                                    }
-                                    // Long trailing stop is not hit, update trailing stop
-                                    else {
-                                        let mut trade = trade.clone();
-                                        trade.trailing_stop = Some(Trade::calc_trailing_stop(
-                                            Order::Long,
-                                            candle.close,
-                                            trailing_stop_type,
-                                            trailing_stop,
-                                        ));
-                                        *open_trade = Some(trade);
-                                    }
-                                }
-                                Order::Short => {
-                                    // Short trailing stop is hit, exit trade
-                                    if stop_triggered(
-                                        &Order::Short,
-                                        &trade.trailing_stop,
-                                        &trade.stop_loss,
-                                        candle,
-                                    ) {
-                                        let mut trade = trade.clone();
-                                        trade.exit(date, candle.close);
-                                        backtest.add_trade(trade);
-                                        *open_trade = None;
-                                    }
-                                    // Short trailing stop is not hit, update trailing stop
-                                    else {
-                                        let mut trade = trade.clone();
-                                        trade.trailing_stop = Some(Trade::calc_trailing_stop(
-                                            Order::Short,
-                                            candle.close,
-                                            trailing_stop_type,
-                                            trailing_stop,
-                                        ));
-                                        *open_trade = Some(trade);
-                                    }
-                                }
-                            }
-                        }
+                        None
                     }
+                };
+                let closed = portfolio.step(
+                    candle,
+                    order,
+                    capital,
+                    trailing_stop_type,
+                    trailing_stop,
+                    stop_loss_pct,
+                );
+                for trade in closed {
+                    backtest.add_trade(trade).map_err(PFSError::BacktestError)?;
                 }
             }
             backtest.summarize();
-            (backtest, corr.cycles)
+            Ok((backtest, corr.cycles))
         });
         threads.push(thread);
     }
     for thread in threads {
         let backtest = thread
             .join()
-            .expect("Failed to join PFS confluent direction backtest thread");
+            .expect("Failed to join PFS confluent backtest thread")?;
         backtests.push(backtest);
     }
-    backtests.sort_by(|a, b| b.0.pnl.partial_cmp(&a.0.pnl).unwrap());
-    write_backtest_csv(backtests.clone(), backtest_file)
-        .expect("Failed to write PFS confluent direction backtest to CSV");
-    backtests
+    backtests.sort_by(|a, b| b.0.pnl.partial_cmp(&a.0.pnl).unwrap_or(Ordering::Equal));
+    write_backtest_csv(backtests.clone(), backtest_file)?;
+    Ok(backtests)
 }
@@ -83,7 +83,7 @@ async fn spx(
     ticker_data: &TickerData,
     pfs_file: String,
     pfs_cycle: u32,
-) {
+) -> PFSResult<()> {
     // ======================== Polarity Factor System ============================
     let pfs = PlotPFS::new(start_date, end_date);
     let daily_pfs = PlotPFS::pfs_months(pfs.start_date, pfs.end_date, ticker_data, pfs_cycle);
@@ -93,7 +93,7 @@ async fn spx(
         &pfs_file,
         &title,
         &GREEN,
-    );
+    )
 }
 
 #[allow(dead_code)]
@@ -103,7 +103,7 @@ async fn btcusd(
     ticker_data: &TickerData,
     pfs_file: String,
     pfs_cycle: u32,
-) {
+) -> PFSResult<()> {
     // ======================== Polarity Factor System ============================
     let pfs = PlotPFS::new(start_date, end_date);
     let daily_pfs = PlotPFS::pfs_months(pfs.start_date, pfs.end_date, ticker_data, pfs_cycle);
@@ -113,7 +113,7 @@ async fn btcusd(
         &pfs_file,
         &title,
         &GREEN,
-    );
+    )
 }
 
 #[allow(dead_code)]
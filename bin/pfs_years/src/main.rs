@@ -103,8 +103,7 @@ async fn main() {
     #[allow(unused_variables)]
     let btc_history = path_to_dir.clone() + "/data/BTCUSD/output/BTC_history.csv";
     #[allow(unused_variables)]
-    let btc_pfs_file =
-        path_to_dir.clone() + "/data/BTCUSD/output/BTC_pfs_" + &pfs_cycle_years + ".png";
+    let btc_pfs_file = path_to_dir + "/data/BTCUSD/output/BTC_pfs_" + &pfs_cycle_years + ".png";
 
     #[allow(unused_variables)]
     let start_date = Time::new(
@@ -127,6 +126,7 @@ async fn main() {
     btc_ticker_data
         .add_csv_series(&PathBuf::from(btc_daily))
         .expect("Failed to add BTC CSV series");
+    info!("BTC daily: {}", btc_ticker_data.stats());
 
     // let mut spx_ticker_data = TickerData::new();
     // spx_ticker_data.build_series(
@@ -174,6 +174,7 @@ async fn main() {
     //     pfs_confluent_years,
     //     &spx_ticker_data,
     //     spx_confluent_backtest_file,
+    //     10,
     //     1000.0,
     //     trailing_stop_type,
     //     trailing_stop,
@@ -200,6 +201,7 @@ async fn spx_confluent_reversal_backtest(
     pfs_confluent_years: &[u32],
     ticker_data: &TickerData,
     pfs_backtest_file: String,
+    min_total: usize,
     capital: f64,
     trailing_stop_type: TrailingStopType,
     trailing_stop: f64,
@@ -212,10 +214,15 @@ async fn spx_confluent_reversal_backtest(
         pfs_confluent_years,
         PFSTimeframe::Year,
         &pfs_backtest_file,
+        min_total,
         capital,
+        PositionSizing::FixedDollar(capital),
+        0.0,
         trailing_stop_type,
         trailing_stop,
         stop_loss_pct,
+        FillMode::Close,
+        HoldPolicy::AlwaysInMarket,
     );
 }
 
@@ -235,6 +242,8 @@ async fn spx_pfs_confluent_direction(
         pfs_confluent_years,
         PFSTimeframe::Year,
         &pfs_confluence_file,
+        0.0,
+        0,
     )?;
     for corr in backtest_corr {
         println!("Cycle: {:?}, Corr: {}", corr.cycles, corr.pct_correlation);
@@ -258,6 +267,7 @@ async fn spx_pfs_confluent_reversal(
         pfs_confluent_years,
         PFSTimeframe::Year,
         &pfs_confluence_file,
+        0,
     )?;
     for corr in backtest_corr {
         println!(
@@ -275,12 +285,12 @@ async fn spx(
     ticker_data: &TickerData,
     pfs_file: String,
     pfs_cycle_years: u32,
-) {
+) -> PFSResult<()> {
     // ======================== Polarity Factor System ============================
     let pfs = PlotPFS::new(start_date, end_date);
-    let daily_pfs = PlotPFS::pfs_years(pfs.start_date, pfs.end_date, ticker_data, pfs_cycle_years);
+    let daily_pfs = PlotPFS::pfs_years(pfs.start_date, pfs.end_date, ticker_data, pfs_cycle_years)?;
     let title = format!("SPX - PFS Years {}", pfs_cycle_years);
-    pfs.plot_pfs(&daily_pfs, &pfs_file, &title, &GREEN);
+    pfs.plot_pfs(&daily_pfs, &pfs_file, &title, &GREEN)
 }
 
 #[allow(dead_code)]
@@ -290,10 +300,10 @@ async fn btcusd(
     ticker_data: &TickerData,
     pfs_file: String,
     pfs_cycle_years: u32,
-) {
+) -> PFSResult<()> {
     // ======================== Polarity Factor System ============================
     let pfs = PlotPFS::new(start_date, end_date);
-    let daily_pfs = PlotPFS::pfs_years(pfs.start_date, pfs.end_date, ticker_data, pfs_cycle_years);
+    let daily_pfs = PlotPFS::pfs_years(pfs.start_date, pfs.end_date, ticker_data, pfs_cycle_years)?;
     let title = format!("BTCUSD - PFS Years {}", pfs_cycle_years);
-    pfs.plot_pfs(&daily_pfs, &pfs_file, &title, &GREEN);
+    pfs.plot_pfs(&daily_pfs, &pfs_file, &title, &GREEN)
 }
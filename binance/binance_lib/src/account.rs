@@ -1,8 +1,201 @@
 use crate::*;
 use log::*;
 use serde::de::DeserializeOwned;
+use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
-use time_series::precise_round;
+use time_series::{precise_round, Candle};
+
+/// Order resting on the dry run book until a candle crosses its price
+#[derive(Debug, Clone)]
+struct PaperOrder {
+    order_id: u64,
+    /// Order list the order is a leg of, or -1
+    order_list_id: i64,
+    trade: BinanceTrade,
+}
+
+impl PaperOrder {
+    /// Price that fills the order once a candle reaches it
+    fn trigger(&self) -> f64 {
+        self.trade
+            .stop_price
+            .or(self.trade.price)
+            .unwrap_or_default()
+    }
+
+    /// Price the order fills at, its limit price or else its trigger
+    fn fill_price(&self) -> f64 {
+        self.trade.price.unwrap_or_else(|| self.trigger())
+    }
+
+    /// True if `candle` reached the trigger from the side the order waits on.
+    /// Buy limits, buy take profits and sell stops wait for price to fall to them, the rest for it to rise.
+    fn crossed_by(&self, candle: &Candle) -> bool {
+        let trigger = self.trigger();
+        match (&self.trade.order_type, &self.trade.side) {
+            (
+                OrderType::Limit
+                | OrderType::LimitMaker
+                | OrderType::TakeProfitLimit
+                | OrderType::TakeProfit,
+                Side::Long,
+            )
+            | (OrderType::StopLossLimit | OrderType::StopLoss, Side::Short) => {
+                candle.low <= trigger
+            }
+            _ => candle.high >= trigger,
+        }
+    }
+}
+
+/// Balances and order book a dry run account trades against instead of Binance
+#[derive(Debug, Clone, Default)]
+pub struct PaperBalances {
+    pub quote: f64,
+    /// Goes negative when more is sold than held, which models a short
+    pub base: f64,
+    /// Price a market order fills at, which is the close of the last candle
+    pub last_price: f64,
+    /// Unix millisecond time of the last candle, which paper events are stamped with
+    pub time: u64,
+    next_order_id: u64,
+    next_order_list_id: u64,
+    /// Orders waiting for a candle to cross their price
+    open: Vec<PaperOrder>,
+    /// Execution reports of paper orders not yet taken with `Account::paper_events`
+    events: Vec<OrderTradeEvent>,
+}
+
+impl PaperBalances {
+    /// Execution report of `order` as the user stream would send it
+    fn event(
+        &self,
+        order: &PaperOrder,
+        status: &str,
+        executed_qty: f64,
+        price: f64,
+    ) -> Result<OrderTradeEvent> {
+        let time = match self.time {
+            0 => BinanceTrade::get_timestamp()?,
+            time => time,
+        };
+        let trade = &order.trade;
+        Ok(OrderTradeEvent {
+            event_type: "executionReport".to_string(),
+            event_time: time,
+            symbol: trade.symbol.clone(),
+            new_client_order_id: trade.client_order_id.clone(),
+            side: trade.side.fmt_binance().to_string(),
+            order_type: trade.order_type.fmt_binance().to_string(),
+            time_in_force: "GTC".to_string(),
+            qty: trade.quantity.to_string(),
+            price: trade.price.unwrap_or_default().to_string(),
+            p_ignore: String::new(),
+            f_ignore: String::new(),
+            g: order.order_list_id as i32,
+            c_ignore: None,
+            execution_type: match status {
+                "FILLED" => "TRADE",
+                status => status,
+            }
+            .to_string(),
+            order_status: status.to_string(),
+            order_reject_reason: "NONE".to_string(),
+            order_id: order.order_id,
+            qty_last_filled_trade: executed_qty.to_string(),
+            accumulated_qty_filled_trades: executed_qty.to_string(),
+            price_last_filled_trade: price.to_string(),
+            cumulative_quote_qty: (executed_qty * price).to_string(),
+            commission: "0".to_string(),
+            asset_commissioned: None,
+            trade_order_time: time,
+            trade_id: order.order_id as i64,
+            i_ignore: 0,
+            w: status == "NEW",
+            is_buyer_maker: false,
+            m_ignore: false,
+        })
+    }
+
+    /// Rest `trade` on the book, or fill it at the last price if it is a market order
+    fn place(&mut self, trade: BinanceTrade, order_list_id: i64) -> Result<PaperOrder> {
+        self.next_order_id += 1;
+        let order = PaperOrder {
+            order_id: self.next_order_id,
+            order_list_id,
+            trade,
+        };
+        if order.trade.order_type == OrderType::Market {
+            self.fill(&order, self.last_price)?;
+        } else {
+            let event = self.event(&order, "NEW", 0.0, 0.0)?;
+            self.events.push(event);
+            self.open.push(order.clone());
+        }
+        Ok(order)
+    }
+
+    /// Trade the balances for all of `order` at `price`
+    fn fill(&mut self, order: &PaperOrder, price: f64) -> Result<()> {
+        let trade = &order.trade;
+        let notional = trade.quantity * price;
+        match trade.side {
+            Side::Long => {
+                self.base += trade.quantity;
+                self.quote -= notional;
+            }
+            Side::Short => {
+                self.base -= trade.quantity;
+                self.quote += notional;
+            }
+        }
+        info!(
+            "📝 Dry run {} {} {} filled @ {}, balances {} base and {} quote",
+            trade.side.fmt_binance(),
+            trade.quantity,
+            trade.symbol,
+            price,
+            precise_round(self.base, 5),
+            precise_round(self.quote, 5),
+        );
+        let event = self.event(order, "FILLED", trade.quantity, price)?;
+        self.events.push(event);
+        Ok(())
+    }
+
+    /// Take the open order matching `is_order` off the book
+    fn take(&mut self, is_order: impl Fn(&PaperOrder) -> bool) -> Option<PaperOrder> {
+        let index = self.open.iter().position(is_order)?;
+        Some(self.open.remove(index))
+    }
+
+    /// Cancel the open order matching `is_order`
+    fn cancel(&mut self, is_order: impl Fn(&PaperOrder) -> bool) -> Result<Option<PaperOrder>> {
+        let order = match self.take(is_order) {
+            Some(order) => order,
+            None => return Ok(None),
+        };
+        let event = self.event(&order, "CANCELED", 0.0, 0.0)?;
+        self.events.push(event);
+        Ok(Some(order))
+    }
+}
+
+fn paper_canceled(order: &PaperOrder) -> OrderCanceled {
+    OrderCanceled {
+        symbol: order.trade.symbol.clone(),
+        orig_client_order_id: Some(order.trade.client_order_id.clone()),
+        order_id: Some(order.order_id),
+        client_order_id: Some(order.trade.client_order_id.clone()),
+    }
+}
+
+fn unknown_order() -> BinanceError {
+    BinanceError::Binance(BinanceContentError {
+        code: -2011,
+        msg: "Unknown order sent.".to_string(),
+    })
+}
 
 #[derive(Clone)]
 pub struct Account {
@@ -11,9 +204,21 @@ pub struct Account {
     pub base_asset: String,
     pub quote_asset: String,
     pub ticker: String,
+    /// Fill trades against `paper` balances and log them without calling Binance
+    pub dry_run: bool,
+    pub paper: Arc<Mutex<PaperBalances>>,
+    /// Trading rules of the ticker, fetched on first use
+    filters: Arc<Mutex<Option<SymbolFilters>>>,
 }
 
 impl Account {
+    /// Filters of a dry run without injected filters, which round like `precise_round` and check no min notional
+    pub const DRY_RUN_FILTERS: SymbolFilters = SymbolFilters {
+        step_size: 0.00001,
+        tick_size: 0.01,
+        min_notional: 0.0,
+    };
+
     #[allow(dead_code)]
     pub fn new(
         client: Client,
@@ -28,6 +233,30 @@ impl Account {
             base_asset,
             quote_asset,
             ticker,
+            dry_run: false,
+            paper: Arc::new(Mutex::new(PaperBalances::default())),
+            filters: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Trade offline against simulated balances of `quote` and `base` asset.
+    /// Market orders fill at the last price as soon as they are placed, other orders rest
+    /// until `paper_candle` crosses their price.
+    pub fn with_dry_run(mut self, quote: f64, base: f64) -> Self {
+        self.dry_run = true;
+        self.paper = Arc::new(Mutex::new(PaperBalances {
+            quote,
+            base,
+            ..Default::default()
+        }));
+        self
+    }
+
+    /// Trading rules of the ticker to use instead of fetching them from exchangeInfo
+    pub fn with_symbol_filters(self, filters: SymbolFilters) -> Self {
+        Self {
+            filters: Arc::new(Mutex::new(Some(filters))),
+            ..self
         }
     }
 
@@ -38,8 +267,37 @@ impl Account {
             .get::<ExchangeInformation>(API::Spot(Spot::ExchangeInfo), Some(req))
     }
 
+    /// Step size, tick size and min notional of the ticker from exchangeInfo,
+    /// which are cached after the first request.
+    /// A dry run never requests them, it uses the filters from `with_symbol_filters` or else `DRY_RUN_FILTERS`.
+    pub fn symbol_filters(&self) -> Result<SymbolFilters> {
+        let mut filters = self.filters.lock()?;
+        if let Some(filters) = *filters {
+            return Ok(filters);
+        }
+        if self.dry_run {
+            *filters = Some(Self::DRY_RUN_FILTERS);
+            return Ok(Self::DRY_RUN_FILTERS);
+        }
+        let info = self.exchange_info(self.ticker.clone())?;
+        let symbol = info
+            .symbols
+            .iter()
+            .find(|symbol| symbol.symbol == self.ticker)
+            .ok_or_else(|| {
+                BinanceError::Custom(format!("{} is not in exchange info", self.ticker))
+            })?;
+        let symbol_filters = SymbolFilters::from_symbol(symbol)?;
+        debug!("{} filters: {:?}", self.ticker, symbol_filters);
+        *filters = Some(symbol_filters);
+        Ok(symbol_filters)
+    }
+
     /// Get account info which includes token balances
     pub fn account_info(&self) -> Result<AccountInfoResponse> {
+        if self.dry_run {
+            return self.paper_account_info();
+        }
         let builder = AccountInfo::request(None);
         let req = builder.request;
         let pre = SystemTime::now();
@@ -74,6 +332,9 @@ impl Account {
 
     /// Get price of a single symbol
     pub fn price(&self) -> Result<f64> {
+        if self.dry_run {
+            return Ok(self.paper.lock()?.last_price);
+        }
         let req = Price::request(self.ticker.to_string());
         let res = self
             .client
@@ -81,6 +342,20 @@ impl Account {
         res.price.parse::<f64>().map_err(BinanceError::ParseFloat)
     }
 
+    /// Bid/ask spread of the symbol as a percent of the mid price.
+    /// A dry run trades at the last price, so it has no spread.
+    pub fn spread_pct(&self) -> Result<f64> {
+        if self.dry_run {
+            return Ok(0.0);
+        }
+        let req = Price::request(self.ticker.to_string());
+        let book = self
+            .client
+            .get::<Tickers>(API::Spot(Spot::BookTicker), Some(req))?;
+        let mid = (book.bid_price + book.ask_price) / 2.0;
+        Ok((book.ask_price - book.bid_price) / mid * 100.0)
+    }
+
     /// Get historical orders for a single symbol
     pub fn all_orders(&self, symbol: String) -> Result<Vec<HistoricalOrder>> {
         let req = AllOrders::request(symbol, Some(5000));
@@ -95,6 +370,9 @@ impl Account {
     /// Get last open trade for a single symbol
     /// Returns Some if there is an open trade, None otherwise
     pub fn open_orders(&self, symbol: String) -> Result<Vec<HistoricalOrder>> {
+        if self.dry_run {
+            return self.paper_open_orders();
+        }
         let req = AllOrders::request(symbol, Some(5000));
         let orders = self
             .client
@@ -110,6 +388,14 @@ impl Account {
     /// Cancel all open orders for a single symbol
     pub fn cancel_all_open_orders(&self) -> Result<Vec<OrderCanceled>> {
         info!("Canceling all active orders");
+        if self.dry_run {
+            let mut paper = self.paper.lock()?;
+            let mut canceled = vec![];
+            while let Some(order) = paper.cancel(|_| true)? {
+                canceled.push(paper_canceled(&order));
+            }
+            return Ok(canceled);
+        }
         let req = CancelOrders::request(self.ticker.clone(), Some(10000));
         let res = self
             .client
@@ -130,6 +416,16 @@ impl Account {
 
     pub fn cancel_order(&self, order_id: u64) -> Result<OrderCanceled> {
         debug!("Canceling order {}", order_id);
+        if self.dry_run {
+            return match self
+                .paper
+                .lock()?
+                .cancel(|order| order.order_id == order_id)?
+            {
+                Some(order) => Ok(paper_canceled(&order)),
+                None => Err(unknown_order()),
+            };
+        }
         let req = CancelOrder::request(order_id, self.ticker.to_string(), Some(10000));
         let res = self
             .client
@@ -147,11 +443,308 @@ impl Account {
         res
     }
 
+    /// Base asset quantities of both legs of a flip from `current_side`,
+    /// as `(exit_qty, entry_qty)` which are both traded on the opposite side.
+    /// The exit closes all of `current_qty` and the entry opens `target_notional` of quote asset at `price`.
+    /// A non-positive price opens no new position.
+    pub fn flip_quantity(
+        current_side: Side,
+        current_qty: f64,
+        target_notional: f64,
+        price: f64,
+    ) -> (f64, f64) {
+        let exit_qty = precise_round(current_qty.abs(), 5);
+        let entry_qty = match price > 0.0 {
+            true => precise_round(target_notional.abs() / price, 5),
+            false => 0.0,
+        };
+        debug!(
+            "Flip {} {}: exit {}, entry {}",
+            current_side.fmt_binance(),
+            current_qty,
+            exit_qty,
+            entry_qty
+        );
+        (exit_qty, entry_qty)
+    }
+
+    /// Place `trade`, unless its notional is below the ticker's min notional
+    /// which Binance would reject, in which case no order is sent.
+    /// If the filters can't be fetched the trade is placed unchecked.
     pub fn trade<T: DeserializeOwned>(&self, trade: BinanceTrade) -> Result<T> {
+        match self.symbol_filters() {
+            Ok(filters) => Self::check_min_notional(&trade, &filters)?,
+            Err(e) => warn!(
+                "No symbol filters to check {} against min notional: {:?}",
+                trade.client_order_id, e
+            ),
+        }
+        if self.dry_run {
+            return self.paper_trade(trade);
+        }
         let req = trade.request();
         self.client.post_signed::<T>(API::Spot(Spot::Order), req)
     }
 
+    /// Place the take profit and stop loss of `oco` as one order list,
+    /// unless either leg is below the ticker's min notional, in which case no order is sent.
+    /// Returns the list with the order IDs of both legs.
+    pub fn trade_oco(&self, oco: OcoTrade) -> Result<OcoOrderResponse> {
+        let legs = [
+            BinanceTrade::new(
+                oco.symbol.clone(),
+                oco.limit_client_order_id(),
+                oco.side.clone(),
+                OrderType::LimitMaker,
+                oco.quantity,
+                Some(oco.price),
+                None,
+                None,
+                None,
+            ),
+            BinanceTrade::new(
+                oco.symbol.clone(),
+                oco.stop_client_order_id(),
+                oco.side.clone(),
+                OrderType::StopLossLimit,
+                oco.quantity,
+                Some(oco.stop_limit_price),
+                Some(oco.stop_price),
+                None,
+                None,
+            ),
+        ];
+        match self.symbol_filters() {
+            Ok(filters) => {
+                for leg in legs.iter() {
+                    Self::check_min_notional(leg, &filters)?;
+                }
+            }
+            Err(e) => warn!(
+                "No symbol filters to check {} against min notional: {:?}",
+                oco.list_client_order_id, e
+            ),
+        }
+        if self.dry_run {
+            info!(
+                "📝 Dry run OCO {} {} {}, take profit @ {}, stop loss @ {}",
+                oco.side.fmt_binance(),
+                oco.quantity,
+                oco.symbol,
+                oco.price,
+                oco.stop_price
+            );
+            let mut paper = self.paper.lock()?;
+            paper.next_order_list_id += 1;
+            let order_list_id = paper.next_order_list_id as i64;
+            let mut orders = vec![];
+            for leg in legs {
+                let order = paper.place(leg, order_list_id)?;
+                orders.push(OcoOrder {
+                    symbol: order.trade.symbol.clone(),
+                    order_id: order.order_id,
+                    client_order_id: order.trade.client_order_id.clone(),
+                });
+            }
+            return Ok(OcoOrderResponse {
+                order_list_id,
+                list_client_order_id: oco.list_client_order_id,
+                symbol: oco.symbol,
+                orders,
+            });
+        }
+        self.client
+            .post_signed::<OcoOrderResponse>(API::Spot(Spot::Oco), oco.request())
+    }
+
+    /// Error if the quantity times the limit or stop price of `trade` is below the min notional.
+    /// Market orders have no price to check until they fill.
+    pub fn check_min_notional(trade: &BinanceTrade, filters: &SymbolFilters) -> Result<()> {
+        if let Some(price) = trade.price.or(trade.stop_price) {
+            let notional = trade.quantity * price;
+            if notional < filters.min_notional {
+                return Err(BinanceError::BelowMinNotional {
+                    notional,
+                    min_notional: filters.min_notional,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Cancel the open order `order_id` and place `trade` in its place in a single request, so the account
+    /// is never left without an order between the two. If the cancel fails the new order is not placed.
+    /// `T` is the response of the new order inside a `CancelReplaceResponse`.
+    pub fn cancel_replace<T: DeserializeOwned>(
+        &self,
+        order_id: u64,
+        trade: BinanceTrade,
+    ) -> Result<T> {
+        let client_order_id = trade.client_order_id.clone();
+        let res = match self.dry_run {
+            true => {
+                let canceled = self
+                    .paper
+                    .lock()?
+                    .cancel(|order| order.order_id == order_id)?
+                    .ok_or_else(unknown_order)?;
+                let new_order = self.paper_trade::<serde_json::Value>(trade)?;
+                serde_json::from_value(serde_json::json!({
+                    "cancelResult": "SUCCESS",
+                    "newOrderResult": "SUCCESS",
+                    "cancelResponse": {
+                        "symbol": self.ticker,
+                        "origClientOrderId": canceled.trade.client_order_id,
+                        "orderId": canceled.order_id,
+                    },
+                    "newOrderResponse": new_order,
+                }))?
+            }
+            false => {
+                let req = CancelReplace::request(trade, order_id, CancelReplaceMode::StopOnFailure);
+                self.client
+                    .post_signed::<T>(API::Spot(Spot::CancelReplace), req)?
+            }
+        };
+        debug!("Replaced order {} with {}", order_id, client_order_id);
+        Ok(res)
+    }
+
+    /// Place `trade` on the paper book and respond as Binance would, filled if it is a market order
+    fn paper_trade<T: DeserializeOwned>(&self, trade: BinanceTrade) -> Result<T> {
+        let mut paper = self.paper.lock()?;
+        info!(
+            "📝 Dry run {} {} {} {} @ {:?}",
+            trade.order_type.fmt_binance(),
+            trade.side.fmt_binance(),
+            trade.quantity,
+            trade.symbol,
+            trade.stop_price.or(trade.price)
+        );
+        let order = paper.place(trade, -1)?;
+        let trade = &order.trade;
+        let (status, executed_qty, price) = match trade.order_type {
+            OrderType::Market => ("FILLED", trade.quantity, paper.last_price),
+            _ => ("NEW", 0.0, order.fill_price()),
+        };
+        let timestamp = BinanceTrade::get_timestamp()?;
+        let res = OrderResponse {
+            symbol: trade.symbol.clone(),
+            order_id: order.order_id,
+            order_list_id: -1,
+            client_order_id: trade.client_order_id.clone(),
+            transact_time: timestamp,
+            price: price.to_string(),
+            orig_qty: trade.quantity.to_string(),
+            executed_qty: executed_qty.to_string(),
+            cummulative_quote_qty: (executed_qty * price).to_string(),
+            status: status.to_string(),
+            time_in_force: "GTC".to_string(),
+            type_: trade.order_type.fmt_binance().to_string(),
+            side: trade.side.fmt_binance().to_string(),
+            working_time: timestamp,
+            self_trade_prevention_mode: "NONE".to_string(),
+            fills: vec![],
+        };
+        Ok(serde_json::from_value(serde_json::to_value(res)?)?)
+    }
+
+    /// Fill the open paper orders `candle` crossed at their price, canceling the other leg of a filled OCO,
+    /// then mark the last price to its close. Execution reports are queued for `paper_events`.
+    pub fn paper_candle(&self, candle: &Candle) -> Result<()> {
+        let mut paper = self.paper.lock()?;
+        paper.time = candle.date.to_unix_ms() as u64;
+        let crossed = paper
+            .open
+            .iter()
+            .filter(|order| order.crossed_by(candle))
+            .cloned()
+            .collect::<Vec<PaperOrder>>();
+        for order in crossed {
+            // gone if it was the other leg of an OCO filled by this candle
+            if paper.take(|open| open.order_id == order.order_id).is_none() {
+                continue;
+            }
+            paper.fill(&order, order.fill_price())?;
+            if order.order_list_id >= 0 {
+                paper.cancel(|open| open.order_list_id == order.order_list_id)?;
+            }
+        }
+        paper.last_price = candle.close;
+        Ok(())
+    }
+
+    /// Take the execution reports of paper orders placed, filled or canceled since the last call
+    pub fn paper_events(&self) -> Result<Vec<OrderTradeEvent>> {
+        Ok(std::mem::take(&mut self.paper.lock()?.events))
+    }
+
+    /// Open paper orders as Binance lists them
+    fn paper_open_orders(&self) -> Result<Vec<HistoricalOrder>> {
+        let paper = self.paper.lock()?;
+        Ok(paper
+            .open
+            .iter()
+            .map(|order| HistoricalOrder {
+                symbol: order.trade.symbol.clone(),
+                order_id: order.order_id,
+                order_list_id: order.order_list_id,
+                client_order_id: order.trade.client_order_id.clone(),
+                price: order.trade.price.unwrap_or_default().to_string(),
+                orig_qty: order.trade.quantity.to_string(),
+                executed_qty: "0".to_string(),
+                cummulative_quote_qty: "0".to_string(),
+                status: "NEW".to_string(),
+                time_in_force: "GTC".to_string(),
+                _type: order.trade.order_type.fmt_binance().to_string(),
+                side: order.trade.side.fmt_binance().to_string(),
+                stop_price: order.trade.stop_price.map(|price| price.to_string()),
+                iceberg_qty: None,
+                time: paper.time as i64,
+                update_time: paper.time as i64,
+                is_working: true,
+                orig_quote_order_qty: "0".to_string(),
+                working_time: paper.time as i64,
+                self_trade_prevention_mode: "NONE".to_string(),
+            })
+            .collect())
+    }
+
+    /// Account info with the paper balances of the base and quote asset, none of it locked
+    fn paper_account_info(&self) -> Result<AccountInfoResponse> {
+        let paper = self.paper.lock()?;
+        let balance = |asset: &str, free: f64| Balance {
+            asset: asset.to_string(),
+            free: free.to_string(),
+            locked: "0".to_string(),
+        };
+        let zero = "0".to_string();
+        Ok(AccountInfoResponse {
+            maker_commission: 0,
+            taker_commission: 0,
+            buyer_commission: 0,
+            seller_commission: 0,
+            commission_rates: CommissionRates {
+                maker: zero.clone(),
+                taker: zero.clone(),
+                buyer: zero.clone(),
+                seller: zero,
+            },
+            can_trade: true,
+            can_withdraw: false,
+            can_deposit: false,
+            brokered: false,
+            require_self_trade_prevention: false,
+            update_time: AccountInfo::get_timestamp()?,
+            account_type: "SPOT".to_string(),
+            balances: vec![
+                balance(&self.quote_asset, paper.quote),
+                balance(&self.base_asset, paper.base),
+            ],
+            permissions: vec!["SPOT".to_string()],
+        })
+    }
+
     pub fn equalize_assets(&self) -> Result<()> {
         info!("Equalizing assets");
         let account_info = self.account_info()?;
@@ -164,16 +757,16 @@ impl Account {
         let base_balance = assets.free_base;
 
         let sum = quote_balance + base_balance;
-        let equal = precise_round!(sum / 2_f64, 5);
-        let quote_diff = precise_round!(quote_balance - equal, 5);
-        let base_diff = precise_round!(base_balance - equal, 5);
+        let equal = precise_round(sum / 2_f64, 5);
+        let quote_diff = precise_round(quote_balance - equal, 5);
+        let base_diff = precise_round(base_balance - equal, 5);
         let min_notional = 0.001;
 
         // buy BTC
         if quote_diff > 0_f64 && quote_diff > min_notional {
             let timestamp = BinanceTrade::get_timestamp()?;
             let client_order_id = format!("{}-{}", timestamp, "EQUALIZE_QUOTE");
-            let long_qty = precise_round!(quote_diff, 5);
+            let long_qty = precise_round(quote_diff, 5);
             info!(
                 "Quote asset too high = {} {}, 50/50 = {} {}, buy base asset = {} {}",
                 quote_balance * price,
@@ -204,7 +797,7 @@ impl Account {
         if base_diff > 0_f64 && base_diff > min_notional {
             let timestamp = BinanceTrade::get_timestamp()?;
             let client_order_id = format!("{}-{}", timestamp, "EQUALIZE_BASE");
-            let short_qty = precise_round!(base_diff, 5);
+            let short_qty = precise_round(base_diff, 5);
             info!(
                 "Base asset too high = {} {}, 50/50 = {} {}, sell base asset = {} {}",
                 base_balance, self.base_asset, equal, self.base_asset, short_qty, self.base_asset
@@ -229,3 +822,340 @@ impl Account {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    #[test]
+    fn long_to_short_flip() {
+        let long_qty = 0.25;
+        let (exit_qty, entry_qty) = Account::flip_quantity(Side::Long, long_qty, 10000.0, 20000.0);
+        // exit closes the whole long, entry opens 10000 quote of short
+        assert_eq!(exit_qty, long_qty);
+        assert_eq!(entry_qty, 0.5);
+        let position = long_qty - exit_qty - entry_qty;
+        assert_eq!(position, -0.5);
+        assert_eq!(
+            Account::flip_quantity(Side::Short, 0.5, 10000.0, 0.0),
+            (0.5, 0.0)
+        );
+    }
+
+    /// Serve `body` to a single HTTP request on a local port, so a second request fails to connect.
+    /// Returns the host to send to and a handle that joins with the request line that was served.
+    fn serve_once(body: &'static str) -> Result<(String, JoinHandle<std::io::Result<String>>)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let host = format!("http://{}", listener.local_addr()?);
+        let server = std::thread::spawn(move || -> std::io::Result<String> {
+            let (stream, _) = listener.accept()?;
+            let mut reader = BufReader::new(stream.try_clone()?);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line)?;
+            let mut header = String::new();
+            while reader.read_line(&mut header)? > 2 {
+                header.clear();
+            }
+            write!(
+                &stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )?;
+            Ok(request_line)
+        });
+        Ok((host, server))
+    }
+
+    fn account(host: String) -> Account {
+        Account::new(
+            Client::new(None, None, host),
+            5000,
+            "BTC".to_string(),
+            "USDT".to_string(),
+            "BTCUSDT".to_string(),
+        )
+    }
+
+    #[test]
+    fn cancel_replace_single_request() -> Result<()> {
+        let (host, server) = serve_once(
+            r#"{"cancelResult":"SUCCESS","newOrderResult":"SUCCESS","cancelResponse":{"symbol":"BTCUSDT","origClientOrderId":"1-ENTRY","orderId":1},"newOrderResponse":{"symbol":"BTCUSDT","orderId":2,"orderListId":-1,"clientOrderId":"2-ENTRY","transactTime":1690000000000}}"#,
+        )?;
+        let account = account(host);
+        let trade = BinanceTrade::new(
+            "BTCUSDT".to_string(),
+            "2-ENTRY".to_string(),
+            Side::Short,
+            OrderType::Limit,
+            0.5,
+            Some(20000.0),
+            None,
+            None,
+            None,
+        );
+        let res = account.cancel_replace::<CancelReplaceResponse<LimitOrderResponse>>(1, trade)?;
+        assert_eq!(res.cancel_result, "SUCCESS");
+        assert_eq!(
+            res.cancel_response.orig_client_order_id.as_deref(),
+            Some("1-ENTRY")
+        );
+        assert_eq!(res.new_order_response.unwrap().order_id, 2);
+
+        let request_line = server.join().unwrap()?;
+        assert!(request_line.starts_with("POST /api/v3/order/cancelReplace?"));
+        assert!(request_line.contains("cancelReplaceMode=STOP_ON_FAILURE"));
+        assert!(request_line.contains("cancelOrderId=1&"));
+        assert!(request_line.contains("newClientOrderId=2-ENTRY"));
+        Ok(())
+    }
+
+    /// exchangeInfo of BTCUSDT with a 0.01 tick size, 0.0001 step size and 10 USDT min notional
+    const EXCHANGE_INFO: &str = r#"{"timezone":"UTC","serverTime":1690000000000,"rateLimits":[],"symbols":[{"symbol":"BTCUSDT","status":"TRADING","baseAsset":"BTC","baseAssetPrecision":8,"quoteAsset":"USDT","quotePrecision":8,"orderTypes":["LIMIT","MARKET"],"icebergAllowed":true,"isSpotTradingAllowed":true,"isMarginTradingAllowed":false,"filters":[{"filterType":"PRICE_FILTER","minPrice":"0.01000000","maxPrice":"1000000.00000000","tickSize":"0.01000000"},{"filterType":"LOT_SIZE","minQty":"0.00010000","maxQty":"9000.00000000","stepSize":"0.00010000"},{"filterType":"NOTIONAL","minNotional":"10.00000000","applyMinToMarket":true,"maxNotional":"9000000.00000000","applyMaxToMarket":false,"avgPriceMins":5}]}]}"#;
+
+    #[test]
+    fn symbol_filters_round_quantity() -> Result<()> {
+        let (host, server) = serve_once(EXCHANGE_INFO)?;
+        let account = account(host);
+        let filters = account.symbol_filters()?;
+        assert_eq!(
+            filters,
+            SymbolFilters {
+                step_size: 0.0001,
+                tick_size: 0.01,
+                min_notional: 10.0,
+            }
+        );
+        // cached, so a second lookup makes no request to the server that only serves one
+        assert_eq!(account.symbol_filters()?, filters);
+        assert!(server
+            .join()
+            .unwrap()?
+            .starts_with("GET /api/v3/exchangeInfo?symbol=BTCUSDT"));
+
+        // floored to the step size rather than rounded up past the balance
+        assert_eq!(BinanceTrade::round_quantity(0.123456789, &filters), 0.1234);
+        assert_eq!(BinanceTrade::round_quantity(0.3, &filters), 0.3);
+        assert_eq!(BinanceTrade::round_price(29246.725, &filters), 29246.73);
+        Ok(())
+    }
+
+    #[test]
+    fn below_min_notional_not_sent() -> Result<()> {
+        // only exchange info is served, so an order request would fail to connect
+        let (host, server) = serve_once(EXCHANGE_INFO)?;
+        let account = account(host);
+        // 0.0004 BTC at 20000 is 8 USDT
+        let trade = BinanceTrade::new(
+            "BTCUSDT".to_string(),
+            "1-ENTRY".to_string(),
+            Side::Long,
+            OrderType::Limit,
+            0.0004,
+            Some(20000.0),
+            None,
+            None,
+            None,
+        );
+        match account.trade::<LimitOrderResponse>(trade) {
+            Err(BinanceError::BelowMinNotional {
+                notional,
+                min_notional,
+            }) => {
+                assert_eq!(notional, 8.0);
+                assert_eq!(min_notional, 10.0);
+            }
+            res => panic!("expected below min notional, got {:?}", res),
+        }
+        assert!(server
+            .join()
+            .unwrap()?
+            .starts_with("GET /api/v3/exchangeInfo"));
+        Ok(())
+    }
+
+    fn dry_run_account() -> Account {
+        // nothing listens on port 1, so any request that reaches Binance fails
+        account("http://127.0.0.1:1".to_string()).with_dry_run(10000.0, 0.0)
+    }
+
+    #[test]
+    fn dry_run_filters_without_exchange_info() -> Result<()> {
+        // any exchangeInfo request would fail to connect
+        let account = dry_run_account();
+        assert_eq!(account.symbol_filters()?, Account::DRY_RUN_FILTERS);
+
+        let filters = SymbolFilters {
+            step_size: 0.0001,
+            tick_size: 0.01,
+            min_notional: 10.0,
+        };
+        let account = dry_run_account().with_symbol_filters(filters);
+        assert_eq!(account.symbol_filters()?, filters);
+
+        // 0.0004 BTC stop loss at 19500 is 7.8 USDT, so neither leg is placed
+        let oco = BinanceTrade::oco(
+            "BTCUSDT".to_string(),
+            Side::Short,
+            0.0004,
+            30000.0,
+            19500.0,
+            19490.0,
+        );
+        assert!(matches!(
+            account.trade_oco(oco),
+            Err(BinanceError::BelowMinNotional { .. })
+        ));
+        assert!(account.open_orders("BTCUSDT".to_string())?.is_empty());
+        Ok(())
+    }
+
+    fn paper_candle(low: f64, high: f64) -> Candle {
+        Candle {
+            date: time_series::Time::from_unix_msec(1690000000000),
+            open: low,
+            high,
+            low,
+            close: high,
+            volume: None,
+        }
+    }
+
+    fn limit(side: Side, qty: f64, price: f64, id: &str) -> BinanceTrade {
+        BinanceTrade::new(
+            "BTCUSDT".to_string(),
+            id.to_string(),
+            side,
+            OrderType::Limit,
+            qty,
+            Some(price),
+            None,
+            None,
+            None,
+        )
+    }
+
+    fn statuses(account: &Account) -> Result<Vec<(String, String)>> {
+        Ok(account
+            .paper_events()?
+            .into_iter()
+            .map(|event| (event.new_client_order_id, event.order_status))
+            .collect())
+    }
+
+    #[test]
+    fn dry_run_flips_position() -> Result<()> {
+        let account = dry_run_account();
+        let base = |account: &Account| -> Result<f64> { account.account_info()?.free_asset("BTC") };
+
+        // flat to long 5000 USDT, resting until a candle trades down to the limit
+        let res =
+            account.trade::<LimitOrderResponse>(limit(Side::Long, 0.25, 20000.0, "1-ENTRY"))?;
+        assert_eq!(res.client_order_id, "1-ENTRY");
+        assert_eq!(account.open_orders("BTCUSDT".to_string())?.len(), 1);
+        account.paper_candle(&paper_candle(20050.0, 20100.0))?;
+        assert_eq!(base(&account)?, 0.0);
+        account.paper_candle(&paper_candle(19990.0, 20100.0))?;
+        assert_eq!(base(&account)?, 0.25);
+        assert_eq!(account.price()?, 20100.0);
+        assert_eq!(
+            statuses(&account)?,
+            vec![
+                ("1-ENTRY".to_string(), "NEW".to_string()),
+                ("1-ENTRY".to_string(), "FILLED".to_string())
+            ]
+        );
+
+        // short signal exits the long and enters short once price rises to the limits
+        account.trade::<LimitOrderResponse>(limit(Side::Short, 0.25, 25000.0, "2-EXIT"))?;
+        account.trade::<LimitOrderResponse>(limit(Side::Short, 0.2, 25000.0, "2-ENTRY"))?;
+        account.paper_candle(&paper_candle(24000.0, 25000.0))?;
+        assert_eq!(base(&account)?, -0.2);
+
+        // a resting stop is canceled, and the short covered at market for the last close
+        account.trade::<LimitOrderResponse>(BinanceTrade::new(
+            "BTCUSDT".to_string(),
+            "2-STOP_LOSS".to_string(),
+            Side::Long,
+            OrderType::StopLossLimit,
+            0.2,
+            Some(26010.0),
+            Some(26000.0),
+            None,
+            None,
+        ))?;
+        let canceled = account.cancel_all_open_orders()?;
+        assert_eq!(canceled.len(), 1);
+        assert_eq!(
+            canceled[0].orig_client_order_id.as_deref(),
+            Some("2-STOP_LOSS")
+        );
+        account.paper_candle(&paper_candle(19000.0, 20000.0))?;
+        account.trade::<OrderResponse>(BinanceTrade::new(
+            "BTCUSDT".to_string(),
+            "3-EXIT".to_string(),
+            Side::Long,
+            OrderType::Market,
+            0.2,
+            None,
+            None,
+            None,
+            None,
+        ))?;
+        assert_eq!(base(&account)?, 0.0);
+        assert!(account.open_orders("BTCUSDT".to_string())?.is_empty());
+        assert!(matches!(
+            account.cancel_order(1),
+            Err(BinanceError::Binance(BinanceContentError {
+                code: -2011,
+                ..
+            }))
+        ));
+
+        // bought at 20000, sold at 25000 and covered at 20000
+        let quote = account.account_info()?.free_asset("USDT")?;
+        assert_eq!(quote, 10000.0 + 0.25 * 5000.0 + 0.2 * 5000.0);
+        Ok(())
+    }
+
+    #[test]
+    fn dry_run_oco_fills_one_leg() -> Result<()> {
+        let account = dry_run_account();
+        account.trade::<LimitOrderResponse>(limit(Side::Long, 0.25, 20000.0, "1-ENTRY"))?;
+        account.paper_candle(&paper_candle(19990.0, 20100.0))?;
+        let oco = BinanceTrade::oco(
+            "BTCUSDT".to_string(),
+            Side::Short,
+            0.25,
+            21000.0,
+            19500.0,
+            19490.0,
+        );
+        let res = account.trade_oco(oco)?;
+        assert_eq!(res.orders.len(), 2);
+        // the list has its own ID rather than one of its legs'
+        assert_eq!(res.order_list_id, 1);
+        assert_eq!(res.orders[0].order_id, 2);
+        assert_eq!(res.orders[1].order_id, 3);
+        account.paper_events()?;
+
+        // take profit fills and the stop loss is canceled with it
+        account.paper_candle(&paper_candle(20500.0, 21000.0))?;
+        let events = account.paper_events()?;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].order_status, "FILLED");
+        assert!(events[0].new_client_order_id.ends_with("-TAKE_PROFIT"));
+        assert_eq!(events[1].order_status, "CANCELED");
+        assert!(events[1].new_client_order_id.ends_with("-STOP_LOSS"));
+        assert!(account.open_orders("BTCUSDT".to_string())?.is_empty());
+        assert_eq!(account.account_info()?.free_asset("BTC")?, 0.0);
+        assert_eq!(
+            account.account_info()?.free_asset("USDT")?,
+            10000.0 + 0.25 * 1000.0
+        );
+        Ok(())
+    }
+}
@@ -22,6 +22,7 @@ pub enum Spot {
     BookTicker,
     Order,
     OrderTest,
+    CancelReplace,
     OpenOrders,
     AllOrders,
     Oco,
@@ -59,6 +60,7 @@ impl From<API> for String {
                 Spot::BookTicker => "/api/v3/ticker/bookTicker",
                 Spot::Order => "/api/v3/order",
                 Spot::OrderTest => "/api/v3/order/test",
+                Spot::CancelReplace => "/api/v3/order/cancelReplace",
                 Spot::OpenOrders => "/api/v3/openOrders",
                 Spot::AllOrders => "/api/v3/allOrders",
                 Spot::Oco => "/api/v3/order/oco",
@@ -0,0 +1,48 @@
+use crate::BinanceTrade;
+
+/// What Binance does with the new order if cancelling the old one fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReplaceMode {
+    /// Only place the new order if the old one was cancelled
+    StopOnFailure,
+    /// Place the new order even if the old one could not be cancelled
+    AllowFailure,
+}
+
+impl CancelReplaceMode {
+    pub fn fmt_binance(&self) -> &str {
+        match self {
+            CancelReplaceMode::StopOnFailure => "STOP_ON_FAILURE",
+            CancelReplaceMode::AllowFailure => "ALLOW_FAILURE",
+        }
+    }
+}
+
+/// Cancel an order and place a new one in a single request
+pub struct CancelReplace {
+    /// Order to place
+    pub trade: BinanceTrade,
+    /// Order ID of the order to cancel
+    pub cancel_order_id: u64,
+    pub mode: CancelReplaceMode,
+}
+
+impl CancelReplace {
+    pub fn request(trade: BinanceTrade, cancel_order_id: u64, mode: CancelReplaceMode) -> String {
+        let me = Self {
+            trade,
+            cancel_order_id,
+            mode,
+        };
+        me.create_request()
+    }
+
+    fn create_request(&self) -> String {
+        format!(
+            "{}&cancelReplaceMode={}&cancelOrderId={}",
+            self.trade.request(),
+            self.mode.fmt_binance(),
+            self.cancel_order_id
+        )
+    }
+}
@@ -0,0 +1,41 @@
+use std::collections::BTreeMap;
+
+pub struct Klines {
+    /// Ticker symbol (e.g. BTCUSDT)
+    pub symbol: String,
+    /// Kline interval (e.g. 5m)
+    pub interval: String,
+    /// Number of most recent klines, up to 1000
+    pub limit: Option<u16>,
+}
+
+impl Klines {
+    pub fn request(symbol: String, interval: String, limit: Option<u16>) -> String {
+        let me = Self {
+            symbol,
+            interval,
+            limit,
+        };
+        me.create_request()
+    }
+
+    fn build(&self) -> BTreeMap<String, String> {
+        let mut btree = BTreeMap::<String, String>::new();
+        btree.insert("symbol".to_string(), self.symbol.to_string());
+        btree.insert("interval".to_string(), self.interval.to_string());
+        if let Some(limit) = self.limit {
+            btree.insert("limit".to_string(), limit.to_string());
+        }
+        btree
+    }
+
+    fn create_request(&self) -> String {
+        let btree = self.build();
+        let mut request = String::new();
+        for (key, value) in btree.iter() {
+            request.push_str(&format!("{}={}&", key, value));
+        }
+        request.pop();
+        request
+    }
+}
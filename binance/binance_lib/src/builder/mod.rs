@@ -3,7 +3,9 @@ pub mod all_assets;
 pub mod all_orders;
 pub mod cancel_order;
 pub mod cancel_orders;
+pub mod cancel_replace;
 pub mod exchange_info;
+pub mod klines;
 pub mod price;
 pub mod trade;
 
@@ -12,6 +14,8 @@ pub use all_assets::*;
 pub use all_orders::*;
 pub use cancel_order::*;
 pub use cancel_orders::*;
+pub use cancel_replace::*;
 pub use exchange_info::*;
+pub use klines::*;
 pub use price::*;
 pub use trade::*;
@@ -1,7 +1,7 @@
-use crate::model::{OrderType, Side};
+use crate::model::{OrderType, Side, SymbolFilters};
 use crate::Result;
 use std::time::{SystemTime, UNIX_EPOCH};
-use time_series::precise_round;
+use time_series::{precise_round, precise_round_dir, RoundDir};
 
 #[derive(Debug, Clone)]
 pub struct BinanceTrade {
@@ -25,7 +25,96 @@ pub struct BinanceTrade {
     pub recv_window: u32,
 }
 
+/// Take profit and stop loss linked as one order list, so when either fills the other is cancelled
+#[derive(Debug, Clone)]
+pub struct OcoTrade {
+    /// Ticker symbol (e.g. BTCUSDC)
+    pub symbol: String,
+    /// Side of both legs, which is opposite the position they exit
+    pub side: Side,
+    /// Base asset quantity of each leg
+    pub quantity: f64,
+    /// Limit price of the take profit leg
+    pub price: f64,
+    /// Trigger price of the stop loss leg
+    pub stop_price: f64,
+    /// Limit price the stop loss leg is placed at once triggered
+    pub stop_limit_price: f64,
+    /// Client order ID of the order list, suffixed for each leg
+    pub list_client_order_id: String,
+    /// The number of milliseconds the request is valid for
+    pub recv_window: u32,
+}
+
+impl OcoTrade {
+    pub fn limit_client_order_id(&self) -> String {
+        format!("{}-TAKE_PROFIT", self.list_client_order_id)
+    }
+
+    pub fn stop_client_order_id(&self) -> String {
+        format!("{}-STOP_LOSS", self.list_client_order_id)
+    }
+
+    fn build(&self) -> Vec<(String, String)> {
+        let timestamp = BinanceTrade::get_timestamp().expect("Failed to get timestamp");
+        vec![
+            ("symbol".to_string(), self.symbol.clone()),
+            (
+                "listClientOrderId".to_string(),
+                self.list_client_order_id.clone(),
+            ),
+            ("side".to_string(), self.side.fmt_binance().to_string()),
+            ("quantity".to_string(), self.quantity.to_string()),
+            (
+                "limitClientOrderId".to_string(),
+                self.limit_client_order_id(),
+            ),
+            ("price".to_string(), self.price.to_string()),
+            ("stopClientOrderId".to_string(), self.stop_client_order_id()),
+            ("stopPrice".to_string(), self.stop_price.to_string()),
+            (
+                "stopLimitPrice".to_string(),
+                self.stop_limit_price.to_string(),
+            ),
+            ("stopLimitTimeInForce".to_string(), "GTC".to_string()),
+            ("timestamp".to_string(), timestamp.to_string()),
+            ("recvWindow".to_string(), self.recv_window.to_string()),
+        ]
+    }
+
+    pub fn request(&self) -> String {
+        self.build()
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<String>>()
+            .join("&")
+    }
+}
+
 impl BinanceTrade {
+    /// Take profit limit at `take_profit` and stop loss limit at `stop_limit`, triggered at `stop_price`,
+    /// linked as a one-cancels-other order list on `side`.
+    pub fn oco(
+        ticker: String,
+        side: Side,
+        quantity: f64,
+        take_profit: f64,
+        stop_price: f64,
+        stop_limit: f64,
+    ) -> OcoTrade {
+        let timestamp = Self::get_timestamp().expect("Failed to get timestamp");
+        OcoTrade {
+            symbol: ticker,
+            side,
+            quantity,
+            price: take_profit,
+            stop_price,
+            stop_limit_price: stop_limit,
+            list_client_order_id: format!("{}-OCO", timestamp),
+            recv_window: 10000,
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         symbol: String,
@@ -104,6 +193,30 @@ impl BinanceTrade {
         request
     }
 
+    /// Floor `quantity` to a multiple of the symbol's step size, so it never exceeds the balance it was sized from
+    pub fn round_quantity(quantity: f64, filters: &SymbolFilters) -> f64 {
+        Self::round_to_increment(quantity, filters.step_size, RoundDir::Down)
+    }
+
+    /// Round `price` to the nearest multiple of the symbol's tick size
+    pub fn round_price(price: f64, filters: &SymbolFilters) -> f64 {
+        Self::round_to_increment(price, filters.tick_size, RoundDir::HalfUp)
+    }
+
+    fn round_to_increment(value: f64, increment: f64, dir: RoundDir) -> f64 {
+        if increment <= 0.0 {
+            return value;
+        }
+        // decimal places of the increment, e.g. 5 for a step size of 0.00001
+        let decimals = increment
+            .to_string()
+            .split_once('.')
+            .map(|(_, fraction)| fraction.len() as u32)
+            .unwrap_or(0);
+        let increments = precise_round_dir(value / increment, 0, dir);
+        precise_round(increments * increment, decimals)
+    }
+
     /// 1 BIP = 100 * % price change, 1% = 100 BIPS
     pub fn bips_trailing_stop(trailing_stop_pct: f64) -> f64 {
         trailing_stop_pct * 100.0
@@ -111,8 +224,8 @@ impl BinanceTrade {
 
     pub fn calc_stop_loss(order: Side, price: f64, stop_loss_pct: f64) -> f64 {
         match order {
-            Side::Long => precise_round!(price * (1.0 - (stop_loss_pct / 100.0)), 2),
-            Side::Short => precise_round!(price * (1.0 + (stop_loss_pct / 100.0)), 2),
+            Side::Long => precise_round(price * (1.0 - (stop_loss_pct / 100.0)), 2),
+            Side::Short => precise_round(price * (1.0 + (stop_loss_pct / 100.0)), 2),
         }
     }
 }
@@ -121,10 +234,53 @@ impl BinanceTrade {
 mod tests {
     use super::*;
 
+    #[test]
+    fn oco_payload() {
+        // exit a long with a take profit above and a stop loss below
+        let oco = BinanceTrade::oco(
+            "BTCUSDT".to_string(),
+            Side::Short,
+            0.25,
+            21000.0,
+            19500.0,
+            19450.0,
+        );
+        let request = oco.request();
+        let params = request
+            .split('&')
+            .filter_map(|param| param.split_once('='))
+            .collect::<Vec<(&str, &str)>>();
+        let param = |key: &str| {
+            params
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, value)| *value)
+        };
+        assert_eq!(param("symbol"), Some("BTCUSDT"));
+        assert_eq!(param("side"), Some("SELL"));
+        assert_eq!(param("quantity"), Some("0.25"));
+        assert_eq!(param("price"), Some("21000"));
+        assert_eq!(param("stopPrice"), Some("19500"));
+        assert_eq!(param("stopLimitPrice"), Some("19450"));
+        assert_eq!(param("stopLimitTimeInForce"), Some("GTC"));
+        assert_eq!(param("recvWindow"), Some("10000"));
+        assert!(param("timestamp").is_some());
+        let list_id = param("listClientOrderId").unwrap();
+        assert!(list_id.ends_with("-OCO"));
+        assert_eq!(
+            param("limitClientOrderId"),
+            Some(format!("{}-TAKE_PROFIT", list_id).as_str())
+        );
+        assert_eq!(
+            param("stopClientOrderId"),
+            Some(format!("{}-STOP_LOSS", list_id).as_str())
+        );
+    }
+
     #[test]
     fn test_round_quantity() {
         let qty = 10_000_f64 / 29246.72 * 0.99;
-        let rounded = precise_round!(qty, 5);
+        let rounded = precise_round(qty, 5);
         println!("rounded: {}", rounded);
     }
 }
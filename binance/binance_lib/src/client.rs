@@ -1,6 +1,6 @@
-use crate::api::API;
+use crate::api::{Spot, API};
 use crate::errors::{BinanceError, Result};
-use crate::BinanceContentError;
+use crate::{BinanceContentError, ServerTime};
 use hex::encode as hex_encode;
 use hmac::{Hmac, Mac};
 use log::*;
@@ -8,6 +8,69 @@ use reqwest::blocking::Response;
 use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, USER_AGENT};
 use serde::de::DeserializeOwned;
 use sha2::Sha256;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Re-sync with Binance server time if the last sync is older than this
+pub const TIME_SYNC_INTERVAL_SECS: u64 = 30 * 60;
+/// Response header with the request weight used in the current minute
+pub const USED_WEIGHT_HEADER: &str = "x-mbx-used-weight-1m";
+
+/// Token bucket of request weight that refills at the start of every minute, as Binance counts it.
+/// The weight used is read from the `X-MBX-USED-WEIGHT-1M` header of each response,
+/// and requests wait for the next minute once the used weight is within `headroom` of the limit.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    pub max_weight_per_min: u32,
+    /// Weight kept in reserve for the requests in flight, a tenth of the limit by default
+    pub headroom: u32,
+    used_weight: u32,
+    /// Minute since the UNIX epoch that `used_weight` was reported in
+    minute: u64,
+}
+
+impl RateLimiter {
+    pub fn new(max_weight_per_min: u32) -> Self {
+        Self {
+            max_weight_per_min,
+            headroom: max_weight_per_min / 10,
+            used_weight: 0,
+            minute: 0,
+        }
+    }
+
+    /// Weight used in the minute of `now_ms`
+    pub fn used_weight(&self, now_ms: u64) -> u32 {
+        match now_ms / 60_000 == self.minute {
+            true => self.used_weight,
+            false => 0,
+        }
+    }
+
+    /// Record the used weight header of a response received at `now_ms`
+    pub fn record(&mut self, headers: &HeaderMap, now_ms: u64) {
+        let used_weight = headers
+            .get(USED_WEIGHT_HEADER)
+            .and_then(|weight| weight.to_str().ok())
+            .and_then(|weight| weight.parse::<u32>().ok());
+        if let Some(used_weight) = used_weight {
+            self.used_weight = used_weight;
+            self.minute = now_ms / 60_000;
+        }
+    }
+
+    /// How long a request at `now_ms` must wait for the bucket to refill, zero if it can go now
+    pub fn wait_time(&self, now_ms: u64) -> Duration {
+        let available = self
+            .max_weight_per_min
+            .saturating_sub(self.used_weight(now_ms));
+        match available > self.headroom {
+            true => Duration::ZERO,
+            false => Duration::from_millis(60_000 - now_ms % 60_000),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Client {
@@ -15,6 +78,11 @@ pub struct Client {
     secret_key: String,
     host: String,
     inner_client: reqwest::blocking::Client,
+    /// Binance server time minus local time in milliseconds
+    time_offset: i64,
+    last_time_sync: Option<SystemTime>,
+    /// Request weight limit shared by clones of this client, if set
+    rate_limiter: Option<Arc<Mutex<RateLimiter>>>,
 }
 
 impl Client {
@@ -29,7 +97,124 @@ impl Client {
                 .connect_timeout(std::time::Duration::from_secs(10))
                 .build()
                 .unwrap(),
+            time_offset: 0,
+            last_time_sync: None,
+            rate_limiter: None,
+        }
+    }
+
+    /// Delay requests that would take the request weight used in a minute past `max_weight_per_min`
+    pub fn with_rate_limit(mut self, max_weight_per_min: u32) -> Self {
+        self.rate_limiter = Some(Arc::new(Mutex::new(RateLimiter::new(max_weight_per_min))));
+        self
+    }
+
+    /// Sleep until the rate limiter has weight for another request
+    fn throttle(&self) -> Result<()> {
+        self.throttle_with(std::thread::sleep).map(|_| ())
+    }
+
+    /// Pass the time to wait for the rate limiter to `sleep` and return it.
+    /// Minutes are counted in server time, as Binance counts the weight used in them.
+    fn throttle_with(&self, sleep: impl FnOnce(Duration)) -> Result<Duration> {
+        let wait = match &self.rate_limiter {
+            Some(rate_limiter) => rate_limiter.lock()?.wait_time(self.server_time_ms()),
+            None => Duration::ZERO,
+        };
+        if !wait.is_zero() {
+            warn!("Request weight limit near, waiting {}ms", wait.as_millis());
+            sleep(wait);
+        }
+        Ok(wait)
+    }
+
+    /// Throttle, then sign the request, so its timestamp accounts for the wait and
+    /// isn't already outside the recv window when it is sent
+    fn signed_url(
+        &self,
+        endpoint: API,
+        request: Option<String>,
+        sleep: impl FnOnce(Duration),
+    ) -> Result<String> {
+        let waited = self.throttle_with(sleep)?;
+        Ok(self.sign_request(endpoint, request, waited))
+    }
+
+    /// Query Binance server time and store the offset from local time,
+    /// which is added to the timestamp of every signed request.
+    pub fn sync_time(&mut self) -> Result<i64> {
+        let request_start = Self::local_time_ms();
+        let server_time = self.get::<ServerTime>(API::Spot(Spot::Time), None)?;
+        let request_end = Self::local_time_ms();
+        Ok(self.apply_server_time(&server_time, request_start, request_end))
+    }
+
+    /// Sync server time if it has never been synced or the last sync is older than `TIME_SYNC_INTERVAL_SECS`.
+    pub fn sync_time_if_due(&mut self) -> Result<()> {
+        let due = match self.last_time_sync {
+            None => true,
+            Some(last_sync) => {
+                SystemTime::now()
+                    .duration_since(last_sync)
+                    .unwrap_or(Duration::ZERO)
+                    .as_secs()
+                    > TIME_SYNC_INTERVAL_SECS
+            }
+        };
+        if due {
+            let offset = self.sync_time()?;
+            info!("Binance server time offset: {}ms", offset);
+        }
+        Ok(())
+    }
+
+    pub fn time_offset(&self) -> i64 {
+        self.time_offset
+    }
+
+    /// Compare server time to the midpoint of the request round trip.
+    fn apply_server_time(
+        &mut self,
+        server_time: &ServerTime,
+        request_start: u64,
+        request_end: u64,
+    ) -> i64 {
+        let local_time = (request_start + request_end) / 2;
+        self.time_offset = server_time.server_time as i64 - local_time as i64;
+        self.last_time_sync = Some(SystemTime::now());
+        self.time_offset
+    }
+
+    fn local_time_ms() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System time is before UNIX EPOCH")
+            .as_millis() as u64
+    }
+
+    /// Local time shifted by the server time offset
+    fn server_time_ms(&self) -> u64 {
+        (Self::local_time_ms() as i64 + self.time_offset) as u64
+    }
+
+    /// Shift the `timestamp` parameter of a request by the server time offset,
+    /// and by how long it `waited` since it was built.
+    fn apply_time_offset(&self, request: String, waited: Duration) -> String {
+        let shift = self.time_offset + waited.as_millis() as i64;
+        if shift == 0 {
+            return request;
         }
+        request
+            .split('&')
+            .map(|param| match param.strip_prefix("timestamp=") {
+                Some(timestamp) => match timestamp.parse::<i64>() {
+                    Ok(timestamp) => format!("timestamp={}", timestamp + shift),
+                    Err(_) => param.to_string(),
+                },
+                None => param.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join("&")
     }
 
     pub fn get_signed<T: DeserializeOwned>(
@@ -37,7 +222,7 @@ impl Client {
         endpoint: API,
         request: Option<String>,
     ) -> Result<T> {
-        let url = self.sign_request(endpoint, request);
+        let url = self.signed_url(endpoint, request, std::thread::sleep)?;
         debug!("url: {}", url);
         let client = &self.inner_client;
         let response = client
@@ -48,7 +233,7 @@ impl Client {
     }
 
     pub fn post_signed<T: DeserializeOwned>(&self, endpoint: API, request: String) -> Result<T> {
-        let url = self.sign_request(endpoint, Some(request));
+        let url = self.signed_url(endpoint, Some(request), std::thread::sleep)?;
         info!("url: {}", url);
         let client = &self.inner_client;
         let request = client.post(url.as_str()).headers(self.build_headers(true)?);
@@ -61,7 +246,7 @@ impl Client {
         endpoint: API,
         request: Option<String>,
     ) -> Result<T> {
-        let url = self.sign_request(endpoint, request);
+        let url = self.signed_url(endpoint, request, std::thread::sleep)?;
         debug!("url: {}", url);
         let client = &self.inner_client;
         let response = client
@@ -79,6 +264,7 @@ impl Client {
             }
         }
         debug!("url: {}", url);
+        self.throttle()?;
         let client = &self.inner_client;
         let response = client.get(url.as_str()).send()?;
         self.handler(response)
@@ -88,6 +274,7 @@ impl Client {
     pub fn post<T: DeserializeOwned>(&self, endpoint: API) -> Result<T> {
         let url: String = format!("{}{}", self.host, String::from(endpoint));
         debug!("url: {}", url);
+        self.throttle()?;
         let client = &self.inner_client;
         let response = client
             .post(url.as_str())
@@ -101,6 +288,7 @@ impl Client {
         let url: String = format!("{}{}", self.host, String::from(endpoint));
         debug!("url: {}", url);
         let data: String = format!("listenKey={}", listen_key);
+        self.throttle()?;
         let client = &self.inner_client;
         let response = client
             .put(url.as_str())
@@ -115,6 +303,7 @@ impl Client {
         let url: String = format!("{}{}", self.host, String::from(endpoint));
         debug!("url: {}", url);
         let data: String = format!("listenKey={}", listen_key);
+        self.throttle()?;
         let client = &self.inner_client;
         let response = client
             .delete(url.as_str())
@@ -125,8 +314,9 @@ impl Client {
     }
 
     // Request must be signed
-    fn sign_request(&self, endpoint: API, request: Option<String>) -> String {
+    fn sign_request(&self, endpoint: API, request: Option<String>, waited: Duration) -> String {
         if let Some(request) = request {
+            let request = self.apply_time_offset(request, waited);
             let mut signed_key =
                 Hmac::<Sha256>::new_from_slice(self.secret_key.as_bytes()).unwrap();
             signed_key.update(request.as_bytes());
@@ -155,6 +345,11 @@ impl Client {
     }
 
     fn handler<T: DeserializeOwned>(&self, response: Response) -> Result<T> {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter
+                .lock()?
+                .record(response.headers(), self.server_time_ms());
+        }
         if response.status().is_success() {
             Ok(response.json::<T>()?)
         } else {
@@ -163,3 +358,118 @@ impl Client {
         }
     }
 }
+
+/// Hide all but the last 4 characters of a credential.
+/// Credentials too short to keep any characters hidden are fully redacted.
+pub fn redact(credential: &str) -> String {
+    let chars = credential.chars().collect::<Vec<char>>();
+    if chars.len() <= 4 {
+        return "****".to_string();
+    }
+    let last_4 = chars[chars.len() - 4..].iter().collect::<String>();
+    format!("****{}", last_4)
+}
+
+impl Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("api_key", &redact(&self.api_key))
+            .field("secret_key", &redact(&self.secret_key))
+            .field("host", &self.host)
+            .field("time_offset", &self.time_offset)
+            .field("last_time_sync", &self.last_time_sync)
+            .field("rate_limiter", &self.rate_limiter)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_time_offset() -> Result<()> {
+        let mut client = Client::new(None, None, "https://testnet.binance.vision".to_string());
+        let server_time: ServerTime = serde_json::from_str(r#"{"serverTime":1690000002500}"#)?;
+        let offset = client.apply_server_time(&server_time, 1690000000000, 1690000001000);
+        assert_eq!(offset, 2000);
+        assert_eq!(client.time_offset(), 2000);
+
+        let request = "symbol=BTCUSDT&timestamp=1690000010000&recvWindow=10000".to_string();
+        let url = client.sign_request(API::Spot(Spot::Account), Some(request), Duration::ZERO);
+        assert!(url.contains("symbol=BTCUSDT&timestamp=1690000012000&recvWindow=10000&signature="));
+        Ok(())
+    }
+
+    #[test]
+    fn rate_limit_waits_near_cap() {
+        let mut rate_limiter = RateLimiter::new(1200);
+        let minute_start = 1690000020000;
+        let mut headers = HeaderMap::new();
+        // weight rises as requests are made through the minute
+        for (i, weight) in [20, 500, 1000, 1080].iter().enumerate() {
+            let now = minute_start + i as u64 * 1000;
+            assert_eq!(rate_limiter.wait_time(now), Duration::ZERO);
+            headers.insert(USED_WEIGHT_HEADER, HeaderValue::from(*weight));
+            rate_limiter.record(&headers, now);
+        }
+        // within the 120 headroom of the cap, so wait for the minute to roll over
+        let now = minute_start + 4000;
+        assert_eq!(rate_limiter.used_weight(now), 1080);
+        assert_eq!(rate_limiter.wait_time(now), Duration::from_millis(56_000));
+        // bucket refills the next minute
+        assert_eq!(
+            rate_limiter.wait_time(minute_start + 60_000),
+            Duration::ZERO
+        );
+
+        // responses without the header leave the weight as it was
+        rate_limiter.record(&HeaderMap::new(), now);
+        assert_eq!(rate_limiter.used_weight(now), 1080);
+    }
+
+    #[test]
+    fn signed_after_rate_limit_wait() -> Result<()> {
+        let client = Client::new(None, None, "https://testnet.binance.vision".to_string())
+            .with_rate_limit(1200);
+        let mut headers = HeaderMap::new();
+        headers.insert(USED_WEIGHT_HEADER, HeaderValue::from(1200));
+        if let Some(rate_limiter) = &client.rate_limiter {
+            rate_limiter
+                .lock()?
+                .record(&headers, client.server_time_ms());
+        }
+
+        let mut waited = Duration::ZERO;
+        let request = "symbol=BTCUSDT&timestamp=1690000010000&recvWindow=10000".to_string();
+        let url = client.signed_url(API::Spot(Spot::Account), Some(request), |wait| {
+            waited = wait
+        })?;
+        assert!(!waited.is_zero());
+        assert!(url.contains(&format!(
+            "timestamp={}&recvWindow=10000&signature=",
+            1690000010000 + waited.as_millis()
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn debug_redacts_credentials() {
+        let api_key = "vmPUZE6mv9SD5VNHk4HlWFsOr6aKE2zvsw0MuIgwCIPy6utIco14y7Ju91duEh8A";
+        let secret_key = "NhqPtmdSJYdKjVHjA7PZj4Mge3R5YNiP1e3UZjInClVN65XAbvqqM6A7H5fATj0j";
+        let client = Client::new(
+            Some(api_key.to_string()),
+            Some(secret_key.to_string()),
+            "https://testnet.binance.vision".to_string(),
+        );
+        let debug = format!("{:?}", client);
+        assert!(!debug.contains(api_key));
+        assert!(!debug.contains(secret_key));
+        assert!(debug.contains("****Eh8A"));
+        assert!(debug.contains("****Tj0j"));
+        assert!(debug.contains("https://testnet.binance.vision"));
+
+        assert_eq!(redact("abcd"), "****");
+        assert_eq!(redact(""), "****");
+    }
+}
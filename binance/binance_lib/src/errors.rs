@@ -1,5 +1,5 @@
 use ephemeris::PLPLError;
-use log::error;
+use log::{error, warn};
 use serde::Deserialize;
 use std::env::VarError;
 use std::num::ParseFloatError;
@@ -21,6 +21,8 @@ pub enum BinanceError {
     SideInvalid,
     OrderTypeInvalid,
     WebSocketDisconnected,
+    /// Websocket failed to reconnect this many times in a row
+    ReconnectFailed(u32),
     Reqwest(reqwest::Error),
     InvalidHeader(reqwest::header::InvalidHeaderValue),
     Io(std::io::Error),
@@ -31,12 +33,26 @@ pub enum BinanceError {
     Tungstenite(tungstenite::Error),
     Time(std::time::SystemTimeError),
     OrderStatusParseError(String),
+    KlineIntervalParseError(String),
+    /// Malformed websocket stream subscription
+    InvalidStream(String),
     PLPL(PLPLError),
     Custom(String),
     SystemTime(SystemTimeError),
     EnvMissing(VarError),
     ExitHandlersInitializedEarly,
     ExitHandlersNotBothInitialized,
+    /// Order quantity times price is below the symbol's minimum, so Binance would reject it
+    BelowMinNotional {
+        notional: f64,
+        min_notional: f64,
+    },
+    /// Configured quote asset is not in the account, e.g. a retired asset like BUSD
+    QuoteAssetMissing {
+        asset: String,
+        /// Assets the account holds a balance of
+        held: Vec<String>,
+    },
 }
 
 impl std::fmt::Display for BinanceError {
@@ -66,6 +82,14 @@ impl std::fmt::Display for BinanceError {
                 error!("WebSocket disconnected");
                 write!(f, "WebSocket disconnected")
             }
+            BinanceError::ReconnectFailed(attempts) => {
+                error!("WebSocket failed to reconnect after {} attempts", attempts);
+                write!(
+                    f,
+                    "WebSocket failed to reconnect after {} attempts",
+                    attempts
+                )
+            }
             BinanceError::Reqwest(e) => {
                 error!("Reqwest error: {:?}", e);
                 write!(f, "Reqwest error: {:?}", e)
@@ -106,6 +130,14 @@ impl std::fmt::Display for BinanceError {
                 error!("Order status parse error: {:?}", e);
                 write!(f, "Order status parse error: {:?}", e)
             }
+            BinanceError::KlineIntervalParseError(e) => {
+                error!("Kline interval parse error: {:?}", e);
+                write!(f, "Kline interval parse error: {:?}", e)
+            }
+            BinanceError::InvalidStream(e) => {
+                error!("Invalid stream subscription: {:?}", e);
+                write!(f, "Invalid stream subscription: {:?}", e)
+            }
             BinanceError::PLPL(e) => {
                 error!("PLPL error: {:?}", e);
                 write!(f, "PLPL error: {:?}", e)
@@ -130,6 +162,29 @@ impl std::fmt::Display for BinanceError {
                 error!("Exit handlers not both initialized");
                 write!(f, "Exit handlers not both initialized")
             }
+            BinanceError::BelowMinNotional {
+                notional,
+                min_notional,
+            } => {
+                warn!(
+                    "Order notional {} is below min notional {}",
+                    notional, min_notional
+                );
+                write!(
+                    f,
+                    "Order notional {} is below min notional {}",
+                    notional, min_notional
+                )
+            }
+            BinanceError::QuoteAssetMissing { asset, held } => {
+                let msg = format!(
+                    "Quote asset {} is not in the account, it may be delisted. Assets held: [{}]",
+                    asset,
+                    held.join(", ")
+                );
+                error!("{}", msg);
+                write!(f, "{}", msg)
+            }
         }
     }
 }
@@ -9,6 +9,11 @@ pub enum ExitType {
     Bips(u32),
     /// Tick (smallest unit of price change). For BTCUSD this is $0.01
     Ticks(u32),
+    /// Price distance, so 35.0 is $35
+    Price(f64),
+    /// Multiple of the average true range of the last `period` candles,
+    /// resolved to a `Price` from candle history when an exit is initialized
+    AtrMultiple { period: usize, mult: f64 },
 }
 
 impl ExitType {
@@ -19,22 +24,56 @@ impl ExitType {
         ((ticks as f64 / 100.0) / origin * 10_000.0).ceil() as u32
     }
 
-    pub fn calc_exit(exit_side: Side, method: ExitType, origin: f64) -> f64 {
-        match exit_side {
-            Side::Short => match method {
-                ExitType::Bips(bips) => {
-                    precise_round!(origin - (origin * bips as f64 / 10_000.0), 2)
-                }
-                ExitType::Ticks(ticks) => precise_round!(origin - ticks as f64 / 100.0, 2),
-            },
-            Side::Long => match method {
-                ExitType::Bips(bips) => {
-                    precise_round!(origin + (origin * bips as f64 / 10_000.0), 2)
+    /// Closed candles needed to resolve the exit
+    pub fn history_len(&self) -> usize {
+        match self {
+            ExitType::AtrMultiple { period, .. } => period + 1,
+            _ => 0,
+        }
+    }
+
+    /// Resolve an ATR multiple to a price distance from `candles`, oldest first.
+    /// Other exits are returned as is.
+    pub fn resolve(&self, candles: &[Candle]) -> Result<ExitType> {
+        match self {
+            ExitType::AtrMultiple { period, mult } => match Candle::atr(candles, *period) {
+                Some(atr) => Ok(ExitType::Price(precise_round(atr * mult, 2))),
+                None => {
+                    error!(
+                        "ATR exit needs {} candles, have {}",
+                        period + 1,
+                        candles.len()
+                    );
+                    Err(BinanceError::Custom(format!(
+                        "ATR exit needs {} candles, have {}",
+                        period + 1,
+                        candles.len()
+                    )))
                 }
-                ExitType::Ticks(ticks) => precise_round!(origin + ticks as f64 / 100.0, 2),
             },
+            _ => Ok(self.clone()),
         }
     }
+
+    /// Price distance of the exit from `origin`
+    fn distance(&self, origin: f64) -> Result<f64> {
+        match self {
+            ExitType::Bips(bips) => Ok(origin * *bips as f64 / 10_000.0),
+            ExitType::Ticks(ticks) => Ok(*ticks as f64 / 100.0),
+            ExitType::Price(price) => Ok(*price),
+            ExitType::AtrMultiple { .. } => Err(BinanceError::Custom(
+                "ATR exit must be resolved from candle history".to_string(),
+            )),
+        }
+    }
+
+    pub fn calc_exit(exit_side: Side, method: ExitType, origin: f64) -> Result<f64> {
+        let distance = method.distance(origin)?;
+        Ok(match exit_side {
+            Side::Short => precise_round(origin - distance, 2),
+            Side::Long => precise_round(origin + distance, 2),
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -59,6 +98,8 @@ pub struct TakeProfitState {
     pub exit_trigger: f64,
     /// Calculated exit trailing bips below exit trigger
     pub exit: f64,
+    /// Exit method resolved at entry, which the exit trails by
+    pub method: ExitType,
 }
 
 #[derive(Debug, Clone)]
@@ -75,67 +116,38 @@ impl TakeProfitHandler {
         }
     }
 
-    pub fn init(&mut self, entry: f64, exit_side: Side) -> Result<TakeProfitState> {
-        match exit_side {
+    /// Take profit from `entry`, with `candles` of history, oldest first, to resolve an ATR exit
+    pub fn init(
+        &mut self,
+        entry: f64,
+        exit_side: Side,
+        candles: &[Candle],
+    ) -> Result<TakeProfitState> {
+        let method = self.method.resolve(candles)?;
+        // exit trigger is twice the exit distance away from entry
+        let offset = match &method {
+            ExitType::Bips(bips) => entry * (*bips as f64 * 2.0) / 100.0,
+            // Tick is $0.01 * 100, so 350 pips = $3.50
+            ExitType::Ticks(ticks) => (*ticks as f64 * 2.0) / 100.0,
+            ExitType::Price(price) => price * 2.0,
+            ExitType::AtrMultiple { .. } => method.distance(entry)?,
+        };
+        let exit_trigger = match exit_side {
             // exit is Short, so entry is Long
             // therefore take profit is above entry price
-            Side::Short => match &self.method {
-                ExitType::Bips(bips) => {
-                    // bips away from entry
-                    let exit_trigger =
-                        precise_round!(entry + (entry * (*bips as f64 * 2.0) / 100.0), 2);
-                    let exit =
-                        ExitType::calc_exit(exit_side.clone(), self.method.clone(), exit_trigger);
-                    self.state = Some(TakeProfitState {
-                        entry,
-                        exit_side,
-                        exit_trigger,
-                        exit,
-                    });
-                }
-                ExitType::Ticks(ticks) => {
-                    let exit_trigger = precise_round!(entry + (*ticks as f64 * 2.0) / 100.0, 2);
-                    // Tick is $0.01 * 100, so 350 pips = $3.50
-                    // ticks / entry * 100 = % of price
-                    // bip = 1/100th of a percent, so multiply by 100 again
-                    let exit =
-                        ExitType::calc_exit(exit_side.clone(), self.method.clone(), exit_trigger);
-                    self.state = Some(TakeProfitState {
-                        entry,
-                        exit_side,
-                        exit_trigger,
-                        exit,
-                    });
-                }
-            },
+            Side::Short => precise_round(entry + offset, 2),
             // exit is Long, so entry is Short
             // therefore take profit is below entry
-            Side::Long => match &self.method {
-                ExitType::Bips(bips) => {
-                    let exit_trigger =
-                        precise_round!(entry - (entry * (*bips as f64 * 2.0) / 100.0), 2);
-                    let exit =
-                        ExitType::calc_exit(exit_side.clone(), self.method.clone(), exit_trigger);
-                    self.state = Some(TakeProfitState {
-                        entry,
-                        exit_side,
-                        exit_trigger,
-                        exit,
-                    });
-                }
-                ExitType::Ticks(ticks) => {
-                    let exit_trigger = precise_round!(entry - (*ticks as f64 * 2.0) / 100.0, 2);
-                    let exit =
-                        ExitType::calc_exit(exit_side.clone(), self.method.clone(), exit_trigger);
-                    self.state = Some(TakeProfitState {
-                        entry,
-                        exit_side,
-                        exit_trigger,
-                        exit,
-                    });
-                }
-            },
-        }
+            Side::Long => precise_round(entry - offset, 2),
+        };
+        let exit = ExitType::calc_exit(exit_side.clone(), method.clone(), exit_trigger)?;
+        self.state = Some(TakeProfitState {
+            entry,
+            exit_side,
+            exit_trigger,
+            exit,
+            method,
+        });
         Ok(self.state.clone().unwrap())
     }
 
@@ -154,9 +166,9 @@ impl TakeProfitHandler {
                         let old_exit = state.exit;
                         let new_exit = ExitType::calc_exit(
                             state.exit_side.clone(),
-                            self.method.clone(),
+                            state.method.clone(),
                             candle.high,
-                        );
+                        )?;
                         debug!(
                             "Pre-Update TP exit trigger, Old: {}, New: {}",
                             old_exit_trigger, new_exit_trigger
@@ -187,9 +199,9 @@ impl TakeProfitHandler {
                         let old_exit = state.exit;
                         let new_exit = ExitType::calc_exit(
                             state.exit_side.clone(),
-                            self.method.clone(),
+                            state.method.clone(),
                             candle.low,
-                        );
+                        )?;
                         debug!(
                             "Pre-Update TP exit trigger, Old: {}, New: {}",
                             old_exit_trigger, new_exit_trigger
@@ -236,6 +248,8 @@ pub struct StopLossState {
     pub exit_side: Side,
     pub exit_trigger: f64,
     pub exit: f64,
+    /// Stop loss was moved to entry
+    pub break_even: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -252,37 +266,128 @@ impl StopLossHandler {
         }
     }
 
-    pub fn init(&mut self, entry: f64, exit_side: Side) -> Result<StopLossState> {
-        match exit_side {
+    /// Stop loss from `entry`, with `candles` of history, oldest first, to resolve an ATR exit
+    pub fn init(
+        &mut self,
+        entry: f64,
+        exit_side: Side,
+        candles: &[Candle],
+    ) -> Result<StopLossState> {
+        let method = self.method.resolve(candles)?;
+        let exit = ExitType::calc_exit(exit_side.clone(), method, entry)?;
+        let exit_trigger = match exit_side {
             // exit is Short, so entry is Long
             // therefore stop loss is below entry
+            Side::Short => precise_round(exit + ((exit - entry).abs() / 4.0), 2),
+            // exit is Long, so entry is Short
+            // therefore stop loss is above entry
+            Side::Long => precise_round(exit - ((exit - entry).abs() / 4.0), 2),
+        };
+        self.state = Some(StopLossState {
+            entry,
+            exit_side,
+            exit_trigger,
+            exit,
+            break_even: false,
+        });
+        Ok(self.state.clone().unwrap())
+    }
+
+    /// Move the stop loss to entry once the candle has advanced `threshold` in favor of the position,
+    /// keeping the gap between the exit trigger and exit.
+    /// Returns the new state if the stop loss moved on this candle.
+    pub fn check_break_even(
+        &mut self,
+        threshold: &ExitType,
+        candle: &Candle,
+    ) -> Result<Option<StopLossState>> {
+        let state = match &mut self.state {
+            Some(state) if !state.break_even => state,
+            _ => return Ok(None),
+        };
+        let gap = (state.exit_trigger - state.exit).abs();
+        let reached = match state.exit_side {
+            // exit is Short, so entry is Long
+            // therefore break even is above entry
             Side::Short => {
-                let exit = ExitType::calc_exit(exit_side.clone(), self.method.clone(), entry);
-                let exit_trigger = precise_round!(exit + ((exit - entry).abs() / 4.0), 2);
-                self.state = Some(StopLossState {
-                    entry,
-                    exit_side,
-                    exit_trigger,
-                    exit,
-                });
+                candle.high >= ExitType::calc_exit(Side::Long, threshold.clone(), state.entry)?
             }
             // exit is Long, so entry is Short
-            // therefore stop loss is above entry
+            // therefore break even is below entry
             Side::Long => {
-                let exit = ExitType::calc_exit(exit_side.clone(), self.method.clone(), entry);
-                let exit_trigger = precise_round!(exit - ((exit - entry).abs() / 4.0), 2);
-                self.state = Some(StopLossState {
-                    entry,
-                    exit_side,
-                    exit_trigger,
-                    exit,
-                });
+                candle.low <= ExitType::calc_exit(Side::Short, threshold.clone(), state.entry)?
             }
+        };
+        if !reached {
+            return Ok(None);
         }
-        Ok(self.state.clone().unwrap())
+        state.exit = state.entry;
+        state.exit_trigger = match state.exit_side {
+            Side::Short => precise_round(state.entry + gap, 2),
+            Side::Long => precise_round(state.entry - gap, 2),
+        };
+        state.break_even = true;
+        debug!(
+            "SL moved to break even, Exit Trigger {}, Exit {}",
+            state.exit_trigger, state.exit
+        );
+        Ok(Some(state.clone()))
     }
 
     pub fn reset(&mut self) {
         self.state = None;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time_series::{Day, Month, Time};
+
+    fn candle(high: f64, low: f64, close: f64) -> Candle {
+        Candle {
+            date: Time::new(2023, &Month::from_num(9), &Day::from_num(1), None, None),
+            open: close,
+            high,
+            low,
+            close,
+            volume: None,
+        }
+    }
+
+    #[test]
+    fn atr_multiple_stop_loss() -> Result<()> {
+        let candles = vec![
+            candle(101.0, 99.0, 100.0),
+            // true range is the candle range
+            candle(102.0, 98.0, 101.0),
+            candle(103.0, 100.0, 102.0),
+            // true range is the gap from the previous close to the low
+            candle(99.0, 95.0, 98.0),
+        ];
+        // (4 + 3 + 7) / 3
+        let atr = Candle::atr(&candles, 3).unwrap();
+        assert!((atr - 14.0 / 3.0).abs() < 1e-9);
+        assert!(Candle::atr(&candles, 4).is_none());
+
+        let method = ExitType::AtrMultiple {
+            period: 3,
+            mult: 1.5,
+        };
+        assert_eq!(method.history_len(), 4);
+        let mut stop_loss = StopLossHandler::new(method.clone());
+        // long entry, so the stop loss sells 1.5 ATR below entry
+        let state = stop_loss.init(100.0, Side::Short, &candles)?;
+        assert_eq!(state.exit, 93.0);
+        assert_eq!(state.exit_trigger, 94.75);
+        // short entry, so the stop loss buys 1.5 ATR above entry
+        let state = stop_loss.init(100.0, Side::Long, &candles)?;
+        assert_eq!(state.exit, 107.0);
+
+        // not enough history to resolve the ATR
+        assert!(StopLossHandler::new(method)
+            .init(100.0, Side::Short, &candles[1..])
+            .is_err());
+        Ok(())
+    }
+}
@@ -6,6 +6,7 @@ pub mod config;
 pub mod errors;
 pub mod exit_tracker;
 pub mod model;
+pub mod signal;
 pub mod userstream;
 pub mod websocket;
 
@@ -17,5 +18,6 @@ pub use config::*;
 pub use errors::*;
 pub use exit_tracker::*;
 pub use model::*;
+pub use signal::*;
 pub use userstream::*;
 pub use websocket::*;
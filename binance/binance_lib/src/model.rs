@@ -1,4 +1,5 @@
 use crate::errors::{BinanceError, Result};
+use log::*;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use time_series::precise_round;
@@ -127,6 +128,52 @@ pub enum Filters {
     },
 }
 
+/// Trading rules of a symbol from its exchangeInfo filters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SymbolFilters {
+    /// Order quantities are a multiple of this, from `LOT_SIZE`
+    pub step_size: f64,
+    /// Order prices are a multiple of this, from `PRICE_FILTER`
+    pub tick_size: f64,
+    /// Smallest quantity times price of an order, from `NOTIONAL` or `MIN_NOTIONAL`, zero if neither is set
+    pub min_notional: f64,
+}
+
+impl SymbolFilters {
+    pub fn from_symbol(symbol: &Symbol) -> Result<Self> {
+        let mut step_size = None;
+        let mut tick_size = None;
+        let mut min_notional = None;
+        for filter in symbol.filters.iter() {
+            match filter {
+                Filters::LotSize {
+                    step_size: step, ..
+                } => step_size = Some(step.parse::<f64>()?),
+                Filters::PriceFilter {
+                    tick_size: tick, ..
+                } => tick_size = Some(tick.parse::<f64>()?),
+                Filters::MinNotional {
+                    min_notional: Some(notional),
+                    ..
+                }
+                | Filters::Notional {
+                    min_notional: Some(notional),
+                    ..
+                } => min_notional = Some(notional.parse::<f64>()?),
+                _ => (),
+            }
+        }
+        let missing = |filter: &str| {
+            BinanceError::Custom(format!("{} has no {} filter", symbol.symbol, filter))
+        };
+        Ok(Self {
+            step_size: step_size.ok_or_else(|| missing("LOT_SIZE"))?,
+            tick_size: tick_size.ok_or_else(|| missing("PRICE_FILTER"))?,
+            min_notional: min_notional.unwrap_or(0.0),
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct AccountInformation {
@@ -411,10 +458,10 @@ impl AccountUpdateEvent {
             BinanceError::Custom(format!("Failed to parse locked base balance: {}", e))
         })?;
         Ok(Assets {
-            free_quote: precise_round!(free_quote, 5),
-            locked_quote: precise_round!(locked_quote, 5),
-            free_base: precise_round!(free_base, 5),
-            locked_base: precise_round!(locked_base, 5),
+            free_quote: precise_round(free_quote, 5),
+            locked_quote: precise_round(locked_quote, 5),
+            free_base: precise_round(free_base, 5),
+            locked_base: precise_round(locked_base, 5),
         })
     }
 }
@@ -482,6 +529,9 @@ pub struct OrderTradeEvent {
     #[serde(rename = "L")]
     pub price_last_filled_trade: String,
 
+    #[serde(rename = "Z")]
+    pub cumulative_quote_qty: String,
+
     #[serde(rename = "n")]
     pub commission: String,
 
@@ -829,10 +879,28 @@ impl Side {
         }
     }
 }
+impl From<time_series::Order> for Side {
+    fn from(order: time_series::Order) -> Self {
+        match order {
+            time_series::Order::Long => Side::Long,
+            time_series::Order::Short => Side::Short,
+        }
+    }
+}
+impl From<Side> for time_series::Order {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::Long => time_series::Order::Long,
+            Side::Short => time_series::Order::Short,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub enum OrderType {
     Limit,
+    /// Limit order rejected if it would fill immediately, the take profit leg of an OCO
+    LimitMaker,
     Market,
     StopLossLimit,
     StopLoss,
@@ -843,6 +911,7 @@ impl OrderType {
     pub fn fmt_binance(&self) -> &str {
         match self {
             OrderType::Limit => "LIMIT",
+            OrderType::LimitMaker => "LIMIT_MAKER",
             OrderType::Market => "MARKET",
             OrderType::StopLossLimit => "STOP_LOSS_LIMIT",
             OrderType::StopLoss => "STOP_LOSS",
@@ -856,6 +925,7 @@ impl FromStr for OrderType {
     fn from_str(s: &str) -> Result<Self> {
         match s {
             "LIMIT" => Ok(OrderType::Limit),
+            "LIMIT_MAKER" => Ok(OrderType::LimitMaker),
             "MARKET" => Ok(OrderType::Market),
             "STOP_LOSS_LIMIT" => Ok(OrderType::StopLossLimit),
             "STOP_LOSS" => Ok(OrderType::StopLoss),
@@ -876,6 +946,47 @@ pub struct LimitOrderResponse {
     pub transact_time: u64,
 }
 
+/// Response of a new OCO order list
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OcoOrderResponse {
+    pub order_list_id: i64,
+    pub list_client_order_id: String,
+    pub symbol: String,
+    /// Both legs of the order list
+    pub orders: Vec<OcoOrder>,
+}
+
+impl OcoOrderResponse {
+    /// Order ID of the leg with `client_order_id`
+    pub fn leg_order_id(&self, client_order_id: &str) -> Option<u64> {
+        self.orders
+            .iter()
+            .find(|order| order.client_order_id == client_order_id)
+            .map(|order| order.order_id)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OcoOrder {
+    pub symbol: String,
+    pub order_id: u64,
+    pub client_order_id: String,
+}
+
+/// Response of a cancel and replace, with the new order response as `T`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelReplaceResponse<T> {
+    /// SUCCESS or FAILURE
+    pub cancel_result: String,
+    /// SUCCESS, FAILURE or NOT_ATTEMPTED
+    pub new_order_result: String,
+    pub cancel_response: OrderCanceled,
+    pub new_order_response: Option<T>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct OrderResponse {
@@ -987,6 +1098,34 @@ impl AccountInfoResponse {
             .map_err(|_| BinanceError::Custom(format!("Failed to parse locked asset {}", asset)))
     }
 
+    /// Check the quote asset is tradable by this account before trading its pair.
+    /// A quote asset with zero balance is allowed, since the base asset can be sold for it.
+    pub fn validate_quote_asset(&self, quote_asset: &str) -> Result<()> {
+        match self.balances.iter().find(|x| x.asset == quote_asset) {
+            Some(balance) => {
+                let free = balance.free.parse::<f64>().unwrap_or(0.0);
+                let locked = balance.locked.parse::<f64>().unwrap_or(0.0);
+                if free + locked == 0.0 {
+                    warn!("Quote asset {} has zero balance", quote_asset);
+                }
+                Ok(())
+            }
+            None => Err(BinanceError::QuoteAssetMissing {
+                asset: quote_asset.to_string(),
+                held: self
+                    .balances
+                    .iter()
+                    .filter(|x| {
+                        [&x.free, &x.locked]
+                            .iter()
+                            .any(|amount| amount.parse::<f64>().unwrap_or(0.0) > 0.0)
+                    })
+                    .map(|x| x.asset.clone())
+                    .collect(),
+            }),
+        }
+    }
+
     pub fn account_assets(&self, quote_asset: &str, base_asset: &str) -> Result<Assets> {
         let free_quote = self.free_asset(quote_asset)?;
         let locked_quote = self.locked_asset(quote_asset)?;
@@ -1009,6 +1148,49 @@ pub struct CommissionRates {
     pub seller: String,
 }
 
+/// Kline from the REST klines endpoint, which returns each kline as an array
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "KlineRow")]
+pub struct KlineSummary {
+    pub open_time: i64,
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    pub volume: String,
+    pub close_time: i64,
+}
+
+#[derive(Deserialize)]
+struct KlineRow(
+    i64,
+    String,
+    String,
+    String,
+    String,
+    String,
+    i64,
+    serde::de::IgnoredAny,
+    serde::de::IgnoredAny,
+    serde::de::IgnoredAny,
+    serde::de::IgnoredAny,
+    serde::de::IgnoredAny,
+);
+
+impl From<KlineRow> for KlineSummary {
+    fn from(row: KlineRow) -> Self {
+        Self {
+            open_time: row.0,
+            open: row.1,
+            high: row.2,
+            low: row.3,
+            close: row.4,
+            volume: row.5,
+            close_time: row.6,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PriceResponse {
     pub symbol: String,
@@ -1051,6 +1233,84 @@ impl HistoricalOrder {
     }
 }
 
+/// Kline intervals Binance streams, e.g. `5m` in `btcusdt@kline_5m`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum KlineInterval {
+    Sec1,
+    Min1,
+    Min3,
+    Min5,
+    Min15,
+    Min30,
+    Hour1,
+    Hour2,
+    Hour4,
+    Hour6,
+    Hour8,
+    Hour12,
+    Day1,
+    Day3,
+    Week1,
+    Month1,
+}
+
+impl KlineInterval {
+    pub const ALL: [KlineInterval; 16] = [
+        KlineInterval::Sec1,
+        KlineInterval::Min1,
+        KlineInterval::Min3,
+        KlineInterval::Min5,
+        KlineInterval::Min15,
+        KlineInterval::Min30,
+        KlineInterval::Hour1,
+        KlineInterval::Hour2,
+        KlineInterval::Hour4,
+        KlineInterval::Hour6,
+        KlineInterval::Hour8,
+        KlineInterval::Hour12,
+        KlineInterval::Day1,
+        KlineInterval::Day3,
+        KlineInterval::Week1,
+        KlineInterval::Month1,
+    ];
+
+    pub fn to_str(&self) -> &str {
+        match self {
+            KlineInterval::Sec1 => "1s",
+            KlineInterval::Min1 => "1m",
+            KlineInterval::Min3 => "3m",
+            KlineInterval::Min5 => "5m",
+            KlineInterval::Min15 => "15m",
+            KlineInterval::Min30 => "30m",
+            KlineInterval::Hour1 => "1h",
+            KlineInterval::Hour2 => "2h",
+            KlineInterval::Hour4 => "4h",
+            KlineInterval::Hour6 => "6h",
+            KlineInterval::Hour8 => "8h",
+            KlineInterval::Hour12 => "12h",
+            KlineInterval::Day1 => "1d",
+            KlineInterval::Day3 => "3d",
+            KlineInterval::Week1 => "1w",
+            KlineInterval::Month1 => "1M",
+        }
+    }
+
+    /// Stream name suffix, e.g. `kline_5m`
+    pub fn as_stream_suffix(&self) -> String {
+        format!("kline_{}", self.to_str())
+    }
+}
+
+impl TryFrom<&str> for KlineInterval {
+    type Error = BinanceError;
+    fn try_from(s: &str) -> Result<Self> {
+        KlineInterval::ALL
+            .into_iter()
+            .find(|interval| interval.to_str() == s)
+            .ok_or(BinanceError::KlineIntervalParseError(s.to_string()))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub enum OrderStatus {
     New,
@@ -1092,3 +1352,60 @@ impl FromStr for OrderStatus {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn side_order_conversions() {
+        assert!(matches!(Side::from(time_series::Order::Long), Side::Long));
+        assert!(matches!(Side::from(time_series::Order::Short), Side::Short));
+        assert_eq!(
+            time_series::Order::from(Side::Long),
+            time_series::Order::Long
+        );
+        assert_eq!(
+            time_series::Order::from(Side::Short),
+            time_series::Order::Short
+        );
+    }
+
+    #[test]
+    fn missing_quote_asset() {
+        let account: AccountInfoResponse = serde_json::from_str(
+            r#"{
+                "makerCommission": 0, "takerCommission": 0, "buyerCommission": 0, "sellerCommission": 0,
+                "commissionRates": {"maker": "0", "taker": "0", "buyer": "0", "seller": "0"},
+                "canTrade": true, "canWithdraw": true, "canDeposit": true, "brokered": false,
+                "requireSelfTradePrevention": false, "updateTime": 0, "accountType": "SPOT",
+                "balances": [
+                    {"asset": "BTC", "free": "0.5", "locked": "0.0"},
+                    {"asset": "USDT", "free": "0.0", "locked": "0.0"}
+                ],
+                "permissions": ["SPOT"]
+            }"#,
+        )
+        .unwrap();
+        assert!(account.validate_quote_asset("USDT").is_ok());
+        let err = account.validate_quote_asset("BUSD").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Quote asset BUSD is not in the account, it may be delisted. Assets held: [BTC]"
+        );
+    }
+
+    #[test]
+    fn kline_summary_from_array() {
+        let klines: Vec<KlineSummary> = serde_json::from_str(
+            r#"[[1499040000000, "0.01634790", "0.80000000", "0.01575800", "0.01577100",
+                "148976.11427815", 1499644799999, "2434.19055334", 308, "1756.87402397",
+                "28.46694368", "0"]]"#,
+        )
+        .unwrap();
+        assert_eq!(klines.len(), 1);
+        assert_eq!(klines[0].open_time, 1499040000000);
+        assert_eq!(klines[0].close, "0.01577100");
+        assert_eq!(klines[0].close_time, 1499644799999);
+    }
+}
@@ -0,0 +1,319 @@
+use crate::Result;
+use ephemeris::{PLPLSignal, PLPLSystem, SignalConditions};
+use log::*;
+use time_series::{Candle, Order, Time};
+
+/// How a position is exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExitMode {
+    /// Take profit and stop loss orders once the entry fills, with a trailing take profit
+    #[default]
+    Brackets,
+    /// No exit orders or trailing, the position is held until the opposite signal flips it
+    ExitOnSignalOnly,
+}
+
+/// Account the PLPL signal state machine trades on.
+pub trait SignalAccount {
+    /// Reason a flat account holds off entering on a signal
+    type Hold;
+
+    fn plpl_system(&self) -> &PLPLSystem;
+
+    /// Side of the active order, if any
+    fn position(&self) -> Option<Order>;
+
+    /// Conditions the signal of `candle` is filtered by, apart from the position
+    fn conditions(&self, _candle: &Candle) -> Result<SignalConditions> {
+        Ok(SignalConditions::default())
+    }
+
+    /// Reason to hold off entering on `order` while flat, if any
+    fn hold(&self, order: &Order, date: Time) -> Option<Self::Hold>;
+
+    /// Enter a position on `order` while flat
+    fn enter(&mut self, candle: &Candle, order: Order) -> Result<()>;
+
+    /// Exit the position and enter on `order`, the opposite side
+    fn flip(&mut self, candle: &Candle, order: Order) -> Result<()>;
+}
+
+/// What the state machine did with a PLPL signal.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignalAction<H> {
+    NoTrade,
+    /// Signal ignored while a trade is active
+    IgnoreActiveTrade,
+    /// Signal ignored while flat, for the account's reason
+    Hold(H),
+    Enter(Order),
+    /// Position exited on the opposite signal and entered the other way
+    Flip(Order),
+}
+
+/// Signal of a candle pair and the action taken on it
+#[derive(Debug)]
+pub struct SignalOutcome<H> {
+    pub signal: PLPLSignal,
+    /// Closest PLPL to the candle close the signal was evaluated against
+    pub closest_plpl: f32,
+    pub action: SignalAction<H>,
+    /// Result of placing the orders of an `Enter` or `Flip`
+    pub result: Result<()>,
+}
+
+/// Enter, flip or ignore state machine of the PLPL signal of a candle pair.
+/// A signal enters when flat, unless the account holds off.
+/// With an active order the opposite signal flips the position if exiting on signal only,
+/// otherwise the active trade is left to play out.
+pub fn handle_plpl_signal<A: SignalAccount>(
+    account: &mut A,
+    prev_candle: &Candle,
+    candle: &Candle,
+    exit_mode: ExitMode,
+) -> Result<SignalOutcome<A::Hold>> {
+    let conditions = SignalConditions {
+        position: account.position(),
+        ..account.conditions(candle)?
+    };
+    let plpl_system = account.plpl_system();
+    let closest_plpl = plpl_system.closest_plpl(candle)?;
+    let signal = plpl_system.signal_at(prev_candle, candle, closest_plpl, &conditions);
+    let order = match &signal {
+        PLPLSignal::Enter(order) => order.clone(),
+        PLPLSignal::NoTrade(_) => {
+            return Ok(SignalOutcome {
+                signal,
+                closest_plpl,
+                action: SignalAction::NoTrade,
+                result: Ok(()),
+            })
+        }
+    };
+    let (action, result) = match &conditions.position {
+        Some(position) if position != &order && exit_mode == ExitMode::ExitOnSignalOnly => {
+            let result = account.flip(candle, order.clone());
+            (SignalAction::Flip(order), result)
+        }
+        Some(_) => {
+            debug!(
+                "Ignore {:?} signal @ {}, active trade in progress",
+                order,
+                candle.date.to_string()
+            );
+            (SignalAction::IgnoreActiveTrade, Ok(()))
+        }
+        None => match account.hold(&order, candle.date) {
+            Some(hold) => (SignalAction::Hold(hold), Ok(())),
+            None => {
+                let result = account.enter(candle, order.clone());
+                (SignalAction::Enter(order), result)
+            }
+        },
+    };
+    Ok(SignalOutcome {
+        signal,
+        closest_plpl,
+        action,
+        result,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BinanceError;
+    use ephemeris::{NoTradeReason, Origin, PLPLSystemConfig, Planet};
+    use time_series::{Day, Month};
+
+    fn date() -> Time {
+        Time::new(2023, &Month::from_num(9), &Day::from_num(1), None, None)
+    }
+
+    fn candle(close: f64) -> Candle {
+        Candle {
+            date: date(),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: None,
+        }
+    }
+
+    /// Account with PLPLs every 360 around 20160 that records the orders it was asked to place
+    struct MockAccount {
+        plpl_system: PLPLSystem,
+        position: Option<Order>,
+        hold: bool,
+        reject: bool,
+        orders: Vec<(&'static str, Order, f64)>,
+    }
+
+    impl MockAccount {
+        fn new(position: Option<Order>) -> Result<Self> {
+            let plpl_system = PLPLSystem::from_planet_angles(
+                PLPLSystemConfig {
+                    planet: Planet::Jupiter,
+                    origin: Origin::Heliocentric,
+                    first_date: date(),
+                    last_date: date(),
+                    plpl_scale: 1.0,
+                    plpl_price: 20000.0,
+                    num_plpls: 10,
+                    cross_margin_pct: 10.0,
+                    price_range: None,
+                },
+                vec![(date(), 0.0)],
+            )?;
+            Ok(Self {
+                plpl_system,
+                position,
+                hold: false,
+                reject: false,
+                orders: Vec::new(),
+            })
+        }
+
+        fn place(&mut self, kind: &'static str, candle: &Candle, order: Order) -> Result<()> {
+            if self.reject {
+                return Err(BinanceError::Custom("rejected".to_string()));
+            }
+            self.orders.push((kind, order.clone(), candle.close));
+            self.position = Some(order);
+            Ok(())
+        }
+    }
+
+    impl SignalAccount for MockAccount {
+        type Hold = &'static str;
+
+        fn plpl_system(&self) -> &PLPLSystem {
+            &self.plpl_system
+        }
+
+        fn position(&self) -> Option<Order> {
+            self.position.clone()
+        }
+
+        fn hold(&self, _order: &Order, _date: Time) -> Option<Self::Hold> {
+            self.hold.then_some("halted")
+        }
+
+        fn enter(&mut self, candle: &Candle, order: Order) -> Result<()> {
+            self.place("enter", candle, order)
+        }
+
+        fn flip(&mut self, candle: &Candle, order: Order) -> Result<()> {
+            self.place("flip", candle, order)
+        }
+    }
+
+    /// Candle pair crossing the 20160 PLPL on the signal side, or staying below it
+    fn candles(signal: Option<&Order>) -> (Candle, Candle) {
+        match signal {
+            None => (candle(20100.0), candle(20120.0)),
+            Some(Order::Long) => (candle(20100.0), candle(20200.0)),
+            Some(Order::Short) => (candle(20200.0), candle(20100.0)),
+        }
+    }
+
+    #[test]
+    fn nine_transitions() -> Result<()> {
+        let sides = [None, Some(Order::Long), Some(Order::Short)];
+        for exit_mode in [ExitMode::Brackets, ExitMode::ExitOnSignalOnly] {
+            for position in sides.iter() {
+                for signal in sides.iter() {
+                    let mut account = MockAccount::new(position.clone())?;
+                    let (prev, curr) = candles(signal.as_ref());
+                    let outcome = handle_plpl_signal(&mut account, &prev, &curr, exit_mode)?;
+                    assert!(outcome.result.is_ok());
+                    assert_eq!(outcome.closest_plpl, 20160.0);
+                    let context = format!(
+                        "{:?} {:?} position, {:?} signal",
+                        exit_mode, position, signal
+                    );
+                    match (position, signal) {
+                        (_, None) => {
+                            assert_eq!(
+                                outcome.signal,
+                                PLPLSignal::NoTrade(NoTradeReason::NoCrossover),
+                                "{}",
+                                context
+                            );
+                            assert_eq!(outcome.action, SignalAction::NoTrade, "{}", context);
+                            assert!(account.orders.is_empty(), "{}", context);
+                        }
+                        (None, Some(signal)) => {
+                            assert_eq!(
+                                outcome.action,
+                                SignalAction::Enter(signal.clone()),
+                                "{}",
+                                context
+                            );
+                            assert_eq!(account.orders, vec![("enter", signal.clone(), curr.close)]);
+                        }
+                        (Some(position), Some(signal)) if position == signal => {
+                            assert_eq!(
+                                outcome.signal,
+                                PLPLSignal::NoTrade(NoTradeReason::PositionAligned),
+                                "{}",
+                                context
+                            );
+                            assert_eq!(outcome.action, SignalAction::NoTrade, "{}", context);
+                            assert!(account.orders.is_empty(), "{}", context);
+                        }
+                        (Some(position), Some(signal)) => match exit_mode {
+                            ExitMode::Brackets => {
+                                assert_eq!(
+                                    outcome.action,
+                                    SignalAction::IgnoreActiveTrade,
+                                    "{}",
+                                    context
+                                );
+                                assert!(account.orders.is_empty(), "{}", context);
+                                assert_eq!(account.position.as_ref(), Some(position));
+                            }
+                            ExitMode::ExitOnSignalOnly => {
+                                assert_eq!(
+                                    outcome.action,
+                                    SignalAction::Flip(signal.clone()),
+                                    "{}",
+                                    context
+                                );
+                                assert_eq!(
+                                    account.orders,
+                                    vec![("flip", signal.clone(), curr.close)]
+                                );
+                            }
+                        },
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn flat_account_holds_off() -> Result<()> {
+        let mut account = MockAccount::new(None)?;
+        account.hold = true;
+        let (prev, curr) = candles(Some(&Order::Long));
+        let outcome = handle_plpl_signal(&mut account, &prev, &curr, ExitMode::Brackets)?;
+        assert_eq!(outcome.action, SignalAction::Hold("halted"));
+        assert!(account.orders.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn rejected_entry_is_reported() -> Result<()> {
+        let mut account = MockAccount::new(None)?;
+        account.reject = true;
+        let (prev, curr) = candles(Some(&Order::Short));
+        let outcome = handle_plpl_signal(&mut account, &prev, &curr, ExitMode::Brackets)?;
+        assert_eq!(outcome.action, SignalAction::Enter(Order::Short));
+        assert!(outcome.result.is_err());
+        assert!(account.position.is_none());
+        Ok(())
+    }
+}
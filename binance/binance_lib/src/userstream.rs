@@ -2,7 +2,15 @@ use crate::api::{Spot, API};
 use crate::client::Client;
 use crate::errors::Result;
 use crate::model::{Success, UserDataStream};
+use crate::BinanceError;
 use log::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Binance expires a listen key 60 minutes after its last keep alive
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30 * 60);
 
 #[derive(Clone)]
 pub struct UserStream {
@@ -27,4 +35,157 @@ impl UserStream {
         self.client
             .delete(API::Spot(Spot::UserDataStream), listen_key)
     }
+
+    /// Keep `listen_key` alive every `KEEP_ALIVE_INTERVAL` on a background thread until aborted.
+    /// If Binance rejects a keep alive the key has expired, so a new user data stream is started
+    /// and its key replaces the old one in the returned handle.
+    /// Websockets subscribed to the old key must resubscribe to the new one.
+    pub fn spawn_keep_alive(&self, listen_key: String) -> KeepAliveHandle {
+        let user_stream = self.clone();
+        let key = Arc::new(Mutex::new(listen_key));
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let key = key.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let start = Instant::now();
+                keep_alive_loop(
+                    KEEP_ALIVE_INTERVAL,
+                    &key,
+                    |key| user_stream.keep_alive(key).map(|_| ()),
+                    || user_stream.start().map(|stream| stream.listen_key),
+                    || start.elapsed(),
+                    |timeout| {
+                        std::thread::park_timeout(timeout);
+                        !stop.load(Ordering::Relaxed)
+                    },
+                )
+            })
+        };
+        KeepAliveHandle {
+            listen_key: key,
+            stop,
+            handle,
+        }
+    }
+}
+
+/// Background keep alive of a user data stream started by `UserStream::spawn_keep_alive`
+pub struct KeepAliveHandle {
+    listen_key: Arc<Mutex<String>>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Result<()>>,
+}
+
+impl KeepAliveHandle {
+    /// Listen key currently kept alive, which changes if the original key expired
+    pub fn listen_key(&self) -> Result<String> {
+        Ok(self.listen_key.lock()?.clone())
+    }
+
+    /// Stop pinging and wait for the keep alive thread to exit
+    pub fn abort(self) -> Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.thread().unpark();
+        self.handle
+            .join()
+            .map_err(|_| BinanceError::Custom("Keep alive thread panicked".to_string()))?
+    }
+}
+
+/// Call `keep_alive` on the listen key each time `interval` has passed on the clock `now`,
+/// replacing the key with one from `start` if a keep alive is rejected by Binance.
+/// `wait` blocks for up to the given duration and returns false to stop the loop.
+fn keep_alive_loop<K, S, N, W>(
+    interval: Duration,
+    listen_key: &Mutex<String>,
+    mut keep_alive: K,
+    mut start: S,
+    mut now: N,
+    mut wait: W,
+) -> Result<()>
+where
+    K: FnMut(&str) -> Result<()>,
+    S: FnMut() -> Result<String>,
+    N: FnMut() -> Duration,
+    W: FnMut(Duration) -> bool,
+{
+    let mut last_ping = now();
+    loop {
+        // waits can wake early, so wait out whatever is left of the interval
+        let elapsed = now().saturating_sub(last_ping);
+        if elapsed < interval {
+            if !wait(interval - elapsed) {
+                return Ok(());
+            }
+            continue;
+        }
+        last_ping = now();
+        let key = listen_key.lock()?.clone();
+        match keep_alive(&key) {
+            Ok(_) => info!("Keep alive user stream"),
+            Err(BinanceError::Binance(e)) => {
+                warn!(
+                    "User stream keep alive rejected, starting a new stream: {}",
+                    e.msg
+                );
+                match start() {
+                    Ok(new_key) => *listen_key.lock()? = new_key,
+                    Err(e) => error!("🛑 Failed to start a new user stream: {}", e),
+                }
+            }
+            Err(e) => error!("🛑 Error on user stream keep alive: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BinanceContentError;
+    use std::cell::{Cell, RefCell};
+
+    #[test]
+    fn keep_alive_interval() -> Result<()> {
+        let clock = Cell::new(Duration::ZERO);
+        let pings = RefCell::new(Vec::<(Duration, String)>::new());
+        let listen_key = Mutex::new("expired".to_string());
+        let three_hours = Duration::from_secs(3 * 60 * 60);
+        keep_alive_loop(
+            KEEP_ALIVE_INTERVAL,
+            &listen_key,
+            |key| {
+                pings.borrow_mut().push((clock.get(), key.to_string()));
+                match key {
+                    "expired" => Err(BinanceError::Binance(BinanceContentError {
+                        code: -1125,
+                        msg: "This listenKey does not exist.".to_string(),
+                    })),
+                    _ => Ok(()),
+                }
+            },
+            || Ok("renewed".to_string()),
+            || clock.get(),
+            |timeout| {
+                // wake a minute early to check the remainder is waited out
+                let early = Duration::from_secs(60);
+                let step = match timeout > early {
+                    true => timeout - early,
+                    false => timeout,
+                };
+                clock.set(clock.get() + step);
+                clock.get() < three_hours
+            },
+        )?;
+
+        let pings = pings.into_inner();
+        let times = pings.iter().map(|(time, _)| *time).collect::<Vec<_>>();
+        let expected = (1..6).map(|i| KEEP_ALIVE_INTERVAL * i).collect::<Vec<_>>();
+        assert_eq!(times, expected);
+        // first ping finds the key expired, the rest keep the new key alive
+        assert_eq!(pings[0].1, "expired");
+        assert!(pings[1..].iter().all(|(_, key)| key == "renewed"));
+        assert_eq!(*listen_key.lock()?, "renewed");
+        Ok(())
+    }
 }
@@ -1,13 +1,14 @@
 use crate::config::Config;
 use crate::errors::Result;
 use crate::model::{
-    AccountUpdateEvent, BalanceUpdateEvent, KlineEvent, OrderTradeEvent, TradeEvent,
+    AccountUpdateEvent, BalanceUpdateEvent, KlineEvent, KlineInterval, OrderTradeEvent, TradeEvent,
 };
 use crate::BinanceError;
 use log::*;
 use serde::{Deserialize, Serialize};
 use std::net::TcpStream;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 use tungstenite::handshake::client::Response;
 use tungstenite::protocol::WebSocket;
 use tungstenite::stream::MaybeTlsStream;
@@ -19,6 +20,8 @@ enum WebSocketAPI {
     Default,
     MultiStream,
     Custom(String),
+    /// Multiple streams of a websocket server other than Binance's
+    CustomMultiStream(String),
 }
 
 impl WebSocketAPI {
@@ -33,6 +36,9 @@ impl WebSocketAPI {
                     subscription
                 ),
                 WebSocketAPI::Custom(url) => format!("{}/{}", url, subscription),
+                WebSocketAPI::CustomMultiStream(url) => {
+                    format!("{}/stream?streams={}", url, subscription)
+                }
             },
             false => match self {
                 WebSocketAPI::Default => {
@@ -43,11 +49,96 @@ impl WebSocketAPI {
                     subscription
                 ),
                 WebSocketAPI::Custom(url) => format!("{}/{}", url, subscription),
+                WebSocketAPI::CustomMultiStream(url) => {
+                    format!("{}/stream?streams={}", url, subscription)
+                }
             },
         }
     }
 }
 
+/// Check a stream subscription before connecting, since Binance silently streams nothing for a typo.
+/// Kline streams must be `<symbol>@kline_<interval>` with a supported interval, e.g. `btcusdt@kline_5m`.
+/// Other streams, such as a user data stream listen key, are only checked for being non-empty.
+pub fn valid_stream(stream: &str) -> bool {
+    match stream.split_once('@') {
+        Some((symbol, name)) if name.starts_with("kline") => {
+            !symbol.is_empty()
+                && symbol
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+                && name
+                    .strip_prefix("kline_")
+                    .map(|interval| KlineInterval::try_from(interval).is_ok())
+                    .unwrap_or(false)
+        }
+        Some((symbol, name)) => !symbol.is_empty() && !name.is_empty(),
+        None => !stream.is_empty(),
+    }
+}
+
+fn validate_streams<S: AsRef<str>>(streams: &[S]) -> Result<()> {
+    match streams.iter().find(|stream| !valid_stream(stream.as_ref())) {
+        Some(stream) => Err(BinanceError::InvalidStream(stream.as_ref().to_string())),
+        None => Ok(()),
+    }
+}
+
+/// Exponential backoff between attempts to reconnect a dropped websocket
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Failed reconnects in a row before giving up
+    pub max_attempts: u32,
+    /// Wait before the first reconnect, doubled after each failure
+    pub initial_delay: Duration,
+    /// Longest wait between reconnects
+    pub max_delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Wait before reconnect `attempt`, counting from zero
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.initial_delay
+            .saturating_mul(2_u32.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+
+    /// Call `connect` until it succeeds, passing the backoff delay to `sleep` before each attempt.
+    /// Errors once `max_attempts` attempts have failed.
+    pub fn retry<C, S>(&self, mut connect: C, mut sleep: S) -> Result<()>
+    where
+        C: FnMut() -> Result<()>,
+        S: FnMut(Duration),
+    {
+        for attempt in 1..=self.max_attempts {
+            let delay = self.delay(attempt - 1);
+            info!(
+                "Reconnect attempt {}/{} in {:?}",
+                attempt, self.max_attempts, delay
+            );
+            sleep(delay);
+            match connect() {
+                Ok(_) => return Ok(()),
+                Err(e) => warn!(
+                    "Reconnect attempt {}/{} failed: {:?}",
+                    attempt, self.max_attempts, e
+                ),
+            }
+        }
+        Err(BinanceError::ReconnectFailed(self.max_attempts))
+    }
+}
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum WebSocketEvent {
@@ -62,6 +153,8 @@ pub struct WebSockets<'a> {
     pub socket: Option<(WebSocket<MaybeTlsStream<TcpStream>>, Response)>,
     handler: Box<dyn FnMut(WebSocketEvent) -> Result<()> + 'a>,
     testnet: bool,
+    /// Server multiple streams connect to instead of Binance's, if set
+    endpoint: Option<String>,
 }
 
 impl<'a> Drop for WebSockets<'a> {
@@ -75,6 +168,7 @@ impl<'a> Drop for WebSockets<'a> {
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged)]
+#[allow(clippy::enum_variant_names)]
 enum Events {
     BalanceUpdateEvent(BalanceUpdateEvent),
     AccountUpdateEvent(AccountUpdateEvent),
@@ -92,22 +186,36 @@ impl<'a> WebSockets<'a> {
             socket: None,
             handler: Box::new(handler),
             testnet,
+            endpoint: None,
         }
     }
 
+    /// Connect multiple streams to the websocket server at `ws_endpoint` instead of Binance's
+    pub fn with_endpoint(mut self, ws_endpoint: String) -> Self {
+        self.endpoint = Some(ws_endpoint);
+        self
+    }
+
     #[allow(dead_code)]
     pub fn connect(&mut self, subscription: &str) -> Result<()> {
+        validate_streams(&[subscription])?;
         self.connect_wss(&WebSocketAPI::Default.params(subscription, self.testnet))
     }
 
     pub fn connect_with_config(&mut self, subscription: &str, config: &Config) -> Result<()> {
+        validate_streams(&[subscription])?;
         self.connect_wss(
             &WebSocketAPI::Custom(config.ws_endpoint.clone()).params(subscription, self.testnet),
         )
     }
 
     pub fn connect_multiple_streams(&mut self, endpoints: &[String], testnet: bool) -> Result<()> {
-        self.connect_wss(&WebSocketAPI::MultiStream.params(&endpoints.join("/"), testnet))
+        validate_streams(endpoints)?;
+        let api = match &self.endpoint {
+            Some(url) => WebSocketAPI::CustomMultiStream(url.clone()),
+            None => WebSocketAPI::MultiStream,
+        };
+        self.connect_wss(&api.params(&endpoints.join("/"), testnet))
     }
 
     fn connect_wss(&mut self, wss: &str) -> Result<()> {
@@ -185,4 +293,194 @@ impl<'a> WebSockets<'a> {
         }
         Ok(())
     }
+
+    /// Run the event loop, and whenever the socket drops while `running`,
+    /// reconnect and resubscribe to the current `endpoints` on the backoff of `policy`,
+    /// e.g. with the listen key the keep alive replaced an expired one with.
+    /// Errors once a reconnect has failed `policy.max_attempts` times in a row.
+    pub fn event_loop_with_reconnect(
+        &mut self,
+        running: &AtomicBool,
+        endpoints: impl Fn() -> Result<Vec<String>>,
+        testnet: bool,
+        policy: &ReconnectPolicy,
+    ) -> Result<()> {
+        while running.load(Ordering::Relaxed) {
+            if let Err(e) = self.event_loop(running) {
+                if !running.load(Ordering::Relaxed) {
+                    break;
+                }
+                warn!("Binance websocket dropped: {:?}", e);
+                let endpoints = endpoints()?;
+                policy.retry(
+                    || self.connect_multiple_streams(&endpoints, testnet),
+                    std::thread::sleep,
+                )?;
+                info!("Binance websocket reconnected to {}", endpoints.join(", "));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kline_stream_subscriptions() {
+        assert_eq!(KlineInterval::Min5.as_stream_suffix(), "kline_5m");
+        assert_eq!(KlineInterval::Month1.as_stream_suffix(), "kline_1M");
+        for interval in KlineInterval::ALL {
+            assert_eq!(
+                KlineInterval::try_from(interval.to_str()).unwrap(),
+                interval
+            );
+        }
+        assert!(matches!(
+            KlineInterval::try_from("5min"),
+            Err(BinanceError::KlineIntervalParseError(_))
+        ));
+
+        assert!(valid_stream("btcusdt@kline_5m"));
+        assert!(valid_stream("btcusdt@kline_1h"));
+        // user data stream listen key
+        assert!(valid_stream(
+            "pqia91ma19a5s61cv6a81va65sdf19v8a65a1a5s61cv6a81va65sdf19v8a65a1"
+        ));
+        assert!(!valid_stream("btcusdt@kline_5min"));
+        assert!(!valid_stream("btcusdt@kline"));
+        assert!(!valid_stream("BTCUSDT@kline_5m"));
+        assert!(!valid_stream("@kline_5m"));
+        assert!(!valid_stream(""));
+
+        let streams = [
+            "btcusdt@kline_5m".to_string(),
+            "btcusdt@kline_5min".to_string(),
+        ];
+        match validate_streams(&streams) {
+            Err(BinanceError::InvalidStream(stream)) => assert_eq!(stream, "btcusdt@kline_5min"),
+            _ => panic!("expected invalid stream error"),
+        }
+    }
+
+    const KLINE: &str = r#"{"e":"kline","E":1690000000000,"s":"BTCUSDT","k":{"t":1690000000000,"T":1690000299999,"s":"BTCUSDT","i":"5m","f":1,"L":2,"o":"29000.0","c":"29100.0","h":"29200.0","l":"28900.0","v":"10.0","n":2,"x":true,"q":"291000.0","V":"5.0","Q":"145500.0","B":"0"}}"#;
+
+    #[test]
+    fn event_loop_reconnects_with_current_streams() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("ws://{}", listener.local_addr()?);
+        // each connection gets a kline, and the first is dropped after it
+        let server = std::thread::spawn(move || -> Vec<String> {
+            let mut paths = vec![];
+            for drop_connection in [true, false] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut path = String::new();
+                let mut ws = tungstenite::accept_hdr(
+                    stream,
+                    |req: &tungstenite::handshake::server::Request, res| {
+                        path = req.uri().to_string();
+                        Ok(res)
+                    },
+                )
+                .unwrap();
+                paths.push(path);
+                ws.write_message(Message::Text(KLINE.to_string())).unwrap();
+                if drop_connection {
+                    // hang up without waiting for the client to acknowledge
+                    ws.close(None).unwrap();
+                    continue;
+                }
+                while ws.read_message().is_ok() {}
+            }
+            paths
+        });
+
+        let running = AtomicBool::new(true);
+        let mut klines = 0;
+        let mut ws = WebSockets::new(true, |event: WebSocketEvent| {
+            if let WebSocketEvent::Kline(_) = event {
+                klines += 1;
+                // stop once the reconnected stream delivers
+                if klines == 2 {
+                    running.store(false, Ordering::Relaxed);
+                }
+            }
+            Ok(())
+        })
+        .with_endpoint(url);
+        // the listen key is replaced between connections
+        let keys = std::cell::Cell::new(0);
+        let subs = || -> Result<Vec<String>> {
+            keys.set(keys.get() + 1);
+            Ok(vec![
+                "btcusdt@kline_5m".to_string(),
+                format!("listenkey{}", keys.get()),
+            ])
+        };
+        let policy = ReconnectPolicy {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+        };
+        ws.connect_multiple_streams(&subs()?, true)?;
+        ws.event_loop_with_reconnect(&running, subs, true, &policy)?;
+        drop(ws);
+
+        assert_eq!(
+            server.join().unwrap(),
+            vec![
+                "/stream?streams=btcusdt@kline_5m/listenkey1".to_string(),
+                "/stream?streams=btcusdt@kline_5m/listenkey2".to_string(),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reconnect_backoff() {
+        let policy = ReconnectPolicy::default();
+        assert_eq!(policy.delay(0), Duration::from_secs(1));
+        assert_eq!(policy.delay(3), Duration::from_secs(8));
+        assert_eq!(policy.delay(6), Duration::from_secs(60));
+        assert_eq!(policy.delay(40), Duration::from_secs(60));
+
+        // stream drops twice, then the third reconnect resubscribes
+        let endpoints = vec!["btcusdt@kline_5m".to_string(), "listenkey".to_string()];
+        let mut failures = 2;
+        let mut subscribed = None;
+        let mut sleeps = vec![];
+        policy
+            .retry(
+                || match failures {
+                    0 => {
+                        subscribed = Some(endpoints.clone());
+                        Ok(())
+                    }
+                    _ => {
+                        failures -= 1;
+                        Err(BinanceError::WebSocketDisconnected)
+                    }
+                },
+                |delay| sleeps.push(delay),
+            )
+            .unwrap();
+        assert_eq!(subscribed, Some(endpoints));
+        assert_eq!(sleeps, [1, 2, 4].map(Duration::from_secs).to_vec());
+
+        let policy = ReconnectPolicy {
+            max_attempts: 3,
+            ..Default::default()
+        };
+        let mut attempts = 0;
+        let res = policy.retry(
+            || {
+                attempts += 1;
+                Err(BinanceError::WebSocketDisconnected)
+            },
+            |_| {},
+        );
+        assert!(matches!(res, Err(BinanceError::ReconnectFailed(3))));
+        assert_eq!(attempts, 3);
+    }
 }
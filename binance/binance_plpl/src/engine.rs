@@ -1,27 +1,71 @@
+use crate::trace::*;
 use crate::utils::*;
 use binance_lib::*;
-use ephemeris::PLPLSystem;
+use ephemeris::{NoTradeReason, PLPLSignal, PLPLSystem, SignalConditions};
 use log::*;
 use serde::de::DeserializeOwned;
-use std::time::SystemTime;
-use time_series::{precise_round, Candle};
+use std::time::{Duration, SystemTime};
+use time_series::{precise_round, Candle, Order, Time, Timeframe};
+
+/// Most klines Binance returns for one request
+pub const MAX_KLINES_LIMIT: u16 = 1000;
 
 #[derive(Clone)]
 pub struct Engine {
-    pub client: Client,
+    /// Places orders on Binance, or on a paper book in a dry run
+    pub account: Account,
     pub plpl_system: PLPLSystem,
     pub recv_window: u64,
     pub base_asset: String,
     pub quote_asset: String,
     pub ticker: String,
+    /// Candle interval the PLPL parameters are tuned for
+    pub timeframe: Timeframe,
     pub active_order: ActiveOrder,
+    /// Realized pnl of the day, which can halt new entries
+    pub session_pnl: SessionPnl,
     pub assets: Assets,
-    pub prev_candle: Option<Candle>,
-    pub candle: Option<Candle>,
+    /// Previous and current candle of the PLPL crossover
+    pub candles: CandleWindow,
+    /// Spacing of closed klines, which warns if the stream is not of the timeframe
+    pub kline_spacing: KlineSpacing,
+    /// Per-candle decision trace, disabled unless set
+    pub tracer: DecisionTracer,
+    /// Higher timeframe trend entries must agree with, if set
+    pub trend_filter: Option<TrendFilter>,
+    /// Price used to size entries, refetched once stale
+    pub price_guard: PriceGuard,
+    /// Closed candles indicators are computed from, entries wait for its warmup
+    pub indicators: IndicatorBuffer,
+    /// Exit with take profit and stop loss orders, or only on the opposite signal
+    pub exit_mode: ExitMode,
+    /// Quote notional entries are sized to
+    pub trade_notional: TradeNotional,
+    /// Advance in favor of a position after which its stop loss moves to entry, if set
+    pub break_even: Option<ExitType>,
+    /// Longest a position is held before exiting at market, if set
+    pub max_hold: Option<Duration>,
+    /// Place the take profit and stop loss as one OCO order list, whose exits are fixed
+    /// since its legs can't be replaced one at a time
+    pub oco_exits: bool,
+    /// Distance the close must reach beyond the PLPL for a signal, if set
+    pub signal_hysteresis: Option<f64>,
+    /// Candles after an entry before another entry is allowed
+    pub entry_cooldown: u32,
+    /// Candles processed since the last entry, None before the first
+    pub candles_since_entry: Option<u32>,
+    /// UTC hours within which entries are allowed, as `(start, end)` with `end` exclusive
+    pub trading_hours: Option<(u32, u32)>,
+    /// Least quote value of both free balances to enter with, since an entry spends one
+    /// and its exits the other
+    pub min_entry_funds: Option<f64>,
+    /// Widest bid/ask spread as a percent of price to enter at, fetched each candle if set
+    pub max_spread_pct: Option<f64>,
 }
 
 impl Engine {
     #[allow(dead_code)]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         client: Client,
         plpl_system: PLPLSystem,
@@ -29,39 +73,107 @@ impl Engine {
         base_asset: String,
         quote_asset: String,
         ticker: String,
+        timeframe: Timeframe,
+        gap_policy: CandleGapPolicy,
         trailing_take_profit: ExitType,
         stop_loss: ExitType,
+        daily_profit_target: Option<f64>,
+        daily_loss_limit: Option<f64>,
     ) -> Self {
-        let take_profit_handler = TakeProfitHandler::new(trailing_take_profit.clone());
-        let stop_loss_handler = StopLossHandler::new(stop_loss.clone());
+        let take_profit_handler = TakeProfitHandler::new(trailing_take_profit);
+        let stop_loss_handler = StopLossHandler::new(stop_loss);
         let active_order = ActiveOrder::new(take_profit_handler, stop_loss_handler);
-        let prev_candle: Option<Candle> = None;
-        let candle: Option<Candle> = None;
         Self {
-            client,
+            account: Account::new(
+                client,
+                recv_window,
+                base_asset.clone(),
+                quote_asset.clone(),
+                ticker.clone(),
+            ),
             plpl_system,
             recv_window,
             base_asset,
             quote_asset,
             ticker,
+            timeframe,
             active_order,
+            session_pnl: SessionPnl::new(daily_profit_target, daily_loss_limit),
             assets: Assets::default(),
-            prev_candle,
-            candle,
+            candles: CandleWindow::new(gap_policy),
+            kline_spacing: KlineSpacing::default(),
+            tracer: DecisionTracer::disabled(),
+            trend_filter: None,
+            price_guard: PriceGuard::default(),
+            indicators: IndicatorBuffer::default(),
+            exit_mode: ExitMode::default(),
+            trade_notional: TradeNotional::default(),
+            break_even: None,
+            max_hold: None,
+            oco_exits: false,
+            signal_hysteresis: None,
+            entry_cooldown: 0,
+            candles_since_entry: None,
+            trading_hours: None,
+            min_entry_funds: None,
+            max_spread_pct: None,
         }
     }
 
     #[allow(dead_code)]
     pub fn exchange_info(&self, symbol: String) -> Result<ExchangeInformation> {
-        let req = ExchangeInfo::request(symbol);
-        self.client
-            .get::<ExchangeInformation>(API::Spot(Spot::ExchangeInfo), Some(req))
+        self.account.exchange_info(symbol)
     }
 
-    /// Place a trade
+    /// Place a trade, with its prices rounded to the ticker's tick size.
+    /// Without the symbol filters the trade is placed as is, so exits are never held back.
     pub fn trade<T: DeserializeOwned>(&self, trade: BinanceTrade) -> Result<T> {
-        let req = trade.request();
-        self.client.post_signed::<T>(API::Spot(Spot::Order), req)
+        self.account.trade(self.round_prices(trade))
+    }
+
+    /// Cancel the open order `order_id` and place `trade` in its place in a single request
+    pub fn cancel_replace<T: DeserializeOwned>(
+        &self,
+        order_id: u64,
+        trade: BinanceTrade,
+    ) -> Result<T> {
+        self.account
+            .cancel_replace(order_id, self.round_prices(trade))
+    }
+
+    /// `trade` with its prices rounded to the ticker's tick size, or as is without the symbol filters
+    fn round_prices(&self, mut trade: BinanceTrade) -> BinanceTrade {
+        match self.account.symbol_filters() {
+            Ok(filters) => {
+                trade.price = trade
+                    .price
+                    .map(|price| BinanceTrade::round_price(price, &filters));
+                trade.stop_price = trade
+                    .stop_price
+                    .map(|price| BinanceTrade::round_price(price, &filters));
+            }
+            Err(e) => warn!(
+                "No symbol filters to round {} with: {:?}",
+                trade.client_order_id, e
+            ),
+        }
+        trade
+    }
+
+    /// Floor `qty` to the ticker's step size
+    fn round_quantity(&self, qty: f64) -> Result<f64> {
+        Ok(BinanceTrade::round_quantity(
+            qty,
+            &self.account.symbol_filters()?,
+        ))
+    }
+
+    /// Round `price` to the ticker's tick size
+    fn round_price(&self, price: f64) -> Result<f64> {
+        Ok(BinanceTrade::round_price(
+            price,
+            &self.account.symbol_filters()?,
+        ))
     }
 
     pub fn trade_or_reset<T: DeserializeOwned>(&mut self, trade: BinanceTrade) -> Result<T> {
@@ -82,7 +194,8 @@ impl Engine {
         }
     }
 
-    fn trade_qty(&self, side: Side, candle: &Candle) -> Result<f64> {
+    /// Size an entry to the configured trade notional, converted at `price`
+    fn trade_qty(&self, side: Side, price: f64) -> Result<f64> {
         let assets = self.assets();
         info!(
             "{}, Free: {}, Locked: {}  |  {}, Free: {}, Locked: {}",
@@ -93,28 +206,26 @@ impl Engine {
             assets.free_base,
             assets.locked_base
         );
+        let long_third = assets.free_quote / price * 1.0 / 3.0;
+        let short_third = assets.free_base * 0.33;
+        let qty = match (self.trade_notional, &side) {
+            (TradeNotional::ThirdOfBalance, Side::Long) => long_third,
+            (TradeNotional::ThirdOfBalance, Side::Short) => short_third,
+            // as close to the notional as the free balance allows
+            (TradeNotional::Quote(notional), Side::Long) => notional.min(assets.free_quote) / price,
+            (TradeNotional::Quote(notional), Side::Short) => {
+                (notional / price).min(assets.free_base)
+            }
+            (TradeNotional::Percent(pct), Side::Long) => assets.free_quote * pct / 100.0 / price,
+            (TradeNotional::Percent(pct), Side::Short) => assets.free_base * pct / 100.0,
+        };
         // if long, check short has 2x balance for exit order
         // if short, check long has 2x balance for exit order
-        // if not, error
-        let long_qty = assets.free_quote / candle.close * 1.0 / 3.0;
-        let short_qty = assets.free_base * 0.33;
-
-        Ok(match side {
-            Side::Long => {
-                let qty = match long_qty > short_qty / 2.0 {
-                    true => short_qty / 2.0,
-                    false => long_qty,
-                };
-                precise_round!(qty, 5)
-            }
-            Side::Short => {
-                let qty = match short_qty > long_qty / 2.0 {
-                    true => long_qty / 2.0,
-                    false => short_qty,
-                };
-                precise_round!(qty, 5)
-            }
-        })
+        let cap = match side {
+            Side::Long => short_third / 2.0,
+            Side::Short => long_third / 2.0,
+        };
+        self.round_quantity(qty.min(cap))
     }
 
     fn long_orders(&mut self, candle: &Candle, timestamp: String) -> Result<OrderBuilder> {
@@ -136,8 +247,10 @@ impl Engine {
                 // each order gets 1/3 of 99% of account balance
                 // 99% is to account for fees
                 // 1/3 is to account for 3 orders
-                let long_qty = self.trade_qty(Side::Long, candle)?;
-                let limit = precise_round!(candle.close, 2);
+                // sized and limited at the same price, which the exits are placed around
+                let price = self.fresh_price()?;
+                let long_qty = self.trade_qty(Side::Long, price)?;
+                let limit = self.round_price(price)?;
                 let entry = BinanceTrade::new(
                     self.ticker.to_string(),
                     format!("{}-{}", timestamp, "ENTRY"),
@@ -149,10 +262,11 @@ impl Engine {
                     None,
                     Some(10000),
                 );
-                let tp_state = self
-                    .active_order
-                    .take_profit_handler
-                    .init(candle.close, Side::Short)?;
+                let history = Vec::from(self.indicators.candles.clone());
+                let tp_state =
+                    self.active_order
+                        .take_profit_handler
+                        .init(limit, Side::Short, &history)?;
                 info!(
                     "TP Short: Entry {}, Exit Trigger {}, Exit {}",
                     tp_state.entry, tp_state.exit_trigger, tp_state.exit
@@ -168,10 +282,10 @@ impl Engine {
                     None,
                     Some(10000),
                 );
-                let sl_state = self
-                    .active_order
-                    .stop_loss_handler
-                    .init(candle.close, Side::Short)?;
+                let sl_state =
+                    self.active_order
+                        .stop_loss_handler
+                        .init(limit, Side::Short, &history)?;
                 info!(
                     "SL Short: Entry {}, Exit Trigger {}, Exit {}",
                     sl_state.entry, sl_state.exit_trigger, sl_state.exit
@@ -207,8 +321,16 @@ impl Engine {
                 Err(BinanceError::ExitHandlersInitializedEarly)
             }
             (None, None) => {
-                let short_qty = self.trade_qty(Side::Short, candle)?;
-                let limit = precise_round!(candle.close, 2);
+                info!(
+                    "No active order, enter Short @ {} | {}",
+                    candle.close,
+                    candle.date.to_string()
+                );
+
+                // sized and limited at the same price, which the exits are placed around
+                let price = self.fresh_price()?;
+                let short_qty = self.trade_qty(Side::Short, price)?;
+                let limit = self.round_price(price)?;
                 let entry = BinanceTrade::new(
                     self.ticker.to_string(),
                     format!("{}-{}", timestamp, "ENTRY"),
@@ -220,10 +342,11 @@ impl Engine {
                     None,
                     Some(10000),
                 );
-                let tp_state = self
-                    .active_order
-                    .take_profit_handler
-                    .init(candle.close, Side::Long)?;
+                let history = Vec::from(self.indicators.candles.clone());
+                let tp_state =
+                    self.active_order
+                        .take_profit_handler
+                        .init(limit, Side::Long, &history)?;
                 info!(
                     "TP Long: Entry {}, Exit Trigger {}, Exit {}",
                     tp_state.entry, tp_state.exit_trigger, tp_state.exit
@@ -239,10 +362,10 @@ impl Engine {
                     None,
                     Some(10000),
                 );
-                let sl_state = self
-                    .active_order
-                    .stop_loss_handler
-                    .init(candle.close, Side::Long)?;
+                let sl_state =
+                    self.active_order
+                        .stop_loss_handler
+                        .init(limit, Side::Long, &history)?;
                 info!(
                     "SL Long: Entry {}, Exit Trigger {}, Exit {}",
                     sl_state.entry, sl_state.exit_trigger, sl_state.exit
@@ -268,7 +391,147 @@ impl Engine {
         }
     }
 
+    /// Filled entry of the position currently held
+    fn position(&self) -> Option<&TradeInfo> {
+        match &self.active_order.entry {
+            Some(PendingOrActiveOrder::Active(entry)) if entry.status == OrderStatus::Filled => {
+                Some(entry)
+            }
+            _ => None,
+        }
+    }
+
+    /// Entry the account holds any of, filled or partially filled
+    fn held(&self) -> Option<&TradeInfo> {
+        match &self.active_order.entry {
+            Some(PendingOrActiveOrder::Active(entry)) if entry.executed_qty > 0.0 => Some(entry),
+            _ => None,
+        }
+    }
+
+    /// Entry without take profit or stop loss when exiting only on the opposite signal,
+    /// sized to `qty` if set, e.g. the entry leg of a flip, otherwise to the trade notional,
+    /// and limited at the same price
+    fn signal_only_entry(
+        &mut self,
+        timestamp: String,
+        side: Side,
+        qty: Option<f64>,
+    ) -> Result<BinanceTrade> {
+        let price = self.fresh_price()?;
+        let qty = match qty {
+            Some(qty) => self.round_quantity(qty)?,
+            None => self.trade_qty(side.clone(), price)?,
+        };
+        Ok(BinanceTrade::new(
+            self.ticker.to_string(),
+            format!("{}-{}", timestamp, "ENTRY"),
+            side,
+            OrderType::Limit,
+            qty,
+            Some(self.round_price(price)?),
+            None,
+            None,
+            Some(10000),
+        ))
+    }
+
+    /// Exit of `qty` of what the `held` entry bought or sold, at the close of the opposite signal
+    fn signal_only_exit(
+        &self,
+        candle: &Candle,
+        timestamp: &str,
+        held: &TradeInfo,
+        qty: f64,
+    ) -> Result<BinanceTrade> {
+        Ok(BinanceTrade::new(
+            self.ticker.to_string(),
+            format!("{}-{}", timestamp, "EXIT"),
+            match held.side {
+                Side::Long => Side::Short,
+                Side::Short => Side::Long,
+            },
+            OrderType::Limit,
+            qty,
+            Some(self.round_price(candle.close)?),
+            None,
+            None,
+            Some(10000),
+        ))
+    }
+
+    fn enter_signal_only(&mut self, timestamp: String, side: Side, qty: Option<f64>) -> Result<()> {
+        let entry = self.signal_only_entry(timestamp, side, qty)?;
+        self.active_order.add_entry(entry.clone());
+        self.log_active_order();
+        self.trade_or_reset::<LimitOrderResponse>(entry)?;
+        Ok(())
+    }
+
+    /// Enter on the signal side when flat. Holding a position, exit it and enter on the signal side
+    /// once the exit fills, which is when its pnl is booked. An unfilled entry is canceled,
+    /// and what a partially filled one holds is exited.
+    fn handle_signal_only(&mut self, candle: &Candle, timestamp: String, side: Side) -> Result<()> {
+        if self.active_order.exit.is_some() {
+            debug!(
+                "Ignore {:?} signal @ {}, exit in progress",
+                side,
+                candle.date.to_string()
+            );
+            return Ok(());
+        }
+        if self.active_order.entry.is_some() {
+            self.cancel_all_open_orders()?;
+        }
+        match self.held().cloned() {
+            Some(held) => {
+                info!(
+                    "Opposite signal, exit {:?} @ {} and enter {:?} once filled | {}",
+                    held.side,
+                    candle.close,
+                    side,
+                    candle.date.to_string()
+                );
+                // both legs are sized together, so the exit closes all that is held
+                // and the entry opens the trade notional on the other side
+                let price = self.fresh_price()?;
+                let target_notional = self.trade_qty(side.clone(), price)? * price;
+                let (exit_qty, entry_qty) = Account::flip_quantity(
+                    held.side.clone(),
+                    held.executed_qty,
+                    target_notional,
+                    price,
+                );
+                let exit = self.signal_only_exit(candle, &timestamp, &held, exit_qty)?;
+                self.active_order.exit = Some(PendingOrActiveOrder::Pending(exit.clone()));
+                self.active_order.flip = Some((side, entry_qty));
+                self.trade_or_reset::<LimitOrderResponse>(exit)?;
+            }
+            None => {
+                self.active_order.reset();
+                self.enter_signal_only(timestamp, side, None)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn handle_signal(&mut self, candle: &Candle, timestamp: String, side: Side) -> Result<()> {
+        if self.exit_mode == ExitMode::ExitOnSignalOnly {
+            return self.handle_signal_only(candle, timestamp, side);
+        }
+        let entry = self.stage_orders(candle, timestamp, side)?;
+        self.trade_or_reset::<LimitOrderResponse>(entry)?;
+        Ok(())
+    }
+
+    /// Build the entry, take profit and stop loss for a signal and track them as the active order.
+    /// Returns the entry to submit, the exits are submitted once it fills.
+    fn stage_orders(
+        &mut self,
+        candle: &Candle,
+        timestamp: String,
+        side: Side,
+    ) -> Result<BinanceTrade> {
         let order_builder = match side {
             Side::Long => self.long_orders(candle, timestamp)?,
             Side::Short => self.short_orders(candle, timestamp)?,
@@ -277,25 +540,200 @@ impl Engine {
         self.active_order
             .add_exits(order_builder.take_profit, order_builder.stop_loss);
         self.log_active_order();
-        self.trade_or_reset::<LimitOrderResponse>(order_builder.entry)?;
+        Ok(order_builder.entry)
+    }
+
+    /// Side of the active order, if any
+    fn active_side(&self) -> Option<Order> {
+        self.active_order.entry.as_ref().map(|entry| {
+            let side = match entry {
+                PendingOrActiveOrder::Pending(trade) => &trade.side,
+                PendingOrActiveOrder::Active(trade) => &trade.side,
+            };
+            Order::from(side.clone())
+        })
+    }
+
+    /// Conditions the PLPL signal of `candle` is filtered by, from the active order, trend filter,
+    /// entry limits and balances, and the spread if a maximum is set
+    fn signal_conditions(&self, candle: &Candle) -> Result<SignalConditions> {
+        let cooldown_candles = match self.candles_since_entry {
+            Some(candles) => self.entry_cooldown.saturating_sub(candles),
+            None => 0,
+        };
+        let funds = self.min_entry_funds.map(|min_funds| {
+            let assets = &self.assets;
+            let available = assets.free_quote.min(assets.free_base * candle.close);
+            (available, min_funds)
+        });
+        let spread_pct = self.max_spread_pct.map(|max_spread_pct| {
+            // a failed lookup holds entries off for the candle rather than stopping the engine
+            let spread_pct = self.account.spread_pct().unwrap_or_else(|e| {
+                warn!("Failed to fetch spread, holding off entries: {}", e);
+                f64::INFINITY
+            });
+            (spread_pct, max_spread_pct)
+        });
+        Ok(SignalConditions {
+            position: self.active_side(),
+            hysteresis: self.signal_hysteresis,
+            trend: self
+                .trend_filter
+                .as_ref()
+                .and_then(|filter| filter.trend.clone()),
+            cooldown_candles,
+            trading_hours: self.trading_hours,
+            funds,
+            spread_pct,
+        })
+    }
+
+    /// True if the filled entry was held longer than `max_hold` as of the candle date
+    /// and is not already exiting
+    fn hold_expired(&self, candle: &Candle) -> bool {
+        match (self.max_hold, self.position()) {
+            (Some(max_hold), Some(entry)) if self.active_order.exit.is_none() => {
+                candle.date.to_unix_ms() - entry.event_time as i64 > max_hold.as_millis() as i64
+            }
+            _ => false,
+        }
+    }
+
+    /// Cancel the exits of the position and exit it at market.
+    /// Pnl is booked once the exit fills.
+    fn exit_max_hold(&mut self, candle: &Candle) -> Result<()> {
+        let position = match self.position() {
+            Some(position) => position.clone(),
+            None => return Ok(()),
+        };
+        info!(
+            "Max hold exceeded, exit {:?} @ market | {}",
+            position.side,
+            candle.date.to_string()
+        );
+        self.cancel_all_open_orders()?;
+        let exit = BinanceTrade::new(
+            self.ticker.to_string(),
+            format!(
+                "{}-{}",
+                ActiveOrder::client_order_id_prefix(&position.client_order_id),
+                "EXIT"
+            ),
+            match position.side {
+                Side::Long => Side::Short,
+                Side::Short => Side::Long,
+            },
+            OrderType::Market,
+            position.executed_qty,
+            None,
+            None,
+            None,
+            Some(10000),
+        );
+        self.active_order.exit = Some(PendingOrActiveOrder::Pending(exit.clone()));
+        self.trade_or_reset::<OrderResponse>(exit)?;
         Ok(())
     }
 
     pub fn process_candle(&mut self, prev_candle: &Candle, candle: &Candle) -> Result<()> {
-        let timestamp = candle.date.to_unix_ms().to_string();
-        if self.active_order.entry.is_none() {
-            let plpl = self.plpl_system.closest_plpl(candle)?;
-            if self.plpl_system.long_signal(prev_candle, candle, plpl) {
-                // if position is None, enter Long
-                // else ignore signal and let active trade play out
-                self.handle_signal(candle, timestamp, Side::Long)?;
-            } else if self.plpl_system.short_signal(prev_candle, candle, plpl) {
-                // if position is None, enter Short
-                // else ignore signal and let active trade play out
-                self.handle_signal(candle, timestamp, Side::Short)?;
+        // dry run orders the candle crossed fill before its signal, as they would have live
+        if self.account.dry_run {
+            self.account.paper_candle(candle)?;
+            self.apply_paper_events()?;
+        }
+        if self.hold_expired(candle) {
+            self.exit_max_hold(candle)?;
+        }
+        let exit_mode = self.exit_mode;
+        let outcome = handle_plpl_signal(self, prev_candle, candle, exit_mode)?;
+        let (order, no_trade_reason) = match outcome.signal {
+            PLPLSignal::Enter(order) => (Some(order), None),
+            PLPLSignal::NoTrade(reason) => {
+                debug!(
+                    "No trade @ {}, closest PLPL {}: {}",
+                    candle.date.to_string(),
+                    self.plpl_system.round_plpl(outcome.closest_plpl),
+                    reason
+                );
+                (None, Some(reason))
+            }
+        };
+        let action = match outcome.action {
+            SignalAction::NoTrade => DecisionAction::NoTrade,
+            SignalAction::IgnoreActiveTrade => DecisionAction::IgnoreActiveTrade,
+            SignalAction::Hold(action) => action,
+            SignalAction::Enter(_) | SignalAction::Flip(_) if outcome.result.is_err() => {
+                DecisionAction::EnterFailed
             }
+            SignalAction::Enter(_) => DecisionAction::Enter,
+            SignalAction::Flip(_) => DecisionAction::Flip,
+        };
+        let res = outcome.result;
+        // the entry cooldown counts candles from the one an entry was placed on
+        self.candles_since_entry = match action {
+            DecisionAction::Enter | DecisionAction::Flip => Some(0),
+            _ => self.candles_since_entry.map(|candles| candles + 1),
+        };
+        self.trace_decision(
+            prev_candle,
+            candle,
+            outcome.closest_plpl,
+            order,
+            no_trade_reason,
+            action,
+        )?;
+        match res {
+            // too small for Binance to accept, skip the signal rather than stop trading
+            Err(BinanceError::BelowMinNotional {
+                notional,
+                min_notional,
+            }) => warn!(
+                "Skip entry @ {} with notional {} below min notional {}",
+                candle.date.to_string(),
+                notional,
+                min_notional
+            ),
+            res => res?,
         }
-        Ok(())
+        self.apply_paper_events()
+    }
+
+    /// Write the decision for this candle to the trace file if tracing is enabled
+    fn trace_decision(
+        &self,
+        prev_candle: &Candle,
+        candle: &Candle,
+        closest_plpl: f32,
+        signal: Option<Order>,
+        no_trade_reason: Option<NoTradeReason>,
+        action: DecisionAction,
+    ) -> Result<()> {
+        if !self.tracer.is_enabled() {
+            return Ok(());
+        }
+        let (plpl_below, plpl_above) = self.plpl_system.bracketing_plpls(candle)?;
+        let order_id = match (&action, &self.active_order.entry) {
+            (DecisionAction::Enter, Some(PendingOrActiveOrder::Pending(trade))) => {
+                Some(trade.client_order_id.clone())
+            }
+            (DecisionAction::Enter, Some(PendingOrActiveOrder::Active(trade))) => {
+                Some(trade.client_order_id.clone())
+            }
+            _ => None,
+        };
+        self.tracer.record(&DecisionRecord {
+            date: candle.date,
+            prev_close: prev_candle.close,
+            close: candle.close,
+            planet_angle: self.plpl_system.planet_angle(candle.date),
+            closest_plpl: closest_plpl as f64,
+            plpl_below,
+            plpl_above,
+            signal,
+            no_trade_reason,
+            action,
+            order_id,
+        })
     }
 
     pub fn reset_active_order(&mut self) -> Result<Vec<OrderCanceled>> {
@@ -305,28 +743,12 @@ impl Engine {
 
     /// Get account info which includes token balances
     pub fn account_info(&self) -> Result<AccountInfoResponse> {
-        let builder = AccountInfo::request(None);
-        let req = builder.request;
-        let pre = SystemTime::now();
-        let res = self
-            .client
-            .get_signed::<AccountInfoResponse>(API::Spot(Spot::Account), Some(req));
-        let dur = SystemTime::now().duration_since(pre).unwrap().as_millis();
-        info!("Request time: {:?}ms", dur);
-        if let Err(e) = res {
-            let now = AccountInfo::get_timestamp()?;
-            let req_time = builder
-                .btree
-                .get("timestamp")
-                .unwrap()
-                .parse::<u64>()
-                .unwrap();
-            // difference between now and req_time
-            let diff = now - req_time;
-            error!("🛑 Failed to get account info in {}ms: {:?}", diff, e);
-            return Err(e);
-        }
-        res
+        self.account.account_info()
+    }
+
+    /// Fail before trading if the configured quote asset is not held by the account
+    pub fn validate_quote_asset(&self) -> Result<()> {
+        self.account_info()?.validate_quote_asset(&self.quote_asset)
     }
 
     pub fn update_assets(&mut self) -> Result<()> {
@@ -339,144 +761,211 @@ impl Engine {
     /// Not available on testnet
     #[allow(dead_code)]
     pub fn all_assets(&self) -> Result<Vec<CoinInfo>> {
-        let req = AllAssets::request(Some(5000));
-        self.client
-            .get_signed::<Vec<CoinInfo>>(API::Savings(Sapi::AllCoins), Some(req))
+        self.account.all_assets()
     }
 
     /// Get price of a single symbol
     pub fn price(&self) -> Result<f64> {
-        let req = Price::request(self.ticker.to_string());
-        let res = self
+        self.account.price()
+    }
+
+    /// Handle a kline of the engine's timeframe. Only a final kline is a candle,
+    /// so intrabar updates never advance the candle window, the entry cooldown or the decision trace.
+    pub fn handle_kline(&mut self, kline_event: &KlineEvent) -> Result<()> {
+        if !kline_event.kline.is_final_bar {
+            return Ok(());
+        }
+        let candle = kline_to_candle(kline_event)?;
+        let timeframe = self.timeframe.clone();
+        self.kline_spacing.matches(&kline_event.kline, &timeframe);
+        if self.candles.exceeds_max_move(&candle).is_none() {
+            self.indicators.push(candle.clone());
+        }
+        // compare previous candle to current candle to check crossover of PLPL signal threshold
+        if let Some((prev_candle, candle)) = self.candles.push(candle, &timeframe) {
+            self.process_candle(&prev_candle, &candle)?;
+        }
+        Ok(())
+    }
+
+    /// Fetch the most recent closed klines to fill the indicator warmup buffer.
+    /// One request returns at most [`MAX_KLINES_LIMIT`] klines, so a longer warmup
+    /// is seeded with the latest 999 closed candles and completes on live candles.
+    pub fn warm_start(&mut self) -> Result<()> {
+        if self.indicators.warmup_bars == 0 {
+            return Ok(());
+        }
+        // the last kline is still open
+        let wanted = self.indicators.warmup_bars + 1;
+        if wanted > MAX_KLINES_LIMIT as usize {
+            warn!(
+                "Indicator warmup of {} candles exceeds the {} klines of one request, the rest fills from live candles",
+                self.indicators.warmup_bars, MAX_KLINES_LIMIT
+            );
+        }
+        let limit = wanted.min(MAX_KLINES_LIMIT as usize) as u16;
+        let req = Klines::request(self.ticker.clone(), self.timeframe.to_string(), Some(limit));
+        let mut klines = self
+            .account
             .client
-            .get::<PriceResponse>(API::Spot(Spot::Price), Some(req))?;
-        res.price.parse::<f64>().map_err(BinanceError::ParseFloat)
+            .get::<Vec<KlineSummary>>(API::Spot(Spot::Klines), Some(req))?;
+        klines.pop();
+        let candles = klines
+            .iter()
+            .map(kline_summary_to_candle)
+            .collect::<Result<Vec<Candle>>>()?;
+        info!(
+            "Fetched {} {} klines for warmup",
+            candles.len(),
+            self.timeframe
+        );
+        self.indicators.seed(candles);
+        Ok(())
+    }
+
+    /// Price no older than the price guard's max age, refetched if stale
+    pub fn fresh_price(&mut self) -> Result<f64> {
+        let account = &self.account;
+        self.price_guard
+            .price(SystemTime::now(), || account.price())
     }
 
     /// Get historical orders for a single symbol
     #[allow(dead_code)]
     pub fn all_orders(&self, symbol: String) -> Result<Vec<HistoricalOrder>> {
-        let req = AllOrders::request(symbol, Some(5000));
-        let mut orders = self
-            .client
-            .get_signed::<Vec<HistoricalOrder>>(API::Spot(Spot::AllOrders), Some(req))?;
-        // order by time
-        orders.sort_by(|a, b| a.update_time.partial_cmp(&b.update_time).unwrap());
-        Ok(orders)
+        self.account.all_orders(symbol)
     }
 
     /// Get last open trade for a single symbol
     /// Returns Some if there is an open trade, None otherwise
     #[allow(dead_code)]
     pub fn open_orders(&self, symbol: String) -> Result<Vec<HistoricalOrder>> {
-        let req = AllOrders::request(symbol, Some(5000));
-        let orders = self
-            .client
-            .get_signed::<Vec<HistoricalOrder>>(API::Spot(Spot::AllOrders), Some(req))?;
-        // filter out orders that are not filled or canceled
-        let open_orders = orders
-            .into_iter()
-            .filter(|order| order.status == "NEW")
-            .collect::<Vec<HistoricalOrder>>();
-        Ok(open_orders)
+        self.account.open_orders(symbol)
     }
 
     /// Cancel all open orders for a single symbol
     pub fn cancel_all_open_orders(&self) -> Result<Vec<OrderCanceled>> {
-        info!("Canceling all active orders");
-        let req = CancelOrders::request(self.ticker.clone(), Some(10000));
-        let res = self
-            .client
-            .delete_signed::<Vec<OrderCanceled>>(API::Spot(Spot::OpenOrders), Some(req));
-        if let Err(e) = &res {
-            if let BinanceError::Binance(err) = &e {
-                return if err.code != -2011 {
-                    error!("🛑 Failed to cancel all active orders: {:?}", e);
-                    Err(BinanceError::Binance(err.clone()))
-                } else {
-                    debug!("No open orders to cancel");
-                    Ok(vec![])
-                };
-            }
-        }
-        res
+        self.account.cancel_all_open_orders()
     }
 
     pub fn cancel_order(&self, order_id: u64) -> Result<OrderCanceled> {
-        debug!("Canceling order {}", order_id);
-        let req = CancelOrder::request(order_id, self.ticker.to_string(), Some(10000));
-        let res = self
-            .client
-            .delete_signed::<OrderCanceled>(API::Spot(Spot::Order), Some(req));
-        if let Err(e) = &res {
-            if let BinanceError::Binance(err) = &e {
-                if err.code != -2011 {
-                    error!("🛑 Failed to cancel order: {:?}", e);
-                    return Err(BinanceError::Binance(err.clone()));
-                } else {
-                    debug!("No order to cancel");
-                }
+        self.account.cancel_order(order_id)
+    }
+
+    /// Apply an execution report of the user stream, placing or canceling orders as the active order requires
+    pub fn handle_order_trade(&mut self, event: OrderTradeEvent) -> Result<()> {
+        // update state
+        self.update_active_order(event)?;
+        // create or cancel orders depending on state
+        self.check_active_order()?;
+        // check trailing take profit and update if necessary
+        self.check_trailing_take_profit()?;
+        Ok(())
+    }
+
+    /// Apply the execution reports of dry run orders as the user stream would,
+    /// until handling them places no further orders
+    fn apply_paper_events(&mut self) -> Result<()> {
+        if !self.account.dry_run {
+            return Ok(());
+        }
+        loop {
+            let events = self.account.paper_events()?;
+            if events.is_empty() {
+                return Ok(());
+            }
+            for event in events {
+                self.handle_order_trade(event)?;
             }
+            self.update_assets()?;
         }
-        res
     }
 
     pub fn update_active_order(&mut self, event: OrderTradeEvent) -> Result<()> {
         let id = ActiveOrder::client_order_id_suffix(&event.new_client_order_id);
-        match &*id {
-            "ENTRY" => {
-                self.active_order.entry = Some(PendingOrActiveOrder::Active(
-                    TradeInfo::from_order_trade_event(&event)?,
-                ));
+        let leg = match &*id {
+            "ENTRY" => &mut self.active_order.entry,
+            "TAKE_PROFIT" => &mut self.active_order.take_profit,
+            "STOP_LOSS" => &mut self.active_order.stop_loss,
+            "EXIT" => &mut self.active_order.exit,
+            _ => {
+                debug!("Unknown order id: {}", id);
+                return Ok(());
             }
-            "TAKE_PROFIT" => {
-                self.active_order.take_profit = Some(PendingOrActiveOrder::Active(
-                    TradeInfo::from_order_trade_event(&event)?,
-                ));
-            }
-            "STOP_LOSS" => {
-                self.active_order.stop_loss = Some(PendingOrActiveOrder::Active(
-                    TradeInfo::from_order_trade_event(&event)?,
-                ));
-            }
-            _ => debug!("Unknown order id: {}", id),
+        };
+        // an order the engine no longer tracks, e.g. the other exit canceled once one fills, is not tracked again
+        if leg.as_ref().map(|leg| leg.client_order_id()) != Some(event.new_client_order_id.as_str())
+        {
+            debug!("Ignore untracked order: {}", event.new_client_order_id);
+            return Ok(());
         }
+        *leg = Some(PendingOrActiveOrder::Active(
+            TradeInfo::from_order_trade_event(&event)?,
+        ));
         self.log_active_order();
         Ok(())
     }
 
     fn take_profit_pnl(&self, entry: &TradeInfo, take_profit: &TradeInfo) -> Result<f64> {
-        let pnl = precise_round!(
+        let pnl = precise_round(
             match entry.side {
-                Side::Long => {
-                    (take_profit.price - entry.price) / entry.price * 100_f64
-                }
-                Side::Short => {
-                    (entry.price - take_profit.price) / entry.price * 100_f64
-                }
+                Side::Long => (take_profit.price - entry.price) / entry.price * 100_f64,
+                Side::Short => (entry.price - take_profit.price) / entry.price * 100_f64,
             },
-            5
+            5,
         );
         Ok(pnl)
     }
 
     fn stop_loss_pnl(&self, entry: &TradeInfo, stop_loss: &TradeInfo) -> Result<f64> {
-        let pnl = precise_round!(
+        let pnl = precise_round(
             match entry.side {
-                Side::Long => {
-                    (stop_loss.price - entry.price) / entry.price * 100_f64
-                }
-                Side::Short => {
-                    (entry.price - stop_loss.price) / entry.price * 100_f64
-                }
+                Side::Long => (stop_loss.price - entry.price) / entry.price * 100_f64,
+                Side::Short => (entry.price - stop_loss.price) / entry.price * 100_f64,
             },
-            5
+            5,
         );
         Ok(pnl)
     }
 
+    fn exit_pnl(&self, entry: &TradeInfo, exit: &TradeInfo) -> f64 {
+        precise_round(
+            match entry.side {
+                Side::Long => (exit.price - entry.price) / entry.price * 100_f64,
+                Side::Short => (entry.price - exit.price) / entry.price * 100_f64,
+            },
+            5,
+        )
+    }
+
     pub fn check_active_order(&mut self) -> Result<()> {
         let copy = self.active_order.clone();
+        if let (
+            Some(PendingOrActiveOrder::Active(entry)),
+            Some(PendingOrActiveOrder::Active(exit)),
+        ) = (&copy.entry, &copy.exit)
+        {
+            if exit.status == OrderStatus::Filled {
+                info!("Exit filled @ {}", exit.price);
+                let pnl = self.exit_pnl(entry, exit);
+                info!("📈 PNL: {}%", pnl);
+                let date = Time::from_unix_msec(exit.event_time as i64);
+                self.session_pnl.record(date, pnl);
+                let flip = self.active_order.flip.take();
+                self.active_order.reset();
+                if let Some((side, qty)) = flip {
+                    match self.session_pnl.halt(date) {
+                        Some(halt) => info!("Skip {:?} entry of flip, {}", side, halt),
+                        None => self.enter_signal_only(
+                            ActiveOrder::client_order_id_prefix(&exit.client_order_id),
+                            side,
+                            Some(qty),
+                        )?,
+                    }
+                }
+            }
+            return Ok(());
+        }
         if let (Some(entry), Some(take_profit), Some(stop_loss)) =
             (&copy.entry, &copy.take_profit, &copy.stop_loss)
         {
@@ -488,8 +977,17 @@ impl Engine {
                 ) => {
                     // do nothing, order is active
                     if entry.status == OrderStatus::Filled {
-                        self.trade_or_reset::<LimitOrderResponse>(tp.clone())?;
-                        self.trade_or_reset::<LimitOrderResponse>(sl.clone())?;
+                        // exits close what was actually filled
+                        let mut tp = tp.clone();
+                        let mut sl = sl.clone();
+                        tp.quantity = entry.executed_qty;
+                        sl.quantity = entry.executed_qty;
+                        if self.oco_exits {
+                            self.trade_oco_exits(&tp, &sl)?;
+                        } else {
+                            self.trade_or_reset::<LimitOrderResponse>(tp)?;
+                            self.trade_or_reset::<LimitOrderResponse>(sl)?;
+                        }
                     }
                 }
                 (
@@ -500,15 +998,19 @@ impl Engine {
                     if tp.status == OrderStatus::Filled && sl.status != OrderStatus::Filled {
                         self.cancel_all_open_orders()?;
                         info!("✅ Take profit filled, canceled stop loss");
-                        let pnl = self.take_profit_pnl(&entry, &tp)?;
+                        let pnl = self.take_profit_pnl(entry, tp)?;
                         info!("📈 PNL: {}%", pnl);
+                        self.session_pnl
+                            .record(Time::from_unix_msec(tp.event_time as i64), pnl);
                         self.active_order.reset();
                     }
                     if sl.status == OrderStatus::Filled && tp.status != OrderStatus::Filled {
                         self.cancel_all_open_orders()?;
                         info!("❌ Stop loss filled, canceled take profit");
-                        let pnl = self.stop_loss_pnl(&entry, &sl)?;
+                        let pnl = self.stop_loss_pnl(entry, sl)?;
                         info!("📈 PNL: {}%", pnl);
+                        self.session_pnl
+                            .record(Time::from_unix_msec(sl.event_time as i64), pnl);
                         self.active_order.reset();
                     }
                     if sl.status == OrderStatus::Filled && tp.status == OrderStatus::Filled {
@@ -616,16 +1118,16 @@ impl Engine {
         let base_balance = assets.free_base;
 
         let sum = quote_balance + base_balance;
-        let equal = precise_round!(sum / 2_f64, 5);
-        let quote_diff = precise_round!(quote_balance - equal, 5);
-        let base_diff = precise_round!(base_balance - equal, 5);
+        let equal = precise_round(sum / 2_f64, 5);
+        let quote_diff = precise_round(quote_balance - equal, 5);
+        let base_diff = precise_round(base_balance - equal, 5);
         let min_notional = 0.001;
 
         // buy BTC
         if quote_diff > 0_f64 && quote_diff > min_notional {
             let timestamp = BinanceTrade::get_timestamp()?;
             let client_order_id = format!("{}-{}", timestamp, "EQUALIZE_QUOTE");
-            let long_qty = precise_round!(quote_diff, 5);
+            let long_qty = self.round_quantity(quote_diff)?;
             info!(
                 "Quote asset too high = {} {}, 50/50 = {} {}, buy base asset = {} {}",
                 quote_balance * price,
@@ -656,7 +1158,7 @@ impl Engine {
         if base_diff > 0_f64 && base_diff > min_notional {
             let timestamp = BinanceTrade::get_timestamp()?;
             let client_order_id = format!("{}-{}", timestamp, "EQUALIZE_BASE");
-            let short_qty = precise_round!(base_diff, 5);
+            let short_qty = self.round_quantity(base_diff)?;
             info!(
                 "Base asset too high = {} {}, 50/50 = {} {}, sell base asset = {} {}",
                 base_balance, self.base_asset, equal, self.base_asset, short_qty, self.base_asset
@@ -698,9 +1200,97 @@ impl Engine {
         );
     }
 
+    /// Move the stop loss to entry once price has advanced `break_even` in favor of the position
+    fn check_break_even(&mut self) -> Result<()> {
+        let (break_even, candle) = match (&self.break_even, self.candles.cache.curr()) {
+            (Some(break_even), Some(candle)) => (break_even.clone(), candle.clone()),
+            _ => return Ok(()),
+        };
+        let history = Vec::from(self.indicators.candles.clone());
+        let threshold = break_even.resolve(&history)?;
+        let sl_state = match self
+            .active_order
+            .stop_loss_handler
+            .check_break_even(&threshold, &candle)?
+        {
+            Some(sl_state) => sl_state,
+            None => return Ok(()),
+        };
+        info!(
+            "Break even @ {}, move stop loss to entry {}",
+            candle.close, sl_state.entry
+        );
+        match self.active_order.stop_loss.clone() {
+            None => error!("No stop loss order to move to break even"),
+            // placed at break even once the entry fills
+            Some(PendingOrActiveOrder::Pending(mut sl)) => {
+                sl.price = Some(sl_state.exit);
+                sl.stop_price = Some(sl_state.exit_trigger);
+                self.active_order.stop_loss = Some(PendingOrActiveOrder::Pending(sl));
+            }
+            // replaced in one request for what the entry filled, so the position is never unprotected
+            Some(PendingOrActiveOrder::Active(sl)) => {
+                let quantity = self
+                    .held()
+                    .map(|entry| entry.executed_qty)
+                    .unwrap_or(sl.quantity);
+                let trade = BinanceTrade::new(
+                    self.ticker.to_string(),
+                    sl.client_order_id.clone(),
+                    sl_state.exit_side,
+                    OrderType::StopLossLimit,
+                    quantity,
+                    Some(sl_state.exit),
+                    Some(sl_state.exit_trigger),
+                    None,
+                    Some(10000),
+                );
+                // the position is still tracked with the stop loss the exchange reports
+                if let Err(e) = self
+                    .cancel_replace::<CancelReplaceResponse<LimitOrderResponse>>(sl.order_id, trade)
+                {
+                    error!("🛑 Error moving stop loss to break even: {:?}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Place `tp` and `sl` as one order list, so the exchange cancels one when the other fills.
+    /// The legs keep the client order IDs the active order tracks them by.
+    fn trade_oco_exits(&mut self, tp: &BinanceTrade, sl: &BinanceTrade) -> Result<()> {
+        let missing = |price: &str| BinanceError::Custom(format!("OCO exit has no {}", price));
+        let mut oco = BinanceTrade::oco(
+            self.ticker.to_string(),
+            tp.side.clone(),
+            tp.quantity,
+            self.round_price(tp.price.ok_or_else(|| missing("take profit price"))?)?,
+            self.round_price(sl.stop_price.ok_or_else(|| missing("stop price"))?)?,
+            self.round_price(sl.price.ok_or_else(|| missing("stop limit price"))?)?,
+        );
+        oco.list_client_order_id = ActiveOrder::client_order_id_prefix(&tp.client_order_id);
+        match self.account.trade_oco(oco) {
+            Ok(res) => {
+                self.active_order.oco_list_id = Some(res.order_list_id);
+                Ok(())
+            }
+            Err(e) => {
+                error!("🛑 Error placing OCO exits: {:?}", e);
+                self.reset_active_order()?;
+                Err(e)
+            }
+        }
+    }
+
     pub fn check_trailing_take_profit(&mut self) -> Result<ActiveOrder> {
+        if self.active_order.oco_list_id.is_some() {
+            return Ok(self.active_order.clone());
+        }
+        self.check_break_even()?;
         let copy = self.active_order.clone();
-        if let (Some(tp_state), Some(candle)) = (&copy.take_profit_handler.state, &self.candle) {
+        if let (Some(tp_state), Some(candle)) =
+            (&copy.take_profit_handler.state, self.candles.cache.curr())
+        {
             let update_action_info = &self
                 .active_order
                 .take_profit_handler
@@ -768,3 +1358,911 @@ impl Engine {
         }
     }
 }
+
+impl SignalAccount for Engine {
+    type Hold = DecisionAction;
+
+    fn plpl_system(&self) -> &PLPLSystem {
+        &self.plpl_system
+    }
+
+    fn position(&self) -> Option<Order> {
+        self.active_side()
+    }
+
+    fn conditions(&self, candle: &Candle) -> Result<SignalConditions> {
+        self.signal_conditions(candle)
+    }
+
+    /// Entries wait for indicator warmup and are halted once a daily limit is hit
+    fn hold(&self, order: &Order, date: Time) -> Option<DecisionAction> {
+        if !self.indicators.is_warm() {
+            debug!(
+                "Ignore {:?} signal @ {}, indicator warmup {}/{}",
+                order,
+                date.to_string(),
+                self.indicators.candles.len(),
+                self.indicators.warmup_bars
+            );
+            return Some(DecisionAction::Warmup {
+                have: self.indicators.candles.len(),
+                need: self.indicators.warmup_bars,
+            });
+        }
+        self.session_pnl.halt(date).map(|halt| {
+            debug!("Ignore {:?} signal @ {}, {}", order, date.to_string(), halt);
+            DecisionAction::Halt(halt)
+        })
+    }
+
+    fn enter(&mut self, candle: &Candle, order: Order) -> Result<()> {
+        let timestamp = candle.date.to_unix_ms().to_string();
+        self.handle_signal(candle, timestamp, order.into())
+    }
+
+    fn flip(&mut self, candle: &Candle, order: Order) -> Result<()> {
+        let timestamp = candle.date.to_unix_ms().to_string();
+        self.handle_signal_only(candle, timestamp, order.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ephemeris::{Origin, PLPLSystemConfig, Planet};
+    use proptest::prelude::*;
+    use std::path::PathBuf;
+    use time_series::{Day, Direction, Month};
+
+    fn date() -> Time {
+        Time::new(2023, &Month::from_num(9), &Day::from_num(1), None, None)
+    }
+
+    fn candle(close: f64) -> Candle {
+        Candle {
+            date: date(),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: None,
+        }
+    }
+
+    /// Engine with PLPLs every 360 around 20160 that traces decisions to `trace_file`
+    fn engine(trace_file: &str) -> Result<Engine> {
+        let plpl_system = PLPLSystem::from_planet_angles(
+            PLPLSystemConfig {
+                planet: Planet::Jupiter,
+                origin: Origin::Heliocentric,
+                first_date: date(),
+                last_date: date(),
+                plpl_scale: 1.0,
+                plpl_price: 20000.0,
+                num_plpls: 10,
+                cross_margin_pct: 10.0,
+                price_range: None,
+            },
+            vec![(date(), 0.0)],
+        )?;
+        let mut engine = Engine::new(
+            Client::new(None, None, "https://testnet.binance.vision".to_string()),
+            plpl_system,
+            10000,
+            "BTC".to_string(),
+            "USDT".to_string(),
+            "BTCUSDT".to_string(),
+            Timeframe::Min5,
+            CandleGapPolicy::SkipSignal,
+            ExitType::Ticks(350),
+            ExitType::Bips(5),
+            None,
+            None,
+        );
+        engine.account = engine.account.clone().with_symbol_filters(SymbolFilters {
+            step_size: 0.00001,
+            tick_size: 0.01,
+            min_notional: 5.0,
+        });
+        engine.tracer = DecisionTracer::new(&trace_path(trace_file))?;
+        Ok(engine)
+    }
+
+    /// Temp path of `trace_file` for this process, so concurrent test runs do not write the same file
+    fn trace_path(trace_file: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}_{}", std::process::id(), trace_file))
+    }
+
+    /// Kline event of the 5 minute candle closing `mins` after the start of `date()`
+    fn kline_event(mins: i64, close: f64, is_final_bar: bool) -> KlineEvent {
+        let close_time = date().to_unix_ms() + mins * 60_000;
+        let close = close.to_string();
+        KlineEvent {
+            event_type: "kline".to_string(),
+            event_time: close_time as u64,
+            symbol: "BTCUSDT".to_string(),
+            kline: Kline {
+                open_time: close_time - 5 * 60_000 + 1,
+                close_time,
+                symbol: "BTCUSDT".to_string(),
+                interval: "5m".to_string(),
+                first_trade_id: 0,
+                last_trade_id: 0,
+                open: close.clone(),
+                close: close.clone(),
+                high: close.clone(),
+                low: close,
+                volume: "0.0".to_string(),
+                number_of_trades: 0,
+                is_final_bar,
+                quote_asset_volume: "0.0".to_string(),
+                taker_buy_base_asset_volume: "0.0".to_string(),
+                taker_buy_quote_asset_volume: "0.0".to_string(),
+                ignore_me: String::new(),
+            },
+        }
+    }
+
+    fn trace_rows(trace_file: &str) -> Result<Vec<String>> {
+        let trace = std::fs::read_to_string(trace_path(trace_file))?;
+        Ok(trace.lines().map(String::from).collect())
+    }
+
+    #[test]
+    fn decision_trace_record() -> Result<()> {
+        let trace_file = "decision_trace_test.csv";
+        let mut engine = engine(trace_file)?;
+        engine.process_candle(&candle(20100.0), &candle(20120.0))?;
+
+        let rows = trace_rows(trace_file)?;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], DecisionRecord::HEADER);
+        assert_eq!(
+            rows[1],
+            format!(
+                "{},20100,20120,0,20160,19440,20160,,No PLPL crossover,no trade,",
+                date().to_unix_ms()
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decision_trace_row_per_final_kline() -> Result<()> {
+        let trace_file = "decision_trace_klines_test.csv";
+        let mut engine = engine(trace_file)?;
+        for mins in [0, 5, 10] {
+            for close in [20100.0, 20105.0, 20110.0] {
+                engine.handle_kline(&kline_event(mins, close, false))?;
+            }
+            engine.handle_kline(&kline_event(mins, 20120.0, true))?;
+        }
+
+        // the first candle only fills the window, the next two are traced once each
+        let rows = trace_rows(trace_file)?;
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], DecisionRecord::HEADER);
+        Ok(())
+    }
+
+    #[test]
+    fn trend_filter_suppresses_long() -> Result<()> {
+        let trace_file = "trend_filter_test.csv";
+        let mut engine = engine(trace_file)?;
+        let mut trend_filter = TrendFilter::new(Timeframe::Hour, 5, 500);
+        trend_filter.trend = Some(Direction::Down);
+        engine.trend_filter = Some(trend_filter);
+
+        // long crossover of the 20160 PLPL
+        engine.process_candle(&candle(20100.0), &candle(20200.0))?;
+        assert!(engine.active_order.entry.is_none());
+        let rows = trace_rows(trace_file)?;
+        assert_eq!(rows.len(), 2);
+        assert!(rows[1].contains(",Against higher timeframe trend,no trade,"));
+        Ok(())
+    }
+
+    #[test]
+    fn signal_conditions_suppress_long() -> Result<()> {
+        let trace_file = "signal_conditions_test.csv";
+        let mut curr = candle(20200.0);
+        curr.date.hour = Some(3);
+        for reason in [
+            NoTradeReason::BelowHysteresis,
+            NoTradeReason::InCooldown,
+            NoTradeReason::OutsideTradingHours,
+            NoTradeReason::InsufficientFunds,
+        ] {
+            let mut engine = engine(trace_file)?;
+            match reason {
+                // the close is 40 beyond the 20160 PLPL
+                NoTradeReason::BelowHysteresis => engine.signal_hysteresis = Some(50.0),
+                NoTradeReason::InCooldown => {
+                    engine.entry_cooldown = 2;
+                    engine.candles_since_entry = Some(1);
+                }
+                // the candle closes at 03:00 UTC
+                NoTradeReason::OutsideTradingHours => engine.trading_hours = Some((13, 21)),
+                // no free balances
+                _ => engine.min_entry_funds = Some(20.0),
+            }
+            // long crossover of the 20160 PLPL
+            engine.process_candle(&candle(20100.0), &curr)?;
+            assert!(engine.active_order.entry.is_none(), "{}", reason);
+            let rows = trace_rows(trace_file)?;
+            assert!(rows[1].contains(&format!(",{},no trade,", reason)));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn entry_cooldown_counts_final_klines() -> Result<()> {
+        let trace_file = "entry_cooldown_klines_test.csv";
+        let mut engine = dry_run_engine(trace_file, 20200.0)?;
+        engine.entry_cooldown = 2;
+
+        // long crossover of the 20160 PLPL on the second final kline
+        engine.handle_kline(&kline_event(0, 20100.0, true))?;
+        engine.handle_kline(&kline_event(5, 20200.0, true))?;
+        assert!(engine.active_order.entry.is_some());
+        assert_eq!(engine.candles_since_entry, Some(0));
+        engine.reset_active_order()?;
+
+        // intrabar updates of the next candle cross again, but are not candles
+        for i in 0..10 {
+            let close = if i % 2 == 0 { 20100.0 } else { 20200.0 };
+            engine.handle_kline(&kline_event(10, close, false))?;
+        }
+        assert!(engine.active_order.entry.is_none());
+        assert_eq!(engine.candles_since_entry, Some(0));
+        assert_eq!(engine.candles.cache.curr().map(|c| c.close), Some(20200.0));
+
+        // the cooldown holds the next two final candles back
+        engine.handle_kline(&kline_event(10, 20100.0, true))?;
+        engine.handle_kline(&kline_event(15, 20200.0, true))?;
+        assert!(engine.active_order.entry.is_none());
+        assert_eq!(engine.candles_since_entry, Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn max_move_rejections_count_final_klines() -> Result<()> {
+        let mut engine = engine("max_move_klines_test.csv")?;
+        engine.candles.max_move_pct = Some(20.0);
+        engine.candles.max_rejections = 3;
+        engine.handle_kline(&kline_event(0, 20100.0, true))?;
+
+        // a burst of intrabar bad prints does not count towards re-anchoring
+        for _ in 0..10 {
+            engine.handle_kline(&kline_event(5, 50000.0, false))?;
+        }
+        assert_eq!(engine.candles.cache.last().map(|c| c.close), Some(20100.0));
+
+        // three final bad prints are rejected, the fourth re-anchors the window
+        for mins in [5, 10, 15] {
+            engine.handle_kline(&kline_event(mins, 50000.0, true))?;
+            assert_eq!(engine.candles.cache.last().map(|c| c.close), Some(20100.0));
+        }
+        engine.handle_kline(&kline_event(20, 50000.0, true))?;
+        assert_eq!(engine.candles.cache.last().map(|c| c.close), Some(50000.0));
+        Ok(())
+    }
+
+    #[test]
+    fn entry_cooldown_counts_candles() -> Result<()> {
+        let mut engine = dry_run_engine("entry_cooldown_test.csv", 20200.0)?;
+        engine.entry_cooldown = 2;
+        // a dry run has no spread
+        engine.max_spread_pct = Some(0.01);
+
+        engine.process_candle(&candle(20100.0), &candle(20200.0))?;
+        assert!(engine.active_order.entry.is_some());
+        assert_eq!(engine.candles_since_entry, Some(0));
+        engine.reset_active_order()?;
+
+        // the two candles after the entry are held back, the third enters
+        for candles_since_entry in 1..=2 {
+            engine.process_candle(&candle(20100.0), &candle(20200.0))?;
+            assert!(engine.active_order.entry.is_none());
+            assert_eq!(engine.candles_since_entry, Some(candles_since_entry));
+        }
+        engine.process_candle(&candle(20100.0), &candle(20200.0))?;
+        assert!(engine.active_order.entry.is_some());
+        assert_eq!(engine.candles_since_entry, Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn warmup_suppresses_entries() -> Result<()> {
+        let trace_file = "warmup_test.csv";
+        let mut engine = engine(trace_file)?;
+        engine.indicators = IndicatorBuffer::new(3);
+        engine
+            .indicators
+            .seed(vec![candle(20000.0), candle(20050.0)]);
+
+        // long crossover of the 20160 PLPL
+        engine.process_candle(&candle(20100.0), &candle(20200.0))?;
+        assert!(engine.active_order.entry.is_none());
+        let rows = trace_rows(trace_file)?;
+        assert_eq!(rows.len(), 2);
+        assert!(rows[1].ends_with(",ignore: indicator warmup 2/3,"));
+
+        engine.indicators.push(candle(20100.0));
+        assert!(engine.indicators.is_warm());
+        // buffer rolls at the warmup length
+        engine.indicators.push(candle(20200.0));
+        assert_eq!(engine.indicators.candles.len(), 3);
+        assert_eq!(engine.indicators.candles[0].close, 20050.0);
+        Ok(())
+    }
+
+    #[test]
+    fn exit_on_signal_only_flips() -> Result<()> {
+        let mut engine = dry_run_engine("exit_on_signal_only_test.csv", 20200.0)?;
+        engine.exit_mode = ExitMode::ExitOnSignalOnly;
+
+        // flat, so only an entry without exit legs
+        engine.process_candle(&candle(20100.0), &candle(20200.0))?;
+        assert!(matches!(
+            &engine.active_order.entry,
+            Some(PendingOrActiveOrder::Active(entry)) if matches!(entry.side, Side::Long)
+        ));
+        assert!(engine.active_order.take_profit.is_none());
+        assert!(engine.active_order.stop_loss.is_none());
+        assert!(engine.active_order.take_profit_handler.state.is_none());
+        assert!(engine.active_order.stop_loss_handler.state.is_none());
+        assert_eq!(engine.account.open_orders("BTCUSDT".to_string())?.len(), 1);
+
+        // the long fills, and the short signal of the same candle exits it without entering yet
+        engine.process_candle(&candle(20200.0), &candle(20150.0))?;
+        let long_qty = engine.position().map(|entry| entry.executed_qty).unwrap();
+        let (flip_side, flip_qty) = engine.active_order.flip.clone().unwrap();
+        assert!(matches!(flip_side, Side::Short));
+        let open = engine.account.open_orders("BTCUSDT".to_string())?;
+        assert_eq!(open.len(), 1);
+        assert!(open[0].client_order_id.ends_with("-EXIT"));
+        assert_eq!(open[0].orig_qty, long_qty.to_string());
+
+        // the exit fills, booking pnl at its price, and only then is the short entered
+        engine.process_candle(&candle(20200.0), &candle(20210.0))?;
+        assert_eq!(
+            engine.session_pnl.pnl,
+            precise_round((20150.0 - 20200.0) / 20200.0 * 100.0, 5)
+        );
+        assert!(engine.active_order.exit.is_none());
+        assert!(matches!(
+            &engine.active_order.entry,
+            Some(PendingOrActiveOrder::Active(entry)) if matches!(entry.side, Side::Short)
+        ));
+        let open = engine.account.open_orders("BTCUSDT".to_string())?;
+        assert_eq!(open.len(), 1);
+        assert!(open[0].client_order_id.ends_with("-ENTRY"));
+        // the short is the entry leg sized with the exit
+        assert!(flip_qty > 0.0);
+        assert_eq!(open[0].orig_qty, flip_qty.to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn signal_only_exits_partial_entry() -> Result<()> {
+        let mut engine = dry_run_engine("signal_only_partial_test.csv", 20200.0)?;
+        engine.exit_mode = ExitMode::ExitOnSignalOnly;
+        engine.active_order.entry = Some(PendingOrActiveOrder::Active(TradeInfo {
+            client_order_id: "1-ENTRY".to_string(),
+            order_id: 1,
+            order_type: OrderType::Limit,
+            status: OrderStatus::PartiallyFilled,
+            event_time: 0,
+            quantity: 0.5,
+            executed_qty: 0.2,
+            price: 20200.0,
+            side: Side::Long,
+        }));
+
+        // the rest of the entry is canceled and what it bought is exited
+        engine.process_candle(&candle(20200.0), &candle(20100.0))?;
+        let open = engine.account.open_orders("BTCUSDT".to_string())?;
+        assert_eq!(open.len(), 1);
+        assert!(open[0].client_order_id.ends_with("-EXIT"));
+        assert_eq!(open[0].orig_qty, "0.2");
+        assert_eq!(open[0].side, "SELL");
+        Ok(())
+    }
+
+    #[test]
+    fn trade_notional_sizes_entry() -> Result<()> {
+        let mut engine = engine("trade_notional_test.csv")?;
+        engine.tracer = DecisionTracer::disabled();
+        engine.assets = Assets {
+            free_quote: 5000.0,
+            locked_quote: 0.0,
+            free_base: 1.0,
+            locked_base: 0.0,
+        };
+        let close = 20200.0;
+        engine.trade_notional = TradeNotional::Quote(2000.0);
+        // 0.0990099 floored to the step size
+        assert_eq!(engine.trade_qty(Side::Long, close)?, 0.099);
+        // short is capped at half of what a third of the quote buys, for its exit
+        assert_eq!(engine.trade_qty(Side::Short, close)?, 0.04125);
+        // more than the free quote buys as much as the account can, up to half a third of the base
+        engine.trade_notional = TradeNotional::Quote(10000.0);
+        assert_eq!(engine.trade_qty(Side::Long, close)?, 0.165);
+        engine.trade_notional = TradeNotional::Percent(10.0);
+        assert_eq!(engine.trade_qty(Side::Long, close)?, 0.02475);
+        assert_eq!(engine.trade_qty(Side::Short, close)?, 0.04125);
+        // too little base to exit a long of the notional
+        engine.assets.free_base = 0.05;
+        engine.trade_notional = TradeNotional::Quote(2000.0);
+        assert_eq!(engine.trade_qty(Side::Long, close)?, 0.00825);
+        Ok(())
+    }
+
+    #[test]
+    fn break_even_moves_stop_to_entry() -> Result<()> {
+        let mut engine = engine("break_even_test.csv")?;
+        engine.tracer = DecisionTracer::disabled();
+        engine.price_guard.update(20200.0, SystemTime::now());
+        engine.assets = Assets {
+            free_quote: 60000.0,
+            locked_quote: 0.0,
+            free_base: 3.0,
+            locked_base: 0.0,
+        };
+        engine.break_even = Some(ExitType::Price(50.0));
+        engine.stage_orders(&candle(20200.0), "1".to_string(), Side::Long)?;
+        let sl_state = engine.active_order.stop_loss_handler.state.clone().unwrap();
+        assert!(sl_state.exit < 20200.0);
+        let gap = sl_state.exit_trigger - sl_state.exit;
+
+        // short of the threshold the stop loss stays put
+        engine.candles.cache.push(candle(20200.0));
+        engine.candles.cache.push(candle(20240.0));
+        engine.check_trailing_take_profit()?;
+        let state = engine.active_order.stop_loss_handler.state.clone().unwrap();
+        assert!(!state.break_even);
+        assert_eq!(state.exit, sl_state.exit);
+
+        // past the threshold the stop loss moves to entry
+        engine.candles.cache.push(candle(20260.0));
+        engine.check_trailing_take_profit()?;
+        let state = engine.active_order.stop_loss_handler.state.clone().unwrap();
+        assert!(state.break_even);
+        assert_eq!(state.exit, 20200.0);
+        assert_eq!(state.exit_trigger, precise_round(20200.0 + gap, 2));
+        match &engine.active_order.stop_loss {
+            Some(PendingOrActiveOrder::Pending(sl)) => {
+                assert_eq!(sl.price, Some(20200.0));
+                assert_eq!(sl.stop_price, Some(state.exit_trigger));
+            }
+            _ => panic!("expected pending stop loss"),
+        }
+        Ok(())
+    }
+
+    /// Dry run engine holding a long filled at 20200, with its take profit and stop loss open
+    /// Untraced engine trading 60000 USDT and 3 BTC in dry run, with `last_price` as the market price
+    fn dry_run_engine(trace_file: &str, last_price: f64) -> Result<Engine> {
+        let mut engine = engine(trace_file)?;
+        engine.tracer = DecisionTracer::disabled();
+        engine.account = engine.account.clone().with_dry_run(60000.0, 3.0);
+        engine.account.paper.lock()?.last_price = last_price;
+        engine.update_assets()?;
+        Ok(engine)
+    }
+
+    fn dry_run_long(trace_file: &str) -> Result<Engine> {
+        let mut engine = dry_run_engine(trace_file, 20100.0)?;
+        engine.process_candle(&candle(20100.0), &candle(20200.0))?;
+        engine.process_candle(&candle(20200.0), &candle(20199.0))?;
+        Ok(engine)
+    }
+
+    fn active_stop_loss(engine: &Engine) -> TradeInfo {
+        match &engine.active_order.stop_loss {
+            Some(PendingOrActiveOrder::Active(sl)) => sl.clone(),
+            _ => panic!("expected active stop loss"),
+        }
+    }
+
+    #[test]
+    fn break_even_replaces_active_stop_loss() -> Result<()> {
+        let mut engine = dry_run_long("break_even_active_test.csv")?;
+        let qty = engine.position().map(|entry| entry.executed_qty).unwrap();
+        let old_sl = active_stop_loss(&engine);
+        engine.break_even = Some(ExitType::Price(1.0));
+        engine.candles.cache.push(candle(20199.0));
+        engine.candles.cache.push(candle(20201.0));
+        engine.check_break_even()?;
+        engine.apply_paper_events()?;
+
+        let sl = active_stop_loss(&engine);
+        assert_ne!(sl.order_id, old_sl.order_id);
+        assert_eq!(sl.price, 20200.0);
+        assert_eq!(sl.quantity, qty);
+        let open = engine.account.open_orders("BTCUSDT".to_string())?;
+        assert_eq!(open.len(), 2);
+        assert!(open.iter().all(|order| order.order_id != old_sl.order_id));
+        Ok(())
+    }
+
+    #[test]
+    fn failed_break_even_keeps_position() -> Result<()> {
+        let mut engine = dry_run_long("break_even_failed_test.csv")?;
+        // the stop loss is gone from the exchange before the engine hears of it
+        engine.cancel_order(active_stop_loss(&engine).order_id)?;
+        engine.break_even = Some(ExitType::Price(1.0));
+        engine.candles.cache.push(candle(20199.0));
+        engine.candles.cache.push(candle(20201.0));
+        engine.check_break_even()?;
+        assert!(engine.position().is_some());
+        assert!(engine.active_order.take_profit.is_some());
+        assert!(engine.active_order.stop_loss.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn oco_exits_fill_one_leg() -> Result<()> {
+        let mut engine = dry_run_engine("oco_exits_test.csv", 20100.0)?;
+        engine.oco_exits = true;
+        engine.process_candle(&candle(20100.0), &candle(20200.0))?;
+        engine.process_candle(&candle(20200.0), &candle(20199.0))?;
+
+        // both exits rest as one order list
+        assert!(engine.active_order.oco_list_id.is_some());
+        let open = engine.account.open_orders("BTCUSDT".to_string())?;
+        assert_eq!(open.len(), 2);
+        assert!(open
+            .iter()
+            .all(|order| Some(order.order_list_id) == engine.active_order.oco_list_id));
+        assert!(matches!(
+            engine.active_order.take_profit,
+            Some(PendingOrActiveOrder::Active(_))
+        ));
+        assert!(matches!(
+            engine.active_order.stop_loss,
+            Some(PendingOrActiveOrder::Active(_))
+        ));
+
+        // the take profit fills and the exchange cancels the stop loss
+        engine.process_candle(&candle(20199.0), &candle(20210.0))?;
+        assert!(engine.active_order.entry.is_none());
+        assert!(engine.active_order.oco_list_id.is_none());
+        assert!(engine
+            .account
+            .open_orders("BTCUSDT".to_string())?
+            .is_empty());
+        assert!(engine.session_pnl.pnl > 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn max_hold_expires_position() -> Result<()> {
+        let mut engine = engine("max_hold_test.csv")?;
+        engine.tracer = DecisionTracer::disabled();
+        let entry_time = date().to_unix_ms();
+        engine.active_order.entry = Some(PendingOrActiveOrder::Active(TradeInfo {
+            client_order_id: "1-ENTRY".to_string(),
+            order_id: 1,
+            order_type: OrderType::Limit,
+            status: OrderStatus::Filled,
+            event_time: entry_time as u64,
+            quantity: 0.5,
+            executed_qty: 0.5,
+            price: 20200.0,
+            side: Side::Long,
+        }));
+        let at = |minutes: i64| Candle {
+            date: Time::from_unix_msec(entry_time + minutes * 60_000),
+            ..candle(20200.0)
+        };
+        // no limit holds until a signal or exit
+        assert!(!engine.hold_expired(&at(600)));
+        engine.max_hold = Some(Duration::from_secs(60 * 60));
+        assert!(!engine.hold_expired(&at(60)));
+        assert!(engine.hold_expired(&at(65)));
+
+        // the market exit books pnl at its average fill price once filled
+        let exit = BinanceTrade::new(
+            "BTCUSDT".to_string(),
+            "1-EXIT".to_string(),
+            Side::Short,
+            OrderType::Market,
+            0.5,
+            None,
+            None,
+            None,
+            Some(10000),
+        );
+        engine.active_order.exit = Some(PendingOrActiveOrder::Pending(exit.clone()));
+        assert!(!engine.hold_expired(&at(65)));
+        let mut event = order_trade_event(&exit, "FILLED", 2);
+        event.cumulative_quote_qty = (0.5 * 20400.0).to_string();
+        engine.update_active_order(event)?;
+        engine.check_active_order()?;
+        assert!(engine.active_order.exit.is_none());
+        assert_eq!(
+            engine.session_pnl.pnl,
+            precise_round(200.0 / 20200.0 * 100.0, 5)
+        );
+
+        // an unfilled entry is not a position
+        engine.active_order.entry = None;
+        assert!(!engine.hold_expired(&at(65)));
+        Ok(())
+    }
+
+    #[test]
+    fn partial_fills_follow_exchange() -> Result<()> {
+        let mut engine = engine("partial_fills_test.csv")?;
+        engine.tracer = DecisionTracer::disabled();
+        engine.price_guard.update(20200.0, SystemTime::now());
+        engine.assets = Assets {
+            free_quote: 60000.0,
+            locked_quote: 0.0,
+            free_base: 3.0,
+            locked_base: 0.0,
+        };
+        let entry = engine.stage_orders(&candle(20200.0), "1".to_string(), Side::Long)?;
+        let quantity = entry.quantity;
+        let first = precise_round(quantity * 0.4, 5);
+        let second = precise_round(quantity - first, 5);
+
+        let mut event = order_trade_event(&entry, "PARTIALLY_FILLED", 1);
+        event.qty_last_filled_trade = first.to_string();
+        event.accumulated_qty_filled_trades = first.to_string();
+        engine.update_active_order(event)?;
+        match &engine.active_order.entry {
+            Some(PendingOrActiveOrder::Active(info)) => {
+                assert_eq!(info.status, OrderStatus::PartiallyFilled);
+                assert_eq!(info.executed_qty, first);
+            }
+            _ => panic!("expected active entry"),
+        }
+        assert!(engine.position().is_none());
+
+        // a repeated event is not counted twice
+        let mut event = order_trade_event(&entry, "PARTIALLY_FILLED", 1);
+        event.qty_last_filled_trade = first.to_string();
+        event.accumulated_qty_filled_trades = first.to_string();
+        engine.update_active_order(event)?;
+        assert_eq!(
+            engine.active_order.entry.as_ref().map(|entry| match entry {
+                PendingOrActiveOrder::Active(info) => info.executed_qty,
+                PendingOrActiveOrder::Pending(_) => 0.0,
+            }),
+            Some(first)
+        );
+
+        let mut event = order_trade_event(&entry, "FILLED", 1);
+        event.qty_last_filled_trade = second.to_string();
+        engine.update_active_order(event)?;
+        match &engine.active_order.entry {
+            Some(PendingOrActiveOrder::Active(info)) => {
+                assert_eq!(info.status, OrderStatus::Filled);
+                assert_eq!(info.executed_qty, quantity);
+            }
+            _ => panic!("expected active entry"),
+        }
+        assert_eq!(engine.position().map(|p| p.executed_qty), Some(quantity));
+        Ok(())
+    }
+
+    #[test]
+    fn entry_sized_and_limited_at_one_price() -> Result<()> {
+        let mut engine = engine("one_price_test.csv")?;
+        engine.tracer = DecisionTracer::disabled();
+        engine.trade_notional = TradeNotional::Quote(2000.0);
+        engine.assets.free_quote = 5000.0;
+        engine.assets.free_base = 1.0;
+        // the fresh price has moved away from the signal candle's close
+        engine.price_guard.update(20400.0, SystemTime::now());
+        let entry = engine.stage_orders(&candle(20200.0), "1".to_string(), Side::Long)?;
+        assert_eq!(entry.price, Some(20400.0));
+        assert_eq!(entry.quantity, 0.09803);
+        let state = engine
+            .active_order
+            .take_profit_handler
+            .state
+            .as_ref()
+            .unwrap();
+        assert_eq!(state.entry, 20400.0);
+        Ok(())
+    }
+
+    #[test]
+    fn dry_run_fills_as_candles_cross() -> Result<()> {
+        let mut engine = dry_run_engine("dry_run_test.csv", 20100.0)?;
+
+        // long crossover of the 20160 PLPL rests an entry at the close
+        engine.process_candle(&candle(20100.0), &candle(20200.0))?;
+        assert_eq!(engine.account.open_orders("BTCUSDT".to_string())?.len(), 1);
+        assert!(engine.position().is_none());
+
+        // the next candle trades down to the entry, which fills and places both exits
+        engine.process_candle(&candle(20200.0), &candle(20199.0))?;
+        let qty = engine.position().map(|entry| entry.executed_qty).unwrap();
+        assert_eq!(engine.account.open_orders("BTCUSDT".to_string())?.len(), 2);
+        assert_eq!(engine.assets.free_base, 3.0 + qty);
+
+        // the take profit triggers and the stop loss is canceled with it
+        engine.process_candle(&candle(20199.0), &candle(20210.0))?;
+        assert!(engine.active_order.entry.is_none());
+        assert!(engine
+            .account
+            .open_orders("BTCUSDT".to_string())?
+            .is_empty());
+        assert!(engine.session_pnl.pnl > 0.0);
+        assert_eq!(engine.assets.free_base, 3.0);
+        Ok(())
+    }
+
+    #[test]
+    fn entry_below_min_notional_is_skipped() -> Result<()> {
+        let mut engine = engine("min_notional_test.csv")?;
+        engine.tracer = DecisionTracer::disabled();
+        // a third of the balance is under the 5 USDT min notional
+        engine.account = engine.account.clone().with_dry_run(9.0, 0.0003);
+        engine.account.paper.lock()?.last_price = 20100.0;
+        engine.update_assets()?;
+
+        engine.process_candle(&candle(20100.0), &candle(20200.0))?;
+        assert!(engine.active_order.entry.is_none());
+        assert!(engine
+            .account
+            .open_orders("BTCUSDT".to_string())?
+            .is_empty());
+        Ok(())
+    }
+
+    /// Event of a replayed dry run session, either a closed candle that fills the orders it crosses
+    /// or an order canceled on the exchange
+    #[derive(Debug, Clone)]
+    enum ReplayEvent {
+        Candle(f64),
+        /// Cancel the open order of the leg, if there is one on the exchange
+        Cancel(&'static str),
+    }
+
+    fn replay_event() -> impl Strategy<Value = ReplayEvent> {
+        let leg = prop_oneof![Just("ENTRY"), Just("TAKE_PROFIT"), Just("STOP_LOSS")];
+        prop_oneof![
+            // closes around the 19800, 20160 and 20520 PLPLs so crossovers are frequent
+            6 => (19700.0..20700.0).prop_map(ReplayEvent::Candle),
+            1 => leg.prop_map(ReplayEvent::Cancel),
+        ]
+    }
+
+    fn order_trade_event(trade: &BinanceTrade, status: &str, order_id: u64) -> OrderTradeEvent {
+        OrderTradeEvent {
+            event_type: "executionReport".to_string(),
+            event_time: order_id,
+            symbol: trade.symbol.clone(),
+            new_client_order_id: trade.client_order_id.clone(),
+            side: trade.side.fmt_binance().to_string(),
+            order_type: trade.order_type.fmt_binance().to_string(),
+            time_in_force: "GTC".to_string(),
+            qty: trade.quantity.to_string(),
+            price: trade.price.unwrap_or_default().to_string(),
+            p_ignore: String::new(),
+            f_ignore: String::new(),
+            g: -1,
+            c_ignore: None,
+            execution_type: "TRADE".to_string(),
+            order_status: status.to_string(),
+            order_reject_reason: "NONE".to_string(),
+            order_id,
+            qty_last_filled_trade: trade.quantity.to_string(),
+            accumulated_qty_filled_trades: trade.quantity.to_string(),
+            price_last_filled_trade: trade.price.unwrap_or_default().to_string(),
+            cumulative_quote_qty: (trade.quantity * trade.price.unwrap_or_default()).to_string(),
+            commission: "0".to_string(),
+            asset_commissioned: None,
+            trade_order_time: order_id,
+            trade_id: order_id as i64,
+            i_ignore: 0,
+            w: true,
+            is_buyer_maker: false,
+            m_ignore: false,
+        }
+    }
+
+    fn trade_info(leg: &Option<PendingOrActiveOrder>) -> Option<(&str, f64)> {
+        leg.as_ref().map(|leg| match leg {
+            PendingOrActiveOrder::Pending(trade) => {
+                (trade.client_order_id.as_str(), trade.quantity)
+            }
+            PendingOrActiveOrder::Active(info) => (info.client_order_id.as_str(), info.quantity),
+        })
+    }
+
+    /// Invariants of the active order against the dry run book after every replayed event
+    fn assert_invariants(engine: &Engine) -> Result<()> {
+        let active = &engine.active_order;
+        let (entry, tp, sl) = (
+            trade_info(&active.entry),
+            trade_info(&active.take_profit),
+            trade_info(&active.stop_loss),
+        );
+        let open = engine.account.open_orders("BTCUSDT".to_string())?;
+        // never two concurrent positions
+        let open_entries = open
+            .iter()
+            .filter(|order| ActiveOrder::client_order_id_suffix(&order.client_order_id) == "ENTRY")
+            .count();
+        assert!(open_entries <= 1, "{} open entries", open_entries);
+        // every open order belongs to the active bundle
+        for order in open.iter() {
+            let (entry_id, _) =
+                entry.unwrap_or_else(|| panic!("{} open while flat", order.client_order_id));
+            assert_eq!(
+                ActiveOrder::client_order_id_prefix(&order.client_order_id),
+                ActiveOrder::client_order_id_prefix(entry_id)
+            );
+        }
+        // exits exist exactly while an entry does, along with their handler state
+        assert_eq!(entry.is_some(), tp.is_some());
+        assert_eq!(entry.is_some(), sl.is_some());
+        assert_eq!(tp.is_some(), active.take_profit_handler.state.is_some());
+        assert_eq!(sl.is_some(), active.stop_loss_handler.state.is_some());
+        if let (Some((entry_id, entry_qty)), Some((tp_id, tp_qty)), Some((sl_id, sl_qty))) =
+            (entry, tp, sl)
+        {
+            // exit quantity never exceeds the held base
+            assert!(
+                tp_qty <= entry_qty,
+                "take profit {} > entry {}",
+                tp_qty,
+                entry_qty
+            );
+            assert!(
+                sl_qty <= entry_qty,
+                "stop loss {} > entry {}",
+                sl_qty,
+                entry_qty
+            );
+            // every leg belongs to the same bundle
+            let prefix = ActiveOrder::client_order_id_prefix(entry_id);
+            assert_eq!(ActiveOrder::client_order_id_prefix(tp_id), prefix);
+            assert_eq!(ActiveOrder::client_order_id_prefix(sl_id), prefix);
+        }
+        Ok(())
+    }
+
+    /// Drive the engine in dry run through `events`, asserting invariants after each
+    fn replay(events: &[ReplayEvent]) -> Result<()> {
+        let mut engine = dry_run_engine("replay_test.csv", 20160.0)?;
+        let mut prev: Option<Candle> = None;
+        for event in events.iter() {
+            match event {
+                ReplayEvent::Candle(close) => {
+                    let curr = candle(precise_round(*close, 2));
+                    if let Some(prev) = prev.replace(curr.clone()) {
+                        engine.process_candle(&prev, &curr)?;
+                    }
+                }
+                ReplayEvent::Cancel(leg) => {
+                    let open = engine.account.open_orders("BTCUSDT".to_string())?;
+                    if let Some(order) = open.iter().find(|order| {
+                        ActiveOrder::client_order_id_suffix(&order.client_order_id) == *leg
+                    }) {
+                        engine.cancel_order(order.order_id)?;
+                        engine.apply_paper_events()?;
+                    }
+                }
+            }
+            assert_invariants(&engine)?;
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(300))]
+
+        #[test]
+        fn replay_preserves_invariants(events in prop::collection::vec(replay_event(), 1..80)) {
+            replay(&events).map_err(|e| TestCaseError::fail(format!("{:?}", e)))?;
+        }
+    }
+}
@@ -1,17 +1,18 @@
 use binance_lib::*;
 use dotenv::dotenv;
 use ephemeris::*;
-use lazy_static::lazy_static;
 use log::*;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
 use std::sync::Mutex;
-use std::time::{SystemTime, UNIX_EPOCH};
-use time_series::{precise_round, Day, Month, Time};
+use std::time::Duration;
+use time_series::{precise_round, Day, Month, Time, Timeframe};
 
 mod engine;
+mod trace;
 mod utils;
 use engine::*;
+use trace::*;
 use utils::*;
 
 // Binance Spot Test Network API credentials
@@ -21,6 +22,12 @@ pub const BINANCE_TEST_API: &str = "https://testnet.binance.vision";
 #[allow(dead_code)]
 pub const BINANCE_LIVE_API: &str = "https://api.binance.us";
 pub const KLINE_STREAM: &str = "btcusdt@kline_5m";
+pub const TIMEFRAME: Timeframe = Timeframe::Min5;
+// higher timeframe whose trend 5 minute entries must agree with
+pub const TREND_KLINE_STREAM: &str = "btcusdt@kline_1h";
+pub const TREND_TIMEFRAME: Timeframe = Timeframe::Hour;
+// request weight Binance.US allows per minute
+pub const REQUEST_WEIGHT_LIMIT: u32 = 1200;
 pub const BASE_ASSET: &str = "BTC";
 pub const QUOTE_ASSET: &str = "USDT";
 pub const TICKER: &str = "BTCUSDT";
@@ -43,11 +50,58 @@ async fn main() -> Result<()> {
     // PLPL parameters; tuned for 5 minute candles
     let trailing_take_profit = ExitType::Ticks(350);
     let stop_loss = ExitType::Bips(5);
+    // move the stop loss to entry once price advances this far in favor of the position
+    let break_even = None;
+    // exit at market once a position is held this long without an exit, None to hold until one
+    let max_hold = None;
+    // or a volatility adaptive exit, e.g. ExitType::AtrMultiple { period: 14, mult: 2.0 }
     let planet = Planet::from("Jupiter");
     let plpl_scale = 0.5;
     let plpl_price = 20000.0;
     let num_plpls = 8000;
     let cross_margin_pct = 55.0;
+    // halt new entries for the day once realized pnl percent hits either limit, e.g. Some(2.0)
+    let daily_profit_target = None;
+    let daily_loss_limit = None;
+    // skip the PLPL crossover between candles across a missed candle, or start over after it,
+    // from env CANDLE_GAP_POLICY of skip or reset
+    let gap_policy = candle_gap_policy()?;
+    // ignore a candle whose close moved more than this percent from the last close, e.g. a flash crash print
+    let max_move_pct = Some(20.0);
+    // accept a move that held for this many rejected candles as real and re-anchor on it
+    let max_move_rejections = 3;
+    // write every candle decision to its own file for auditing
+    let decision_trace = false;
+    // refetch the price entries are sized with once it is older than this
+    let price_max_age = Duration::from_secs(5);
+    // closed candles of history indicators need before entries, fetched on startup up to 999,
+    // and at least what an ATR exit is computed from
+    let warmup_bars = trailing_take_profit
+        .history_len()
+        .max(stop_loss.history_len());
+    // exit with take profit and stop loss orders, or hold until the opposite signal flips the position
+    let exit_mode = ExitMode::Brackets;
+    // quote notional each entry is sized to from env TRADE_NOTIONAL, e.g. 1000 quote or 25%,
+    // a third of the balance if unset
+    let trade_notional = trade_notional()?;
+    // place the take profit and stop loss as one OCO order list, without trailing or break even
+    let oco_exits = false;
+    // candles on either side of a 1 hour reversal for entries to agree with the 1 hour trend, e.g. Some(5),
+    // None to trade either way, and 1 hour candles kept to find reversals in
+    let trend_candle_range = None;
+    let trend_max_candles = 500;
+    // hold back signals whose close is less than this far beyond the PLPL, e.g. Some(10.0)
+    let signal_hysteresis = None;
+    // candles after an entry before another entry is allowed
+    let entry_cooldown = 0;
+    // UTC hours within which entries are allowed as (start, end), e.g. Some((13, 21))
+    let trading_hours = None;
+    // least quote value of both free balances to enter with, e.g. Some(20.0)
+    let min_entry_funds = None;
+    // widest bid/ask spread in percent of price to enter at, e.g. Some(0.05)
+    let max_spread_pct = None;
+    // trade paper balances of (quote, base) asset that fill as candles cross order prices, None to trade on Binance
+    let dry_run = None;
 
     // initialize PLPL
     let plpl_system = PLPLSystem::new(PLPLSystemConfig {
@@ -59,6 +113,7 @@ async fn main() -> Result<()> {
         plpl_price,
         num_plpls,
         cross_margin_pct,
+        price_range: None,
     })?;
 
     let testnet = is_testnet()?;
@@ -86,93 +141,111 @@ async fn main() -> Result<()> {
     let mut engine = match testnet {
         true => Engine::new(
             Client::new(
-                Some(binance_test_api_key.to_string()),
-                Some(binance_test_api_secret.to_string()),
+                Some(binance_test_api_key),
+                Some(binance_test_api_secret),
                 BINANCE_TEST_API.to_string(),
-            ),
+            )
+            .with_rate_limit(REQUEST_WEIGHT_LIMIT),
             plpl_system,
             10000,
             BASE_ASSET.to_string(),
             QUOTE_ASSET.to_string(),
             TICKER.to_string(),
+            TIMEFRAME,
+            gap_policy,
             trailing_take_profit,
             stop_loss,
+            daily_profit_target,
+            daily_loss_limit,
         ),
         false => Engine::new(
             Client::new(
-                Some(binance_live_api_key.to_string()),
-                Some(binance_live_api_secret.to_string()),
+                Some(binance_live_api_key),
+                Some(binance_live_api_secret),
                 BINANCE_LIVE_API.to_string(),
-            ),
+            )
+            .with_rate_limit(REQUEST_WEIGHT_LIMIT),
             plpl_system,
             10000,
             BASE_ASSET.to_string(),
             QUOTE_ASSET.to_string(),
             TICKER.to_string(),
+            TIMEFRAME,
+            gap_policy,
             trailing_take_profit,
             stop_loss,
+            daily_profit_target,
+            daily_loss_limit,
         ),
     };
 
-    let user_stream_keep_alive_time = Mutex::new(SystemTime::now());
+    engine.trend_filter = trend_candle_range
+        .map(|candle_range| TrendFilter::new(TREND_TIMEFRAME, candle_range, trend_max_candles));
+    engine.price_guard = PriceGuard::new(price_max_age);
+    engine.indicators = IndicatorBuffer::new(warmup_bars);
+    engine.exit_mode = exit_mode;
+    engine.trade_notional = trade_notional;
+    engine.break_even = break_even;
+    engine.max_hold = max_hold;
+    engine.oco_exits = oco_exits;
+    engine.signal_hysteresis = signal_hysteresis;
+    engine.entry_cooldown = entry_cooldown;
+    engine.trading_hours = trading_hours;
+    engine.min_entry_funds = min_entry_funds;
+    engine.max_spread_pct = max_spread_pct;
+    engine.candles.max_move_pct = max_move_pct;
+    engine.candles.max_rejections = max_move_rejections;
+    if let Some((quote, base)) = dry_run {
+        // mark the paper book to the live price before orders are placed against it
+        let price = engine.price()?;
+        engine.account = engine.account.clone().with_dry_run(quote, base);
+        engine.account.paper.lock()?.last_price = price;
+    }
+    if decision_trace {
+        engine.tracer = DecisionTracer::new(&PathBuf::from("plpl_trace.csv".to_string()))?;
+    }
+
     let user_stream = user_stream.lock()?;
     let answer = user_stream.start()?;
     let listen_key = answer.listen_key;
+    // keep the listen key alive in the background so Binance does not expire it
+    let keep_alive = user_stream.spawn_keep_alive(listen_key);
 
+    // offset signed request timestamps by drift between local and Binance server time
+    engine.account.client.sync_time_if_due()?;
     // cancel all open orders to start with a clean slate
     engine.cancel_all_open_orders()?;
+    // configured quote asset must be tradable by this account
+    engine.validate_quote_asset()?;
     // equalize base and quote assets to 50/50
     engine.equalize_assets()?;
+    // seed indicators with recent klines
+    engine.warm_start()?;
     // get initial asset balances
     engine.update_assets()?;
     engine.log_assets();
 
+    let trend_stream = engine.trend_filter.is_some();
+    // websocket events are handled one at a time, so order operations never interleave
     let engine = Mutex::new(engine);
     let mut ws = WebSockets::new(testnet, |event: WebSocketEvent| {
-        let now = SystemTime::now();
-        let mut keep_alive = user_stream_keep_alive_time.lock()?;
-        // check if timestamp is 10 minutes after last UserStream keep alive ping
-        let secs_since_keep_alive = now.duration_since(*keep_alive).map(|d| d.as_secs())?;
-
-        if secs_since_keep_alive > 30 * 60 {
-            match user_stream.keep_alive(&listen_key) {
-                Ok(_) => {
-                    let now = Time::from_unix_msec(
-                        now.duration_since(UNIX_EPOCH).unwrap().as_millis() as i64,
-                    );
-                    info!("Keep alive user stream @ {}", now.to_string())
-                }
-                Err(e) => error!("🛑 Error on user stream keep alive: {}", e),
-            }
-            *keep_alive = now;
-        }
-        drop(keep_alive);
-
         let mut engine = engine.lock()?;
+        if let Err(e) = engine.account.client.sync_time_if_due() {
+            error!("🛑 Error syncing Binance server time: {}", e);
+        }
 
         match event {
-            WebSocketEvent::Kline(kline_event) => {
-                let candle = kline_to_candle(&kline_event)?;
-
-                // compare previous candle to current candle to check crossover of PLPL signal threshold
-                match (&engine.prev_candle.clone(), &engine.candle.clone()) {
-                    (None, None) => engine.prev_candle = Some(candle),
-                    (Some(prev_candle), None) => {
-                        engine.candle = Some(candle.clone());
-                        engine.process_candle(prev_candle, &candle)?;
-                    }
-                    (None, Some(_)) => {
-                        error!(
-                            "🛑 Previous candle is None and current candle is Some. Should never occur."
-                        );
-                    }
-                    (Some(_prev_candle), Some(curr_candle)) => {
-                        engine.process_candle(curr_candle, &candle)?;
-                        engine.prev_candle = Some(curr_candle.clone());
-                        engine.candle = Some(candle);
+            WebSocketEvent::Kline(kline_event)
+                if kline_event.kline.interval == TREND_TIMEFRAME.to_string() =>
+            {
+                if kline_event.kline.is_final_bar {
+                    let candle = kline_to_candle(&kline_event)?;
+                    if let Some(trend_filter) = engine.trend_filter.as_mut() {
+                        trend_filter.update(candle)?;
                     }
                 }
             }
+            WebSocketEvent::Kline(kline_event) => engine.handle_kline(&kline_event)?,
             WebSocketEvent::AccountUpdate(account_update) => {
                 let assets = account_update.assets(&engine.quote_asset, &engine.base_asset)?;
                 debug!(
@@ -182,7 +255,7 @@ async fn main() -> Result<()> {
             }
             WebSocketEvent::OrderTrade(event) => {
                 let order_type = ActiveOrder::client_order_id_suffix(&event.new_client_order_id);
-                let entry_price = precise_round!(event.price.parse::<f64>()?, 2);
+                let entry_price = precise_round(event.price.parse::<f64>()?, 2);
                 debug!(
                     "{},  {},  {} @ {},  Execution: {},  Status: {},  Order: {}",
                     event.symbol,
@@ -193,20 +266,22 @@ async fn main() -> Result<()> {
                     event.order_status,
                     order_type
                 );
-                // update state
-                engine.update_active_order(event)?;
-                // create or cancel orders depending on state
-                engine.check_active_order()?;
-                // check trailing take profit and update if necessary
-                engine.check_trailing_take_profit()?;
+                engine.handle_order_trade(event)?;
             }
             _ => (),
         };
         Ok(())
     });
 
-    let subs = vec![KLINE_STREAM.to_string(), listen_key.clone()];
-    match ws.connect_multiple_streams(&subs, testnet) {
+    // the listen key is replaced if it expires, so subscribe with whichever is kept alive
+    let subs = || -> Result<Vec<String>> {
+        let mut subs = vec![KLINE_STREAM.to_string(), keep_alive.listen_key()?];
+        if trend_stream {
+            subs.push(TREND_KLINE_STREAM.to_string());
+        }
+        Ok(subs)
+    };
+    match ws.connect_multiple_streams(&subs()?, testnet) {
         Err(e) => {
             error!("🛑 Failed to connect to Binance websocket: {}", e);
             return Err(e);
@@ -214,32 +289,17 @@ async fn main() -> Result<()> {
         Ok(_) => info!("Binance websocket connected"),
     }
 
-    if let Err(e) = ws.event_loop(&AtomicBool::new(true)) {
+    let res = ws.event_loop_with_reconnect(
+        &AtomicBool::new(true),
+        subs,
+        testnet,
+        &ReconnectPolicy::default(),
+    );
+    keep_alive.abort()?;
+    if let Err(e) = res {
         error!("🛑 Binance websocket error: {}", e);
         return Err(e);
     }
 
     Ok(())
-
-    // user_stream.close(&listen_key)?;
-    //
-    // match ws.disconnect() {
-    //     Err(e) => {
-    //         error!("🛑 Failed to disconnect from Binance websocket: {}", e);
-    //         match ws.connect_multiple_streams(&subs, testnet) {
-    //             Err(e) => {
-    //                 error!("🛑 Failed to connect to Binance websocket: {}", e);
-    //                 Err(e)
-    //             }
-    //             Ok(_) => {
-    //                 info!("Binance websocket reconnected");
-    //                 Ok(())
-    //             }
-    //         }
-    //     }
-    //     Ok(_) => {
-    //         warn!("Binance websocket disconnected");
-    //         Ok(())
-    //     }
-    // }
 }
@@ -0,0 +1,125 @@
+use crate::utils::*;
+use binance_lib::*;
+use ephemeris::NoTradeReason;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use time_series::{Order, Time};
+
+/// What the engine did with a candle
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecisionAction {
+    NoTrade,
+    /// Signal ignored while a trade is active
+    IgnoreActiveTrade,
+    /// Signal ignored after a daily limit was hit
+    Halt(DailyHalt),
+    /// Signal ignored until indicators have this many of their warmup candles
+    Warmup {
+        have: usize,
+        need: usize,
+    },
+    Enter,
+    /// Position exited on the opposite signal and entered the other way
+    Flip,
+    /// Entry order was rejected and the active order reset
+    EnterFailed,
+}
+
+impl Display for DecisionAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecisionAction::NoTrade => write!(f, "no trade"),
+            DecisionAction::IgnoreActiveTrade => write!(f, "ignore: active trade in progress"),
+            DecisionAction::Halt(halt) => write!(f, "ignore: {}", halt),
+            DecisionAction::Warmup { have, need } => {
+                write!(f, "ignore: indicator warmup {}/{}", have, need)
+            }
+            DecisionAction::Enter => write!(f, "enter"),
+            DecisionAction::Flip => write!(f, "flip"),
+            DecisionAction::EnterFailed => write!(f, "enter failed"),
+        }
+    }
+}
+
+/// Everything the engine knew and did when it processed a candle
+#[derive(Debug, Clone)]
+pub struct DecisionRecord {
+    pub date: Time,
+    pub prev_close: f64,
+    pub close: f64,
+    pub planet_angle: Option<f32>,
+    pub closest_plpl: f64,
+    /// Nearest PLPL at or below the close
+    pub plpl_below: Option<f32>,
+    /// Nearest PLPL above the close
+    pub plpl_above: Option<f32>,
+    pub signal: Option<Order>,
+    pub no_trade_reason: Option<NoTradeReason>,
+    pub action: DecisionAction,
+    /// Client order ID of the entry, if one was placed
+    pub order_id: Option<String>,
+}
+
+impl DecisionRecord {
+    pub const HEADER: &'static str = "timestamp,prev_close,close,planet_angle,closest_plpl,plpl_below,plpl_above,signal,no_trade_reason,action,order_id";
+
+    /// Trace line with empty fields for missing values
+    pub fn to_csv(&self) -> String {
+        fn field<T: Display>(value: &Option<T>) -> String {
+            match value {
+                Some(value) => value.to_string(),
+                None => String::new(),
+            }
+        }
+        [
+            self.date.to_unix_ms().to_string(),
+            self.prev_close.to_string(),
+            self.close.to_string(),
+            field(&self.planet_angle),
+            self.closest_plpl.to_string(),
+            field(&self.plpl_below),
+            field(&self.plpl_above),
+            field(&self.signal.as_ref().map(|order| format!("{:?}", order))),
+            field(&self.no_trade_reason),
+            self.action.to_string(),
+            field(&self.order_id),
+        ]
+        .join(",")
+    }
+}
+
+/// Opt-in per-candle decision trace written to its own CSV file, apart from the normal logs.
+#[derive(Debug, Clone, Default)]
+pub struct DecisionTracer {
+    file: Option<Arc<Mutex<File>>>,
+}
+
+impl DecisionTracer {
+    /// Tracer that writes nothing
+    pub fn disabled() -> Self {
+        Self { file: None }
+    }
+
+    /// Create the trace file and write the header
+    pub fn new(trace_file: &PathBuf) -> Result<Self> {
+        let mut file = File::create(trace_file)?;
+        writeln!(file, "{}", DecisionRecord::HEADER)?;
+        Ok(Self {
+            file: Some(Arc::new(Mutex::new(file))),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    pub fn record(&self, record: &DecisionRecord) -> Result<()> {
+        if let Some(file) = &self.file {
+            writeln!(file.lock()?, "{}", record.to_csv())?;
+        }
+        Ok(())
+    }
+}
@@ -4,10 +4,13 @@ use simplelog::{
     ColorChoice, CombinedLogger, Config as SimpleLogConfig, ConfigBuilder, TermLogger,
     TerminalMode, WriteLogger,
 };
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::path::PathBuf;
 use std::str::FromStr;
-use time_series::{Candle, Time};
+use std::time::{Duration, SystemTime};
+use time_series::{Candle, CandleCache, Direction, MarketStructure, TickerData, Time, Timeframe};
 
 pub fn init_logger(log_file: &PathBuf) -> Result<()> {
     CombinedLogger::init(vec![
@@ -40,10 +43,264 @@ pub fn kline_to_candle(kline_event: &KlineEvent) -> Result<Candle> {
         high: kline_event.kline.high.parse::<f64>()?,
         low: kline_event.kline.low.parse::<f64>()?,
         close: kline_event.kline.close.parse::<f64>()?,
-        volume: None,
+        volume: Some(kline_event.kline.volume.parse::<f64>()?),
     })
 }
 
+/// Candle of a kline fetched from the REST klines endpoint, dated at its close like a final websocket kline
+pub fn kline_summary_to_candle(kline: &KlineSummary) -> Result<Candle> {
+    Ok(Candle {
+        date: Time::from_unix_msec(kline.close_time),
+        open: kline.open.parse::<f64>()?,
+        high: kline.high.parse::<f64>()?,
+        low: kline.low.parse::<f64>()?,
+        close: kline.close.parse::<f64>()?,
+        volume: Some(kline.volume.parse::<f64>()?),
+    })
+}
+
+/// Spacing of consecutive closed klines, checked against the timeframe the engine is tuned for.
+#[derive(Debug, Clone, Default)]
+pub struct KlineSpacing {
+    last_open_time: Option<i64>,
+}
+
+impl KlineSpacing {
+    /// Check the kline opened a whole number of timeframe intervals after the last one.
+    /// Logs a warning and returns false on a mismatch, e.g. subscribed to `kline_1m` with a 5 minute engine.
+    /// Missed klines are left to the candle gap policy.
+    pub fn matches(&mut self, kline: &Kline, timeframe: &Timeframe) -> bool {
+        let last_open_time = self.last_open_time.replace(kline.open_time);
+        let (last_open_time, expected_ms) = match (last_open_time, timeframe.to_secs()) {
+            (Some(last), Some(secs)) => (last, secs * 1000),
+            _ => return true,
+        };
+        let spacing_ms = kline.open_time - last_open_time;
+        if spacing_ms < expected_ms || spacing_ms % expected_ms != 0 {
+            warn!(
+                "Kline {} opened {}s after the last but engine timeframe {} expects {}s",
+                kline.interval,
+                spacing_ms / 1000,
+                timeframe,
+                expected_ms / 1000
+            );
+            return false;
+        }
+        true
+    }
+}
+
+/// How the engine handles a candle that arrives more than one interval after the current candle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CandleGapPolicy {
+    /// Keep caching candles but skip signal evaluation of the pair across the gap
+    #[default]
+    SkipSignal,
+    /// Drop cached candles and start over from the candle after the gap
+    Reset,
+}
+
+impl CandleGapPolicy {
+    /// Parse `skip` or `reset`
+    pub fn parse(policy: &str) -> Result<Self> {
+        match policy.trim().to_lowercase().as_str() {
+            "skip" => Ok(CandleGapPolicy::SkipSignal),
+            "reset" => Ok(CandleGapPolicy::Reset),
+            _ => Err(BinanceError::Custom(format!(
+                "Invalid candle gap policy {}, expected skip or reset",
+                policy
+            ))),
+        }
+    }
+}
+
+/// Candle gap policy from env CANDLE_GAP_POLICY, skipping the signal across a gap if unset
+pub fn candle_gap_policy() -> Result<CandleGapPolicy> {
+    match std::env::var("CANDLE_GAP_POLICY") {
+        Ok(policy) => CandleGapPolicy::parse(&policy),
+        Err(_) => Ok(CandleGapPolicy::default()),
+    }
+}
+
+/// Quote notional each entry is sized to.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TradeNotional {
+    /// A third of the free balances, leaving balance for the exit orders
+    #[default]
+    ThirdOfBalance,
+    /// Fixed quote amount, or as close as the account can get
+    Quote(f64),
+    /// Percent of the free balance of the asset the entry spends
+    Percent(f64),
+}
+
+impl TradeNotional {
+    /// Parse a quote amount like `1000`, or a percent like `25%`
+    pub fn parse(notional: &str) -> Result<Self> {
+        let notional = notional.trim();
+        Ok(match notional.strip_suffix('%') {
+            Some(pct) => TradeNotional::Percent(pct.trim().parse::<f64>()?),
+            None => TradeNotional::Quote(notional.parse::<f64>()?),
+        })
+    }
+}
+
+/// Trade notional from env TRADE_NOTIONAL, a third of the balance if unset
+pub fn trade_notional() -> Result<TradeNotional> {
+    match std::env::var("TRADE_NOTIONAL") {
+        Ok(notional) => TradeNotional::parse(&notional),
+        Err(_) => Ok(TradeNotional::ThirdOfBalance),
+    }
+}
+
+/// Previous and current candle the PLPL crossover is evaluated on.
+#[derive(Debug, Clone, Default)]
+pub struct CandleWindow {
+    pub cache: CandleCache,
+    pub gap_policy: CandleGapPolicy,
+    /// Reject a candle whose close moved more than this percent from the last close as a bad print
+    pub max_move_pct: Option<f64>,
+    /// Consecutive candles rejected by the max move after which the next one is accepted,
+    /// since a move that persists is real rather than a bad print
+    pub max_rejections: usize,
+    rejections: usize,
+}
+
+impl CandleWindow {
+    pub fn new(gap_policy: CandleGapPolicy) -> Self {
+        Self {
+            cache: CandleCache::Empty,
+            gap_policy,
+            max_move_pct: None,
+            max_rejections: 3,
+            rejections: 0,
+        }
+    }
+
+    /// Percent move of the candle close from the last cached close if it exceeds the max move
+    pub fn exceeds_max_move(&self, candle: &Candle) -> Option<f64> {
+        let (max_move_pct, last) = (self.max_move_pct?, self.cache.last()?);
+        let move_pct = (candle.close - last.close) / last.close * 100.0;
+        (move_pct.abs() > max_move_pct).then_some(move_pct)
+    }
+
+    /// Cache the incoming candle and return the pair to evaluate for a signal, if any.
+    /// A candle more than one `timeframe` interval after the last cached candle is handled per the gap policy.
+    /// A candle that exceeds the max move is dropped so later candles compare against the last good close,
+    /// unless `max_rejections` candles in a row were, in which case the window re-anchors on it without a signal.
+    pub fn push(&mut self, candle: Candle, timeframe: &Timeframe) -> Option<(Candle, Candle)> {
+        if let Some(move_pct) = self.exceeds_max_move(&candle) {
+            if self.rejections < self.max_rejections {
+                self.rejections += 1;
+                warn!(
+                    "Ignore candle @ {} with close {}, {:.2}% move from the last close is likely a bad print",
+                    candle.date.to_string(),
+                    candle.close,
+                    move_pct
+                );
+                return None;
+            }
+            warn!(
+                "Re-anchor on candle @ {} with close {}, {:.2}% move held for {} candles",
+                candle.date.to_string(),
+                candle.close,
+                move_pct,
+                self.rejections + 1
+            );
+            self.rejections = 0;
+            self.cache.reset(candle);
+            return None;
+        }
+        self.rejections = 0;
+        let gap = match (self.cache.last(), timeframe.to_secs()) {
+            (Some(last), Some(secs)) => {
+                let gap_secs = candle.date.to_unix() - last.date.to_unix();
+                (gap_secs > secs).then_some(gap_secs)
+            }
+            _ => None,
+        };
+        match gap {
+            Some(gap_secs) => {
+                warn!(
+                    "Candle @ {} is {}s after the last candle, more than the {} interval. {:?}",
+                    candle.date.to_string(),
+                    gap_secs,
+                    timeframe,
+                    self.gap_policy
+                );
+                match self.gap_policy {
+                    CandleGapPolicy::SkipSignal => {
+                        self.cache.push(candle);
+                    }
+                    CandleGapPolicy::Reset => self.cache.reset(candle),
+                }
+                None
+            }
+            None => self.cache.push(candle),
+        }
+    }
+}
+
+/// Trend of a higher timeframe kline stream that entries must agree with,
+/// e.g. only take 5 minute longs while the 1 hour trend is up.
+#[derive(Debug, Clone)]
+pub struct TrendFilter {
+    pub timeframe: Timeframe,
+    /// Number of candles on either side of a reversal
+    pub candle_range: usize,
+    /// Most recent closed candles kept to identify market structure
+    pub max_candles: usize,
+    pub ticker_data: TickerData,
+    /// Latest trend, None until market structure is established
+    pub trend: Option<Direction>,
+}
+
+impl TrendFilter {
+    pub fn new(timeframe: Timeframe, candle_range: usize, max_candles: usize) -> Self {
+        Self {
+            timeframe,
+            candle_range,
+            max_candles,
+            ticker_data: TickerData::new(),
+            trend: None,
+        }
+    }
+
+    /// Add a closed higher timeframe candle and update the trend.
+    /// Each candle confirms at most the reversal `candle_range` candles back,
+    /// so market structure is only rebuilt when it does.
+    pub fn update(&mut self, candle: Candle) -> Result<Option<Direction>> {
+        self.ticker_data
+            .push_candle_capped(candle, self.max_candles)
+            .map_err(|e| BinanceError::Custom(e.to_string()))?;
+        if self.confirms_reversal()? {
+            let market_structure = MarketStructure::new(&self.ticker_data, self.candle_range);
+            let trend = market_structure
+                .trends
+                .last()
+                .and_then(|trend| trend.direction.clone());
+            if trend != self.trend {
+                info!("{} trend: {:?}", self.timeframe, trend);
+            }
+            self.trend = trend;
+        }
+        Ok(self.trend.clone())
+    }
+
+    /// True if the candle `candle_range` back is a reversal now that the candles after it have closed
+    fn confirms_reversal(&self) -> Result<bool> {
+        let candles = self.ticker_data.get_candles();
+        let window = 2 * self.candle_range + 1;
+        if candles.len() < window {
+            return Ok(false);
+        }
+        let mut tail = TickerData::new();
+        tail.add_series(candles[candles.len() - window..].to_vec())
+            .map_err(|e| BinanceError::Custom(e.to_string()))?;
+        Ok(!tail.find_reversals(self.candle_range).is_empty())
+    }
+}
+
 pub struct OrderBuilder {
     pub entry: BinanceTrade,
     pub take_profit: BinanceTrade,
@@ -57,7 +314,10 @@ pub struct TradeInfo {
     pub order_type: OrderType,
     pub status: OrderStatus,
     pub event_time: u64,
+    /// Quantity of the order
     pub quantity: f64,
+    /// Quantity filled so far, which is below `quantity` while partially filled
+    pub executed_qty: f64,
     pub price: f64,
     pub side: Side,
 }
@@ -71,7 +331,8 @@ impl TradeInfo {
             order_type: OrderType::from_str(historical_order._type.as_str())?,
             status: OrderStatus::from_str(&historical_order.status)?,
             event_time: historical_order.update_time as u64,
-            quantity: historical_order.executed_qty.parse::<f64>()?,
+            quantity: historical_order.orig_qty.parse::<f64>()?,
+            executed_qty: historical_order.executed_qty.parse::<f64>()?,
             price: historical_order.price.parse::<f64>()?,
             side: Side::from_str(&historical_order.side)?,
         })
@@ -80,14 +341,25 @@ impl TradeInfo {
     pub fn from_order_trade_event(order_trade_event: &OrderTradeEvent) -> Result<Self> {
         let order_type = OrderType::from_str(order_trade_event.order_type.as_str())?;
         let status = OrderStatus::from_str(&order_trade_event.order_status)?;
+        // cumulative, so repeated or out of order events never double count a fill
+        let executed_qty = order_trade_event
+            .accumulated_qty_filled_trades
+            .parse::<f64>()?;
         Ok(Self {
             client_order_id: order_trade_event.new_client_order_id.clone(),
             order_id: order_trade_event.order_id,
-            order_type,
             status,
             event_time: order_trade_event.event_time,
             quantity: order_trade_event.qty.parse::<f64>()?,
-            price: order_trade_event.price.parse::<f64>()?,
+            price: match order_type {
+                // average fill price of an order without a limit price
+                OrderType::Market if executed_qty > 0.0 => {
+                    order_trade_event.cumulative_quote_qty.parse::<f64>()? / executed_qty
+                }
+                _ => order_trade_event.price.parse::<f64>()?,
+            },
+            order_type,
+            executed_qty,
             side: Side::from_str(&order_trade_event.side)?,
         })
     }
@@ -99,6 +371,15 @@ pub enum PendingOrActiveOrder {
     Active(TradeInfo),
 }
 
+impl PendingOrActiveOrder {
+    pub fn client_order_id(&self) -> &str {
+        match self {
+            PendingOrActiveOrder::Pending(trade) => &trade.client_order_id,
+            PendingOrActiveOrder::Active(info) => &info.client_order_id,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ActiveOrder {
     pub entry: Option<PendingOrActiveOrder>,
@@ -106,6 +387,12 @@ pub struct ActiveOrder {
     pub take_profit: Option<PendingOrActiveOrder>,
     pub stop_loss_handler: StopLossHandler,
     pub stop_loss: Option<PendingOrActiveOrder>,
+    /// Order closing the position apart from the take profit and stop loss, e.g. after the max hold
+    pub exit: Option<PendingOrActiveOrder>,
+    /// Side and base asset quantity entered once the exit fills, when flipping on the opposite signal
+    pub flip: Option<(Side, f64)>,
+    /// Order list ID if the take profit and stop loss were placed as an OCO
+    pub oco_list_id: Option<i64>,
 }
 
 impl ActiveOrder {
@@ -117,6 +404,9 @@ impl ActiveOrder {
             take_profit_handler,
             stop_loss: None,
             stop_loss_handler,
+            exit: None,
+            flip: None,
+            oco_list_id: None,
         }
     }
 
@@ -141,7 +431,444 @@ impl ActiveOrder {
         self.entry = None;
         self.take_profit = None;
         self.stop_loss = None;
+        self.exit = None;
+        self.flip = None;
+        self.oco_list_id = None;
         self.take_profit_handler.reset();
         self.stop_loss_handler.reset();
     }
 }
+
+/// Daily limit that halts new entries for the rest of the session
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DailyHalt {
+    ProfitTarget(f64),
+    LossLimit(f64),
+}
+
+impl Display for DailyHalt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DailyHalt::ProfitTarget(pnl) => write!(f, "daily profit target hit with {}% pnl", pnl),
+            DailyHalt::LossLimit(pnl) => write!(f, "daily loss limit hit with {}% pnl", pnl),
+        }
+    }
+}
+
+/// Realized pnl percent of the current UTC day session.
+#[derive(Debug, Clone, Default)]
+pub struct SessionPnl {
+    /// Date of the current session
+    pub session: Option<Time>,
+    /// Sum of realized pnl percent of trades exited this session
+    pub pnl: f64,
+    /// Halt new entries once session pnl reaches this percent
+    pub daily_profit_target: Option<f64>,
+    /// Halt new entries once session pnl falls to this percent below zero
+    pub daily_loss_limit: Option<f64>,
+}
+
+impl SessionPnl {
+    pub fn new(daily_profit_target: Option<f64>, daily_loss_limit: Option<f64>) -> Self {
+        Self {
+            session: None,
+            pnl: 0.0,
+            daily_profit_target,
+            daily_loss_limit,
+        }
+    }
+
+    /// Reset pnl if `date` is in a new session
+    fn roll_session(&mut self, date: Time) {
+        // Time equality compares dates only
+        if self.session != Some(date) {
+            self.session = Some(date);
+            self.pnl = 0.0;
+        }
+    }
+
+    /// Record realized pnl percent of a trade exited at `date`
+    pub fn record(&mut self, date: Time, pnl: f64) {
+        self.roll_session(date);
+        self.pnl += pnl;
+        if let Some(halt) = self.halt(date) {
+            info!("Halt new entries until next session, {}", halt);
+        }
+    }
+
+    /// Daily limit hit in the session of `date`, if any.
+    /// A session not yet recorded has no pnl, so it is never halted.
+    pub fn halt(&self, date: Time) -> Option<DailyHalt> {
+        // Time equality compares dates only
+        let pnl = match self.session {
+            Some(session) if session == date => self.pnl,
+            _ => 0.0,
+        };
+        if let Some(target) = self.daily_profit_target {
+            if pnl >= target {
+                return Some(DailyHalt::ProfitTarget(pnl));
+            }
+        }
+        if let Some(limit) = self.daily_loss_limit {
+            if pnl <= -limit {
+                return Some(DailyHalt::LossLimit(pnl));
+            }
+        }
+        None
+    }
+}
+
+/// Rolling history of closed candles that indicators are computed from.
+/// Entries are held off until `warmup_bars` candles are buffered, 0 trades immediately.
+#[derive(Debug, Clone, Default)]
+pub struct IndicatorBuffer {
+    pub warmup_bars: usize,
+    pub candles: VecDeque<Candle>,
+}
+
+impl IndicatorBuffer {
+    pub fn new(warmup_bars: usize) -> Self {
+        Self {
+            warmup_bars,
+            candles: VecDeque::with_capacity(warmup_bars),
+        }
+    }
+
+    /// Add a closed candle, dropping the oldest once the buffer holds `warmup_bars`
+    pub fn push(&mut self, candle: Candle) {
+        let was_warm = self.is_warm();
+        self.candles.push_back(candle);
+        while self.candles.len() > self.warmup_bars {
+            self.candles.pop_front();
+        }
+        if !was_warm && self.is_warm() {
+            info!(
+                "Indicator warmup complete with {} candles",
+                self.warmup_bars
+            );
+        }
+    }
+
+    /// Buffer historical candles, oldest first, e.g. klines fetched on startup
+    pub fn seed(&mut self, candles: Vec<Candle>) {
+        for candle in candles {
+            self.push(candle);
+        }
+        if !self.is_warm() {
+            info!(
+                "Indicator warmup {}/{} candles, entries held until complete",
+                self.candles.len(),
+                self.warmup_bars
+            );
+        }
+    }
+
+    pub fn is_warm(&self) -> bool {
+        self.candles.len() >= self.warmup_bars
+    }
+}
+
+/// Last fetched price of the ticker and when it was fetched,
+/// so sizing never uses a price older than `max_age`.
+#[derive(Debug, Clone)]
+pub struct PriceGuard {
+    pub max_age: Duration,
+    last: Option<(f64, SystemTime)>,
+}
+
+impl Default for PriceGuard {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(5))
+    }
+}
+
+impl PriceGuard {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            last: None,
+        }
+    }
+
+    pub fn update(&mut self, price: f64, fetched_at: SystemTime) {
+        self.last = Some((price, fetched_at));
+    }
+
+    /// True if no price was fetched, or the last price is older than the max age at `now`.
+    /// A fetch time after `now` from a clock adjustment is also stale.
+    pub fn is_stale(&self, now: SystemTime) -> bool {
+        match self.last {
+            Some((_, fetched_at)) => now
+                .duration_since(fetched_at)
+                .map(|age| age > self.max_age)
+                .unwrap_or(true),
+            None => true,
+        }
+    }
+
+    /// Last price if still fresh at `now`, otherwise the price returned by `fetch`
+    pub fn price(&mut self, now: SystemTime, fetch: impl FnOnce() -> Result<f64>) -> Result<f64> {
+        if let (false, Some((price, _))) = (self.is_stale(now), self.last) {
+            return Ok(price);
+        }
+        let price = fetch()?;
+        debug!("Refetched stale price: {}", price);
+        self.update(price, now);
+        Ok(price)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time_series::{Day, Month};
+
+    fn kline(open_time: i64, interval_secs: i64, interval: &str) -> Kline {
+        Kline {
+            open_time,
+            close_time: open_time + interval_secs * 1000 - 1,
+            symbol: "BTCUSDT".to_string(),
+            interval: interval.to_string(),
+            first_trade_id: 0,
+            last_trade_id: 0,
+            open: "20000.0".to_string(),
+            close: "20000.0".to_string(),
+            high: "20000.0".to_string(),
+            low: "20000.0".to_string(),
+            volume: "0.0".to_string(),
+            number_of_trades: 0,
+            is_final_bar: true,
+            quote_asset_volume: "0.0".to_string(),
+            taker_buy_base_asset_volume: "0.0".to_string(),
+            taker_buy_quote_asset_volume: "0.0".to_string(),
+            ignore_me: String::new(),
+        }
+    }
+
+    /// Flat candle closing at `close` `mins` after midnight of 2023-09-01
+    fn candle(mins: i64, close: f64) -> Candle {
+        let start = Time::new(2023, &Month::from_num(9), &Day::from_num(1), Some(0), None);
+        Candle {
+            date: Time::from_unix(start.to_unix() + mins * 60),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: None,
+        }
+    }
+
+    #[test]
+    fn kline_volume_parsed() -> Result<()> {
+        let mut kline = kline(1_693_526_400_000, 300, "5m");
+        kline.volume = "12.345".to_string();
+        let candle = kline_to_candle(&KlineEvent {
+            event_type: "kline".to_string(),
+            event_time: kline.close_time as u64,
+            symbol: kline.symbol.clone(),
+            kline,
+        })?;
+        assert_eq!(candle.volume, Some(12.345));
+        Ok(())
+    }
+
+    #[test]
+    fn kline_timeframe_mismatch() {
+        let open_time = 1_693_526_400_000;
+        let mut five_minute_engine = KlineSpacing::default();
+        let mut one_minute_engine = KlineSpacing::default();
+        for i in 0..3 {
+            let one_minute = kline(open_time + i * 60_000, 60, "1m");
+            // the first kline has nothing to be spaced from
+            assert_eq!(
+                five_minute_engine.matches(&one_minute, &Timeframe::Min5),
+                i == 0
+            );
+            assert!(one_minute_engine.matches(&one_minute, &Timeframe::Min1));
+        }
+
+        let mut spacing = KlineSpacing::default();
+        for (mins, matches) in [(0, true), (5, true), (15, true), (18, false), (25, false)] {
+            let five_minute = kline(open_time + mins * 60_000, 5 * 60, "5m");
+            assert_eq!(spacing.matches(&five_minute, &Timeframe::Min5), matches);
+        }
+    }
+
+    #[test]
+    fn candle_gap_skips_signal() -> Result<()> {
+        let timeframe = Timeframe::Min5;
+
+        let mut window = CandleWindow::new(CandleGapPolicy::SkipSignal);
+        assert!(window.push(candle(0, 100.0), &timeframe).is_none());
+        let (prev, curr) = window.push(candle(5, 100.0), &timeframe).unwrap();
+        assert_eq!((prev.close, curr.close), (100.0, 100.0));
+        // a crossover across a missed candle is not evaluated
+        assert!(window.push(candle(15, 200.0), &timeframe).is_none());
+        assert_eq!(window.cache.curr().map(|c| c.close), Some(200.0));
+        let (prev, curr) = window.push(candle(20, 200.0), &timeframe).unwrap();
+        assert_eq!((prev.close, curr.close), (200.0, 200.0));
+
+        assert_eq!(CandleGapPolicy::parse(" Reset")?, CandleGapPolicy::Reset);
+        assert!(CandleGapPolicy::parse("drop").is_err());
+        let mut window = CandleWindow::new(CandleGapPolicy::Reset);
+        window.push(candle(0, 100.0), &timeframe);
+        window.push(candle(5, 100.0), &timeframe);
+        assert!(window.push(candle(15, 200.0), &timeframe).is_none());
+        assert_eq!(window.cache.prev().map(|c| c.close), Some(200.0));
+        assert!(window.cache.curr().is_none());
+        let (prev, curr) = window.push(candle(20, 201.0), &timeframe).unwrap();
+        assert_eq!((prev.close, curr.close), (200.0, 201.0));
+        Ok(())
+    }
+
+    #[test]
+    fn max_move_rejects_bad_print() {
+        let timeframe = Timeframe::Min5;
+
+        let mut window = CandleWindow::new(CandleGapPolicy::SkipSignal);
+        window.max_move_pct = Some(20.0);
+        window.push(candle(0, 100.0), &timeframe);
+        // 60% jump is dropped
+        assert_eq!(window.exceeds_max_move(&candle(5, 160.0)), Some(60.0));
+        assert!(window.push(candle(5, 160.0), &timeframe).is_none());
+        assert_eq!(window.cache.last().map(|c| c.close), Some(100.0));
+        // 5% move passes and compares against the last good close
+        assert_eq!(window.exceeds_max_move(&candle(5, 105.0)), None);
+        let (prev, curr) = window.push(candle(5, 105.0), &timeframe).unwrap();
+        assert_eq!((prev.close, curr.close), (100.0, 105.0));
+
+        window.max_move_pct = None;
+        assert_eq!(window.exceeds_max_move(&candle(10, 500.0)), None);
+    }
+
+    #[test]
+    fn max_move_reanchors_after_rejections() {
+        let timeframe = Timeframe::Min5;
+
+        let mut window = CandleWindow::new(CandleGapPolicy::SkipSignal);
+        window.max_move_pct = Some(20.0);
+        window.max_rejections = 2;
+        window.push(candle(0, 100.0), &timeframe);
+        // a good candle between bad prints restarts the count
+        assert!(window.push(candle(5, 150.0), &timeframe).is_none());
+        assert!(window.push(candle(5, 101.0), &timeframe).is_some());
+        // a real move rejected twice is accepted on the third candle, without a signal across it
+        assert!(window.push(candle(10, 150.0), &timeframe).is_none());
+        assert!(window.push(candle(15, 151.0), &timeframe).is_none());
+        assert!(window.push(candle(20, 152.0), &timeframe).is_none());
+        assert_eq!(window.cache.last().map(|c| c.close), Some(152.0));
+        // later candles compare against the new level
+        let (prev, curr) = window.push(candle(25, 153.0), &timeframe).unwrap();
+        assert_eq!((prev.close, curr.close), (152.0, 153.0));
+    }
+
+    #[test]
+    fn higher_timeframe_trend() -> Result<()> {
+        // lower highs and lower lows
+        let closes = [100.0, 90.0, 95.0, 85.0, 92.0, 80.0, 88.0, 75.0, 84.0];
+        let mut filter = TrendFilter::new(Timeframe::Hour, 1, 5);
+        for (i, close) in closes.iter().enumerate() {
+            filter.update(candle(i as i64 * 60, *close))?;
+        }
+        assert_eq!(filter.trend, Some(Direction::Down));
+        assert_eq!(filter.ticker_data.get_candles().len(), 5);
+
+        // higher highs and higher lows
+        let closes = [
+            100.0, 110.0, 105.0, 115.0, 108.0, 120.0, 112.0, 125.0, 118.0,
+        ];
+        let mut filter = TrendFilter::new(Timeframe::Hour, 1, 100);
+        for (i, close) in closes.iter().enumerate() {
+            filter.update(candle(i as i64 * 60, *close))?;
+            // same trend as market structure rebuilt from every candle
+            let rebuilt = match filter.ticker_data.find_reversals(1).is_empty() {
+                true => None,
+                false => MarketStructure::new(&filter.ticker_data, 1)
+                    .trends
+                    .last()
+                    .and_then(|trend| trend.direction.clone()),
+            };
+            assert_eq!(filter.trend, rebuilt);
+        }
+        assert_eq!(filter.trend, Some(Direction::Up));
+        Ok(())
+    }
+
+    #[test]
+    fn daily_loss_limit_halts_entries() {
+        let day = |day: u32, hour: u32| {
+            Time::new(
+                2023,
+                &Month::from_num(9),
+                &Day::from_num(day),
+                Some(hour),
+                Some(0),
+            )
+        };
+        let mut session = SessionPnl::new(Some(2.0), Some(1.0));
+        assert_eq!(session.halt(day(1, 0)), None);
+
+        session.record(day(1, 2), -0.6);
+        assert_eq!(session.halt(day(1, 3)), None);
+        session.record(day(1, 4), -0.6);
+        assert!(matches!(
+            session.halt(day(1, 5)),
+            Some(DailyHalt::LossLimit(_))
+        ));
+        // exits are still recorded while halted
+        session.record(day(1, 6), 0.1);
+        assert!(matches!(
+            session.halt(day(1, 23)),
+            Some(DailyHalt::LossLimit(_))
+        ));
+
+        // halt resets at the next session
+        assert_eq!(session.halt(day(2, 0)), None);
+        session.record(day(2, 1), 2.5);
+        assert!(matches!(
+            session.halt(day(2, 2)),
+            Some(DailyHalt::ProfitTarget(_))
+        ));
+    }
+
+    #[test]
+    fn trade_notional_parsed() -> Result<()> {
+        assert_eq!(TradeNotional::parse("1000")?, TradeNotional::Quote(1000.0));
+        assert_eq!(TradeNotional::parse(" 25% ")?, TradeNotional::Percent(25.0));
+        assert!(TradeNotional::parse("all").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn stale_price_refetched() -> Result<()> {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_693_526_400);
+        let fetches = std::cell::Cell::new(0);
+        let fetch = |price: f64| {
+            fetches.set(fetches.get() + 1);
+            Ok(price)
+        };
+        let mut guard = PriceGuard::new(Duration::from_secs(5));
+
+        // nothing fetched yet
+        assert_eq!(guard.price(start, || fetch(20000.0))?, 20000.0);
+        assert_eq!(fetches.get(), 1);
+        // still fresh
+        let fresh = start + Duration::from_secs(5);
+        assert_eq!(guard.price(fresh, || fetch(20100.0))?, 20000.0);
+        assert_eq!(fetches.get(), 1);
+        // stale price is refetched before sizing
+        let stale = start + Duration::from_secs(6);
+        assert!(guard.is_stale(stale));
+        assert_eq!(guard.price(stale, || fetch(20200.0))?, 20200.0);
+        assert_eq!(fetches.get(), 2);
+        assert!(!guard.is_stale(stale));
+
+        // failed refetch does not fall back to the stale price
+        let later = stale + Duration::from_secs(10);
+        assert!(guard
+            .price(later, || Err(BinanceError::Custom("timeout".to_string())))
+            .is_err());
+        assert!(guard.is_stale(later));
+        Ok(())
+    }
+}
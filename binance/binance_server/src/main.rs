@@ -27,8 +27,8 @@ lazy_static! {
         .expect("Failed to parse env TESTNET to boolean")
     {
         true => {
-            Mutex::new(Account {
-                client: Client::new(
+            Mutex::new(Account::new(
+                Client::new(
                     Some(
                         std::env::var("BINANCE_TEST_API_KEY")
                             .expect("Failed to parse BINANCE_TEST_API_KEY from env"),
@@ -39,15 +39,15 @@ lazy_static! {
                     ),
                     BINANCE_TEST_API.to_string(),
                 ),
-                recv_window: 5000,
-                base_asset: BASE_ASSET.to_string(),
-                quote_asset: QUOTE_ASSET.to_string(),
-                ticker: TICKER.to_string(),
-            })
+                5000,
+                BASE_ASSET.to_string(),
+                QUOTE_ASSET.to_string(),
+                TICKER.to_string(),
+            ))
         }
         false => {
-            Mutex::new(Account {
-                client: Client::new(
+            Mutex::new(Account::new(
+                Client::new(
                     Some(
                         std::env::var("BINANCE_LIVE_API_KEY")
                             .expect("Failed to parse BINANCE_LIVE_API_KEY from env"),
@@ -58,11 +58,11 @@ lazy_static! {
                     ),
                     BINANCE_LIVE_API.to_string(),
                 ),
-                recv_window: 5000,
-                base_asset: BASE_ASSET.to_string(),
-                quote_asset: QUOTE_ASSET.to_string(),
-                ticker: TICKER.to_string(),
-            })
+                5000,
+                BASE_ASSET.to_string(),
+                QUOTE_ASSET.to_string(),
+                TICKER.to_string(),
+            ))
         }
     };
 }
@@ -69,6 +69,7 @@ impl Alignment {
     }
   }
   
+  #[allow(clippy::excessive_precision)]
   pub fn to_num(&self) -> f32 {
     match *self {
       Alignment::Conjunct => 0.0,
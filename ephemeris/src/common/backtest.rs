@@ -12,6 +12,7 @@ pub struct Backtest {
   pub win_rate: f64,
 }
 impl Backtest {
+  #[allow(clippy::should_implement_trait)]
   pub fn default() -> Backtest {
     Backtest {
       signals: None,
@@ -2,6 +2,8 @@
 pub enum DataType {
   RightAscension,
   Declination,
+  /// Longitude along the ecliptic as seen from the observer, in degrees
+  EclipticLongitude,
 }
 
 pub struct RightAscension {
@@ -1,5 +1,6 @@
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, Serialize, Deserialize)]
 pub enum Origin {
   Geocentric,
   Heliocentric
@@ -1,3 +1,5 @@
+use crate::DataType;
+
 
 pub struct Quantities {
   pub value: String,
@@ -6,9 +8,25 @@ pub struct Quantities {
 /// [Documentation](https://ssd.jpl.nasa.gov/horizons/manual.html#output)
 impl Quantities {
   /// Astrometric right ascension and declination
+  #[allow(clippy::should_implement_trait)]
   pub fn default() -> Self {
     Self {
       value: String::from("&QUANTITIES='1'"),
     }
   }
+
+  /// Observer ecliptic longitude and latitude
+  pub fn ecliptic() -> Self {
+    Self {
+      value: String::from("&QUANTITIES='31'"),
+    }
+  }
+
+  /// Quantities the API must return to read the data type from the response
+  pub fn for_data_type(data_type: &DataType) -> Self {
+    match data_type {
+      DataType::RightAscension | DataType::Declination => Self::default(),
+      DataType::EclipticLongitude => Self::ecliptic(),
+    }
+  }
 }
\ No newline at end of file
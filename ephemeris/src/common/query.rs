@@ -14,6 +14,8 @@ pub enum QueryError {
     StopTimeBeforeEndTime,
     ReqwestError(reqwest::Error),
     TimeError(TimeError),
+    /// Response has no angle on the requested date
+    NoDataForDate(Time),
 }
 
 pub type QueryResult<T> = Result<T, QueryError>;
@@ -24,6 +26,7 @@ impl Display for QueryError {
             QueryError::StopTimeBeforeEndTime => write!(f, "Stop time must be after start time"),
             QueryError::ReqwestError(e) => write!(f, "Reqwest error: {}", e),
             QueryError::TimeError(e) => write!(f, "Time error: {}", e),
+            QueryError::NoDataForDate(date) => write!(f, "No data for date {}", date.to_string()),
         }
     }
 }
@@ -60,7 +63,7 @@ impl Query {
             Target::new(planet),
             start_time,
             stop_time,
-            Quantities::default(),
+            Quantities::for_data_type(&data_type),
             origin,
         );
 
@@ -69,6 +72,7 @@ impl Query {
         match data_type {
             DataType::RightAscension => Ok(Self::format_for_right_ascension(data)),
             DataType::Declination => Ok(Self::format_for_declination(data)),
+            DataType::EclipticLongitude => Ok(Self::format_for_ecliptic_longitude(data)),
         }
     }
 
@@ -91,7 +95,7 @@ impl Query {
             Target::new(planet),
             start_time,
             stop_time,
-            Quantities::default(),
+            Quantities::for_data_type(&data_type),
             origin,
         );
 
@@ -103,6 +107,7 @@ impl Query {
         match data_type {
             DataType::RightAscension => Ok(Self::format_for_right_ascension(data)),
             DataType::Declination => Ok(Self::format_for_declination(data)),
+            DataType::EclipticLongitude => Ok(Self::format_for_ecliptic_longitude(data)),
         }
     }
 
@@ -202,6 +207,21 @@ impl Query {
         vec
     }
 
+    /// Format API response of into vector of (Time, ecliptic longitude as degrees)
+    pub fn format_for_ecliptic_longitude(data: String) -> Vec<(Time, f32)> {
+        let mut vec = Vec::new();
+        let lines = data.lines();
+        for line in lines {
+            let line = line.split_whitespace().collect::<Vec<&str>>();
+            let time = Time::convert_api_response(line[0]);
+            // last 2 values are ecliptic longitude and latitude,
+            // solar and lunar presence markers may sit between them and the time
+            let longitude = line[line.len() - 2].parse::<f32>().unwrap();
+            vec.push((time, longitude));
+        }
+        vec
+    }
+
     /// Finds duplicate Alignments on consecutive dates where f32 is within margin of error
     /// filters for the date with f32 closest to the actual Alignment angle
     pub fn remove_duplicate_values(
@@ -239,3 +259,32 @@ impl Query {
         clean_values
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time_series::{Day, Month};
+
+    #[test]
+    fn ecliptic_longitude_response() {
+        let response = "
+*******************************************************************************
+ Date__(UT)__HR:MN     ObsEcLon    ObsEcLat
+*******************************************************************************
+$$SOE
+ 2023-Sep-01 00:00     164.2021294   1.0910089
+ 2023-Sep-02 00:00 *m  163.6813157   0.8569440
+$$EOE
+*******************************************************************************
+"
+        .to_string();
+        let angles = Query::format_for_ecliptic_longitude(Query::extract_data(response));
+        assert_eq!(angles.len(), 2);
+        assert_eq!(
+            angles[0].0.to_unix(),
+            Time::new(2023, &Month::September, &Day::One, None, None).to_unix()
+        );
+        assert_eq!(angles[0].1, 164.202_13);
+        assert_eq!(angles[1].1, 163.681_32);
+    }
+}
@@ -4,6 +4,7 @@ pub struct StepSize {
 }
 
 impl StepSize {
+  #[allow(clippy::should_implement_trait)]
   pub fn default() -> Self {
     Self {
       value: String::from("&STEP_SIZE='1d'"),
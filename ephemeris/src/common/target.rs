@@ -1,3 +1,28 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EphemerisError {
+  /// Name does not match any planet
+  UnknownPlanet(String),
+}
+
+impl Display for EphemerisError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      EphemerisError::UnknownPlanet(name) => {
+        let valid = Planet::to_vec()
+          .iter()
+          .map(|planet| planet.to_str().to_string())
+          .collect::<Vec<String>>()
+          .join(", ");
+        write!(f, "Unknown planet \"{}\", expected one of: {}", name, valid)
+      }
+    }
+  }
+}
+
+impl std::error::Error for EphemerisError {}
 
 #[derive(Clone, Debug)]
 pub struct Target {
@@ -13,7 +38,7 @@ impl Target {
 }
 
 #[allow(dead_code)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Planet {
   Moon,
   Sun,
@@ -59,6 +84,14 @@ impl Planet {
     }
   }
 
+  /// Planet for a name as returned by `to_str`, or an error listing the valid names
+  pub fn try_from_str(name: &str) -> Result<Planet, EphemerisError> {
+    Self::to_vec()
+      .into_iter()
+      .find(|planet| planet.to_str() == name)
+      .ok_or_else(|| EphemerisError::UnknownPlanet(name.to_string()))
+  }
+
   pub fn to_vec() -> Vec<Planet> {
     vec![
       Planet::Moon,
@@ -102,20 +135,46 @@ impl PartialEq for Planet {
   }
 }
 
+impl Eq for Planet {}
+
+/// Ordered by `to_num`, from the Moon out to Pluto
+impl PartialOrd for Planet {
+  fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Planet {
+  fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    self.to_num().cmp(&other.to_num())
+  }
+}
+
 impl From<&str> for Planet {
   fn from(s: &str) -> Self {
-    match s {
-      "Moon" => Planet::Moon,
-      "Sun" => Planet::Sun,
-      "Mercury" => Planet::Mercury,
-      "Venus" => Planet::Venus,
-      "Mars" => Planet::Mars,
-      "Jupiter" => Planet::Jupiter,
-      "Saturn" => Planet::Saturn,
-      "Uranus" => Planet::Uranus,
-      "Neptune" => Planet::Neptune,
-      "Pluto" => Planet::Pluto,
-      _ => panic!("Invalid planet"),
+    Planet::try_from_str(s).expect("Invalid planet")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn try_from_str_valid_planets() {
+    for planet in Planet::to_vec() {
+      assert_eq!(Planet::try_from_str(planet.to_str()), Ok(planet.clone()));
+      assert_eq!(Planet::from(planet.to_str()), planet);
     }
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn try_from_str_unknown_planet() {
+    let err = Planet::try_from_str("Jupit").unwrap_err();
+    assert_eq!(err, EphemerisError::UnknownPlanet("Jupit".to_string()));
+    assert_eq!(
+      err.to_string(),
+      "Unknown planet \"Jupit\", expected one of: Moon, Sun, Mercury, Venus, Mars, Jupiter, Saturn, Uranus, Neptune, Pluto"
+    );
+  }
+}
@@ -1,5 +1,5 @@
 use crate::*;
-use log::debug;
+use log::{debug, warn};
 use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
@@ -8,12 +8,14 @@ use time_series::{Time, TimeError};
 #[derive(Debug)]
 pub enum EclipseError {
     TimeError(TimeError),
+    Csv(csv::Error),
 }
 
 impl std::fmt::Display for EclipseError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             EclipseError::TimeError(e) => write!(f, "TimeError: {}", e),
+            EclipseError::Csv(e) => write!(f, "CSV error: {}", e),
         }
     }
 }
@@ -26,38 +28,68 @@ pub struct Eclipses {
 }
 
 impl Eclipses {
-    pub fn new(solar_eclipse_csv: &PathBuf, lunar_eclipse_csv: &PathBuf) -> Self {
-        //
-        // read Solar Eclipse CSV and load into Vec<EclipseEvent>
-        let solar_buffer = File::open(solar_eclipse_csv).unwrap();
-        let mut solar = csv::Reader::from_reader(solar_buffer);
-        let mut solar_events = Vec::<EclipseEvent>::new();
-        for record in solar.records().flatten() {
-            let date_unformatted = &record[1];
-            let date = Time::from_eclipse_date_format(date_unformatted);
-            let eclipse_type = &record[6];
-            let eclipse_phase = EclipseType::from_symbol(eclipse_type, EclipseClass::Solar);
-            solar_events.push(EclipseEvent::new(date, eclipse_phase));
-        }
-        //
-        // read Lunar Eclipse CSV and load into Vec<EclipseEvent>
-        let lunar_buffer = File::open(lunar_eclipse_csv).unwrap();
-        let mut lunar = csv::Reader::from_reader(lunar_buffer);
-        let mut lunar_events = Vec::<EclipseEvent>::new();
-        for record in lunar.records().flatten() {
-            let date_unformatted = &record[1];
-            let date = Time::from_eclipse_date_format(date_unformatted);
-            let eclipse_type = &record[6];
-            let eclipse_phase = EclipseType::from_symbol(eclipse_type, EclipseClass::Lunar);
-            lunar_events.push(EclipseEvent::new(date, eclipse_phase));
-        }
+    /// Load the solar and lunar eclipse catalogs, sorted by date.
+    /// Malformed rows are skipped with a warning.
+    pub fn new(solar_eclipse_csv: &PathBuf, lunar_eclipse_csv: &PathBuf) -> EclipseResult<Self> {
+        // solar catalog has the eclipse magnitude in column 8, lunar the umbral magnitude in column 10
+        let solar_events = Self::read_csv(solar_eclipse_csv, EclipseClass::Solar, 8)?;
+        let lunar_events = Self::read_csv(lunar_eclipse_csv, EclipseClass::Lunar, 10)?;
         //
         // concatenate solar and lunar events into one Vec<(Time, EclipseType)>
         let mut events = [solar_events, lunar_events].concat();
         // sort events by Time
         events.sort_by(|eclipse_1, eclipse_2| eclipse_1.date.partial_cmp(&eclipse_2.date).unwrap());
 
-        Self { events }
+        Ok(Self { events })
+    }
+
+    fn read_csv(
+        path: &PathBuf,
+        class: EclipseClass,
+        magnitude_column: usize,
+    ) -> EclipseResult<Vec<EclipseEvent>> {
+        let mut reader = csv::Reader::from_path(path).map_err(EclipseError::Csv)?;
+        let mut events = Vec::<EclipseEvent>::new();
+        for (index, record) in reader.records().enumerate() {
+            let event = record
+                .ok()
+                .and_then(|record| Self::parse_record(&record, class.clone(), magnitude_column));
+            match event {
+                Some(event) => events.push(event),
+                // header is line 1
+                None => warn!(
+                    "Skip malformed {:?} eclipse on line {} of {:?}",
+                    class,
+                    index + 2,
+                    path
+                ),
+            }
+        }
+        Ok(events)
+    }
+
+    fn parse_record(
+        record: &csv::StringRecord,
+        class: EclipseClass,
+        magnitude_column: usize,
+    ) -> Option<EclipseEvent> {
+        let date = Time::try_from_eclipse_date_format(record.get(1)?).ok()?;
+        let kind = EclipseType::try_from_symbol(record.get(6)?, class)?;
+        let magnitude = record.get(magnitude_column)?.parse::<f64>().ok()?;
+        Some(EclipseEvent::new(date, kind, magnitude))
+    }
+
+    /// First eclipse strictly after `after`
+    pub fn next_eclipse(&self, after: &Time) -> Option<&EclipseEvent> {
+        let index = self.events.partition_point(|event| &event.date <= after);
+        self.events.get(index)
+    }
+
+    /// Eclipses from `start` through `end`, inclusive
+    pub fn eclipses_between(&self, start: &Time, end: &Time) -> &[EclipseEvent] {
+        let first = self.events.partition_point(|event| &event.date < start);
+        let last = self.events.partition_point(|event| &event.date <= end);
+        &self.events[first..last.max(first)]
     }
 
     pub fn print(&self, file: &PathBuf, start_date: &Time, end_date: &Time) {
@@ -148,8 +180,8 @@ impl Eclipses {
         start_time: Time,
         end_time: Time,
         error_margin_degrees: f32,
-        planets: &Vec<Planet>,
-        harmonics: &Vec<Alignment>,
+        planets: &[Planet],
+        harmonics: &[Alignment],
     ) -> Vec<PlanetPairAlignmentOnEclipse> {
         let planet_matrix = PlanetMatrix::new(
             Origin::Geocentric,
@@ -192,7 +224,7 @@ impl Eclipses {
         start_date: Time,
         end_date: Time,
         error_margin_days: i64,
-        planets: &Vec<Planet>,
+        planets: &[Planet],
     ) -> Vec<PlanetRetrogradeOnEclipse> {
         let retrograde = Retrograde::new(start_date, end_date, planets)
             .await
@@ -363,3 +395,82 @@ impl Eclipses {
         Ok(signals)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time_series::{Day, Month};
+
+    const SOLAR_CSV: &str = "\
+Catalog Number,Calendar Date,Eclipse Time,Delta T (s),Lunation Number,Saros Number,Eclipse Type,Gamma,Eclipse Magnitude
+09710,2024 April 8,18:18:29,74,295,139,T,0.3431,1.0566
+09711,2024 October 2,18:46:13,74,301,144,A,-0.3509,0.9326
+09712,2025 March 29,10:48:36,74,307,149,Q,1.0405,0.9376
+";
+
+    const LUNAR_CSV: &str = "\
+Catalog Number,Calendar Date,Eclipse Time,Delta T (s),Lunation Number,Saros Number,Eclipse Type,Quincena Solar Eclipse,Gamma,Penumbral Magnitude,Umbral Magnitude
+12345,2024 March 25,07:13:61,74,294,113,N,-t,1.0610,0.9577,-0.1304
+12346,2024 Septembr 18,02:45:25,74,300,118,P,-a,-0.9792,1.0366,0.0848
+12347,2025 March 14,06:59:56,74,306,123,T,t-,0.3485,2.2595,1.1784
+";
+
+    /// Eclipses loaded from catalogs written to temp files named for the `test`,
+    /// so tests running at the same time do not overwrite each other's files
+    fn eclipses(test: &str) -> EclipseResult<Eclipses> {
+        let dir = std::env::temp_dir();
+        let solar = dir.join(format!("eclipses_{}_solar.csv", test));
+        let lunar = dir.join(format!("eclipses_{}_lunar.csv", test));
+        std::fs::write(&solar, SOLAR_CSV).unwrap();
+        std::fs::write(&lunar, LUNAR_CSV).unwrap();
+        Eclipses::new(&solar, &lunar)
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> Time {
+        Time::new(
+            year,
+            &Month::from_num(month),
+            &Day::from_num(day),
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn load_skips_malformed_rows() -> EclipseResult<()> {
+        let eclipses = eclipses("load_skips_malformed_rows")?;
+        // unknown solar symbol and misspelled lunar month are skipped
+        let dates = eclipses
+            .events
+            .iter()
+            .map(|event| event.date.to_string_daily())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            dates,
+            vec!["2024-03-25", "2024-04-08", "2024-10-02", "2025-03-14"]
+        );
+        assert!(matches!(
+            eclipses.events[0].kind,
+            EclipseType::PenumbralLunar
+        ));
+        assert_eq!(eclipses.events[0].magnitude, -0.1304);
+        assert!(matches!(eclipses.events[1].kind, EclipseType::TotalSolar));
+        assert_eq!(eclipses.events[1].magnitude, 1.0566);
+        Ok(())
+    }
+
+    #[test]
+    fn next_eclipse_and_range() -> EclipseResult<()> {
+        let eclipses = eclipses("next_eclipse_and_range")?;
+        let next = eclipses.next_eclipse(&date(2024, 4, 8)).unwrap();
+        assert_eq!(next.date.to_string_daily(), "2024-10-02");
+        assert!(eclipses.next_eclipse(&date(2025, 3, 14)).is_none());
+
+        let between = eclipses.eclipses_between(&date(2024, 4, 8), &date(2025, 3, 14));
+        assert_eq!(between.len(), 3);
+        assert!(eclipses
+            .eclipses_between(&date(2024, 5, 1), &date(2024, 9, 30))
+            .is_empty());
+        Ok(())
+    }
+}
@@ -122,42 +122,51 @@ impl EclipseType {
   /// H = hybrid eclipse: Earth traverses Moon's umbra and antumbra, so annular and total eclipses are visible in different locations on Earth.
   ///
   pub fn from_symbol(symbol: &str, kind: EclipseClass) -> Self {
+    match (Self::try_from_symbol(symbol, kind.clone()), kind) {
+      (Some(eclipse_type), _) => eclipse_type,
+      (None, EclipseClass::Lunar) => panic!("Invalid lunar eclipse symbol"),
+      (None, EclipseClass::Solar) => panic!("Invalid solar eclipse symbol"),
+    }
+  }
+
+  /// Same as `from_symbol`, but None for an unknown symbol
+  pub fn try_from_symbol(symbol: &str, kind: EclipseClass) -> Option<Self> {
     match kind {
       EclipseClass::Lunar => {
         match symbol {
-          "N" => EclipseType::PenumbralLunar,
-          "Nx" => EclipseType::PenumbralLunar,
-          "Ne" => EclipseType::PenumbralLunar,
-          "Nb" => EclipseType::PenumbralLunar,
-          "P" => EclipseType::PartialLunar,
-          "T" => EclipseType::TotalLunar,
-          "T+" => EclipseType::TotalLunar,
-          "T-" => EclipseType::TotalLunar,
-          _ => panic!("Invalid lunar eclipse symbol"),
+          "N" => Some(EclipseType::PenumbralLunar),
+          "Nx" => Some(EclipseType::PenumbralLunar),
+          "Ne" => Some(EclipseType::PenumbralLunar),
+          "Nb" => Some(EclipseType::PenumbralLunar),
+          "P" => Some(EclipseType::PartialLunar),
+          "T" => Some(EclipseType::TotalLunar),
+          "T+" => Some(EclipseType::TotalLunar),
+          "T-" => Some(EclipseType::TotalLunar),
+          _ => None,
         }
       },
       EclipseClass::Solar => {
         match symbol {
-          "P" => EclipseType::PartialSolar,
-          "Pe" => EclipseType::PartialSolar,
-          "Pb" => EclipseType::PartialSolar,
-          "A" => EclipseType::AnnularSolar,
-          "A+" => EclipseType::AnnularSolar,
-          "A-" => EclipseType::AnnularSolar,
-          "Am" => EclipseType::AnnularSolar,
-          "An" => EclipseType::AnnularSolar,
-          "As" => EclipseType::AnnularSolar,
-          "T" => EclipseType::TotalSolar,
-          "T+" => EclipseType::TotalSolar,
-          "T-" => EclipseType::TotalSolar,
-          "Tm" => EclipseType::TotalSolar,
-          "Tn" => EclipseType::TotalSolar,
-          "Ts" => EclipseType::TotalSolar,
-          "H" => EclipseType::TotalSolar,
-          "Hm" => EclipseType::TotalSolar,
-          "H2" => EclipseType::TotalSolar,
-          "H3" => EclipseType::TotalSolar,
-          _ => panic!("Invalid solar eclipse symbol"),
+          "P" => Some(EclipseType::PartialSolar),
+          "Pe" => Some(EclipseType::PartialSolar),
+          "Pb" => Some(EclipseType::PartialSolar),
+          "A" => Some(EclipseType::AnnularSolar),
+          "A+" => Some(EclipseType::AnnularSolar),
+          "A-" => Some(EclipseType::AnnularSolar),
+          "Am" => Some(EclipseType::AnnularSolar),
+          "An" => Some(EclipseType::AnnularSolar),
+          "As" => Some(EclipseType::AnnularSolar),
+          "T" => Some(EclipseType::TotalSolar),
+          "T+" => Some(EclipseType::TotalSolar),
+          "T-" => Some(EclipseType::TotalSolar),
+          "Tm" => Some(EclipseType::TotalSolar),
+          "Tn" => Some(EclipseType::TotalSolar),
+          "Ts" => Some(EclipseType::TotalSolar),
+          "H" => Some(EclipseType::TotalSolar),
+          "Hm" => Some(EclipseType::TotalSolar),
+          "H2" => Some(EclipseType::TotalSolar),
+          "H3" => Some(EclipseType::TotalSolar),
+          _ => None,
         }
       },
     }
@@ -188,13 +197,16 @@ impl EclipseType {
 pub struct EclipseEvent {
   pub date: Time,
   pub kind: EclipseType,
+  /// Eclipse magnitude for solar eclipses, umbral magnitude for lunar eclipses
+  pub magnitude: f64,
 }
 
 impl EclipseEvent {
-  pub fn new(date: Time, kind: EclipseType) -> Self {
+  pub fn new(date: Time, kind: EclipseType, magnitude: f64) -> Self {
     Self {
       date,
       kind,
+      magnitude,
     }
   }
 }
@@ -3,12 +3,14 @@ pub mod retrograde;
 pub mod declination;
 pub mod eclipses;
 pub mod plpl;
+pub mod plpl_backtest;
 
 pub use planet_matrix::*;
 pub use retrograde::*;
 pub use declination::*;
 pub use eclipses::*;
 pub use plpl::*;
+pub use plpl_backtest::*;
 
 use std::path::PathBuf;
 use csv::WriterBuilder;
@@ -1,4 +1,5 @@
 use crate::*;
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
@@ -402,3 +403,99 @@ impl PlanetMatrix {
         }
     }
 }
+
+/// Ecliptic longitude of every planet on `date` from `origin`, in one call.
+/// The Horizons API takes one target per request, so every planet is queried at once
+/// rather than one after another. The Sun is left out from a heliocentric origin.
+pub fn all_longitudes(date: Time, origin: Origin) -> QueryResult<BTreeMap<Planet, f64>> {
+    all_longitudes_with(date, origin, |planet| {
+        Query::sync_query(
+            origin,
+            planet,
+            DataType::EclipticLongitude,
+            date,
+            date.delta_date(1),
+        )
+    })
+}
+
+/// Same as [`all_longitudes`] with each planet's angles from `query`, run for every planet at once,
+/// e.g. angles already queried for a date range.
+pub fn all_longitudes_with(
+    date: Time,
+    origin: Origin,
+    query: impl Fn(&Planet) -> QueryResult<Vec<(Time, f32)>> + Sync,
+) -> QueryResult<BTreeMap<Planet, f64>> {
+    let planets = Planet::to_vec()
+        .into_iter()
+        .filter(|planet| !matches!((planet, &origin), (Planet::Sun, Origin::Heliocentric)))
+        .collect::<Vec<Planet>>();
+    let query = &query;
+    let angles = std::thread::scope(|scope| {
+        planets
+            .iter()
+            .map(|planet| scope.spawn(move || query(planet)))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().expect("Planet longitude query panicked"))
+            .collect::<Vec<_>>()
+    });
+    let mut longitudes = BTreeMap::<Planet, f64>::new();
+    for (planet, angles) in planets.into_iter().zip(angles) {
+        let angle = angles?
+            .into_iter()
+            .find(|(time, _)| *time == date)
+            .map(|(_, angle)| angle as f64)
+            .ok_or(QueryError::NoDataForDate(date))?;
+        longitudes.insert(planet, angle);
+    }
+    Ok(longitudes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Daily angles of a planet starting the day before `date`
+    fn angles(planet: &Planet, date: Time) -> Vec<(Time, f32)> {
+        (-1..=1)
+            .map(|i| (date.delta_date(i), planet.to_num() as f32 * 30.0 + i as f32))
+            .collect()
+    }
+
+    #[test]
+    fn longitudes_of_every_planet() -> QueryResult<()> {
+        let date = Time::new(2023, &Month::from_num(9), &Day::from_num(1), None, None);
+        let queries = AtomicUsize::new(0);
+        let geocentric = all_longitudes_with(date, Origin::Geocentric, |planet| {
+            queries.fetch_add(1, Ordering::SeqCst);
+            Ok(angles(planet, date))
+        })?;
+        assert_eq!(queries.into_inner(), Planet::to_vec().len());
+        assert_eq!(
+            geocentric.keys().cloned().collect::<Vec<Planet>>(),
+            Planet::to_vec()
+        );
+        for (planet, longitude) in geocentric.iter() {
+            let individual = angles(planet, date)
+                .into_iter()
+                .find(|(time, _)| *time == date)
+                .map(|(_, angle)| angle as f64);
+            assert_eq!(Some(*longitude), individual);
+        }
+
+        let heliocentric = all_longitudes_with(date, Origin::Heliocentric, |planet| {
+            Ok(angles(planet, date))
+        })?;
+        assert_eq!(heliocentric.len(), Planet::to_vec().len() - 1);
+        assert!(!heliocentric.contains_key(&Planet::Sun));
+
+        // response without the date
+        let missing = all_longitudes_with(date, Origin::Geocentric, |planet| {
+            Ok(angles(planet, date.delta_date(5)))
+        });
+        assert!(matches!(missing, Err(QueryError::NoDataForDate(_))));
+        Ok(())
+    }
+}
@@ -1,7 +1,12 @@
 use crate::*;
 use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::fmt::Display;
-use time_series::{Candle, TickerDataError, Time};
+use std::fs::File;
+use std::path::Path;
+use time_series::{precise_round, BacktestError, Candle, Direction, Order, TickerDataError, Time};
 
 #[derive(Debug)]
 pub enum PLPLError {
@@ -10,6 +15,12 @@ pub enum PLPLError {
     NoPLPLClosest,
     TickerDataError(TickerDataError),
     QueryError(QueryError),
+    BacktestError(BacktestError),
+    InvalidConfig(PLPLConfigError),
+    ConfigIo(std::io::Error),
+    ConfigJson(serde_json::Error),
+    /// Multi-planet PLPL grid needs at least one planet
+    NoPlanets,
 }
 
 impl Display for PLPLError {
@@ -20,13 +31,140 @@ impl Display for PLPLError {
             PLPLError::NoPLPLClosest => write!(f, "No PLPL closest to date"),
             PLPLError::TickerDataError(e) => write!(f, "TickerDataError: {}", e),
             PLPLError::QueryError(e) => write!(f, "QueryError: {}", e),
+            PLPLError::BacktestError(e) => write!(f, "BacktestError: {}", e),
+            PLPLError::InvalidConfig(e) => write!(f, "InvalidConfig: {}", e),
+            PLPLError::ConfigIo(e) => write!(f, "ConfigIo: {}", e),
+            PLPLError::ConfigJson(e) => write!(f, "ConfigJson: {}", e),
+            PLPLError::NoPlanets => write!(f, "No planets for PLPL grid"),
         }
     }
 }
 
+impl From<TickerDataError> for PLPLError {
+    fn from(e: TickerDataError) -> Self {
+        PLPLError::TickerDataError(e)
+    }
+}
+
 pub type PLPLResult<T> = Result<T, PLPLError>;
 
-#[derive(Debug, Clone)]
+/// Field of a [`PLPLSystemConfig`] that cannot build a PLPL system.
+#[derive(Debug)]
+pub enum PLPLConfigError {
+    NonPositiveScale(f32),
+    NonPositivePrice(f32),
+    NoPLPLs,
+    /// First date is not before the last date
+    InvalidDateRange {
+        first_date: Time,
+        last_date: Time,
+    },
+    /// Planet has no angle as seen from the origin, e.g. the Sun from the Sun
+    UnsupportedTarget {
+        planet: Planet,
+        origin: Origin,
+    },
+    /// Price range low is not a positive price below the high
+    InvalidPriceRange(f64, f64),
+}
+
+impl Display for PLPLConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PLPLConfigError::NonPositiveScale(scale) => {
+                write!(f, "PLPL scale must be positive, got {}", scale)
+            }
+            PLPLConfigError::NonPositivePrice(price) => {
+                write!(f, "PLPL price must be positive, got {}", price)
+            }
+            PLPLConfigError::NoPLPLs => write!(f, "Number of PLPLs must be positive"),
+            PLPLConfigError::InvalidDateRange {
+                first_date,
+                last_date,
+            } => write!(
+                f,
+                "First date {} must be before last date {}",
+                first_date.to_string(),
+                last_date.to_string()
+            ),
+            PLPLConfigError::UnsupportedTarget { planet, origin } => {
+                write!(
+                    f,
+                    "{} is not supported from {:?} origin",
+                    planet.to_str(),
+                    origin
+                )
+            }
+            PLPLConfigError::InvalidPriceRange(low, high) => write!(
+                f,
+                "Price range low {} must be positive and below high {}",
+                low, high
+            ),
+        }
+    }
+}
+
+/// Why a candle evaluated against the PLPL system did not produce a trade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoTradeReason {
+    /// Price did not cross a PLPL
+    NoCrossover,
+    /// Price crossed a PLPL but did not close far enough beyond it
+    BelowHysteresis,
+    /// Signal is against the trend of the higher timeframe
+    AgainstTrend,
+    /// Too few candles since the last entry
+    InCooldown,
+    /// Already in a position on the side of the signal
+    PositionAligned,
+    OutsideTradingHours,
+    InsufficientFunds,
+    SpreadTooWide,
+}
+
+impl Display for NoTradeReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NoTradeReason::NoCrossover => write!(f, "No PLPL crossover"),
+            NoTradeReason::BelowHysteresis => write!(f, "Crossover below hysteresis"),
+            NoTradeReason::AgainstTrend => write!(f, "Against higher timeframe trend"),
+            NoTradeReason::InCooldown => write!(f, "In cooldown"),
+            NoTradeReason::PositionAligned => write!(f, "Position already aligned with signal"),
+            NoTradeReason::OutsideTradingHours => write!(f, "Outside trading hours"),
+            NoTradeReason::InsufficientFunds => write!(f, "Insufficient funds"),
+            NoTradeReason::SpreadTooWide => write!(f, "Spread too wide"),
+        }
+    }
+}
+
+/// Outcome of evaluating a candle against the PLPL system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PLPLSignal {
+    Enter(Order),
+    NoTrade(NoTradeReason),
+}
+
+/// Conditions besides the PLPL crossover that can hold back a signal.
+/// The default holds back nothing.
+#[derive(Debug, Clone, Default)]
+pub struct SignalConditions {
+    /// Side of the open position, if any
+    pub position: Option<Order>,
+    /// Distance the close must reach beyond the PLPL in the direction of the signal
+    pub hysteresis: Option<f64>,
+    /// Trend of a higher timeframe the signal must agree with, if known
+    pub trend: Option<Direction>,
+    /// Candles remaining before another entry is allowed
+    pub cooldown_candles: u32,
+    /// UTC hours within which entries are allowed, as `(start, end)` with `end` exclusive
+    pub trading_hours: Option<(u32, u32)>,
+    /// Funds available to enter, and the minimum required
+    pub funds: Option<(f64, f64)>,
+    /// Bid/ask spread as a percent of price, and the maximum allowed
+    pub spread_pct: Option<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PLPLSystemConfig {
     pub planet: Planet,
     pub origin: Origin,
@@ -36,20 +174,117 @@ pub struct PLPLSystemConfig {
     pub plpl_price: f32,
     pub num_plpls: u32,
     pub cross_margin_pct: f32,
+    /// Low and high price the PLPLs must cover, which replaces `num_plpls` if set
+    #[serde(default)]
+    pub price_range: Option<(f64, f64)>,
+}
+
+impl PLPLSystemConfig {
+    /// Number of PLPLs to generate, enough to cover `price_range` if set.
+    /// Every close within the range has a PLPL above and below it for any planet angle.
+    pub fn resolved_num_plpls(&self) -> u32 {
+        let (low, high) = match self.price_range {
+            None => return self.num_plpls,
+            Some(range) => range,
+        };
+        let spacing = 360.0 * self.plpl_scale as f64;
+        let base = (self.plpl_price as f64 / spacing).round() * spacing;
+        // highest PLPL is (num_plpls / 2 - 2) spacings above the base + angle
+        let above = ((high - base) / spacing).ceil() + 3.0;
+        // lowest PLPL is (num_plpls / 2) spacings below the base + angle, and angles reach 360
+        let below = ((base + 360.0 - low) / spacing).ceil() + 1.0;
+        let half = above.max(below).max(2.0) as u32;
+        half * 2
+    }
+
+    /// Save the config as JSON to reproduce the PLPL system later.
+    pub fn to_json_file<P: AsRef<Path>>(&self, path: P) -> PLPLResult<()> {
+        let file = File::create(path).map_err(PLPLError::ConfigIo)?;
+        serde_json::to_writer_pretty(file, self).map_err(PLPLError::ConfigJson)
+    }
+
+    pub fn from_json_file<P: AsRef<Path>>(path: P) -> PLPLResult<Self> {
+        let file = File::open(path).map_err(PLPLError::ConfigIo)?;
+        serde_json::from_reader(file).map_err(PLPLError::ConfigJson)
+    }
+
+    /// Check every field before querying planet angles for the system.
+    pub fn validate(&self) -> Result<(), PLPLConfigError> {
+        if self.plpl_scale.is_nan() || self.plpl_scale <= 0.0 {
+            return Err(PLPLConfigError::NonPositiveScale(self.plpl_scale));
+        }
+        if self.plpl_price.is_nan() || self.plpl_price <= 0.0 {
+            return Err(PLPLConfigError::NonPositivePrice(self.plpl_price));
+        }
+        match self.price_range {
+            Some((low, high)) if !(low > 0.0 && low < high && high.is_finite()) => {
+                return Err(PLPLConfigError::InvalidPriceRange(low, high));
+            }
+            None if self.num_plpls == 0 => return Err(PLPLConfigError::NoPLPLs),
+            _ => (),
+        }
+        if self.first_date >= self.last_date {
+            return Err(PLPLConfigError::InvalidDateRange {
+                first_date: self.first_date,
+                last_date: self.last_date,
+            });
+        }
+        if let (Planet::Sun, Origin::Heliocentric) = (&self.planet, &self.origin) {
+            return Err(PLPLConfigError::UnsupportedTarget {
+                planet: self.planet.clone(),
+                origin: self.origin,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Tick size of quote assets like USDT
+pub const DEFAULT_TICK_SIZE: f64 = 0.01;
+
+/// Whether a close exactly on the PLPL counts as being on either side of it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CrossBoundary {
+    /// A previous close on the PLPL can cross it, so a touch of the line is a cross
+    #[default]
+    Inclusive,
+    /// The previous and current closes must be strictly on opposite sides of the PLPL,
+    /// so neither a close on the line nor one within the margin short of it is a cross
+    Exclusive,
 }
 
 #[derive(Debug, Clone)]
 pub struct PLPLSystem {
     pub planet: Planet,
+    /// Every planet projected onto the PLPL grid, `planet` is the first
+    pub planets: Vec<Planet>,
     pub origin: Origin,
     pub first_date: Time,
     pub last_date: Time,
     pub planet_angles: Vec<(Time, f32)>,
-    pub plpls: Vec<PLPL>,
+    /// PLPLs of each date, private so every change goes through [`PLPLSystem::set_plpls`]
+    /// and drops cached levels
+    plpls: Vec<PLPL>,
     pub scale: f32,
     pub price: f32,
     pub cross_margin_pct: f32,
     pub num_plpls: u32,
+    /// Price increment of the traded symbol, used to round PLPLs for display
+    pub tick_size: f64,
+    pub boundary: CrossBoundary,
+    cache: RefCell<PLPLCache>,
+}
+
+/// Most days of PLPL levels cached at once, the earliest day is dropped to make room
+pub const MAX_CACHED_DAYS: usize = 32;
+
+/// PLPL levels already looked up for a day, so candles on the same day reuse them
+#[derive(Debug, Clone, Default)]
+struct PLPLCache {
+    /// Sorted PLPL levels keyed by the unix timestamp of midnight UTC of the day
+    levels: BTreeMap<i64, Vec<f64>>,
+    /// Lookups that were not cached
+    misses: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -60,34 +295,163 @@ pub struct PLPL {
 
 impl PLPLSystem {
     pub fn new(config: PLPLSystemConfig) -> PLPLResult<Self> {
-        if config.num_plpls % 2 != 0 {
+        config.validate().map_err(PLPLError::InvalidConfig)?;
+        if config.resolved_num_plpls() % 2 != 0 {
+            return Err(PLPLError::NumPLPLsNotEven);
+        }
+        let planet_angles = Self::helio(&config)?;
+        Self::from_planet_angles(config, planet_angles)
+    }
+
+    /// Build the PLPL system from a config saved by [`PLPLSystemConfig::to_json_file`].
+    pub fn from_config_json<P: AsRef<Path>>(path: P) -> PLPLResult<Self> {
+        Self::new(PLPLSystemConfig::from_json_file(path)?)
+    }
+
+    /// Build the PLPL system from planet angles that were already queried,
+    /// which avoids another Horizons API request for the same planet and date range.
+    pub fn from_planet_angles(
+        config: PLPLSystemConfig,
+        planet_angles: Vec<(Time, f32)>,
+    ) -> PLPLResult<Self> {
+        let num_plpls = config.resolved_num_plpls();
+        if num_plpls % 2 != 0 {
             return Err(PLPLError::NumPLPLsNotEven);
         }
         let mut me = Self {
+            planets: vec![config.planet.clone()],
             planet: config.planet,
             origin: config.origin,
             first_date: config.first_date,
             last_date: config.last_date,
-            planet_angles: vec![],
+            planet_angles,
             plpls: vec![],
             scale: config.plpl_scale,
             price: config.plpl_price,
             cross_margin_pct: config.cross_margin_pct,
-            num_plpls: config.num_plpls,
+            num_plpls,
+            tick_size: DEFAULT_TICK_SIZE,
+            boundary: CrossBoundary::default(),
+            cache: RefCell::new(PLPLCache::default()),
         };
-        me.planet_angles = me.helio()?;
         me.plpls = me.plpls()?;
         Ok(me)
     }
 
-    fn helio(&self) -> PLPLResult<Vec<(Time, f32)>> {
+    /// Build one PLPL grid from several planets, e.g. Jupiter and Saturn.
+    /// The config's `planet` is ignored in favor of `planets`.
+    pub fn new_multi(config: PLPLSystemConfig, planets: Vec<Planet>) -> PLPLResult<Self> {
+        let mut planet_angles = Vec::new();
+        for planet in planets {
+            let config = PLPLSystemConfig {
+                planet: planet.clone(),
+                ..config.clone()
+            };
+            config.validate().map_err(PLPLError::InvalidConfig)?;
+            let angles = Self::helio(&config)?;
+            planet_angles.push((planet, angles));
+        }
+        Self::from_multi_planet_angles(config, planet_angles)
+    }
+
+    /// Build one PLPL grid from the already queried angles of each planet.
+    /// Levels of every planet on a date are merged into one sorted grid,
+    /// and levels within `plpl_scale` of a lower level are dropped.
+    /// Dates missing an angle for any planet have no PLPLs.
+    pub fn from_multi_planet_angles(
+        config: PLPLSystemConfig,
+        planet_angles: Vec<(Planet, Vec<(Time, f32)>)>,
+    ) -> PLPLResult<Self> {
+        let mut systems = Vec::new();
+        for (planet, angles) in planet_angles {
+            let config = PLPLSystemConfig {
+                planet,
+                ..config.clone()
+            };
+            systems.push(Self::from_planet_angles(config, angles)?);
+        }
+        let mut others = systems.split_off(1.min(systems.len()));
+        let mut me = systems.pop().ok_or(PLPLError::NoPlanets)?;
+
+        let mut plpls = Vec::new();
+        for plpl in me.plpls.iter() {
+            let mut levels = plpl.plpls.clone();
+            let mut all_planets = true;
+            for other in others.iter() {
+                match other.plpls_for_date(plpl.date) {
+                    Ok(other_levels) => levels.extend(other_levels),
+                    Err(_) => all_planets = false,
+                }
+            }
+            if all_planets {
+                plpls.push(PLPL {
+                    date: plpl.date,
+                    plpls: Self::merge_levels(levels, me.scale),
+                });
+            }
+        }
+        me.set_plpls(plpls);
+        me.planets
+            .extend(others.drain(..).map(|other| other.planet));
+        Ok(me)
+    }
+
+    /// Sort PLPL levels and drop each level within `tolerance` of the last level kept
+    pub fn merge_levels(mut levels: Vec<f32>, tolerance: f32) -> Vec<f32> {
+        levels.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let mut merged: Vec<f32> = Vec::with_capacity(levels.len());
+        for level in levels {
+            match merged.last() {
+                Some(last) if level - last <= tolerance => (),
+                _ => merged.push(level),
+            }
+        }
+        merged
+    }
+
+    /// Set the price increment PLPLs are rounded to for display
+    pub fn with_tick_size(mut self, tick_size: f64) -> Self {
+        self.tick_size = tick_size;
+        self
+    }
+
+    /// Set whether a previous close on the PLPL can cross it
+    pub fn with_boundary(mut self, boundary: CrossBoundary) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    /// Query planet angles for the config's date range, padded by a day on either side.
+    /// Right ascension from the Sun for a heliocentric origin,
+    /// ecliptic longitude from the Earth for a geocentric origin.
+    pub fn helio(config: &PLPLSystemConfig) -> PLPLResult<Vec<(Time, f32)>> {
         debug!("Querying ephemeris from Horizons API");
-        let start_date = self.first_date.delta_date(-1);
-        let end_date = self.last_date.delta_date(1);
-        Query::sync_query(
-            self.origin,
-            &self.planet,
-            DataType::RightAscension,
+        Self::planet_angles_with(config, Query::sync_query)
+    }
+
+    /// Planet angle the PLPLs are derived from for an origin
+    pub fn angle_data_type(origin: &Origin) -> DataType {
+        match origin {
+            Origin::Heliocentric => DataType::RightAscension,
+            Origin::Geocentric => DataType::EclipticLongitude,
+        }
+    }
+
+    /// Query planet angles for the config's date range with `query`,
+    /// which takes the same arguments as [`Query::sync_query`].
+    pub fn planet_angles_with<F>(
+        config: &PLPLSystemConfig,
+        query: F,
+    ) -> PLPLResult<Vec<(Time, f32)>>
+    where
+        F: FnOnce(Origin, &Planet, DataType, Time, Time) -> QueryResult<Vec<(Time, f32)>>,
+    {
+        let start_date = config.first_date.delta_date(-1);
+        let end_date = config.last_date.delta_date(1);
+        query(
+            config.origin,
+            &config.planet,
+            Self::angle_data_type(&config.origin),
             start_date,
             end_date,
         )
@@ -140,28 +504,79 @@ impl PLPLSystem {
         Ok(plpls)
     }
 
-    /// Find the closest PLPL to price on this date
+    /// Every PLPL on this date in ascending order, e.g. to chart the grid.
+    /// [`PLPLSystem::closest_plpl`] picks from these levels.
+    /// Levels are cached by day.
+    pub fn plpl_levels(&self, date: &Time) -> PLPLResult<Vec<f64>> {
+        self.with_plpl_levels(date, |levels| levels.to_vec())
+    }
+
+    /// Run `f` on the cached ascending PLPL levels of this date, looking them up on a miss
+    fn with_plpl_levels<R>(&self, date: &Time, f: impl FnOnce(&[f64]) -> R) -> PLPLResult<R> {
+        let day = Time::new(date.year, &date.month, &date.day, None, None).to_unix();
+        if let Some(levels) = self.cache.borrow().levels.get(&day) {
+            return Ok(f(levels));
+        }
+        let mut levels = self
+            .plpls_for_date(*date)?
+            .into_iter()
+            .map(|plpl| plpl as f64)
+            .collect::<Vec<f64>>();
+        levels.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let res = f(&levels);
+        let mut cache = self.cache.borrow_mut();
+        cache.misses += 1;
+        if cache.levels.len() >= MAX_CACHED_DAYS {
+            cache.levels.pop_first();
+        }
+        cache.levels.insert(day, levels);
+        Ok(res)
+    }
+
+    /// Drop cached PLPL levels, e.g. in a long running process once past days are no longer needed.
+    pub fn clear_cache(&mut self) {
+        self.cache.get_mut().levels.clear();
+    }
+
+    /// Number of days of PLPL levels cached, at most [`MAX_CACHED_DAYS`]
+    pub fn cached_days(&self) -> usize {
+        self.cache.borrow().levels.len()
+    }
+
+    /// PLPLs of each date
+    pub fn daily_plpls(&self) -> &[PLPL] {
+        &self.plpls
+    }
+
+    /// Replace the PLPLs of each date, dropping levels cached from the old PLPLs
+    pub fn set_plpls(&mut self, plpls: Vec<PLPL>) {
+        self.plpls = plpls;
+        self.clear_cache();
+    }
+
+    /// Number of PLPL level lookups that were not cached
+    pub fn cache_misses(&self) -> usize {
+        self.cache.borrow().misses
+    }
+
+    /// Find the closest PLPL to price on this date.
+    /// A price halfway between two PLPLs is closest to the lower one,
+    /// the first of the two in the grid.
     pub fn closest_plpl(&self, candle: &Candle) -> PLPLResult<f32> {
-        let mut closest_plpl = None;
-        let mut closest_plpl_distance: Option<f64> = None;
-        let plpls = self.plpls_for_date(candle.date)?;
-        for plpl in plpls {
-            match closest_plpl_distance {
-                None => {
-                    closest_plpl_distance = Some((plpl as f64 - candle.close).abs());
-                    closest_plpl = Some(plpl);
-                }
-                Some(closest_distance) => {
-                    let curr_distance = (plpl as f64 - candle.close).abs();
-                    if curr_distance < closest_distance {
-                        closest_plpl_distance = Some(curr_distance);
-                        closest_plpl = Some(plpl);
-                    }
+        let closest_plpl = self.with_plpl_levels(&candle.date, |levels| {
+            // first level above the close, so the one before it is at or below
+            let above = levels.partition_point(|plpl| *plpl <= candle.close);
+            match (above.checked_sub(1).map(|i| levels[i]), levels.get(above)) {
+                (Some(below), Some(above)) if above - candle.close < candle.close - below => {
+                    Some(*above)
                 }
+                (Some(below), _) => Some(below),
+                (None, above) => above.copied(),
             }
-        }
+        })?;
         match closest_plpl {
-            Some(plpl) => Ok(plpl),
+            // widened from the f32 PLPL so the cast back is exact
+            Some(plpl) => Ok(plpl as f32),
             None => {
                 error!("No closest PLPL found for date {}", candle.date.to_string());
                 Err(PLPLError::NoPLPLClosest)
@@ -169,6 +584,45 @@ impl PLPLSystem {
         }
     }
 
+    /// Closest PLPL rounded to the tick size for logging.
+    /// Signals use the full precision [`PLPLSystem::closest_plpl`].
+    pub fn closest_plpl_rounded(&self, candle: &Candle) -> PLPLResult<f64> {
+        Ok(self.round_plpl(self.closest_plpl(candle)?))
+    }
+
+    /// PLPL rounded to the tick size for logging, for a PLPL already found
+    pub fn round_plpl(&self, plpl: f32) -> f64 {
+        let plpl = plpl as f64;
+        let ticks = (plpl / self.tick_size).round() * self.tick_size;
+        // drop float tails left by multiplying by the tick size
+        let decimals = (-self.tick_size.log10()).ceil().max(0.0) as u32;
+        precise_round(ticks, decimals)
+    }
+
+    /// Nearest PLPL at or below and above the candle close on its date
+    pub fn bracketing_plpls(&self, candle: &Candle) -> PLPLResult<(Option<f32>, Option<f32>)> {
+        let plpls = self.plpls_for_date(candle.date)?;
+        let below = plpls
+            .iter()
+            .filter(|plpl| **plpl as f64 <= candle.close)
+            .copied()
+            .reduce(f32::max);
+        let above = plpls
+            .iter()
+            .filter(|plpl| **plpl as f64 > candle.close)
+            .copied()
+            .reduce(f32::min);
+        Ok((below, above))
+    }
+
+    /// Planet angle the PLPLs of this date are derived from
+    pub fn planet_angle(&self, date: Time) -> Option<f32> {
+        self.planet_angles
+            .iter()
+            .find(|(angle_date, _)| *angle_date == date)
+            .map(|(_, angle)| *angle)
+    }
+
     fn plpls_for_date(&self, date: Time) -> PLPLResult<Vec<f32>> {
         let mut plpls = None;
         for plpl in self.plpls.iter() {
@@ -187,13 +641,742 @@ impl PLPLSystem {
         self.up_op() * self.cross_margin_pct / 100.0
     }
 
-    pub fn long_signal(&self, prev_candle: &Candle, candle: &Candle, closest_plpl: f32) -> bool {
+    /// How decisively the candle crossed the PLPL, as the distance the close moved past
+    /// the crossover margin in units of the PLPL spacing (360 * scale).
+    /// Positive for a long cross, negative for a short cross, and 0.0 without a cross.
+    /// A candle that meets both crosses, i.e. the previous close is on the PLPL, is long.
+    /// See [`CrossBoundary`] for a previous close on the PLPL.
+    pub fn signal_strength(&self, prev_candle: &Candle, candle: &Candle, closest_plpl: f32) -> f64 {
+        self.long_strength(prev_candle, candle, closest_plpl)
+            .or_else(|| self.short_strength(prev_candle, candle, closest_plpl))
+            .unwrap_or(0.0)
+    }
+
+    /// Positive strength of a long cross, if the candle crossed up
+    fn long_strength(
+        &self,
+        prev_candle: &Candle,
+        candle: &Candle,
+        closest_plpl: f32,
+    ) -> Option<f64> {
         let plpl = closest_plpl as f64;
-        prev_candle.close <= plpl && candle.close > plpl - self.margin() as f64
+        let below = match self.boundary {
+            CrossBoundary::Inclusive => prev_candle.close <= plpl,
+            CrossBoundary::Exclusive => prev_candle.close < plpl && candle.close > plpl,
+        };
+        let crossed = plpl - self.margin() as f64;
+        (below && candle.close > crossed).then(|| (candle.close - crossed) / self.up_op() as f64)
     }
 
-    pub fn short_signal(&self, prev_candle: &Candle, candle: &Candle, closest_plpl: f32) -> bool {
+    /// Negative strength of a short cross, if the candle crossed down
+    fn short_strength(
+        &self,
+        prev_candle: &Candle,
+        candle: &Candle,
+        closest_plpl: f32,
+    ) -> Option<f64> {
         let plpl = closest_plpl as f64;
-        prev_candle.close >= plpl && candle.close < plpl + self.margin() as f64
+        let above = match self.boundary {
+            CrossBoundary::Inclusive => prev_candle.close >= plpl,
+            CrossBoundary::Exclusive => prev_candle.close > plpl && candle.close < plpl,
+        };
+        let crossed = plpl + self.margin() as f64;
+        (above && candle.close < crossed).then(|| (candle.close - crossed) / self.up_op() as f64)
+    }
+
+    /// Long cross of the PLPL, a positive [`Self::signal_strength`]
+    pub fn long_signal(&self, prev_candle: &Candle, candle: &Candle, closest_plpl: f32) -> bool {
+        self.signal_strength(prev_candle, candle, closest_plpl) > 0.0
+    }
+
+    /// Short cross of the PLPL, a negative [`Self::signal_strength`]
+    pub fn short_signal(&self, prev_candle: &Candle, candle: &Candle, closest_plpl: f32) -> bool {
+        self.signal_strength(prev_candle, candle, closest_plpl) < 0.0
+    }
+
+    /// Evaluate a candle for an entry, or the first reason it should not trade.
+    pub fn signal(
+        &self,
+        prev_candle: &Candle,
+        candle: &Candle,
+        conditions: &SignalConditions,
+    ) -> PLPLResult<PLPLSignal> {
+        let plpl = self.closest_plpl(candle)?;
+        Ok(self.signal_at(prev_candle, candle, plpl, conditions))
+    }
+
+    /// Evaluate a candle for an entry against `plpl`, its closest PLPL already found
+    pub fn signal_at(
+        &self,
+        prev_candle: &Candle,
+        candle: &Candle,
+        plpl: f32,
+        conditions: &SignalConditions,
+    ) -> PLPLSignal {
+        let order = if self.long_signal(prev_candle, candle, plpl) {
+            Order::Long
+        } else if self.short_signal(prev_candle, candle, plpl) {
+            Order::Short
+        } else {
+            return PLPLSignal::NoTrade(NoTradeReason::NoCrossover);
+        };
+        if let Some(hysteresis) = conditions.hysteresis {
+            let beyond = match order {
+                Order::Long => candle.close - plpl as f64,
+                Order::Short => plpl as f64 - candle.close,
+            };
+            if beyond < hysteresis {
+                return PLPLSignal::NoTrade(NoTradeReason::BelowHysteresis);
+            }
+        }
+        if let Some(trend) = &conditions.trend {
+            if &Direction::from(order.clone()) != trend {
+                return PLPLSignal::NoTrade(NoTradeReason::AgainstTrend);
+            }
+        }
+        if conditions.position.as_ref() == Some(&order) {
+            return PLPLSignal::NoTrade(NoTradeReason::PositionAligned);
+        }
+        if conditions.cooldown_candles > 0 {
+            return PLPLSignal::NoTrade(NoTradeReason::InCooldown);
+        }
+        if let (Some((start, end)), Some(hour)) = (conditions.trading_hours, candle.date.hour) {
+            let in_hours = match start <= end {
+                true => hour >= start && hour < end,
+                // window wraps past midnight
+                false => hour >= start || hour < end,
+            };
+            if !in_hours {
+                return PLPLSignal::NoTrade(NoTradeReason::OutsideTradingHours);
+            }
+        }
+        if let Some((available, required)) = conditions.funds {
+            if available < required {
+                return PLPLSignal::NoTrade(NoTradeReason::InsufficientFunds);
+            }
+        }
+        if let Some((spread_pct, max_spread_pct)) = conditions.spread_pct {
+            if spread_pct > max_spread_pct {
+                return PLPLSignal::NoTrade(NoTradeReason::SpreadTooWide);
+            }
+        }
+        PLPLSignal::Enter(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time_series::{Day, Month};
+
+    fn candle(close: f64, hour: u32) -> Candle {
+        Candle {
+            date: Time::new(
+                2023,
+                &Month::from_num(1),
+                &Day::from_num(1),
+                Some(hour),
+                Some(0),
+            ),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: None,
+        }
+    }
+
+    /// Jupiter PLPLs from Jan 1 2023 through `days` later, 180 apart around 20000 with a crossover margin of 99
+    fn config(days: i64) -> PLPLSystemConfig {
+        let first_date = Time::new(2023, &Month::from_num(1), &Day::from_num(1), None, None);
+        PLPLSystemConfig {
+            planet: Planet::Jupiter,
+            origin: Origin::Heliocentric,
+            first_date,
+            last_date: first_date.delta_date(days),
+            plpl_scale: 0.5,
+            plpl_price: 20000.0,
+            num_plpls: 10,
+            cross_margin_pct: 55.0,
+            price_range: None,
+        }
+    }
+
+    /// PLPLs every 360 around 20160 with a crossover margin of 36, shifted up by `angle`
+    fn plpl_grid(angle: f32) -> PLPLResult<PLPLSystem> {
+        let config = PLPLSystemConfig {
+            plpl_scale: 1.0,
+            cross_margin_pct: 10.0,
+            ..config(0)
+        };
+        let date = config.first_date;
+        PLPLSystem::from_planet_angles(config, vec![(date, angle)])
+    }
+
+    #[test]
+    fn closest_plpl_rounded_to_tick() -> PLPLResult<()> {
+        let date = Time::new(2023, &Month::from_num(1), &Day::from_num(1), None, None);
+        let plpl_system = PLPLSystem::from_planet_angles(config(0), vec![(date, 123.4567)])?;
+        let candle = candle(20000.0, 12);
+        let raw = plpl_system.closest_plpl(&candle)?;
+
+        assert_eq!(
+            plpl_system.closest_plpl_rounded(&candle)?,
+            precise_round(raw as f64, 2)
+        );
+        let plpl_system = plpl_system.with_tick_size(0.5);
+        let rounded = plpl_system.closest_plpl_rounded(&candle)?;
+        assert_eq!(rounded % 0.5, 0.0);
+        assert!((rounded - raw as f64).abs() <= 0.25);
+        // signal math keeps full precision
+        assert_eq!(plpl_system.closest_plpl(&candle)?, raw);
+        Ok(())
+    }
+
+    #[test]
+    fn bracketing_plpls_around_close() -> PLPLResult<()> {
+        let date = Time::new(2023, &Month::from_num(1), &Day::from_num(1), None, None);
+        // PLPLs every 360 around 20170
+        let plpl_system = plpl_grid(10.0)?;
+        assert_eq!(
+            plpl_system.bracketing_plpls(&candle(20300.0, 12))?,
+            (Some(20170.0), Some(20530.0))
+        );
+        assert_eq!(
+            plpl_system.bracketing_plpls(&candle(20170.0, 12))?,
+            (Some(20170.0), Some(20530.0))
+        );
+        assert_eq!(plpl_system.planet_angle(date), Some(10.0));
+        assert_eq!(plpl_system.planet_angle(date.delta_date(1)), None);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_config_fields() {
+        let config = config(30);
+        assert!(config.validate().is_ok());
+
+        let invalid = |edit: fn(&mut PLPLSystemConfig)| {
+            let mut config = config.clone();
+            edit(&mut config);
+            config.validate().unwrap_err()
+        };
+        assert!(matches!(
+            invalid(|c| c.plpl_scale = 0.0),
+            PLPLConfigError::NonPositiveScale(_)
+        ));
+        assert!(matches!(
+            invalid(|c| c.plpl_scale = f32::NAN),
+            PLPLConfigError::NonPositiveScale(_)
+        ));
+        assert!(matches!(
+            invalid(|c| c.plpl_price = -1.0),
+            PLPLConfigError::NonPositivePrice(_)
+        ));
+        assert!(matches!(
+            invalid(|c| c.num_plpls = 0),
+            PLPLConfigError::NoPLPLs
+        ));
+        assert!(matches!(
+            invalid(|c| c.last_date = c.first_date),
+            PLPLConfigError::InvalidDateRange { .. }
+        ));
+        assert!(matches!(
+            invalid(|c| c.planet = Planet::Sun),
+            PLPLConfigError::UnsupportedTarget { .. }
+        ));
+        // the Sun has an angle from the Earth
+        let mut geocentric_sun = config.clone();
+        geocentric_sun.planet = Planet::Sun;
+        geocentric_sun.origin = Origin::Geocentric;
+        assert!(geocentric_sun.validate().is_ok());
+
+        // rejected before any Horizons API request
+        let mut config = config;
+        config.num_plpls = 0;
+        assert!(matches!(
+            PLPLSystem::new(config),
+            Err(PLPLError::InvalidConfig(PLPLConfigError::NoPLPLs))
+        ));
+    }
+
+    #[test]
+    fn config_json_round_trip() -> PLPLResult<()> {
+        let config = PLPLSystemConfig {
+            origin: Origin::Geocentric,
+            ..config(1)
+        };
+        let first_date = config.first_date;
+        let path = std::env::temp_dir().join("plpl_config_round_trip.json");
+        config.to_json_file(&path)?;
+        let loaded = PLPLSystemConfig::from_json_file(&path)?;
+        std::fs::remove_file(&path).map_err(PLPLError::ConfigIo)?;
+
+        assert_eq!(loaded.planet.to_str(), config.planet.to_str());
+        assert!(matches!(loaded.origin, Origin::Geocentric));
+        assert_eq!(loaded.first_date.to_unix(), config.first_date.to_unix());
+        assert_eq!(loaded.last_date.to_unix(), config.last_date.to_unix());
+        assert_eq!(loaded.plpl_scale, config.plpl_scale);
+        assert_eq!(loaded.plpl_price, config.plpl_price);
+        assert_eq!(loaded.num_plpls, config.num_plpls);
+        assert_eq!(loaded.cross_margin_pct, config.cross_margin_pct);
+
+        // same planet angles rebuild the same PLPLs
+        let planet_angles = vec![(first_date, 123.4), (first_date.delta_date(1), 123.5)];
+        let original = PLPLSystem::from_planet_angles(config, planet_angles.clone())?;
+        let rebuilt = PLPLSystem::from_planet_angles(loaded, planet_angles)?;
+        assert_eq!(rebuilt.plpls.len(), original.plpls.len());
+        for (a, b) in rebuilt.plpls.iter().zip(original.plpls.iter()) {
+            assert_eq!(a.date.to_unix(), b.date.to_unix());
+            assert_eq!(a.plpls, b.plpls);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn origin_changes_plpl_grid() -> PLPLResult<()> {
+        let first_date = Time::new(2023, &Month::from_num(9), &Day::from_num(1), None, None);
+        let config = |origin: Origin| PLPLSystemConfig {
+            planet: Planet::Mercury,
+            origin,
+            first_date,
+            last_date: first_date.delta_date(1),
+            ..config(1)
+        };
+        // Horizons API responses for Mercury from the Sun and from the Earth
+        let query = |origin: Origin,
+                     _: &Planet,
+                     data_type: DataType,
+                     _: Time,
+                     _: Time|
+         -> QueryResult<Vec<(Time, f32)>> {
+            Ok(match (origin, data_type) {
+                (Origin::Heliocentric, DataType::RightAscension) => {
+                    Query::format_for_right_ascension(
+                        " 2023-Sep-01 00:00     21 10 05.12 -17 40 11.4\r\n\
+                         2023-Sep-02 00:00     21 24 48.96 -16 20 02.7\r\n"
+                            .to_string(),
+                    )
+                }
+                (Origin::Geocentric, DataType::EclipticLongitude) => {
+                    Query::format_for_ecliptic_longitude(
+                        " 2023-Sep-01 00:00     164.2021294   1.0910089\r\n\
+                         2023-Sep-02 00:00 *m  163.6813157   0.8569440\r\n"
+                            .to_string(),
+                    )
+                }
+                _ => panic!("unexpected data type for origin"),
+            })
+        };
+        let helio = PLPLSystem::from_planet_angles(
+            config(Origin::Heliocentric),
+            PLPLSystem::planet_angles_with(&config(Origin::Heliocentric), query)?,
+        )?;
+        let geo = PLPLSystem::from_planet_angles(
+            config(Origin::Geocentric),
+            PLPLSystem::planet_angles_with(&config(Origin::Geocentric), query)?,
+        )?;
+
+        assert_eq!(helio.plpls.len(), geo.plpls.len());
+        for (h, g) in helio.plpls.iter().zip(geo.plpls.iter()) {
+            assert_eq!(h.date.to_unix(), g.date.to_unix());
+            assert_ne!(h.plpls, g.plpls);
+        }
+        // signals read either grid the same way
+        let mut candle = candle(20000.0, 12);
+        candle.date = first_date;
+        assert_ne!(helio.closest_plpl(&candle)?, geo.closest_plpl(&candle)?);
+        Ok(())
+    }
+
+    #[test]
+    fn multi_planet_grid() -> PLPLResult<()> {
+        let date = Time::new(2023, &Month::from_num(1), &Day::from_num(1), None, None);
+        let config = PLPLSystemConfig {
+            plpl_scale: 1.0,
+            cross_margin_pct: 10.0,
+            ..config(1)
+        };
+        let jupiter = vec![(date, 10.0), (date.delta_date(1), 10.1)];
+        let grid_len = |saturn: Vec<(Time, f32)>| -> PLPLResult<usize> {
+            let merged = PLPLSystem::from_multi_planet_angles(
+                config.clone(),
+                vec![(Planet::Jupiter, jupiter.clone()), (Planet::Saturn, saturn)],
+            )?;
+            assert_eq!(merged.planets.len(), 2);
+            assert_eq!(merged.plpls.len(), 2);
+            let levels = &merged.plpls[0].plpls;
+            assert!(levels.windows(2).all(|w| w[1] - w[0] > config.plpl_scale));
+            Ok(levels.len())
+        };
+        let single = PLPLSystem::from_planet_angles(config.clone(), jupiter.clone())?;
+        let single_len = single.plpls[0].plpls.len();
+
+        // Saturn levels fall between Jupiter levels
+        let apart = grid_len(vec![(date, 100.0), (date.delta_date(1), 100.1)])?;
+        // Saturn levels within plpl_scale of Jupiter levels are deduplicated
+        let overlap = grid_len(vec![(date, 10.5), (date.delta_date(1), 10.6)])?;
+        for len in [apart, overlap] {
+            assert!(len >= single_len);
+            assert!(len <= 2 * single_len);
+        }
+        assert_eq!(apart, 2 * single_len);
+        assert_eq!(overlap, single_len);
+
+        // signals read the merged grid, Saturn PLPL at 20260 is closest
+        let merged = PLPLSystem::from_multi_planet_angles(
+            config.clone(),
+            vec![
+                (Planet::Jupiter, jupiter),
+                (
+                    Planet::Saturn,
+                    vec![(date, 100.0), (date.delta_date(1), 100.1)],
+                ),
+            ],
+        )?;
+        let closest = merged.closest_plpl(&candle(20240.0, 12))?;
+        assert_eq!(closest, 20260.0);
+        assert!(merged.long_signal(&candle(20250.0, 12), &candle(20270.0, 12), closest));
+
+        assert!(matches!(
+            PLPLSystem::from_multi_planet_angles(config, vec![]),
+            Err(PLPLError::NoPlanets)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn closest_plpl_from_levels() -> PLPLResult<()> {
+        let date = Time::new(2023, &Month::from_num(1), &Day::from_num(1), None, None);
+        let plpl_system = PLPLSystem::from_planet_angles(config(0), vec![(date, 123.4567)])?;
+        let levels = plpl_system.plpl_levels(&date)?;
+        assert_eq!(levels.len(), plpl_system.plpls[0].plpls.len());
+        assert!(levels.windows(2).all(|w| w[0] < w[1]));
+
+        // halfway between two levels, on a level, and outside the grid
+        let midpoint = (levels[3] + levels[4]) / 2.0;
+        let closes = [
+            0.0, 19000.0, 19990.0, 20050.0, 20500.0, 21000.0, 1.0e6, midpoint, levels[5],
+        ];
+        for close in closes {
+            let candle = candle(close, 12);
+            // first of the grid wins a tie, as in a linear scan
+            let nearest = levels
+                .iter()
+                .copied()
+                .reduce(|a, b| match (b - close).abs() < (a - close).abs() {
+                    true => b,
+                    false => a,
+                })
+                .unwrap();
+            assert_eq!(plpl_system.closest_plpl(&candle)? as f64, nearest);
+        }
+        assert_eq!(
+            plpl_system.closest_plpl(&candle(midpoint, 12))? as f64,
+            levels[3]
+        );
+        assert!(matches!(
+            plpl_system.plpl_levels(&date.delta_date(1)),
+            Err(PLPLError::NoPLPLForDate)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn crossover_signal_strength() -> PLPLResult<()> {
+        let plpl_system = plpl_grid(0.0)?;
+        let plpl = 20160.0;
+        let strength = |prev: f64, close: f64| {
+            plpl_system.signal_strength(&candle(prev, 12), &candle(close, 12), plpl)
+        };
+
+        // clean cross 0.5 of the PLPL spacing beyond the margin
+        assert_eq!(strength(20100.0, 20304.0), 0.5);
+        assert_eq!(strength(20200.0, 20016.0), -0.5);
+        // marginal cross just past the margin
+        let marginal = strength(20100.0, 20127.6);
+        assert!(marginal > 0.0 && marginal < 0.01);
+        let marginal = strength(20200.0, 20192.4);
+        assert!(marginal < 0.0 && marginal > -0.01);
+        // no cross
+        assert_eq!(strength(20100.0, 20110.0), 0.0);
+        assert_eq!(strength(20170.0, 20250.0), 0.0);
+
+        // boolean signals read the sign
+        assert!(plpl_system.long_signal(&candle(20100.0, 12), &candle(20304.0, 12), plpl));
+        assert!(!plpl_system.short_signal(&candle(20100.0, 12), &candle(20304.0, 12), plpl));
+        assert!(plpl_system.short_signal(&candle(20200.0, 12), &candle(20016.0, 12), plpl));
+        assert!(!plpl_system.long_signal(&candle(20100.0, 12), &candle(20110.0, 12), plpl));
+        // previous close on the PLPL meets the cross of either side, and the strength is long
+        let (on_plpl, within_margin) = (candle(20160.0, 12), candle(20170.0, 12));
+        assert!(strength(20160.0, 20170.0) > 0.0);
+        assert!(plpl_system.long_signal(&on_plpl, &within_margin, plpl));
+        assert!(!plpl_system.short_signal(&on_plpl, &within_margin, plpl));
+        Ok(())
+    }
+
+    #[test]
+    fn crossover_boundary() -> PLPLResult<()> {
+        let inclusive = plpl_grid(0.0)?;
+        let exclusive = inclusive.clone().with_boundary(CrossBoundary::Exclusive);
+        let plpl = 20160.0;
+        let signals = |system: &PLPLSystem, prev: f64, close: f64| {
+            let (prev, close) = (candle(prev, 12), candle(close, 12));
+            (
+                system.long_signal(&prev, &close, plpl),
+                system.short_signal(&prev, &close, plpl),
+            )
+        };
+
+        // strict cross of the line
+        assert_eq!(signals(&inclusive, 20100.0, 20200.0), (true, false));
+        assert_eq!(signals(&exclusive, 20100.0, 20200.0), (true, false));
+        assert_eq!(signals(&inclusive, 20200.0, 20100.0), (false, true));
+        assert_eq!(signals(&exclusive, 20200.0, 20100.0), (false, true));
+        // close exactly on the line from either side
+        assert_eq!(signals(&inclusive, 20100.0, 20160.0), (true, false));
+        assert_eq!(signals(&exclusive, 20100.0, 20160.0), (false, false));
+        assert_eq!(signals(&inclusive, 20200.0, 20160.0), (false, true));
+        assert_eq!(signals(&exclusive, 20200.0, 20160.0), (false, false));
+        // close within the margin short of the line
+        assert_eq!(signals(&inclusive, 20100.0, 20150.0), (true, false));
+        assert_eq!(signals(&exclusive, 20100.0, 20150.0), (false, false));
+        // touch of the line then away from it
+        assert_eq!(signals(&inclusive, 20160.0, 20250.0), (true, false));
+        assert_eq!(signals(&exclusive, 20160.0, 20250.0), (false, false));
+        assert_eq!(signals(&inclusive, 20160.0, 20050.0), (false, true));
+        assert_eq!(signals(&exclusive, 20160.0, 20050.0), (false, false));
+        // two equal closes on the line meet both crosses, which is long
+        assert_eq!(signals(&inclusive, 20160.0, 20160.0), (true, false));
+        assert_eq!(signals(&exclusive, 20160.0, 20160.0), (false, false));
+        // two equal closes within the margin on either side of the line
+        assert_eq!(signals(&inclusive, 20150.0, 20150.0), (true, false));
+        assert_eq!(signals(&exclusive, 20150.0, 20150.0), (false, false));
+        assert_eq!(signals(&inclusive, 20170.0, 20170.0), (false, true));
+        assert_eq!(signals(&exclusive, 20170.0, 20170.0), (false, false));
+        Ok(())
+    }
+
+    #[test]
+    fn plpls_cover_price_range() -> PLPLResult<()> {
+        let date = Time::new(2023, &Month::from_num(1), &Day::from_num(1), None, None);
+        // num_plpls is ignored in favor of the price range
+        let config = PLPLSystemConfig {
+            price_range: Some((10000.0, 100000.0)),
+            ..config(1)
+        };
+        assert!(config.validate().is_ok());
+        let spacing = 180.0;
+        for angle in [0.0, 123.4567, 359.9] {
+            let plpl_system = PLPLSystem::from_planet_angles(config.clone(), vec![(date, angle)])?;
+            assert!(plpl_system.num_plpls > config.num_plpls);
+            let levels = plpl_system.plpl_levels(&date)?;
+            let (lowest, highest) = (levels[0], levels[levels.len() - 1]);
+            for close in [10000.0, 99990.0, 100000.0] {
+                let closest = plpl_system.closest_plpl(&candle(close, 12))? as f64;
+                assert!((closest - close).abs() <= spacing / 2.0);
+                assert!(closest > lowest && closest < highest);
+            }
+        }
+
+        let mut invalid = config;
+        invalid.price_range = Some((100000.0, 10000.0));
+        assert!(matches!(
+            invalid.validate(),
+            Err(PLPLConfigError::InvalidPriceRange(..))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn plpl_levels_cached_by_day() -> PLPLResult<()> {
+        let date = Time::new(2023, &Month::from_num(1), &Day::from_num(1), None, None);
+        let mut plpl_system = PLPLSystem::from_planet_angles(
+            PLPLSystemConfig {
+                num_plpls: 8000,
+                ..config(1)
+            },
+            vec![(date, 123.4567), (date.delta_date(1), 123.6789)],
+        )?;
+        // every 5 minute candle of the day
+        let mut closest = Vec::new();
+        for i in 0..288 {
+            let mut candle = candle(20000.0 + i as f64, 0);
+            candle.date.hour = Some(i / 12);
+            candle.date.minute = Some(i % 12 * 5);
+            closest.push(plpl_system.closest_plpl(&candle)?);
+        }
+        assert_eq!(plpl_system.cache_misses(), 1);
+        assert_eq!(closest[0], plpl_system.closest_plpl(&candle(20000.0, 0))?);
+
+        // next day is looked up once
+        let mut next_day = candle(20000.0, 0);
+        next_day.date = date.delta_date(1);
+        plpl_system.closest_plpl(&next_day)?;
+        plpl_system.closest_plpl(&next_day)?;
+        assert_eq!(plpl_system.cache_misses(), 2);
+
+        plpl_system.clear_cache();
+        plpl_system.closest_plpl(&next_day)?;
+        assert_eq!(plpl_system.cache_misses(), 3);
+
+        // new PLPLs drop the cached levels of the old ones
+        let shifted = plpl_system
+            .daily_plpls()
+            .iter()
+            .map(|plpl| PLPL {
+                date: plpl.date,
+                plpls: plpl.plpls.iter().map(|level| level + 1.0).collect(),
+            })
+            .collect::<Vec<PLPL>>();
+        let before = plpl_system.closest_plpl(&next_day)?;
+        plpl_system.set_plpls(shifted);
+        assert_eq!(plpl_system.closest_plpl(&next_day)?, before + 1.0);
+        assert_eq!(plpl_system.cache_misses(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn plpl_cache_is_bounded() -> PLPLResult<()> {
+        let date = Time::new(2023, &Month::from_num(1), &Day::from_num(1), None, None);
+        let days = MAX_CACHED_DAYS as i64 + 8;
+        let plpl_system = PLPLSystem::from_planet_angles(
+            config(days - 1),
+            (0..days).map(|i| (date.delta_date(i), 123.4567)).collect(),
+        )?;
+        for i in 0..days {
+            let mut candle = candle(20000.0, 0);
+            candle.date = date.delta_date(i);
+            plpl_system.closest_plpl(&candle)?;
+            assert!(plpl_system.cached_days() <= MAX_CACHED_DAYS);
+        }
+        assert_eq!(plpl_system.cached_days(), MAX_CACHED_DAYS);
+
+        // earliest days were dropped and are looked up again
+        let misses = plpl_system.cache_misses();
+        plpl_system.closest_plpl(&candle(20000.0, 0))?;
+        assert_eq!(plpl_system.cache_misses(), misses + 1);
+        let mut latest = candle(20000.0, 0);
+        latest.date = date.delta_date(days - 1);
+        plpl_system.closest_plpl(&latest)?;
+        assert_eq!(plpl_system.cache_misses(), misses + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn no_trade_reasons() -> PLPLResult<()> {
+        let plpl_system = plpl_grid(0.0)?;
+        let prev_candle = candle(20100.0, 12);
+        let long_candle = candle(20200.0, 12);
+        let signal = |candle: &Candle, conditions: SignalConditions| {
+            plpl_system.signal(&prev_candle, candle, &conditions)
+        };
+
+        assert_eq!(
+            signal(&long_candle, SignalConditions::default())?,
+            PLPLSignal::Enter(Order::Long)
+        );
+        assert_eq!(
+            plpl_system.signal(
+                &candle(20170.0, 12),
+                &long_candle,
+                &SignalConditions::default()
+            )?,
+            PLPLSignal::NoTrade(NoTradeReason::NoCrossover)
+        );
+        assert_eq!(
+            signal(
+                &long_candle,
+                SignalConditions {
+                    hysteresis: Some(50.0),
+                    ..Default::default()
+                }
+            )?,
+            PLPLSignal::NoTrade(NoTradeReason::BelowHysteresis)
+        );
+        // 1 hour trend gates the long signal
+        assert_eq!(
+            signal(
+                &long_candle,
+                SignalConditions {
+                    trend: Some(Direction::Down),
+                    ..Default::default()
+                }
+            )?,
+            PLPLSignal::NoTrade(NoTradeReason::AgainstTrend)
+        );
+        assert_eq!(
+            signal(
+                &long_candle,
+                SignalConditions {
+                    trend: Some(Direction::Up),
+                    ..Default::default()
+                }
+            )?,
+            PLPLSignal::Enter(Order::Long)
+        );
+        assert_eq!(
+            signal(
+                &long_candle,
+                SignalConditions {
+                    position: Some(Order::Long),
+                    ..Default::default()
+                }
+            )?,
+            PLPLSignal::NoTrade(NoTradeReason::PositionAligned)
+        );
+        assert_eq!(
+            signal(
+                &long_candle,
+                SignalConditions {
+                    position: Some(Order::Short),
+                    ..Default::default()
+                }
+            )?,
+            PLPLSignal::Enter(Order::Long)
+        );
+        assert_eq!(
+            signal(
+                &long_candle,
+                SignalConditions {
+                    cooldown_candles: 2,
+                    ..Default::default()
+                }
+            )?,
+            PLPLSignal::NoTrade(NoTradeReason::InCooldown)
+        );
+        let trading_hours = SignalConditions {
+            trading_hours: Some((13, 21)),
+            ..Default::default()
+        };
+        assert_eq!(
+            signal(&long_candle, trading_hours.clone())?,
+            PLPLSignal::NoTrade(NoTradeReason::OutsideTradingHours)
+        );
+        assert_eq!(
+            signal(&candle(20200.0, 14), trading_hours)?,
+            PLPLSignal::Enter(Order::Long)
+        );
+        assert_eq!(
+            signal(
+                &long_candle,
+                SignalConditions {
+                    funds: Some((5.0, 10.0)),
+                    ..Default::default()
+                }
+            )?,
+            PLPLSignal::NoTrade(NoTradeReason::InsufficientFunds)
+        );
+        assert_eq!(
+            signal(
+                &long_candle,
+                SignalConditions {
+                    spread_pct: Some((0.2, 0.1)),
+                    ..Default::default()
+                }
+            )?,
+            PLPLSignal::NoTrade(NoTradeReason::SpreadTooWide)
+        );
+        Ok(())
     }
 }
@@ -0,0 +1,300 @@
+use crate::*;
+use log::debug;
+use time_series::{
+    Backtest, BacktestState, Candle, Order, PositionSizing, TickerData, Time, Trade,
+    TrailingStopType,
+};
+
+/// PLPL strategy parameters shared by every backtest in a sweep.
+#[derive(Debug, Clone, Copy)]
+pub struct PLPLBacktestParams {
+    pub first_date: Time,
+    pub last_date: Time,
+    pub plpl_price: f32,
+    pub num_plpls: u32,
+    pub cross_margin_pct: f32,
+    /// Quote asset equity before the first trade
+    pub capital: f64,
+    /// Quote asset committed to each new trade
+    pub sizing: PositionSizing,
+    /// Bid/ask spread in bips crossed by every entry and exit
+    pub spread_bps: f64,
+    pub trailing_stop_type: TrailingStopType,
+    pub trailing_stop: f64,
+    pub stop_loss_pct: f64,
+}
+
+impl PLPLBacktestParams {
+    pub fn system_config(
+        &self,
+        planet: Planet,
+        origin: Origin,
+        plpl_scale: f32,
+    ) -> PLPLSystemConfig {
+        PLPLSystemConfig {
+            planet,
+            origin,
+            first_date: self.first_date,
+            last_date: self.last_date,
+            plpl_scale,
+            plpl_price: self.plpl_price,
+            num_plpls: self.num_plpls,
+            cross_margin_pct: self.cross_margin_pct,
+            price_range: None,
+        }
+    }
+}
+
+/// Backtest the PLPL strategy on candles between the first and last date of the system.
+/// Same as the live engine, a signal is only acted on if no trade is open,
+/// and an open trade is exited by its trailing stop or stop loss.
+pub fn plpl_backtest(
+    plpl_system: &PLPLSystem,
+    ticker_data: &TickerData,
+    params: &PLPLBacktestParams,
+) -> PLPLResult<Backtest> {
+    let mut backtest = Backtest::new(params.capital);
+    backtest.sizing = params.sizing;
+    backtest.spread_bps = params.spread_bps;
+    plpl_backtest_resume(
+        plpl_system,
+        backtest.checkpoint(),
+        ticker_data.get_candles(),
+        params,
+    )
+}
+
+/// Continue a PLPL backtest from a checkpoint on new candles,
+/// e.g. a daily job that appends the latest candle.
+/// Candles at or before the last processed candle are skipped.
+pub fn plpl_backtest_resume(
+    plpl_system: &PLPLSystem,
+    state: BacktestState,
+    new_candles: &[Candle],
+    params: &PLPLBacktestParams,
+) -> PLPLResult<Backtest> {
+    let mut backtest = Backtest::from_checkpoint(state);
+    // the last processed candle is the previous candle of the first new candle
+    let mut candles = backtest.last_candle.iter().cloned().collect::<Vec<_>>();
+    candles.extend(
+        new_candles
+            .iter()
+            .filter(|c| c.date >= plpl_system.first_date && c.date <= plpl_system.last_date)
+            .filter(|c| match &backtest.last_candle {
+                Some(last) => c.date > last.date,
+                None => true,
+            })
+            .cloned(),
+    );
+
+    let spread_bps = backtest.spread_bps;
+    for window in candles.windows(2) {
+        let (prev_candle, candle) = (&window[0], &window[1]);
+        match &mut backtest.open_trade {
+            Some(trade) => {
+                if trade.stop_triggered(candle) {
+                    let exit_price = Trade::exit_fill(&trade.order, candle.close, spread_bps);
+                    trade.exit(candle.date, exit_price);
+                    let trade = trade.clone();
+                    backtest.open_trade = None;
+                    backtest
+                        .add_trade(trade)
+                        .map_err(PLPLError::BacktestError)?;
+                } else {
+                    // only move trailing stop in favor of the trade
+                    let trailing_stop = Trade::calc_trailing_stop(
+                        trade.order.clone(),
+                        candle.close,
+                        params.trailing_stop_type,
+                        params.trailing_stop,
+                    );
+                    trade.trailing_stop = match (&trade.order, trade.trailing_stop) {
+                        (Order::Long, Some(stop)) => Some(stop.max(trailing_stop)),
+                        (Order::Short, Some(stop)) => Some(stop.min(trailing_stop)),
+                        (_, None) => Some(trailing_stop),
+                    };
+                }
+            }
+            None => {
+                let plpl = plpl_system.closest_plpl(candle)?;
+                let order = if plpl_system.long_signal(prev_candle, candle, plpl) {
+                    Order::Long
+                } else if plpl_system.short_signal(prev_candle, candle, plpl) {
+                    Order::Short
+                } else {
+                    continue;
+                };
+                debug!("PLPL {:?} signal: {}", order, candle.date.to_string());
+                let trailing_stop = Trade::calc_trailing_stop(
+                    order.clone(),
+                    candle.close,
+                    params.trailing_stop_type,
+                    params.trailing_stop,
+                );
+                let stop_loss =
+                    Trade::calc_stop_loss(order.clone(), candle.close, params.stop_loss_pct);
+                let fill_price = Trade::entry_fill(&order, candle.close, spread_bps);
+                let capital = backtest.trade_capital(fill_price, Some(stop_loss));
+                backtest.open_trade = Some(Trade::new(
+                    candle.date,
+                    order,
+                    Trade::trade_quantity(capital, fill_price),
+                    fill_price,
+                    capital,
+                    Some(trailing_stop),
+                    Some(stop_loss),
+                ));
+            }
+        }
+    }
+    if let Some(last) = candles.last() {
+        backtest.last_candle = Some(last.clone());
+    }
+    backtest.summarize();
+    Ok(backtest)
+}
+
+/// Backtest the PLPL strategy for each scale, sorted by pnl with the most profitable scale first.
+/// Planet angles are queried once and shared by every scale.
+pub fn plpl_scale_sweep(
+    ticker_data: &TickerData,
+    planet: Planet,
+    origin: Origin,
+    scales: &[f64],
+    params: &PLPLBacktestParams,
+) -> PLPLResult<Vec<(f64, Backtest)>> {
+    let config = params.system_config(planet.clone(), origin, 1.0);
+    let planet_angles = PLPLSystem::helio(&config)?;
+    plpl_scale_sweep_with_angles(ticker_data, planet, origin, &planet_angles, scales, params)
+}
+
+/// Same as [`plpl_scale_sweep`] using planet angles that were already queried.
+pub fn plpl_scale_sweep_with_angles(
+    ticker_data: &TickerData,
+    planet: Planet,
+    origin: Origin,
+    planet_angles: &[(Time, f32)],
+    scales: &[f64],
+    params: &PLPLBacktestParams,
+) -> PLPLResult<Vec<(f64, Backtest)>> {
+    let mut results = Vec::<(f64, Backtest)>::new();
+    for scale in scales.iter() {
+        let config = params.system_config(planet.clone(), origin, *scale as f32);
+        let plpl_system = PLPLSystem::from_planet_angles(config, planet_angles.to_vec())?;
+        let backtest = plpl_backtest(&plpl_system, ticker_data, params)?;
+        results.push((*scale, backtest));
+    }
+    results.sort_by(|a, b| {
+        b.1.pnl
+            .unwrap_or(0.0)
+            .partial_cmp(&a.1.pnl.unwrap_or(0.0))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time_series::{Day, Month};
+
+    /// Candles, planet angles and params of a 60 day backtest
+    type Fixture = (TickerData, Vec<(Time, f32)>, PLPLBacktestParams);
+
+    fn fixture() -> PLPLResult<Fixture> {
+        let first_date = Time::new(2023, &Month::from_num(1), &Day::from_num(1), None, None);
+        let last_date = first_date.delta_date(59);
+        let mut candles = Vec::<Candle>::new();
+        let mut planet_angles = Vec::<(Time, f32)>::new();
+        for i in 0..60 {
+            let date = first_date.delta_date(i);
+            let close = 20000.0 + 400.0 * (i as f64 / 3.0).sin();
+            candles.push(Candle {
+                date,
+                open: close,
+                high: close,
+                low: close,
+                close,
+                volume: None,
+            });
+            planet_angles.push((date, i as f32));
+        }
+        let mut ticker_data = TickerData::new();
+        ticker_data
+            .add_series(candles)
+            .map_err(PLPLError::TickerDataError)?;
+
+        let params = PLPLBacktestParams {
+            first_date,
+            last_date,
+            plpl_price: 20000.0,
+            num_plpls: 200,
+            cross_margin_pct: 55.0,
+            capital: 1000.0,
+            sizing: PositionSizing::Full,
+            spread_bps: 0.0,
+            trailing_stop_type: TrailingStopType::Percent,
+            trailing_stop: 0.01,
+            stop_loss_pct: 0.01,
+        };
+        Ok((ticker_data, planet_angles, params))
+    }
+
+    #[test]
+    fn scale_sweep_sorted_by_pnl() -> PLPLResult<()> {
+        let (ticker_data, planet_angles, params) = fixture()?;
+        let scales = [0.1, 0.25, 0.5, 1.0];
+        let results = plpl_scale_sweep_with_angles(
+            &ticker_data,
+            Planet::Jupiter,
+            Origin::Heliocentric,
+            &planet_angles,
+            &scales,
+            &params,
+        )?;
+
+        assert_eq!(results.len(), scales.len());
+        for scale in scales.iter() {
+            assert!(results.iter().any(|(s, _)| s == scale));
+        }
+        for pair in results.windows(2) {
+            assert!(pair[0].1.pnl.unwrap_or(0.0) >= pair[1].1.pnl.unwrap_or(0.0));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn resume_from_checkpoint() -> PLPLResult<()> {
+        let (ticker_data, planet_angles, params) = fixture()?;
+        let config = params.system_config(Planet::Jupiter, Origin::Heliocentric, 0.25);
+        let plpl_system = PLPLSystem::from_planet_angles(config, planet_angles)?;
+        let whole = plpl_backtest(&plpl_system, &ticker_data, &params)?;
+        assert!(!whole.trades.is_empty());
+
+        for split in [1, 17, 30, 45] {
+            let (history, new_candles) = ticker_data.get_candles().split_at(split);
+            let mut state = Backtest::new(params.capital).checkpoint();
+            state.sizing = params.sizing;
+            state.spread_bps = params.spread_bps;
+            let first = plpl_backtest_resume(&plpl_system, state, history, &params)?;
+            let resumed =
+                plpl_backtest_resume(&plpl_system, first.checkpoint(), new_candles, &params)?;
+
+            assert_eq!(resumed.trades.len(), whole.trades.len());
+            for (a, b) in resumed.trades.iter().zip(whole.trades.iter()) {
+                assert_eq!(a.entry_date.to_unix(), b.entry_date.to_unix());
+                assert_eq!(
+                    a.exit_date.map(|d| d.to_unix()),
+                    b.exit_date.map(|d| d.to_unix())
+                );
+                assert_eq!(a.pnl, b.pnl);
+            }
+            assert_eq!(
+                resumed.open_trade.map(|t| t.entry_date.to_unix()),
+                whole.open_trade.clone().map(|t| t.entry_date.to_unix())
+            );
+            assert_eq!(resumed.pnl, whole.pnl);
+        }
+        Ok(())
+    }
+}
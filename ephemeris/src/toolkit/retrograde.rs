@@ -41,7 +41,7 @@ impl Retrograde {
     pub async fn new(
         start_date: Time,
         end_date: Time,
-        planets: &Vec<Planet>,
+        planets: &[Planet],
     ) -> std::io::Result<Self> {
         if start_date > end_date {
             return Err(std::io::Error::new(
@@ -1,5 +1,30 @@
-use crate::{Candle, Time};
+use crate::{precise_round, Candle, TickerData, TickerDataError, Time};
+use log::warn;
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum BacktestError {
+    /// Trade entered before the previous trade exited
+    OverlappingTrades { prev_exit: Time, entry: Time },
+}
+
+impl Display for BacktestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BacktestError::OverlappingTrades { prev_exit, entry } => write!(
+                f,
+                "Trade entry {} is before previous trade exit {}",
+                entry.to_string(),
+                prev_exit.to_string()
+            ),
+        }
+    }
+}
+
+pub type BacktestResult<T> = Result<T, BacktestError>;
 
 #[derive(Debug, Clone)]
 pub enum ReversalType {
@@ -51,19 +76,173 @@ impl Display for Direction {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Order {
     Long,
     Short,
 }
 
+/// Up enters Long, Down enters Short
+impl From<Direction> for Order {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::Up => Order::Long,
+            Direction::Down => Order::Short,
+        }
+    }
+}
+
+impl From<Order> for Direction {
+    fn from(order: Order) -> Self {
+        match order {
+            Order::Long => Direction::Up,
+            Order::Short => Direction::Down,
+        }
+    }
+}
+
+/// A reversal inverts the prior move, so a Low enters Long and a High enters Short
+impl From<ReversalType> for Order {
+    fn from(reversal: ReversalType) -> Self {
+        Direction::from(reversal).into()
+    }
+}
+
+/// Direction of the move that follows the reversal
+impl From<ReversalType> for Direction {
+    fn from(reversal: ReversalType) -> Self {
+        match reversal {
+            ReversalType::Low => Direction::Up,
+            ReversalType::High => Direction::Down,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Copy)]
 pub enum TrailingStopType {
     Percent,
     Pips,
 }
 
-#[derive(Debug, Clone)]
+/// Price a backtest fills entries and stop exits at within a candle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FillMode {
+    /// Enter and exit at the candle close, only checking stops against the close
+    #[default]
+    Close,
+    /// Enter at the candle open and check stops against the candle high and low,
+    /// exiting at the stop price
+    OpenIntrabar,
+}
+impl FillMode {
+    pub fn entry_price(&self, candle: &Candle) -> f64 {
+        match self {
+            FillMode::Close => candle.close,
+            FillMode::OpenIntrabar => candle.open,
+        }
+    }
+
+    /// Price the trade exits at if the candle hits its trailing stop or stop loss.
+    pub fn stop_exit_price(&self, trade: &Trade, candle: &Candle) -> Option<f64> {
+        match self {
+            FillMode::Close => trade.stop_triggered(candle).then_some(candle.close),
+            FillMode::OpenIntrabar => {
+                let stops = [trade.trailing_stop, trade.stop_loss];
+                let stops = stops.iter().flatten().copied();
+                // nearest stop to price is hit first, and a gap through the stop fills at the open
+                match trade.order {
+                    Order::Long => stops
+                        .reduce(f64::max)
+                        .filter(|stop| candle.low <= *stop)
+                        .map(|stop| stop.min(candle.open)),
+                    Order::Short => stops
+                        .reduce(f64::min)
+                        .filter(|stop| candle.high >= *stop)
+                        .map(|stop| stop.max(candle.open)),
+                }
+            }
+        }
+    }
+}
+
+/// Whether a backtest holds a trade until an opposing signal or only for a window after its signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HoldPolicy {
+    /// Hold until an opposing signal flips the trade or a stop is hit
+    #[default]
+    AlwaysInMarket,
+    /// Exit at the close once the trade has been held this many bars without a fresh signal
+    FlatBetweenSignals { hold_bars: usize },
+}
+impl HoldPolicy {
+    /// True if a trade held for `bars_held` bars since its last signal should exit.
+    pub fn hold_expired(&self, bars_held: usize) -> bool {
+        match self {
+            HoldPolicy::AlwaysInMarket => false,
+            HoldPolicy::FlatBetweenSignals { hold_bars } => bars_held >= *hold_bars,
+        }
+    }
+}
+
+/// How much quote asset a backtest commits to each new trade.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum PositionSizing {
+    /// Fraction of current equity, e.g. 0.1 for 10%
+    FixedFraction(f64),
+    /// Same quote asset amount every trade
+    FixedDollar(f64),
+    /// All current equity
+    #[default]
+    Full,
+    /// Size so that a fill at the stop loss loses this fraction of current equity, e.g. 0.01 for 1%,
+    /// committing at most all current equity
+    RiskPerTrade(f64),
+}
+impl PositionSizing {
+    /// Quote asset amount of a new trade entered at `price` given the current equity.
+    /// `RiskPerTrade` without a stop loss, or with a stop loss at the entry price, sizes to zero.
+    pub fn trade_capital(&self, equity: f64, price: f64, stop_loss: Option<f64>) -> f64 {
+        match self {
+            PositionSizing::FixedFraction(fraction) => equity * fraction,
+            PositionSizing::FixedDollar(amount) => *amount,
+            PositionSizing::Full => equity,
+            PositionSizing::RiskPerTrade(fraction) => {
+                let risk = match stop_loss {
+                    Some(stop_loss) => (price - stop_loss).abs() / price,
+                    None => return 0.0,
+                };
+                if risk == 0.0 {
+                    return 0.0;
+                }
+                (equity * fraction / risk).min(equity)
+            }
+        }
+    }
+}
+
+/// Trading costs in bips of the quote asset notional of each fill.
+/// Market fills also cross the backtest's `spread_bps`, limit fills rest at their own price.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Fees {
+    /// Fee of a resting limit order
+    pub maker_bps: f64,
+    /// Fee of an order that crosses the book
+    pub taker_bps: f64,
+    /// Price lost to the book moving before the fill, paid with taker fees
+    pub slippage_bps: f64,
+}
+impl Fees {
+    /// Quote asset cost of a fill of `notional`, a taker fill also paying slippage
+    pub fn fill_cost(&self, notional: f64, maker: bool) -> f64 {
+        let bps = match maker {
+            true => self.maker_bps,
+            false => self.taker_bps + self.slippage_bps,
+        };
+        notional.abs() * bps / 10_000.0
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Trade {
     /// Time of trade entry
     pub entry_date: Time,
@@ -79,12 +258,30 @@ pub struct Trade {
     pub capital: f64,
     /// Exit price
     pub exit_price: Option<f64>,
-    /// Percent profit or loss relative to capital
+    /// Percent profit or loss relative to capital, net of fees
     pub pnl: Option<f64>,
+    /// Percent profit or loss relative to capital before fees
+    #[serde(default)]
+    pub gross_pnl: Option<f64>,
+    /// Fees charged on entry and exit, or `None` to charge the fees of the backtest it is added to
+    #[serde(default)]
+    pub fees: Option<Fees>,
+    /// Entry rested on the book as a limit order, paying maker fees
+    #[serde(default)]
+    pub maker_entry: bool,
+    /// Exit rested on the book as a limit order, e.g. a take profit, paying maker fees
+    #[serde(default)]
+    pub maker_exit: bool,
     /// Trailing stop
     pub trailing_stop: Option<f64>,
     /// Stop loss
     pub stop_loss: Option<f64>,
+    /// Planned take profit price
+    #[serde(default)]
+    pub target: Option<f64>,
+    /// Longest the trade is held before exiting at market
+    #[serde(default)]
+    pub max_hold: Option<Duration>,
 }
 impl Trade {
     pub fn new(
@@ -105,24 +302,79 @@ impl Trade {
             capital,
             exit_price: None,
             pnl: None,
+            gross_pnl: None,
+            fees: None,
+            maker_entry: false,
+            maker_exit: false,
             trailing_stop,
             stop_loss,
+            target: None,
+            max_hold: None,
+        }
+    }
+
+    /// Reward to the target, or to the exit if there is no target, in multiples of the risk
+    /// from entry to stop loss. `None` without a stop loss, or without a target or exit.
+    pub fn risk_reward(&self) -> Option<f64> {
+        self.r_multiple_at(self.target.or(self.exit_price)?)
+    }
+
+    /// Realized profit or loss in multiples of the risk from entry to stop loss,
+    /// e.g. a long entered at 100 with a stop at 95 that exits at 110 is 2R.
+    /// `None` without a stop loss or before exit.
+    pub fn r_multiple(&self) -> Option<f64> {
+        self.r_multiple_at(self.exit_price?)
+    }
+
+    fn r_multiple_at(&self, price: f64) -> Option<f64> {
+        let risk = (self.entry_price - self.stop_loss?).abs();
+        if risk == 0.0 {
+            return None;
         }
+        let reward = match self.order {
+            Order::Long => price - self.entry_price,
+            Order::Short => self.entry_price - price,
+        };
+        Some(reward / risk)
     }
 
     pub fn trade_quantity(capital: f64, price: f64) -> f64 {
-        let quantity = capital / price;
-        (quantity * 1000000.0).round() / 1000000.0
+        precise_round(capital / price, 6)
     }
 
     pub fn exit(&mut self, exit_date: Time, exit_price: f64) {
         self.exit_date = Some(exit_date);
         self.exit_price = Some(exit_price);
-        let pnl = self.pnl();
-        self.pnl = Some(pnl);
+        self.gross_pnl = Some(self.gross_pnl());
+        self.pnl = Some(self.pnl());
     }
 
-    pub fn quote_asset_pnl(&self) -> f64 {
+    /// Exit with a limit order resting at `exit_price`, e.g. a take profit, which pays maker fees
+    /// and fills at its price without crossing the spread.
+    pub fn exit_limit(&mut self, exit_date: Time, exit_price: f64) {
+        self.maker_exit = true;
+        self.exit(exit_date, exit_price);
+    }
+
+    /// Charge `fees` on the trade, recomputing its pnl if it has exited
+    pub fn apply_fees(&mut self, fees: Fees) {
+        self.fees = Some(fees);
+        if let (Some(exit_date), Some(exit_price)) = (self.exit_date, self.exit_price) {
+            self.exit(exit_date, exit_price);
+        }
+    }
+
+    /// Quote asset fees of the entry and exit, as makers if they filled as limit orders
+    pub fn fee_cost(&self) -> f64 {
+        let fees = self.fees.unwrap_or_default();
+        let entry = fees.fill_cost(self.entry_price * self.contracts, self.maker_entry);
+        let exit = self.exit_price.map_or(0.0, |exit_price| {
+            fees.fill_cost(exit_price * self.contracts, self.maker_exit)
+        });
+        entry + exit
+    }
+
+    fn gross_quote_asset_pnl(&self) -> f64 {
         let exit_price = self.exit_price.unwrap();
         let entry_price = self.entry_price;
         let contracts = self.contracts;
@@ -132,15 +384,39 @@ impl Trade {
         }
     }
 
+    /// Quote asset profit or loss net of fees
+    pub fn quote_asset_pnl(&self) -> f64 {
+        self.gross_quote_asset_pnl() - self.fee_cost()
+    }
+
+    /// Percent profit or loss relative to capital before fees
+    pub fn gross_pnl(&self) -> f64 {
+        self.gross_quote_asset_pnl() / self.capital * 100.0
+    }
+
+    /// Percent profit or loss relative to capital net of fees
     pub fn pnl(&self) -> f64 {
-        let exit_price = self.exit_price.unwrap();
-        let entry_price = self.entry_price;
-        let contracts = self.contracts;
-        let pnl = match self.order {
-            Order::Long => (exit_price - entry_price) * contracts,
-            Order::Short => (entry_price - exit_price) * contracts,
-        };
-        pnl / self.capital * 100.0
+        self.quote_asset_pnl() / self.capital * 100.0
+    }
+
+    /// Price a market entry at the candle `price` fills at, crossing half of a `spread_bps` spread
+    /// to buy at the ask for Long and sell at the bid for Short. A limit entry fills at its own price.
+    pub fn entry_fill(order: &Order, price: f64, spread_bps: f64) -> f64 {
+        let half_spread = price * spread_bps / 10_000.0 / 2.0;
+        match order {
+            Order::Long => price + half_spread,
+            Order::Short => price - half_spread,
+        }
+    }
+
+    /// Price a market exit at the candle `price` fills at, crossing half of a `spread_bps` spread
+    /// to sell at the bid for Long and buy at the ask for Short. A limit exit fills at its own price.
+    pub fn exit_fill(order: &Order, price: f64, spread_bps: f64) -> f64 {
+        let half_spread = price * spread_bps / 10_000.0 / 2.0;
+        match order {
+            Order::Long => price - half_spread,
+            Order::Short => price + half_spread,
+        }
     }
 
     pub fn calc_stop_loss(order: Order, price: f64, stop_loss_pct: f64) -> f64 {
@@ -150,6 +426,25 @@ impl Trade {
         }
     }
 
+    /// Check if the trade has been open longer than its max hold as of `date`.
+    pub fn hold_exceeded(&self, date: &Time) -> bool {
+        match self.max_hold {
+            Some(max_hold) => {
+                date.to_unix() - self.entry_date.to_unix() > max_hold.as_secs() as i64
+            }
+            None => false,
+        }
+    }
+
+    /// Check if candle close crossed the trailing stop or stop loss.
+    pub fn stop_triggered(&self, candle: &Candle) -> bool {
+        let stops = [self.trailing_stop, self.stop_loss];
+        match self.order {
+            Order::Long => stops.iter().flatten().any(|stop| candle.close < *stop),
+            Order::Short => stops.iter().flatten().any(|stop| candle.close > *stop),
+        }
+    }
+
     /// Find value of one pip for a given candle price.
     /// One pip equals the smallest decimal place of ticker.
     fn find_pip_value(price: f64) -> f64 {
@@ -189,30 +484,178 @@ impl Trade {
 pub struct Backtest {
     pub trades: Vec<Trade>,
     pub pnl: Option<f64>,
-    pub capital: f64,
+    /// Quote asset equity before the first trade
+    pub starting_equity: f64,
+    /// Quote asset committed to each new trade
+    pub sizing: PositionSizing,
+    /// Bid/ask spread in bips around candle prices, crossed by every market entry and exit
+    pub spread_bps: f64,
+    /// Fees charged on added trades that have none of their own
+    pub fees: Fees,
     pub start_date: Option<Time>,
     pub end_date: Option<Time>,
     pub avg_trade_pnl: Option<f64>,
     pub avg_win_trade_pnl: Option<f64>,
     pub avg_loss_trade_pnl: Option<f64>,
+    /// Average realized R multiple of closed trades with a stop loss, i.e. expectancy in R
+    pub avg_r_multiple: Option<f64>,
+    /// Trades per year used to annualize the Sharpe and Sortino ratios, 1.0 leaves them per trade
+    pub periods_per_year: f64,
+    pub sharpe_ratio: Option<f64>,
+    pub sortino_ratio: Option<f64>,
+    /// Reject overlapping trades instead of logging a warning
+    pub strict: bool,
+    /// Trades open at once before they overlap, above one for units of a pyramided position
+    pub max_open_trades: usize,
+    /// Trade entered but not yet exited, kept by candle by candle strategies that can resume
+    pub open_trade: Option<Trade>,
+    /// Last candle processed by a strategy that can resume
+    pub last_candle: Option<Candle>,
+}
+
+/// Summary of a backtest with zeros in place of missing values, e.g. for a backtest without trades.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BacktestReport {
+    /// Entry date of the first trade, empty without trades
+    pub start_date: String,
+    /// Entry date of the last trade, empty without trades
+    pub end_date: String,
+    pub pnl: f64,
+    pub avg_trade_pnl: f64,
+    pub avg_win_trade_pnl: f64,
+    pub avg_loss_trade_pnl: f64,
+    pub avg_r_multiple: f64,
+    pub sharpe_ratio: f64,
+    pub sortino_ratio: f64,
+    pub win_trades: usize,
+    pub loss_trades: usize,
+    pub trades: usize,
+}
+
+/// Saved progress of a backtest, to resume on new candles without reprocessing history.
+/// Only a strategy that walks candles in order and keeps its open trade and last candle
+/// on the backtest can resume, which is the PLPL backtest (`ephemeris::plpl_backtest_resume`).
+/// PFS backtests project each date from the whole series, so they are rerun instead.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestState {
+    #[serde(alias = "capital")]
+    pub starting_equity: f64,
+    #[serde(default)]
+    pub sizing: PositionSizing,
+    #[serde(default)]
+    pub spread_bps: f64,
+    #[serde(default)]
+    pub fees: Fees,
+    #[serde(default = "default_periods_per_year")]
+    pub periods_per_year: f64,
+    pub trades: Vec<Trade>,
+    pub open_trade: Option<Trade>,
+    pub last_candle: Option<Candle>,
+    pub strict: bool,
+    #[serde(default = "default_max_open_trades")]
+    pub max_open_trades: usize,
+}
+
+fn default_periods_per_year() -> f64 {
+    1.0
 }
+
+fn default_max_open_trades() -> usize {
+    1
+}
+
 impl Backtest {
-    pub fn new(capital: f64) -> Self {
+    /// Backtest that sizes each trade with all of its current equity.
+    pub fn new(starting_equity: f64) -> Self {
         Self {
             trades: vec![],
             pnl: None,
-            capital,
+            starting_equity,
+            sizing: PositionSizing::default(),
+            spread_bps: 0.0,
+            fees: Fees::default(),
             start_date: None,
             end_date: None,
             avg_trade_pnl: None,
             avg_win_trade_pnl: None,
             avg_loss_trade_pnl: None,
+            avg_r_multiple: None,
+            periods_per_year: default_periods_per_year(),
+            sharpe_ratio: None,
+            sortino_ratio: None,
+            strict: false,
+            max_open_trades: default_max_open_trades(),
+            open_trade: None,
+            last_candle: None,
         }
     }
 
-    pub fn add_trade(&mut self, trade: Trade) {
+    /// Charge `fees` on trades added to the backtest
+    pub fn with_fees(mut self, fees: Fees) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// Save the progress of a backtest to resume, see [`BacktestState`] for which strategies can
+    pub fn checkpoint(&self) -> BacktestState {
+        BacktestState {
+            starting_equity: self.starting_equity,
+            sizing: self.sizing,
+            spread_bps: self.spread_bps,
+            fees: self.fees,
+            periods_per_year: self.periods_per_year,
+            trades: self.trades.clone(),
+            open_trade: self.open_trade.clone(),
+            last_candle: self.last_candle.clone(),
+            strict: self.strict,
+            max_open_trades: self.max_open_trades,
+        }
+    }
+
+    /// Restore a backtest from a checkpoint with its pnl and summary recomputed.
+    pub fn from_checkpoint(state: BacktestState) -> Self {
+        let mut backtest = Self::new(state.starting_equity);
+        backtest.sizing = state.sizing;
+        backtest.spread_bps = state.spread_bps;
+        backtest.fees = state.fees;
+        backtest.periods_per_year = state.periods_per_year;
+        backtest.trades = state.trades;
+        backtest.open_trade = state.open_trade;
+        backtest.last_candle = state.last_candle;
+        backtest.strict = state.strict;
+        backtest.max_open_trades = state.max_open_trades;
+        backtest.pnl = backtest.pnl();
+        backtest.summarize();
+        backtest
+    }
+
+    /// Only `max_open_trades` can be open at a time, so a trade that enters before that many previous trades exit
+    /// is a bug in the strategy loop. If `strict` the trade is rejected, otherwise a warning is logged.
+    pub fn add_trade(&mut self, mut trade: Trade) -> BacktestResult<()> {
+        if trade.fees.is_none() {
+            trade.apply_fees(self.fees);
+        }
+        let open_exits = self
+            .trades
+            .iter()
+            .map(|prev_trade| prev_trade.exit_date.unwrap_or(prev_trade.entry_date))
+            .filter(|prev_exit| trade.entry_date < *prev_exit)
+            .collect::<Vec<Time>>();
+        if open_exits.len() >= self.max_open_trades {
+            if let Some(prev_exit) = open_exits.last() {
+                let err = BacktestError::OverlappingTrades {
+                    prev_exit: *prev_exit,
+                    entry: trade.entry_date,
+                };
+                if self.strict {
+                    return Err(err);
+                }
+                warn!("{}", err);
+            }
+        }
         self.trades.push(trade);
         self.pnl = self.pnl();
+        Ok(())
     }
 
     pub fn pnl(&self) -> Option<f64> {
@@ -243,6 +686,16 @@ impl Backtest {
         pnl
     }
 
+    /// Starting equity plus the quote asset pnl of every closed trade.
+    pub fn equity(&self) -> f64 {
+        self.starting_equity + self.quote_asset_pnl()
+    }
+
+    /// Quote asset amount to commit to the next trade entered at `price`, so closed trades compound.
+    pub fn trade_capital(&self, price: f64, stop_loss: Option<f64>) -> f64 {
+        self.sizing.trade_capital(self.equity(), price, stop_loss)
+    }
+
     pub fn avg_trade_pnl(&self) -> Option<f64> {
         if let Some(pnl) = self.pnl {
             let trades = self.trades.len();
@@ -320,6 +773,121 @@ impl Backtest {
         trades
     }
 
+    pub fn avg_r_multiple(&self) -> Option<f64> {
+        let r_multiples = self
+            .trades
+            .iter()
+            .filter_map(|trade| trade.r_multiple())
+            .collect::<Vec<f64>>();
+        if r_multiples.is_empty() {
+            None
+        } else {
+            Some(r_multiples.iter().sum::<f64>() / r_multiples.len() as f64)
+        }
+    }
+
+    /// Equity after each closed trade in order of exit, starting from `starting_equity`.
+    /// Open trades are not included.
+    pub fn equity_curve(&self) -> Vec<(Time, f64)> {
+        let mut closed = self
+            .trades
+            .iter()
+            .filter_map(|trade| trade.exit_date.map(|exit_date| (exit_date, trade)))
+            .collect::<Vec<(Time, &Trade)>>();
+        closed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let mut equity = self.starting_equity;
+        closed
+            .into_iter()
+            .map(|(exit_date, trade)| {
+                equity += trade.quote_asset_pnl();
+                (exit_date, equity)
+            })
+            .collect()
+    }
+
+    /// Largest percent decline of the equity curve from a prior peak, including the starting equity
+    pub fn max_drawdown(&self) -> f64 {
+        let mut peak = self.starting_equity;
+        let mut max_drawdown = 0.0;
+        for (_, equity) in self.equity_curve() {
+            peak = f64::max(peak, equity);
+            if peak > 0.0 {
+                max_drawdown = f64::max(max_drawdown, (peak - equity) / peak * 100.0);
+            }
+        }
+        max_drawdown
+    }
+
+    /// Fractional return of each closed trade on its capital
+    fn trade_returns(&self) -> Vec<f64> {
+        self.trades
+            .iter()
+            .filter_map(|trade| trade.pnl)
+            .map(|pnl| pnl / 100.0)
+            .collect()
+    }
+
+    /// Mean trade return over the sample standard deviation of trade returns,
+    /// annualized by the square root of `periods_per_year`.
+    /// None with fewer than two closed trades or returns without variance.
+    pub fn sharpe_ratio(&self, periods_per_year: f64) -> Option<f64> {
+        let returns = self.trade_returns();
+        if returns.len() < 2 {
+            return None;
+        }
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let variance =
+            returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64;
+        match variance > 0.0 {
+            true => Some(mean / variance.sqrt() * periods_per_year.sqrt()),
+            false => None,
+        }
+    }
+
+    /// Mean trade return over the downside deviation, the root mean square of losing returns
+    /// with winning returns counted as zero, annualized by the square root of `periods_per_year`.
+    /// None without a losing trade.
+    pub fn sortino_ratio(&self, periods_per_year: f64) -> Option<f64> {
+        let returns = self.trade_returns();
+        if returns.is_empty() {
+            return None;
+        }
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let downside =
+            returns.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>() / returns.len() as f64;
+        match downside > 0.0 {
+            true => Some(mean / downside.sqrt() * periods_per_year.sqrt()),
+            false => None,
+        }
+    }
+
+    /// Summary computed from the trades, whether or not `summarize` was called
+    pub fn report(&self) -> BacktestReport {
+        let date = |trade: Option<&Trade>| {
+            trade
+                .map(|trade| trade.entry_date.to_string_daily())
+                .unwrap_or_default()
+        };
+        let pnl = self.pnl().unwrap_or(0.0);
+        BacktestReport {
+            start_date: date(self.trades.first()),
+            end_date: date(self.trades.last()),
+            pnl,
+            avg_trade_pnl: match self.trades.len() {
+                0 => 0.0,
+                trades => pnl / trades as f64,
+            },
+            avg_win_trade_pnl: self.avg_win_trade_pnl().unwrap_or(0.0),
+            avg_loss_trade_pnl: self.avg_loss_trade_pnl().unwrap_or(0.0),
+            avg_r_multiple: self.avg_r_multiple().unwrap_or(0.0),
+            sharpe_ratio: self.sharpe_ratio(self.periods_per_year).unwrap_or(0.0),
+            sortino_ratio: self.sortino_ratio(self.periods_per_year).unwrap_or(0.0),
+            win_trades: self.num_win_trades(),
+            loss_trades: self.num_loss_trades(),
+            trades: self.trades.len(),
+        }
+    }
+
     pub fn summarize(&mut self) {
         if self.trades.is_empty() {
             return;
@@ -329,5 +897,502 @@ impl Backtest {
         self.avg_trade_pnl = self.avg_trade_pnl();
         self.avg_win_trade_pnl = self.avg_win_trade_pnl();
         self.avg_loss_trade_pnl = self.avg_loss_trade_pnl();
+        self.avg_r_multiple = self.avg_r_multiple();
+        self.sharpe_ratio = self.sharpe_ratio(self.periods_per_year);
+        self.sortino_ratio = self.sortino_ratio(self.periods_per_year);
+    }
+}
+
+/// Run the same strategy and params on candles loaded from each CSV,
+/// e.g. one file per timeframe, and tag each backtest with its file.
+pub fn backtest_multi<P, E: From<TickerDataError>>(
+    paths: &[PathBuf],
+    strategy: impl Fn(&TickerData, &P) -> Result<Backtest, E>,
+    params: &P,
+) -> Result<Vec<(PathBuf, Backtest)>, E> {
+    let mut results = Vec::<(PathBuf, Backtest)>::new();
+    for path in paths.iter() {
+        let mut ticker_data = TickerData::new();
+        ticker_data.add_csv_series(path)?;
+        results.push((path.clone(), strategy(&ticker_data, params)?));
+    }
+    Ok(results)
+}
+
+/// Run the same strategy and params on successive windows of `window_days`,
+/// each starting `step_days` after the previous, to compare performance across regimes.
+/// Only windows that fit within the candles are run, and each backtest is tagged
+/// with the first and last date of its window.
+pub fn rolling_window_backtest<P, E: From<TickerDataError>>(
+    ticker_data: &TickerData,
+    window_days: u32,
+    step_days: u32,
+    strategy: impl Fn(&TickerData, &P) -> Result<Backtest, E>,
+    params: &P,
+) -> Result<Vec<(Time, Time, Backtest)>, E> {
+    if window_days == 0 || step_days == 0 {
+        return Err(TickerDataError::CustomError(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Rolling window and step must be at least one day",
+        ))
+        .into());
+    }
+    let mut results = Vec::<(Time, Time, Backtest)>::new();
+    let candles = ticker_data.get_candles();
+    let (first, last) = match (candles.first(), candles.last()) {
+        (Some(first), Some(last)) => (first.date, last.date),
+        _ => return Ok(results),
+    };
+    let mut start = first;
+    while start.delta_date(window_days as i64 - 1) <= last {
+        let end = start.delta_date(window_days as i64);
+        let window = candles
+            .iter()
+            .filter(|c| c.date >= start && c.date < end)
+            .cloned()
+            .collect::<Vec<Candle>>();
+        if !window.is_empty() {
+            let mut window_data = TickerData::new();
+            window_data.add_series(window)?;
+            let backtest = strategy(&window_data, params)?;
+            results.push((start, start.delta_date(window_days as i64 - 1), backtest));
+        }
+        start = start.delta_date(step_days as i64);
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Day, Month};
+
+    /// Day of January 2023
+    fn date(day: u32) -> Time {
+        Time::new(2023, &Month::from_num(1), &Day::from_num(day), None, None)
+    }
+
+    fn trade(entry_day: u32, exit_day: u32) -> Trade {
+        let mut trade = Trade::new(date(entry_day), Order::Long, 1.0, 100.0, 100.0, None, None);
+        trade.exit(date(exit_day), 101.0);
+        trade
+    }
+
+    #[test]
+    fn direction_reversal_order_conversions() {
+        assert_eq!(Order::from(Direction::Up), Order::Long);
+        assert_eq!(Order::from(Direction::Down), Order::Short);
+        assert_eq!(Direction::from(Order::Long), Direction::Up);
+        assert_eq!(Direction::from(Order::Short), Direction::Down);
+        // reversals invert the prior move
+        assert_eq!(Order::from(ReversalType::Low), Order::Long);
+        assert_eq!(Order::from(ReversalType::High), Order::Short);
+        assert_eq!(Direction::from(ReversalType::Low), Direction::Up);
+        assert_eq!(Direction::from(ReversalType::High), Direction::Down);
+    }
+
+    #[test]
+    fn sizing_modes() {
+        // $1,000 at 250 is 4 contracts regardless of equity
+        let capital = PositionSizing::FixedDollar(1_000.0).trade_capital(50_000.0, 250.0, None);
+        assert_eq!(Trade::trade_quantity(capital, 250.0), 4.0);
+        // 10% of $20,000 is $2,000, or 8 contracts at 250
+        let capital = PositionSizing::FixedFraction(0.1).trade_capital(20_000.0, 250.0, None);
+        assert_eq!(Trade::trade_quantity(capital, 250.0), 8.0);
+        // 1% of $20,000 is $200 at risk, 5 from entry to stop is 40 contracts
+        let risk = PositionSizing::RiskPerTrade(0.01);
+        let capital = risk.trade_capital(20_000.0, 250.0, Some(245.0));
+        let qty = Trade::trade_quantity(capital, 250.0);
+        assert_eq!(qty, 40.0);
+        assert_eq!(qty * (250.0 - 245.0), 200.0);
+        // a short's stop above entry risks the same distance
+        let capital = risk.trade_capital(20_000.0, 250.0, Some(255.0));
+        assert_eq!(Trade::trade_quantity(capital, 250.0), 40.0);
+        // 5% at risk with a stop 1% away would be 5x equity, so it commits all of it
+        let capital =
+            PositionSizing::RiskPerTrade(0.05).trade_capital(20_000.0, 250.0, Some(247.5));
+        assert_eq!(capital, 20_000.0);
+        // no distance to the stop, nothing to size against
+        assert_eq!(risk.trade_capital(20_000.0, 250.0, Some(250.0)), 0.0);
+        assert_eq!(risk.trade_capital(20_000.0, 250.0, None), 0.0);
+    }
+
+    #[test]
+    fn overlapping_trades() -> BacktestResult<()> {
+        let mut backtest = Backtest::new(100.0);
+        backtest.strict = true;
+        backtest.add_trade(trade(1, 5))?;
+        // entry on the previous exit date does not overlap
+        backtest.add_trade(trade(5, 8))?;
+        assert!(matches!(
+            backtest.add_trade(trade(7, 10)),
+            Err(BacktestError::OverlappingTrades { .. })
+        ));
+        assert_eq!(backtest.num_trades(), 2);
+
+        // non-strict backtest keeps the trade and only warns
+        backtest.strict = false;
+        backtest.add_trade(trade(7, 10))?;
+        assert_eq!(backtest.num_trades(), 3);
+
+        // units of a pyramided position overlap up to the open trade limit
+        let mut backtest = Backtest::new(100.0);
+        backtest.strict = true;
+        backtest.max_open_trades = 2;
+        backtest.add_trade(trade(1, 5))?;
+        backtest.add_trade(trade(3, 6))?;
+        assert!(matches!(
+            backtest.add_trade(trade(4, 7)),
+            Err(BacktestError::OverlappingTrades { .. })
+        ));
+        backtest.add_trade(trade(5, 7))?;
+        assert_eq!(backtest.num_trades(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn fixed_fraction_sizing_compounds() -> BacktestResult<()> {
+        let mut backtest = Backtest::new(10_000.0);
+        backtest.sizing = PositionSizing::FixedFraction(0.1);
+
+        // 10% of $10k, and a 10% gain grows equity by $100
+        let capital = backtest.trade_capital(100.0, None);
+        assert_eq!(capital, 1000.0);
+        let mut trade = Trade::new(
+            date(1),
+            Order::Long,
+            Trade::trade_quantity(capital, 100.0),
+            100.0,
+            capital,
+            None,
+            None,
+        );
+        trade.exit(date(2), 110.0);
+        backtest.add_trade(trade)?;
+        assert!((backtest.equity() - 10_100.0).abs() < 1e-6);
+
+        // next trade is 10% of the compounded equity
+        let capital = backtest.trade_capital(110.0, None);
+        assert!((capital - 1010.0).abs() < 1e-6);
+        let mut trade = Trade::new(
+            date(2),
+            Order::Short,
+            Trade::trade_quantity(capital, 110.0),
+            110.0,
+            capital,
+            None,
+            None,
+        );
+        trade.exit(date(3), 99.0);
+        backtest.add_trade(trade)?;
+        assert!((backtest.equity() - 10_201.0).abs() < 1e-4);
+
+        // sizing survives a checkpoint
+        let restored = Backtest::from_checkpoint(backtest.checkpoint());
+        assert_eq!(restored.sizing, PositionSizing::FixedFraction(0.1));
+        assert!((restored.trade_capital(99.0, None) - 1020.1).abs() < 1e-4);
+
+        // risking 1% of equity with a stop 5% away commits 20% of equity
+        let mut backtest = restored;
+        backtest.sizing = PositionSizing::RiskPerTrade(0.01);
+        assert!((backtest.trade_capital(100.0, Some(95.0)) - 2040.2).abs() < 1e-4);
+        Ok(())
+    }
+
+    #[test]
+    fn backtest_multiple_csvs() -> Result<(), TickerDataError> {
+        // named for this test and process, so concurrent test runs do not write the same files
+        let path = |timeframe: &str| {
+            std::env::temp_dir().join(format!(
+                "backtest_multiple_csvs_{}_{}.csv",
+                timeframe,
+                std::process::id()
+            ))
+        };
+        let daily = path("daily");
+        let hourly = path("hourly");
+        let write = |path: &PathBuf, step: i64, closes: &[f64]| {
+            let mut csv = "date,open,high,low,close,volume\n".to_string();
+            for (i, close) in closes.iter().enumerate() {
+                csv += &format!(
+                    "{},{},{},{},{},NaN\n",
+                    1672531200 + step * i as i64,
+                    close,
+                    close,
+                    close,
+                    close
+                );
+            }
+            std::fs::write(path, csv).map_err(TickerDataError::CustomError)
+        };
+        write(&daily, 86400, &[100.0, 110.0, 120.0])?;
+        write(&hourly, 3600, &[100.0, 90.0, 95.0, 105.0])?;
+
+        // buy the first close and sell the last close
+        let buy_and_hold = |ticker_data: &TickerData, capital: &f64| {
+            let candles = ticker_data.get_candles();
+            let (first, last) = (&candles[0], &candles[candles.len() - 1]);
+            let mut trade = Trade::new(
+                first.date,
+                Order::Long,
+                Trade::trade_quantity(*capital, first.close),
+                first.close,
+                *capital,
+                None,
+                None,
+            );
+            trade.exit(last.date, last.close);
+            let mut backtest = Backtest::new(*capital);
+            backtest
+                .add_trade(trade)
+                .expect("single trade cannot overlap");
+            Ok::<_, TickerDataError>(backtest)
+        };
+        let paths = vec![daily.clone(), hourly.clone()];
+        let results = backtest_multi(&paths, buy_and_hold, &1000.0)?;
+        std::fs::remove_file(&daily).map_err(TickerDataError::CustomError)?;
+        std::fs::remove_file(&hourly).map_err(TickerDataError::CustomError)?;
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, daily);
+        assert_eq!(results[1].0, hourly);
+        assert_eq!(results[0].1.trades[0].exit_price, Some(120.0));
+        assert_eq!(results[1].1.trades[0].exit_price, Some(105.0));
+        Ok(())
+    }
+
+    #[test]
+    fn rolling_yearly_windows() -> Result<(), TickerDataError> {
+        let first = Time::new(2021, &Month::from_num(1), &Day::from_num(1), None, None);
+        let candles = (0..730)
+            .map(|i| {
+                let close = 100.0 + i as f64;
+                Candle {
+                    date: first.delta_date(i),
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    volume: None,
+                }
+            })
+            .collect::<Vec<Candle>>();
+        let mut ticker_data = TickerData::new();
+        ticker_data.add_series(candles)?;
+
+        // buy the first close and sell the last close
+        let buy_and_hold = |ticker_data: &TickerData, capital: &f64| {
+            let candles = ticker_data.get_candles();
+            let (first, last) = (&candles[0], &candles[candles.len() - 1]);
+            let mut trade = Trade::new(
+                first.date,
+                Order::Long,
+                Trade::trade_quantity(*capital, first.close),
+                first.close,
+                *capital,
+                None,
+                None,
+            );
+            trade.exit(last.date, last.close);
+            let mut backtest = Backtest::new(*capital);
+            backtest
+                .add_trade(trade)
+                .expect("single trade cannot overlap");
+            Ok::<_, TickerDataError>(backtest)
+        };
+        let results = rolling_window_backtest(&ticker_data, 365, 365, buy_and_hold, &1000.0)?;
+
+        assert_eq!(results.len(), 2);
+        let date = |year: i32, month: u32, day: u32| {
+            Time::new(
+                year,
+                &Month::from_num(month),
+                &Day::from_num(day),
+                None,
+                None,
+            )
+            .to_unix()
+        };
+        let bounds = results
+            .iter()
+            .map(|(start, end, _)| (start.to_unix(), end.to_unix()))
+            .collect::<Vec<(i64, i64)>>();
+        assert_eq!(
+            bounds,
+            vec![
+                (date(2021, 1, 1), date(2021, 12, 31)),
+                (date(2022, 1, 1), date(2022, 12, 31))
+            ]
+        );
+        // windows do not share candles
+        let trade = |i: usize| &results[i].2.trades[0];
+        assert_eq!(trade(0).exit_price, Some(464.0));
+        assert_eq!(trade(1).entry_price, 465.0);
+        assert!(trade(0).exit_date.unwrap() < trade(1).entry_date);
+
+        assert!(rolling_window_backtest(&ticker_data, 365, 0, buy_and_hold, &1000.0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn realized_risk_reward() {
+        let mut long = Trade::new(date(1), Order::Long, 1.0, 100.0, 100.0, None, Some(95.0));
+        assert_eq!(long.risk_reward(), None);
+        long.target = Some(115.0);
+        assert_eq!(long.risk_reward(), Some(3.0));
+        long.exit(date(2), 110.0);
+        assert_eq!(long.r_multiple(), Some(2.0));
+
+        // a short stopped out loses 1R
+        let mut short = Trade::new(date(3), Order::Short, 1.0, 100.0, 100.0, None, Some(105.0));
+        short.exit(date(4), 105.0);
+        assert_eq!(short.risk_reward(), Some(-1.0));
+        let mut no_stop = Trade::new(date(5), Order::Long, 1.0, 100.0, 100.0, None, None);
+        no_stop.exit(date(6), 110.0);
+        assert_eq!(no_stop.risk_reward(), None);
+
+        let mut backtest = Backtest::new(100.0);
+        backtest.trades = vec![long, short, no_stop];
+        backtest.summarize();
+        assert_eq!(backtest.avg_r_multiple, Some(0.5));
+    }
+
+    #[test]
+    fn equity_curve_and_drawdown() -> BacktestResult<()> {
+        let mut backtest = Backtest::new(1000.0);
+        for (entry_day, exit_day, exit_price) in [(1, 2, 110.0), (3, 4, 80.0), (5, 6, 120.0)] {
+            let mut trade = Trade::new(date(entry_day), Order::Long, 5.0, 100.0, 500.0, None, None);
+            trade.exit(date(exit_day), exit_price);
+            backtest.add_trade(trade)?;
+        }
+        // +50, -100, +100
+        let curve = backtest.equity_curve();
+        assert_eq!(
+            curve,
+            vec![(date(2), 1050.0), (date(4), 950.0), (date(6), 1050.0)]
+        );
+        // peak 1050 to 950
+        assert!((backtest.max_drawdown() - 100.0 / 1050.0 * 100.0).abs() < 1e-9);
+        assert_eq!(Backtest::new(1000.0).max_drawdown(), 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn sharpe_and_sortino() -> BacktestResult<()> {
+        let mut backtest = Backtest::new(1000.0);
+        // returns of 2%, -1%, 2%, -1%
+        for (i, exit_price) in [102.0, 99.0, 102.0, 99.0].into_iter().enumerate() {
+            let day = 2 * i as u32 + 1;
+            let mut trade = Trade::new(date(day), Order::Long, 1.0, 100.0, 100.0, None, None);
+            trade.exit(date(day + 1), exit_price);
+            backtest.add_trade(trade)?;
+        }
+        backtest.periods_per_year = 4.0;
+        backtest.summarize();
+
+        // mean 0.005 over a sample deviation of sqrt(0.0003) is 1 / (2 * sqrt(3)) per trade
+        let sharpe = backtest.sharpe_ratio(1.0).unwrap();
+        assert!((sharpe - 1.0 / (2.0 * 3_f64.sqrt())).abs() < 1e-9);
+        assert!((backtest.sharpe_ratio.unwrap() - 1.0 / 3_f64.sqrt()).abs() < 1e-9);
+        // downside deviation sqrt((0.01^2 + 0.01^2) / 4) is 0.01 / sqrt(2)
+        let sortino = backtest.sortino_ratio(1.0).unwrap();
+        assert!((sortino - 1.0 / 2_f64.sqrt()).abs() < 1e-9);
+        assert!((backtest.sortino_ratio.unwrap() - 2_f64.sqrt()).abs() < 1e-9);
+        assert_eq!(
+            backtest.report().sortino_ratio,
+            backtest.sortino_ratio.unwrap()
+        );
+
+        // without a losing trade or variance there is no ratio
+        let mut backtest = Backtest::new(1000.0);
+        backtest.add_trade(trade(1, 2))?;
+        backtest.add_trade(trade(3, 4))?;
+        assert_eq!(backtest.sharpe_ratio(1.0), None);
+        assert_eq!(backtest.sortino_ratio(1.0), None);
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip_fees() -> BacktestResult<()> {
+        let round_trip = |fees: Fees| -> BacktestResult<Backtest> {
+            let mut backtest = Backtest::new(100.0).with_fees(fees);
+            let mut trade = Trade::new(date(1), Order::Long, 1.0, 100.0, 100.0, None, None);
+            trade.exit(date(2), 110.0);
+            backtest.add_trade(trade)?;
+            Ok(backtest)
+        };
+        let frictionless = round_trip(Fees::default())?;
+        let trade = &frictionless.trades[0];
+        assert_eq!((trade.gross_pnl, trade.pnl), (Some(10.0), Some(10.0)));
+
+        let taker = Fees {
+            taker_bps: 10.0,
+            ..Default::default()
+        };
+        let with_fees = round_trip(taker)?;
+        let trade = &with_fees.trades[0];
+        // 10 bps of the 100 entry and 110 exit notional
+        assert_eq!(trade.gross_pnl, Some(10.0));
+        assert!((trade.pnl.unwrap() - 9.79).abs() < 1e-9);
+        assert!((with_fees.equity() - 109.79).abs() < 1e-9);
+        assert!(with_fees.pnl().unwrap() < frictionless.pnl().unwrap());
+
+        // a trade with its own fees keeps them, even if they are zero
+        let mut backtest = Backtest::new(100.0).with_fees(taker);
+        let mut trade = Trade::new(date(1), Order::Long, 1.0, 100.0, 100.0, None, None);
+        trade.apply_fees(Fees::default());
+        trade.exit(date(2), 110.0);
+        backtest.add_trade(trade)?;
+        assert_eq!(backtest.trades[0].pnl, Some(10.0));
+
+        // a limit exit pays the maker fee instead of taker fees and slippage
+        let fees = Fees {
+            maker_bps: 2.0,
+            taker_bps: 10.0,
+            slippage_bps: 5.0,
+        };
+        let mut backtest = Backtest::new(100.0).with_fees(fees);
+        let mut trade = Trade::new(date(1), Order::Long, 1.0, 100.0, 100.0, None, None);
+        trade.exit_limit(date(2), 110.0);
+        backtest.add_trade(trade)?;
+        // 15 bps of the 100 entry and 2 bps of the 110 exit notional
+        assert!((backtest.trades[0].fee_cost() - 0.172).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn backtest_report() -> BacktestResult<()> {
+        let backtest = Backtest::new(100.0);
+        assert_eq!(backtest.report(), BacktestReport::default());
+        assert_eq!(backtest.report().pnl, 0.0);
+        assert_eq!(backtest.report().trades, 0);
+
+        let mut backtest = Backtest::new(100.0);
+        backtest.add_trade(trade(1, 2))?;
+        let mut loss = trade(3, 4);
+        loss.stop_loss = Some(98.0);
+        loss.exit(*loss.exit_date.as_ref().unwrap(), 99.5);
+        backtest.add_trade(loss)?;
+        backtest.summarize();
+
+        let report = backtest.report();
+        assert_eq!(
+            report.start_date,
+            backtest.start_date.unwrap().to_string_daily()
+        );
+        assert_eq!(
+            report.end_date,
+            backtest.end_date.unwrap().to_string_daily()
+        );
+        assert_eq!(Some(report.pnl), backtest.pnl);
+        assert_eq!(Some(report.avg_trade_pnl), backtest.avg_trade_pnl);
+        assert_eq!(Some(report.avg_win_trade_pnl), backtest.avg_win_trade_pnl);
+        assert_eq!(Some(report.avg_loss_trade_pnl), backtest.avg_loss_trade_pnl);
+        assert_eq!(report.avg_r_multiple, -0.25);
+        assert_eq!(
+            (report.win_trades, report.loss_trades, report.trades),
+            (1, 1, 2)
+        );
+        Ok(())
     }
 }
@@ -1,9 +1,10 @@
 use crate::Time;
+use serde::{Deserialize, Serialize};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 
 /// Event for a single candlestick for a given ticker.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Candle {
     /// UNIX timestamp in seconds
     pub date: Time,
@@ -23,6 +24,60 @@ impl Candle {
     pub fn percent_change(&self, prev_close: f64) -> f64 {
         ((100.0 / prev_close) * self.close) - 100.0
     }
+
+    /// Percent change from `prev_close` measured on the price `scale`.
+    pub fn scaled_percent_change(&self, prev_close: f64, scale: PriceScale) -> f64 {
+        scale.percent_change(prev_close, self.close)
+    }
+
+    /// Greatest of the candle range and the gaps from `prev_close` to the high and low.
+    pub fn true_range(&self, prev_close: f64) -> f64 {
+        (self.high - self.low)
+            .max((self.high - prev_close).abs())
+            .max((self.low - prev_close).abs())
+    }
+
+    /// Average true range of the last `period` candles, oldest first.
+    /// Needs `period + 1` candles so the first true range has a previous close.
+    pub fn atr(candles: &[Candle], period: usize) -> Option<f64> {
+        if period == 0 || candles.len() < period + 1 {
+            return None;
+        }
+        let recent = &candles[candles.len() - period - 1..];
+        let sum = recent
+            .windows(2)
+            .map(|pair| pair[1].true_range(pair[0].close))
+            .sum::<f64>();
+        Some(sum / period as f64)
+    }
+}
+
+/// Price axis used to measure returns between candles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PriceScale {
+    #[default]
+    Linear,
+    /// Natural log of price, so equal percent moves are the same size at any price level.
+    /// Only defined for positive prices.
+    Log,
+}
+
+impl PriceScale {
+    pub fn scale_price(&self, price: f64) -> f64 {
+        match self {
+            PriceScale::Linear => price,
+            PriceScale::Log => price.ln(),
+        }
+    }
+
+    /// Percent change from `prev_close` to `close`.
+    /// On a log scale this is the log return, `100 * ln(close / prev_close)`.
+    pub fn percent_change(&self, prev_close: f64, close: f64) -> f64 {
+        match self {
+            PriceScale::Linear => ((100.0 / prev_close) * close) - 100.0,
+            PriceScale::Log => (self.scale_price(close) - self.scale_price(prev_close)) * 100.0,
+        }
+    }
 }
 
 impl PartialEq for Candle {
@@ -31,6 +86,58 @@ impl PartialEq for Candle {
     }
 }
 
+/// Previous and current candle a crossover signal is evaluated on.
+/// A current candle can only be cached after a previous candle.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum CandleCache {
+    #[default]
+    Empty,
+    /// First candle, which has no previous candle to compare against
+    One(Candle),
+    /// Previous and current candle
+    Two(Candle, Candle),
+}
+
+impl CandleCache {
+    /// Cache the incoming candle as the current candle
+    /// and return the previous and current candle once there are two.
+    pub fn push(&mut self, candle: Candle) -> Option<(Candle, Candle)> {
+        *self = match std::mem::take(self) {
+            CandleCache::Empty => CandleCache::One(candle),
+            CandleCache::One(prev) | CandleCache::Two(_, prev) => CandleCache::Two(prev, candle),
+        };
+        match self {
+            CandleCache::Two(prev, curr) => Some((prev.clone(), curr.clone())),
+            _ => None,
+        }
+    }
+
+    /// Drop cached candles and start over from `candle`
+    pub fn reset(&mut self, candle: Candle) {
+        *self = CandleCache::One(candle);
+    }
+
+    pub fn prev(&self) -> Option<&Candle> {
+        match self {
+            CandleCache::Empty => None,
+            CandleCache::One(prev) | CandleCache::Two(prev, _) => Some(prev),
+        }
+    }
+
+    /// Current candle, None until a second candle arrives
+    pub fn curr(&self) -> Option<&Candle> {
+        match self {
+            CandleCache::Two(_, curr) => Some(curr),
+            _ => None,
+        }
+    }
+
+    /// Most recently cached candle
+    pub fn last(&self) -> Option<&Candle> {
+        self.curr().or(self.prev())
+    }
+}
+
 pub trait CandleTrait {
     fn unix_date(&self) -> u64;
 }
@@ -65,3 +172,45 @@ impl CandleHashTrait for CandleHasher {
         self.finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Day, Month};
+
+    #[test]
+    fn candle_cache_arrivals() {
+        let candle = |day: u32| {
+            let close = 100.0 + day as f64;
+            Candle {
+                date: Time::new(2023, &Month::from_num(1), &Day::from_num(day), None, None),
+                open: close,
+                high: close,
+                low: close,
+                close,
+                volume: None,
+            }
+        };
+        let mut cache = CandleCache::default();
+        assert!(cache.last().is_none());
+
+        assert!(cache.push(candle(1)).is_none());
+        assert_eq!(cache, CandleCache::One(candle(1)));
+        assert!(cache.curr().is_none());
+
+        for day in 2..6 {
+            let (prev, curr) = cache.push(candle(day)).expect("expected candle pair");
+            assert_eq!(prev, candle(day - 1));
+            assert_eq!(curr, candle(day));
+            assert_eq!(cache, CandleCache::Two(candle(day - 1), candle(day)));
+            assert_eq!(cache.last(), Some(&candle(day)));
+        }
+
+        // a reset keeps the new candle as the previous candle rather than the current candle
+        cache.reset(candle(10));
+        assert_eq!(cache.prev(), Some(&candle(10)));
+        assert!(cache.curr().is_none());
+        let (prev, curr) = cache.push(candle(11)).expect("expected candle pair");
+        assert_eq!((prev, curr), (candle(10), candle(11)));
+    }
+}
@@ -1,10 +1,17 @@
-use crate::{Candle, MarketStructure, MarketStructureError, Time, TimeError};
+use crate::{
+    Candle, DetrendMethod, HurstError, MarketStructure, MarketStructureError, ReversalType,
+    TickerData, TickerDataError, Time, TimeError, Timeframe,
+};
 use chrono::Duration;
 use log::debug;
 use rayon::prelude::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::f64::consts::PI;
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Clone, Copy)]
 pub enum CycleTimeframe {
@@ -58,6 +65,8 @@ pub enum CycleError {
     EmptyStdDev,
     EmptyZScore,
     MarketStructureError(MarketStructureError),
+    TickerDataError(TickerDataError),
+    HurstError(HurstError),
 }
 
 impl Display for CycleError {
@@ -77,6 +86,8 @@ impl Display for CycleError {
             CycleError::EmptyStdDev => write!(f, "Empty standard deviation"),
             CycleError::EmptyZScore => write!(f, "Empty zscore"),
             CycleError::MarketStructureError(e) => write!(f, "Market structure error: {}", e),
+            CycleError::TickerDataError(e) => write!(f, "Ticker data error: {}", e),
+            CycleError::HurstError(e) => write!(f, "Hurst error: {}", e),
         }
     }
 }
@@ -111,6 +122,28 @@ pub struct CycleCorrelation {
     pub correlation: f64,
 }
 
+impl CycleCorrelation {
+    /// Wilson score interval of the hit rate `correlated / total` at `z` standard deviations,
+    /// e.g. 1.96 for 95% confidence. Without samples the interval is (0.0, 1.0).
+    pub fn wilson_interval(&self, z: f64) -> (f64, f64) {
+        if self.total == 0 {
+            return (0.0, 1.0);
+        }
+        let n = self.total as f64;
+        let p = self.correlated as f64 / n;
+        let z2 = z * z;
+        let denom = 1.0 + z2 / n;
+        let center = (p + z2 / (2.0 * n)) / denom;
+        let margin = z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / denom;
+        ((center - margin).max(0.0), (center + margin).min(1.0))
+    }
+
+    /// Lower bound of the Wilson score interval, which ranks small samples conservatively
+    pub fn lower_bound(&self, z: f64) -> f64 {
+        self.wilson_interval(z).0
+    }
+}
+
 impl SineCycle {
     pub fn new(
         start_date: Time,
@@ -128,12 +161,18 @@ impl SineCycle {
 
     /// TODO: compute wave function amplitude based on Self::sine_wave price magnitude ?
     pub fn wave_function(&self, x: &Time) -> CycleResult<f64> {
+        self.wave_function_phased(x, 0.0)
+    }
+
+    /// Sine wave with its trough delayed from the start date by `phase` fractions of the period
+    fn wave_function_phased(&self, x: &Time, phase: f32) -> CycleResult<f64> {
         let period = x
             .diff_minutes(&self.start_date)
             .map_err(CycleError::TimeError)? as f64;
 
-        // phase shift the sine wave to start at trough (-90 degrees)
-        let phase_shift = -PI / 2.0;
+        // phase shift the sine wave to start at trough (-90 degrees),
+        // delayed by `phase` since `period` counts back from the start date
+        let phase_shift = -PI / 2.0 + 2.0 * PI * phase as f64;
 
         let omega = 2.0 * PI / self.cycle_period_minutes as f64;
         let sine = (phase_shift + period * omega).sin();
@@ -161,18 +200,74 @@ impl SineCycle {
 
     /// Measure sine wave correlation to time series by using comparing slope of Candle to SineCycle slope
     pub fn series_correlation(&self, series: &MarketStructure) -> CycleResult<CycleCorrelation> {
+        self.series_correlation_phased(series, 0.0, None)
+    }
+
+    /// Measure sine wave correlation to time series with the trend of price removed first by `detrend`,
+    /// so a strongly trending series does not bias price slopes in the trend direction.
+    /// `None` measures the raw series like `series_correlation`.
+    pub fn detrended_correlation(
+        &self,
+        series: &MarketStructure,
+        detrend: Option<DetrendMethod>,
+    ) -> CycleResult<CycleCorrelation> {
+        self.series_correlation_phased(series, 0.0, detrend)
+    }
+
+    /// Slide the phase of the sine wave across `phase_steps` even fractions of the period
+    /// and return the phase, as a fraction of the period, with the highest correlation.
+    /// Ties keep the earliest phase, and a phase of 0.0 starts the wave at its trough.
+    pub fn best_phase_correlation(
+        &self,
+        market_structure: &MarketStructure,
+        phase_steps: usize,
+    ) -> CycleResult<(f32, CycleCorrelation)> {
+        let phase_steps = phase_steps.max(1);
+        let mut best: Option<(f32, CycleCorrelation)> = None;
+        for step in 0..phase_steps {
+            let phase = step as f32 / phase_steps as f32;
+            let correlation = self.series_correlation_phased(market_structure, phase, None)?;
+            if !matches!(&best, Some((_, best)) if best.correlation >= correlation.correlation) {
+                best = Some((phase, correlation));
+            }
+        }
+        Ok(best.expect("At least one phase is measured"))
+    }
+
+    fn series_correlation_phased(
+        &self,
+        series: &MarketStructure,
+        phase: f32,
+        detrend: Option<DetrendMethod>,
+    ) -> CycleResult<CycleCorrelation> {
+        let last = series.candles.last().ok_or(CycleError::EmptySeries)?;
+        // with detrending the first major low and the slopes are both taken from the detrended series
+        let series = match detrend {
+            Some(method) => {
+                let mut ticker_data = TickerData::new();
+                ticker_data
+                    .add_series(series.candles.clone())
+                    .map_err(CycleError::TickerDataError)?;
+                let detrended = ticker_data
+                    .detrended(method)
+                    .map_err(CycleError::TickerDataError)?;
+                if detrended.candles.is_empty() {
+                    return Err(CycleError::EmptySeries);
+                }
+                Cow::Owned(MarketStructure::new(
+                    &detrended,
+                    series.reversal_candle_range,
+                ))
+            }
+            None => Cow::Borrowed(series),
+        };
         // start cycle analysis at first major low of time series
         let first_low = series
             .first_low()
             .map_err(CycleError::MarketStructureError)?;
-        match series.candles.last() {
-            Some(last) => {
-                // assert time series dates are less than or equal to cycle dates
-                if first_low.candle.date < self.start_date || self.end_date < last.date {
-                    return Err(CycleError::TimeSeriesDatesBeyondCycle);
-                }
-            }
-            _ => return Err(CycleError::EmptySeries),
+        // assert time series dates are less than or equal to cycle dates
+        if first_low.candle.date < self.start_date || self.end_date < last.date {
+            return Err(CycleError::TimeSeriesDatesBeyondCycle);
         }
 
         // filter series.candles from self.start_date to self.end_date
@@ -189,8 +284,9 @@ impl SineCycle {
                 // compute slope of price change
                 let price_slope = curr.close - prev.close > 0.0;
                 // compute slope of sine wave
-                let wave_slope =
-                    self.wave_function(&curr.date)? - self.wave_function(&prev.date)? > 0.0;
+                let wave_slope = self.wave_function_phased(&curr.date, phase)?
+                    - self.wave_function_phased(&prev.date, phase)?
+                    > 0.0;
                 // compare slopes, correlated if sloped are both positive or negative
                 if price_slope == wave_slope {
                     correlated += 1;
@@ -207,6 +303,53 @@ impl SineCycle {
     }
 }
 
+/// Start date, end date and period in minutes of a sine cycle
+type SineCycleKey = (i64, i64, u64);
+
+/// Sine waves memoized by dates and period,
+/// so composites that share a component sum the cached wave instead of recomputing it.
+#[derive(Debug, Default)]
+pub struct SineCycleCache {
+    waves: Mutex<HashMap<SineCycleKey, Arc<Vec<CyclePoint>>>>,
+    hits: AtomicUsize,
+}
+
+impl SineCycleCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Evaluate the sine wave of `cycle`, or reuse it if already evaluated.
+    pub fn sine_wave(&self, cycle: &SineCycle) -> CycleResult<Arc<Vec<CyclePoint>>> {
+        let key = (
+            cycle.start_date.to_unix(),
+            cycle.end_date.to_unix(),
+            cycle.cycle_period_minutes,
+        );
+        if let Some(wave) = self.waves.lock().unwrap().get(&key) {
+            self.hits.fetch_add(1, AtomicOrdering::Relaxed);
+            return Ok(wave.clone());
+        }
+        // evaluate without holding the lock so other threads can read cached waves
+        let wave = Arc::new(cycle.sine_wave()?);
+        self.waves.lock().unwrap().insert(key, wave.clone());
+        Ok(wave)
+    }
+
+    pub fn len(&self) -> usize {
+        self.waves.lock().unwrap().len()
+    }
+
+    /// Number of sine waves reused instead of evaluated
+    pub fn hits(&self) -> usize {
+        self.hits.load(AtomicOrdering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 pub type CycleHarmonics = Vec<u32>;
 
 #[derive(Debug, Clone)]
@@ -219,6 +362,15 @@ pub struct CompositeCycle {
 
 impl CompositeCycle {
     pub fn composite_wave(cycles: &[SineCycle]) -> CycleResult<Vec<CyclePoint>> {
+        Self::composite_wave_cached(cycles, &SineCycleCache::new())
+    }
+
+    /// Compute composite wave by summing the sine wave of each cycle,
+    /// reusing sine waves already evaluated in `cache`.
+    pub fn composite_wave_cached(
+        cycles: &[SineCycle],
+        cache: &SineCycleCache,
+    ) -> CycleResult<Vec<CyclePoint>> {
         let first_cycle = match cycles.first() {
             Some(first) => first,
             None => return Err(CycleError::EmptyCycle),
@@ -231,33 +383,33 @@ impl CompositeCycle {
             }
         }
 
-        // compute composite wave by summing all sine wave functions at each date
-        let mut composite: Vec<CyclePoint> = Vec::new();
-        let first_sine = first_cycle.sine_wave()?;
-        for date in first_sine.iter().map(|c| c.x) {
-            // sum sine wave function of each cycle
-            let mut components: Vec<f64> = Vec::new();
-            for cycle in cycles.iter() {
-                // TODO: handle amplitude of each wave function by returning (amplitude, equation) tuple ?
-                let wave_function = cycle.wave_function(&date)?;
-                components.push(wave_function);
+        // composite point is sum of sine wave functions at each date
+        let mut composite = cache.sine_wave(first_cycle)?.as_ref().clone();
+        for cycle in cycles.iter().skip(1) {
+            // TODO: handle amplitude of each wave function by returning (amplitude, equation) tuple ?
+            let wave = cache.sine_wave(cycle)?;
+            for (point, component) in composite.iter_mut().zip(wave.iter()) {
+                point.y += component.y;
             }
-            // composite point is sum of sine wave functions
-            let point: f64 = components.into_iter().sum();
-            composite.push(CyclePoint { x: date, y: point });
         }
         Ok(composite)
     }
 
+    /// Composite wave of each combination of `cycle_periods` with `min_components`
+    /// through `max_components` cycles, since every combination is often too many to evaluate.
     pub fn cycle_combinations(
         start_date: &Time,
         end_date: &Time,
         // period in timeframe units (e.g. 1 month cycle)
         cycle_periods: &[u64],
         cycle_timeframe: CycleTimeframe,
+        min_components: usize,
+        max_components: usize,
     ) -> CycleResult<Vec<CompositeCycle>> {
         let mut composites: Vec<CompositeCycle> = Vec::new();
-        for k in 1..=cycle_periods.len() {
+        // combinations share component cycles, so evaluate each sine wave once
+        let cache = SineCycleCache::new();
+        for k in min_components.max(1)..=max_components.min(cycle_periods.len()) {
             // cycle combinations of length k
             let combs = Self::combinations_inner(cycle_periods, k);
 
@@ -275,12 +427,12 @@ impl CompositeCycle {
                             end_date: *end_date,
                             cycle_period_minutes: cycle_timeframe
                                 .timeframe_to_period_minutes(*period as f32),
-                            timeframe: cycle_timeframe.clone(),
+                            timeframe: cycle_timeframe,
                         })
                         .collect::<Vec<SineCycle>>();
 
                     // compute composite wave for these harmonic cycles
-                    let composite = Self::composite_wave(&sine_cycles).unwrap();
+                    let composite = Self::composite_wave_cached(&sine_cycles, &cache).unwrap();
                     debug!(
                         "comb: {:?}, time: {}ms",
                         comb,
@@ -367,7 +519,64 @@ impl CompositeCycle {
         })
     }
 
+    /// Composite wave from `from` through `to` every `step`, summing the sine components
+    /// at each date so the wave can be read beyond the end date of the composite.
+    pub fn project(
+        &self,
+        from: &Time,
+        to: &Time,
+        step: Timeframe,
+    ) -> CycleResult<Vec<(Time, f32)>> {
+        let mut projection = Vec::new();
+        let mut date = *from;
+        while date.to_unix() <= to.to_unix() {
+            projection.push((date, self.composite_function(&date)? as f32));
+            date = match step.to_secs() {
+                Some(secs) => Time::from_unix(date.to_unix() + secs),
+                None => date.delta_months(1).map_err(CycleError::TimeError)?,
+            };
+        }
+        Ok(projection)
+    }
+
+    /// Projected highs and lows of the composite wave from `from` through `to` every `step`,
+    /// where a point above (below) the previous point and not below (above) the next is a high (low).
+    /// The first and last points of the window are never turning points.
+    pub fn projected_turning_points(
+        &self,
+        from: &Time,
+        to: &Time,
+        step: Timeframe,
+    ) -> CycleResult<Vec<(Time, ReversalType)>> {
+        let projection = self.project(from, to, step)?;
+        Ok(projection
+            .windows(3)
+            .filter_map(|points| {
+                let (prev, (date, value), next) = (points[0].1, points[1], points[2].1);
+                if value > prev && value >= next {
+                    Some((date, ReversalType::High))
+                } else if value < prev && value <= next {
+                    Some((date, ReversalType::Low))
+                } else {
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Composite wave at `x`, read from the evaluated composite if `x` falls on it.
     fn composite_function(&self, x: &Time) -> CycleResult<f64> {
+        // composite points are one minute apart from the start date
+        let index = self
+            .start_date
+            .diff_minutes(x)
+            .map_err(CycleError::TimeError)?;
+        if let Some(point) = usize::try_from(index)
+            .ok()
+            .and_then(|i| self.composite.get(i))
+        {
+            return Ok(point.y);
+        }
         let mut composite = 0.0;
         for cycle in self.sine_cycles.iter() {
             composite += cycle.wave_function(x)?;
@@ -376,12 +585,328 @@ impl CompositeCycle {
     }
 }
 
+/// In place radix-2 FFT of the complex series `re` + i`im`, whose length is a power of two
+pub(crate) fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_im, w_re) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::*;
     use log::info;
 
+    /// Daily candles from `start_date` that open, trade and close at each of `closes`
+    fn daily_series(start_date: Time, closes: impl IntoIterator<Item = f64>) -> TickerData {
+        let candles = closes
+            .into_iter()
+            .enumerate()
+            .map(|(day, close)| Candle {
+                date: start_date.delta_date(day as i64),
+                open: close,
+                high: close,
+                low: close,
+                close,
+                volume: None,
+            })
+            .collect::<Vec<Candle>>();
+        let mut ticker_data = TickerData::new();
+        ticker_data.add_series(candles).unwrap();
+        ticker_data
+    }
+
+    #[test]
+    fn wilson_interval_seven_of_ten() {
+        let correlation = CycleCorrelation {
+            correlated: 7,
+            total: 10,
+            correlation: 0.7,
+        };
+        let (lower, upper) = correlation.wilson_interval(1.96);
+        assert!((lower - 0.3968).abs() < 1e-4);
+        assert!((upper - 0.8922).abs() < 1e-4);
+        assert_eq!(correlation.lower_bound(1.96), lower);
+        // more samples at the same rate narrow the interval
+        let more = CycleCorrelation {
+            correlated: 70,
+            total: 100,
+            correlation: 0.7,
+        };
+        assert!(more.lower_bound(1.96) > lower);
+    }
+
+    #[test]
+    fn best_phase_of_shifted_sine() -> CycleResult<()> {
+        let start_date = Time::new(2020, &Month::from_num(1), &Day::from_num(1), None, None);
+        // 20 day cycle with troughs a quarter period after the start date
+        let ticker_data = daily_series(
+            start_date,
+            (0..120).map(|day| 100.0 - 10.0 * (2.0 * PI * (day as f64 - 5.0) / 20.0).cos()),
+        );
+        let end_date = *ticker_data.latest_date();
+        let market_structure = MarketStructure::new(&ticker_data, 3);
+
+        let cycle = SineCycle::new(
+            start_date,
+            end_date,
+            CycleTimeframe::Day.timeframe_to_period_minutes(20.0),
+            CycleTimeframe::Day,
+        );
+        let (phase, correlation) = cycle.best_phase_correlation(&market_structure, 4)?;
+        assert_eq!(phase, 0.25);
+        assert_eq!(correlation.correlated, correlation.total);
+        // unshifted wave is out of phase with price
+        let unshifted = cycle.series_correlation(&market_structure)?;
+        assert!(unshifted.correlation < correlation.correlation);
+        Ok(())
+    }
+
+    #[test]
+    fn detrended_correlation_of_trending_cycle() -> CycleResult<()> {
+        let start_date = Time::new(2020, &Month::from_num(1), &Day::from_num(1), None, None);
+        // 20 day cycle with troughs on the start date riding an uptrend
+        let ticker_data = daily_series(
+            start_date,
+            (0..120).map(|day| {
+                let t = day as f64;
+                100.0 + 1.5 * t - 10.0 * (2.0 * PI * t / 20.0).cos()
+            }),
+        );
+        let end_date = *ticker_data.latest_date();
+        let market_structure = MarketStructure::new(&ticker_data, 3);
+
+        let cycle = SineCycle::new(
+            start_date,
+            end_date,
+            CycleTimeframe::Day.timeframe_to_period_minutes(20.0),
+            CycleTimeframe::Day,
+        );
+        let raw = cycle.detrended_correlation(&market_structure, None)?;
+        assert_eq!(
+            raw.correlated,
+            cycle.series_correlation(&market_structure)?.correlated
+        );
+        let detrended =
+            cycle.detrended_correlation(&market_structure, Some(DetrendMethod::Linear))?;
+        assert!(detrended.correlation > raw.correlation);
+        assert!(detrended.correlation > 0.9);
+        Ok(())
+    }
+
+    #[test]
+    fn detrended_correlation_starts_at_detrended_low() -> CycleResult<()> {
+        let start_date = Time::new(2020, &Month::from_num(1), &Day::from_num(1), None, None);
+        // trend steep enough that price never turns down, so only the detrended series has lows
+        let ticker_data = daily_series(
+            start_date,
+            (0..120).map(|day| {
+                let t = day as f64;
+                100.0 + 5.0 * t - 10.0 * (2.0 * PI * t / 20.0).cos()
+            }),
+        );
+        let end_date = *ticker_data.latest_date();
+        let market_structure = MarketStructure::new(&ticker_data, 3);
+        let cycle = SineCycle::new(
+            start_date,
+            end_date,
+            CycleTimeframe::Day.timeframe_to_period_minutes(20.0),
+            CycleTimeframe::Day,
+        );
+        let detrended =
+            cycle.detrended_correlation(&market_structure, Some(DetrendMethod::Linear))?;
+        assert!(detrended.correlation > 0.9);
+        Ok(())
+    }
+
+    #[test]
+    fn project_single_cycle() -> CycleResult<()> {
+        let start_date = Time::new(2020, &Month::from_num(1), &Day::from_num(1), None, None);
+        let end_date = start_date.delta_date(2);
+        let cycle = SineCycle::new(
+            start_date,
+            end_date,
+            CycleTimeframe::Day.timeframe_to_period_minutes(10.0),
+            CycleTimeframe::Day,
+        );
+        let composite = CompositeCycle {
+            start_date,
+            end_date,
+            sine_cycles: vec![cycle.clone()],
+            composite: CompositeCycle::composite_wave(&[cycle])?,
+        };
+        let from = start_date.delta_date(20);
+        let to = start_date.delta_date(30);
+        let projection = composite.project(&from, &to, Timeframe::Day)?;
+        assert_eq!(projection.len(), 11);
+        assert_eq!(projection.first().unwrap().0, from);
+        assert_eq!(projection.last().unwrap().0, to);
+        // 23 days after the trough of a 10 day cycle
+        let (date, value) = projection[3];
+        assert_eq!(date, start_date.delta_date(23));
+        let expected = (-PI / 2.0 + 2.0 * PI * 23.0 / 10.0).sin() as f32;
+        assert!((value - expected).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn projected_highs_one_period_apart() -> CycleResult<()> {
+        let start_date = Time::new(2020, &Month::from_num(1), &Day::from_num(1), None, None);
+        let end_date = start_date.delta_date(1);
+        let cycle = SineCycle::new(
+            start_date,
+            end_date,
+            CycleTimeframe::Day.timeframe_to_period_minutes(10.0),
+            CycleTimeframe::Day,
+        );
+        let composite = CompositeCycle {
+            start_date,
+            end_date,
+            sine_cycles: vec![cycle.clone()],
+            composite: CompositeCycle::composite_wave(&[cycle])?,
+        };
+        let turning_points = composite.projected_turning_points(
+            &start_date.delta_date(2),
+            &start_date.delta_date(40),
+            Timeframe::Hour,
+        )?;
+        let highs = turning_points
+            .iter()
+            .filter(|(_, reversal)| reversal == &ReversalType::High)
+            .map(|(date, _)| date.to_unix())
+            .collect::<Vec<i64>>();
+        let lows = turning_points
+            .iter()
+            .filter(|(_, reversal)| reversal == &ReversalType::Low)
+            .count();
+        // highs 5, 15, 25 and 35 days after the trough on the start date
+        assert_eq!(highs.len(), 4);
+        assert_eq!(highs[0], start_date.delta_date(5).to_unix());
+        assert!(highs
+            .windows(2)
+            .all(|pair| pair[1] - pair[0] == 10 * 86_400));
+        assert_eq!(lows, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn combinations_capped_by_components() -> CycleResult<()> {
+        let start_date = Time::new(2020, &Month::from_num(1), &Day::from_num(1), None, None);
+        let end_date = start_date.delta_date(1);
+        let periods = [1, 2, 3, 4, 5];
+        let composites = CompositeCycle::cycle_combinations(
+            &start_date,
+            &end_date,
+            &periods,
+            CycleTimeframe::Hour,
+            1,
+            2,
+        )?;
+        // C(5,1) + C(5,2)
+        assert_eq!(composites.len(), 15);
+        assert!(composites.iter().all(|c| c.sine_cycles.len() <= 2));
+
+        let pairs = CompositeCycle::cycle_combinations(
+            &start_date,
+            &end_date,
+            &periods,
+            CycleTimeframe::Hour,
+            2,
+            2,
+        )?;
+        assert_eq!(pairs.len(), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn dominant_cycles_of_two_sines() -> CycleResult<()> {
+        let start_date = Time::new(2020, &Month::from_num(1), &Day::from_num(1), None, None);
+        let ticker_data = daily_series(
+            start_date,
+            (0..500).map(|day| {
+                let t = day as f64;
+                100.0
+                    + 0.05 * t
+                    + 5.0 * (2.0 * PI * t / 32.0).sin()
+                    + 3.0 * (2.0 * PI * t / 20.0).sin()
+            }),
+        );
+
+        let cycles = ticker_data.dominant_cycles(2)?;
+        assert_eq!(cycles.len(), 2);
+        // the larger amplitude cycle has more power
+        assert!((cycles[0].0 - 32.0).abs() < 1.0);
+        assert!((cycles[1].0 - 20.0).abs() < 1.0);
+        assert!(cycles[0].1 > cycles[1].1);
+        Ok(())
+    }
+
+    #[test]
+    fn outlier_period_z_threshold() -> CycleResult<()> {
+        let start_date = Time::new(2020, &Month::from_num(1), &Day::from_num(1), None, None);
+        // V shaped lows 10 days apart, except one 40 day cycle
+        let market_structure = |lows: &[i64]| {
+            let ticker_data = daily_series(
+                start_date,
+                (0..lows.last().unwrap() + 5).map(|day| {
+                    100.0 + lows.iter().map(|low| (day - low).abs()).min().unwrap() as f64
+                }),
+            );
+            let market_structure = MarketStructure::new(&ticker_data, 3);
+            // cycle lows are the pivot lows
+            let reversal_lows = market_structure
+                .reversals
+                .iter()
+                .filter(|r| r.reversal_type == ReversalType::Low)
+                .map(|r| r.candle.clone())
+                .collect::<Vec<Candle>>();
+            assert_eq!(reversal_lows, ticker_data.pivot_lows(3, 3));
+            market_structure
+        };
+
+        let lows = market_structure(&[5, 15, 25, 35, 45, 55, 95, 105]);
+        let filtered = lows.filtered_cycle_periods(CycleTimeframe::Day, 1.0)?;
+        assert_eq!(filtered, vec![10.0; 6]);
+        let filtered = lows.filtered_cycle_periods(CycleTimeframe::Day, 3.0)?;
+        assert_eq!(filtered, vec![10.0, 10.0, 10.0, 10.0, 10.0, 40.0, 10.0]);
+        // a single low has no period
+        assert!(matches!(
+            market_structure(&[5]).filtered_cycle_periods(CycleTimeframe::Day, 1.0),
+            Err(CycleError::EmptyMedian)
+        ));
+        Ok(())
+    }
+
     #[test]
     fn sine_wave() -> CycleResult<()> {
         let start_date = Time::new(2020, &Month::from_num(1), &Day::from_num(1), None, None);
@@ -398,4 +923,35 @@ mod tests {
         }
         Ok(())
     }
+    #[test]
+    fn cached_composite_wave() -> CycleResult<()> {
+        let start_date = Time::new(2020, &Month::from_num(1), &Day::from_num(1), None, None);
+        let end_date = start_date.delta_date(30);
+        let cycle = |days: u64| {
+            SineCycle::new(
+                start_date,
+                end_date,
+                CycleTimeframe::Day.timeframe_to_period_minutes(days as f32),
+                CycleTimeframe::Day,
+            )
+        };
+        let cache = SineCycleCache::new();
+
+        let first = CompositeCycle::composite_wave_cached(&[cycle(5), cycle(10)], &cache)?;
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.hits(), 0);
+
+        // cached evaluation equals evaluating each wave function fresh
+        for point in first.iter().step_by(97) {
+            let fresh = cycle(5).wave_function(&point.x)? + cycle(10).wave_function(&point.x)?;
+            assert_eq!(point.y, fresh);
+        }
+
+        // second combination reuses the cached 10 day component
+        let second = CompositeCycle::composite_wave_cached(&[cycle(10)], &cache)?;
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(second.len(), first.len());
+        Ok(())
+    }
 }
@@ -17,6 +17,7 @@ pub enum Timeframe {
     Hour,
     Hour4,
     Day,
+    Week,
     Month
 }
 
@@ -29,11 +30,28 @@ impl Display for Timeframe {
             Timeframe::Hour => write!(f, "1h"),
             Timeframe::Hour4 => write!(f, "4h"),
             Timeframe::Day => write!(f, "1d"),
+            Timeframe::Week => write!(f, "1w"),
             Timeframe::Month => write!(f, "1M"),
         }
     }
 }
 
+impl Timeframe {
+    /// Length of a candle in seconds, or None for months which vary in length
+    pub fn to_secs(&self) -> Option<i64> {
+        match self {
+            Timeframe::Min1 => Some(60),
+            Timeframe::Min5 => Some(5 * 60),
+            Timeframe::Min15 => Some(15 * 60),
+            Timeframe::Hour => Some(60 * 60),
+            Timeframe::Hour4 => Some(4 * 60 * 60),
+            Timeframe::Day => Some(24 * 60 * 60),
+            Timeframe::Week => Some(7 * 24 * 60 * 60),
+            Timeframe::Month => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TimeSeries {
     pub series: TickerData,
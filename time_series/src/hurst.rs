@@ -1,42 +1,74 @@
+use std::fmt::{Display, Formatter};
+
 #[derive(Debug)]
 pub enum HurstError {
     LinearRegression(String),
     Polyfit(String),
+    /// Fewer than two window sizes had returns to measure
+    InsufficientData,
+}
+
+impl Display for HurstError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HurstError::LinearRegression(e) => write!(f, "Linear regression error: {}", e),
+            HurstError::Polyfit(e) => write!(f, "Polyfit error: {}", e),
+            HurstError::InsufficientData => write!(f, "Insufficient data for Hurst exponent"),
+        }
+    }
 }
 
 pub type HurstResult<T> = Result<T, HurstError>;
 
-/// This library is a translation of Hurst Exponent calculation from Python to Rust:
-/// @[Introduction to the Hurst exponent — with code in Python](https://towardsdatascience.com/introduction-to-the-hurst-exponent-with-code-in-python-4da0414ca52e)
+/// Smallest window of returns measured by rescaled range analysis
+const MIN_RS_WINDOW: usize = 8;
+
+/// Hurst exponent of a price series as the slope of log mean rescaled range of its returns
+/// against log window size, for windows doubling from 8 returns up to `max_lags`.
+/// A finite series can measure a slope outside [0, 1], which is clamped to that range.
+/// Returns from a price of 0 are undefined and skipped.
 pub fn hurst(series: &[f64], max_lags: u32) -> HurstResult<f64> {
-    let lags = (2..max_lags).collect::<Vec<u32>>();
-    let tau = lags
-        .iter()
-        .map(|lag| {
-            let lag = *lag as usize;
-            let series_lagged = series[lag..].to_vec();
-            let series = series[..(series.len() - lag)].to_vec();
-            let diff = series_lagged
-                .iter()
-                .zip(series.iter())
-                .map(|(a, b)| a - b)
-                .collect::<Vec<f64>>();
-            std_dev(&diff).unwrap()
-        })
+    let returns = series
+        .windows(2)
+        .filter(|pair| pair[0] != 0.0)
+        .map(|pair| (pair[1] - pair[0]) / pair[0])
         .collect::<Vec<f64>>();
-    // np.log(lags)
-    let lags_log = lags
-        .iter()
-        .map(|lag| (*lag as f64).ln())
-        .collect::<Vec<f64>>();
-    // np.log(tau)
-    let tau_log = tau.iter().map(|tau| tau.ln()).collect::<Vec<f64>>();
-    // reg = np.polyfit(np.log(lags), np.log(tau), 1)
-    let reg: (f64, f64) = linreg::linear_regression(&lags_log, &tau_log)
+    let mut window_logs = Vec::<f64>::new();
+    let mut rs_logs = Vec::<f64>::new();
+    let mut window = MIN_RS_WINDOW;
+    while window <= (max_lags as usize).min(returns.len()) {
+        let rs = returns
+            .chunks_exact(window)
+            .filter_map(|chunk| {
+                let chunk_mean = mean(chunk)?;
+                let chunk_std_dev = std_dev(chunk)?;
+                if chunk_std_dev == 0.0 {
+                    return None;
+                }
+                // range of cumulative deviations from the mean
+                let mut cumulative = 0.0;
+                let mut max = f64::MIN;
+                let mut min = f64::MAX;
+                for value in chunk {
+                    cumulative += value - chunk_mean;
+                    max = max.max(cumulative);
+                    min = min.min(cumulative);
+                }
+                Some((max - min) / chunk_std_dev)
+            })
+            .collect::<Vec<f64>>();
+        if let Some(rs_mean) = mean(&rs) {
+            window_logs.push((window as f64).ln());
+            rs_logs.push(rs_mean.ln());
+        }
+        window *= 2;
+    }
+    if window_logs.len() < 2 {
+        return Err(HurstError::InsufficientData);
+    }
+    let reg: (f64, f64) = linreg::linear_regression(&window_logs, &rs_logs)
         .map_err(|e| HurstError::LinearRegression(e.to_string()))?;
-    let h = reg.0;
-    let _c = reg.1;
-    Ok(h)
+    Ok(reg.0.clamp(0.0, 1.0))
 }
 
 fn mean(data: &[f64]) -> Option<f64> {
@@ -66,3 +98,89 @@ fn std_dev(data: &[f64]) -> Option<f64> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Candle, CycleError, CycleResult, Day, Month, TickerData, Time};
+
+    /// Deterministic uniform samples in [0, 1)
+    fn xorshift(seed: u64) -> impl FnMut() -> f64 {
+        let mut x = seed;
+        move || {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            (x >> 11) as f64 / (1u64 << 53) as f64
+        }
+    }
+
+    fn ticker_data(returns: impl Iterator<Item = f64>) -> TickerData {
+        let start_date = Time::new(2015, &Month::from_num(1), &Day::from_num(1), None, None);
+        let mut close = 100.0;
+        let mut candles = vec![];
+        for (day, change) in std::iter::once(0.0).chain(returns).enumerate() {
+            close *= 1.0 + change;
+            candles.push(Candle {
+                date: start_date.delta_date(day as i64),
+                open: close,
+                high: close,
+                low: close,
+                close,
+                volume: None,
+            });
+        }
+        let mut ticker_data = TickerData::new();
+        ticker_data.add_series(candles).unwrap();
+        ticker_data
+    }
+
+    #[test]
+    fn random_walk_and_trend() -> CycleResult<()> {
+        let seed = 0x2545_F491_4F6C_DD1D;
+        let mut uniform = xorshift(seed);
+        let random_walk = ticker_data((0..2048).map(|_| 0.01 * (uniform() - 0.5)));
+        let h = random_walk.hurst_exponent(256)?;
+        assert!((0.45..0.65).contains(&h), "random walk H = {}", h);
+
+        // persistent returns keep price moving in the same direction
+        let mut uniform = xorshift(seed);
+        let mut change = 0.0;
+        let trending = ticker_data((0..2048).map(|_| {
+            change = 0.9 * change + 0.002 * (uniform() - 0.5);
+            change
+        }));
+        let h = trending.hurst_exponent(256)?;
+        assert!(h > 0.75, "trending H = {}", h);
+
+        assert!(matches!(
+            random_walk.hurst_exponent(8),
+            Err(CycleError::HurstError(HurstError::InsufficientData))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn hurst_bounds() -> HurstResult<()> {
+        // returns that flip every 8 steps look far more persistent over 16 than 8 returns,
+        // a slope above 1 that is clamped
+        let mut closes = vec![100.0];
+        for i in 0..1024 {
+            let sign = if (i / 8) % 2 == 0 { 1.0 } else { -1.0 };
+            let change = 0.001 * sign + 1e-6 * ((i * 7) % 5) as f64;
+            closes.push(closes[closes.len() - 1] * (1.0 + change));
+        }
+        assert_eq!(hurst(&closes, 16)?, 1.0);
+
+        // a zero price contributes no return rather than an infinite one
+        let mut uniform = xorshift(0x2545_F491_4F6C_DD1D);
+        let mut closes = vec![100.0];
+        for _ in 0..512 {
+            closes.push(closes[closes.len() - 1] * (1.0 + 0.01 * (uniform() - 0.5)));
+        }
+        closes[100] = 0.0;
+        let h = hurst(&closes, 256)?;
+        assert!(h.is_finite() && (0.0..=1.0).contains(&h), "H = {}", h);
+        Ok(())
+    }
+}
@@ -6,6 +6,7 @@ pub mod hda;
 pub mod hurst;
 pub mod market_structure;
 pub mod pfs;
+pub mod portfolio;
 pub mod precise_round;
 pub mod rapid_api;
 pub mod square_of_nine;
@@ -20,6 +21,7 @@ pub use hda::*;
 pub use hurst::*;
 pub use market_structure::*;
 pub use pfs::*;
+pub use portfolio::*;
 pub use precise_round::*;
 pub use rapid_api::*;
 pub use square_of_nine::*;
@@ -1,5 +1,8 @@
 use crate::backtest::Direction;
-use crate::{Candle, Reversal, ReversalType, TickerData};
+use crate::{
+    Candle, CycleError, CycleResult, CycleTimeframe, PriceScale, Reversal, ReversalType,
+    TickerData, TickerDataResult,
+};
 use log::debug;
 use std::path::PathBuf;
 
@@ -39,6 +42,16 @@ pub struct MarketStructure {
 }
 
 impl MarketStructure {
+    /// Identify market structure on candles transformed onto `scale`.
+    /// Candles in the result hold scaled prices.
+    pub fn new_scaled(
+        ticker_data: &TickerData,
+        candle_range: usize,
+        scale: PriceScale,
+    ) -> TickerDataResult<Self> {
+        Ok(Self::new(&ticker_data.price_scaled(scale)?, candle_range))
+    }
+
     /// Identify market structure in vector of reversals .
     /// by finding higher highs and higher lows for positive market structure,
     /// and lower highs and lower lows for negative market structure.
@@ -303,4 +316,59 @@ impl MarketStructure {
             println!();
         }
     }
+
+    /// Periods between consecutive reversal lows in `timeframe` units,
+    /// without outlier periods more than `z_threshold` standard deviations from the median period.
+    /// Reversal lows are the candles of `TickerData::pivot_lows` with the reversal candle range on both sides.
+    pub fn filtered_cycle_periods(
+        &self,
+        timeframe: CycleTimeframe,
+        z_threshold: f64,
+    ) -> CycleResult<Vec<f32>> {
+        let lows = self
+            .reversals
+            .iter()
+            .filter(|r| r.reversal_type == ReversalType::Low)
+            .collect::<Vec<_>>();
+        let periods = lows
+            .windows(2)
+            .map(|pair| {
+                let period = pair[0]
+                    .candle
+                    .date
+                    .diff_minutes(&pair[1].candle.date)
+                    .map_err(CycleError::TimeError)? as u64;
+                Ok(timeframe.period_minutes_to_timeframe(period))
+            })
+            .collect::<CycleResult<Vec<f32>>>()?;
+        filter_outlier_periods(&periods, z_threshold)
+    }
+}
+
+/// Periods within `z_threshold` standard deviations of the median period.
+/// Median is used instead of mean so outliers do not skew the center or the deviation.
+fn filter_outlier_periods(periods: &[f32], z_threshold: f64) -> CycleResult<Vec<f32>> {
+    let mut sorted = periods.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mid = sorted.len() / 2;
+    let median = match sorted.len() {
+        0 => return Err(CycleError::EmptyMedian),
+        count if count % 2 == 0 => (sorted[mid - 1] + sorted[mid]) / 2.0,
+        _ => sorted[mid],
+    };
+    let variance = periods
+        .iter()
+        .map(|period| (period - median).powi(2))
+        .sum::<f32>()
+        / periods.len() as f32;
+    let std_dev = variance.sqrt();
+    // identical periods have no outliers
+    if std_dev == 0.0 {
+        return Ok(periods.to_vec());
+    }
+    Ok(periods
+        .iter()
+        .filter(|period| (((*period - median) / std_dev).abs() as f64) < z_threshold)
+        .copied()
+        .collect())
 }
@@ -1,15 +1,18 @@
 use crate::{
-    Backtest, Candle, CycleError, Direction, Order, ReversalType, TickerData, TickerDataError,
-    Time, TimeError, Trade, TrailingStopType,
+    Backtest, BacktestError, Candle, CycleError, DetrendMethod, Direction, FillMode, HoldPolicy,
+    Order, PositionSizing, PriceScale, ReversalType, TickerData, TickerDataError, Time, TimeError,
+    Timeframe, Trade, TrailingStopType,
 };
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::error::Error;
 use std::fs::File;
 
-use chrono::{Duration, Local, NaiveDate, TimeZone};
+use chrono::{Datelike, Duration, Local, NaiveDate, TimeZone};
 use log::{debug, info};
 use plotters::prelude::*;
+use rayon::prelude::*;
 use std::io::Write;
-use std::sync::{Arc, Mutex};
 
 #[derive(Debug)]
 pub enum PFSError {
@@ -17,6 +20,13 @@ pub enum PFSError {
     TimeError(TimeError),
     TickerDataError(TickerDataError),
     BacktestEmpty,
+    BacktestError(BacktestError),
+    /// PFS is not computed on this timeframe
+    UnsupportedTimeframe(PFSTimeframe),
+    /// A cycle of zero never looks back
+    ZeroCycle,
+    /// Gaussian smoothing needs a positive, finite standard deviation
+    InvalidSmoothing(Smoothing),
     CustomError(std::io::Error),
 }
 
@@ -27,6 +37,14 @@ impl std::fmt::Display for PFSError {
             PFSError::TimeError(e) => write!(f, "Time error: {}", e),
             PFSError::TickerDataError(e) => write!(f, "Ticker data error: {}", e),
             PFSError::BacktestEmpty => write!(f, "Backtest is empty"),
+            PFSError::BacktestError(e) => write!(f, "Backtest error: {}", e),
+            PFSError::UnsupportedTimeframe(timeframe) => {
+                write!(f, "PFS not supported on {:?} timeframe", timeframe)
+            }
+            PFSError::ZeroCycle => write!(f, "PFS cycle must be at least 1"),
+            PFSError::InvalidSmoothing(smoothing) => {
+                write!(f, "Invalid PFS smoothing: {:?}", smoothing)
+            }
             PFSError::CustomError(e) => write!(f, "Custom error: {}", e),
         }
     }
@@ -65,27 +83,178 @@ pub enum PFSTimeframe {
     Minute,
     Hour,
     Day,
+    Week,
     Month,
     Year,
 }
 
+impl PFSTimeframe {
+    /// Dates from `start_date` through `end_date` a PFS is computed for on this timeframe.
+    /// Weeks start on the Monday of the week of `start_date`, hours step from `start_date`,
+    /// and other timeframes step by day.
+    pub fn dates(&self, start_date: &Time, end_date: &Time) -> Vec<Time> {
+        let (start, step) = match self {
+            PFSTimeframe::Week => {
+                let weekday = start_date.to_naive_date().weekday().num_days_from_monday();
+                (start_date.delta_date(-(weekday as i64)), 7 * 24 * 60 * 60)
+            }
+            PFSTimeframe::Hour => (*start_date, 60 * 60),
+            _ => return start_date.time_period(end_date),
+        };
+        let end = end_date.to_unix();
+        let mut dates = Vec::new();
+        let mut unix = start.to_unix();
+        while unix <= end {
+            dates.push(Time::from_unix(unix));
+            unix += step;
+        }
+        dates
+    }
+
+    /// True if `a` and `b` are the same date on this timeframe.
+    /// Hours compare the full timestamp, other timeframes only the day.
+    pub fn same_date(&self, a: &Time, b: &Time) -> bool {
+        match self {
+            PFSTimeframe::Hour => a.to_unix() == b.to_unix(),
+            _ => a == b,
+        }
+    }
+
+    /// Fewest seconds in one unit of this timeframe, so a span holds no more cycles than it measures
+    fn min_secs(&self) -> i64 {
+        match self {
+            PFSTimeframe::Minute => 60,
+            PFSTimeframe::Hour => 60 * 60,
+            PFSTimeframe::Day => 24 * 60 * 60,
+            PFSTimeframe::Week => 7 * 24 * 60 * 60,
+            PFSTimeframe::Month => 28 * 24 * 60 * 60,
+            PFSTimeframe::Year => 365 * 24 * 60 * 60,
+        }
+    }
+
+    /// Candle timeframe the series is aggregated to before computing PFS, if any
+    fn bucket(&self) -> Option<Timeframe> {
+        match self {
+            PFSTimeframe::Hour => Some(Timeframe::Hour),
+            PFSTimeframe::Week => Some(Timeframe::Week),
+            _ => None,
+        }
+    }
+
+    /// Series aggregated into candles of this timeframe,
+    /// or the series itself if it is not aggregated or its candles are already as long
+    pub fn bucketed<'a>(&self, ticker_data: &'a TickerData) -> PFSResult<Cow<'a, TickerData>> {
+        match self.bucket() {
+            Some(bucket) => match ticker_data.resample(bucket) {
+                Ok(resampled) => Ok(Cow::Owned(resampled)),
+                Err(TickerDataError::ResampleTimeframe(_)) => Ok(Cow::Borrowed(ticker_data)),
+                Err(e) => Err(PFSError::TickerDataError(e)),
+            },
+            None => Ok(Cow::Borrowed(ticker_data)),
+        }
+    }
+}
+
+/// Smoothing of a PFS projection, centered on each date so turning points are not shifted.
+/// Dates near either end of the projection are smoothed over the part of the window in range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Smoothing {
+    /// Mean of this many dates centered on each date, rounded up to an odd window
+    MovingAverage(usize),
+    /// Gaussian weights with this standard deviation in dates, truncated at 3 deviations.
+    /// The deviation must be positive.
+    Gaussian(f64),
+}
+
+impl Smoothing {
+    fn weights(&self) -> PFSResult<Vec<f64>> {
+        match *self {
+            Smoothing::MovingAverage(window) => Ok(vec![1.0; window / 2 * 2 + 1]),
+            Smoothing::Gaussian(sigma) => {
+                if !sigma.is_finite() || sigma <= 0.0 {
+                    return Err(PFSError::InvalidSmoothing(*self));
+                }
+                let radius = (3.0 * sigma).ceil() as i64;
+                Ok((-radius..=radius)
+                    .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+                    .collect())
+            }
+        }
+    }
+
+    /// Smoothed copy of the projection values. Confidence bands are left as computed.
+    pub fn apply(&self, pfs: &[PFS]) -> PFSResult<Vec<PFS>> {
+        let weights = self.weights()?;
+        let radius = (weights.len() / 2) as i64;
+        Ok((0..pfs.len() as i64)
+            .map(|i| {
+                let (mut sum, mut weight_sum) = (0.0, 0.0);
+                for (j, weight) in weights.iter().enumerate() {
+                    let index = i + j as i64 - radius;
+                    if index >= 0 && index < pfs.len() as i64 {
+                        sum += pfs[index as usize].value * weight;
+                        weight_sum += weight;
+                    }
+                }
+                PFS {
+                    value: sum / weight_sum,
+                    ..pfs[i as usize].clone()
+                }
+            })
+            .collect())
+    }
+}
+
 /// Polarity Factor System
 #[derive(Debug, Clone)]
 pub struct PFS {
     pub date: Time,
     pub value: f64,
     pub cycle: u32,
+    /// Low and high of the confidence band around the value, one standard error of the mean
+    /// of the cycle changes it averages. None if fewer than two changes were averaged.
+    pub band: Option<(f64, f64)>,
 }
 
 impl PFS {
     pub fn new(date: Time, value: f64, cycle: u32) -> Self {
-        Self { date, value, cycle }
+        Self {
+            date,
+            value,
+            cycle,
+            band: None,
+        }
+    }
+
+    /// Set the confidence band to one standard error of the mean of `samples` around the value
+    pub fn with_band(mut self, samples: &[f64]) -> Self {
+        if samples.len() < 2 {
+            self.band = None;
+            return self;
+        }
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        let standard_error = (variance / n).sqrt();
+        self.band = Some((self.value - standard_error, self.value + standard_error));
+        self
     }
 }
 
 pub struct PlotPFS {
     pub start_date: Time,
     pub end_date: Time,
+    /// Smoothing of the cycles computed for confluence and of plotted projections, off by default
+    pub smoothing: Option<Smoothing>,
+    /// Value a normalized PFS is centered on, which is the PFS of a flat price series.
+    /// Only `pfs_normalized` reads it, the `pfs_*` projections and plots are centered on 100.
+    pub baseline: f64,
+    /// Candles back the percent change of a cycle date is measured from, 1 for the candle before it.
+    /// Only `pfs_normalized` reads it, the `pfs_*` projections measure from the candle before.
+    pub change_lookback: usize,
+    /// Price axis the percent change of a cycle date is measured on.
+    /// Only `pfs_normalized` reads it, the `pfs_*_scaled` projections take their scale as an argument.
+    pub scale: PriceScale,
 }
 
 impl PlotPFS {
@@ -93,6 +262,131 @@ impl PlotPFS {
         Self {
             start_date,
             end_date,
+            smoothing: None,
+            baseline: 100.0,
+            change_lookback: 1,
+            scale: PriceScale::Linear,
+        }
+    }
+
+    /// Percent change into the candle at `index` from the close `self.change_lookback` candles before it,
+    /// `None` if there are not enough candles before it.
+    pub fn percent_change(&self, candles: &[Candle], index: usize) -> Option<f64> {
+        let lookback = self.change_lookback.max(1);
+        if index < lookback {
+            return None;
+        }
+        let prev = candles.get(index - lookback)?;
+        let candle = candles.get(index)?;
+        Some(self.scale.percent_change(prev.close, candle.close))
+    }
+
+    /// Date `cycles` cycles of `cycle` length before `date` on the timeframe
+    fn cycle_date(
+        date: &Time,
+        cycle: u32,
+        cycles: u32,
+        timeframe: PFSTimeframe,
+    ) -> PFSResult<Time> {
+        let back = (cycle * cycles) as i64;
+        match timeframe {
+            PFSTimeframe::Minute => Err(PFSError::UnsupportedTimeframe(timeframe)),
+            PFSTimeframe::Hour => Ok(Time::from_unix(date.to_unix() - back * 60 * 60)),
+            PFSTimeframe::Day => Ok(date.delta_date(-back)),
+            PFSTimeframe::Week => Ok(date.delta_date(-back * 7)),
+            PFSTimeframe::Month => date
+                .delta_months(-(back as i32))
+                .map_err(PFSError::TimeError),
+            PFSTimeframe::Year => Ok(Time::new(
+                date.year - back as i32,
+                &date.month,
+                &date.day,
+                None,
+                None,
+            )),
+        }
+    }
+
+    /// PFS of `cycle` for each date from `self.start_date` to `self.end_date`, as `self.baseline`
+    /// plus the mean percent change of every cycle date back through the candle history.
+    /// Percent changes are measured over `self.change_lookback` candles on `self.scale`,
+    /// so instruments of different volatility can be compared around the same baseline.
+    pub fn pfs_normalized(
+        &self,
+        ticker_data: &TickerData,
+        cycle: u32,
+        timeframe: PFSTimeframe,
+    ) -> PFSResult<Vec<PFS>> {
+        if cycle == 0 {
+            return Err(PFSError::ZeroCycle);
+        }
+        ticker_data
+            .validate_price_scale(self.scale)
+            .map_err(PFSError::TickerDataError)?;
+        let earliest = *ticker_data.earliest_date();
+        let candles = &ticker_data.candles;
+        let mut pfs = Vec::<PFS>::new();
+        for date in self.start_date.time_period(&self.end_date).iter() {
+            let mut changes = Vec::<f64>::new();
+            // most cycles that fit between the earliest candle and this date
+            let num_cycles = (date.to_unix() - earliest.to_unix()).max(0)
+                / (timeframe.min_secs() * cycle as i64);
+            for cycles in 1..num_cycles as u32 + 1 {
+                let cycle_date = Self::cycle_date(date, cycle, cycles, timeframe)?;
+                if cycle_date.year < earliest.year {
+                    break;
+                }
+                // a yearly cycle back from a leap day lands on a date that may not exist in its year
+                let exists = NaiveDate::from_ymd_opt(
+                    cycle_date.year,
+                    cycle_date.month.to_num(),
+                    cycle_date.day.to_num(),
+                )
+                .is_some();
+                if !exists {
+                    continue;
+                }
+                if cycle_date < earliest {
+                    break;
+                }
+                // first candle on or after the cycle date
+                let change = candles
+                    .iter()
+                    .position(|candle| candle.date >= cycle_date)
+                    .and_then(|index| self.percent_change(candles, index));
+                if let Some(change) = change {
+                    changes.push(change);
+                }
+            }
+            let value = match changes.is_empty() {
+                true => self.baseline,
+                false => self.baseline + changes.iter().sum::<f64>() / changes.len() as f64,
+            };
+            pfs.push(PFS::new(*date, value, cycle).with_band(&changes));
+        }
+        pfs.dedup_by(|a, b| a.date == b.date);
+        Ok(pfs)
+    }
+
+    /// Projection smoothed by `self.smoothing`, or unchanged if smoothing is off
+    pub fn smoothed(&self, pfs: &[PFS]) -> PFSResult<Vec<PFS>> {
+        match self.smoothing {
+            Some(smoothing) => smoothing.apply(pfs),
+            None => Ok(pfs.to_vec()),
+        }
+    }
+
+    /// Series with its trend removed, or the series itself if not detrended
+    fn detrend_series(
+        ticker_data: &TickerData,
+        detrend: Option<DetrendMethod>,
+    ) -> PFSResult<Cow<'_, TickerData>> {
+        match detrend {
+            Some(method) => ticker_data
+                .detrended(method)
+                .map(Cow::Owned)
+                .map_err(PFSError::TickerDataError),
+            None => Ok(Cow::Borrowed(ticker_data)),
         }
     }
 
@@ -103,6 +397,45 @@ impl PlotPFS {
         end_date: Time,
         ticker_data: &TickerData,
         cycle_months: u32,
+    ) -> Vec<PFS> {
+        Self::pfs_months_with_scale(
+            start_date,
+            end_date,
+            ticker_data,
+            cycle_months,
+            PriceScale::Linear,
+        )
+    }
+
+    /// Compute PFS based on monthly cycles with percent changes measured on `scale`,
+    /// optionally on the series with its trend removed
+    pub fn pfs_months_scaled(
+        start_date: Time,
+        end_date: Time,
+        ticker_data: &TickerData,
+        cycle_months: u32,
+        scale: PriceScale,
+        detrend: Option<DetrendMethod>,
+    ) -> PFSResult<Vec<PFS>> {
+        let ticker_data = &*Self::detrend_series(ticker_data, detrend)?;
+        ticker_data
+            .validate_price_scale(scale)
+            .map_err(PFSError::TickerDataError)?;
+        Ok(Self::pfs_months_with_scale(
+            start_date,
+            end_date,
+            ticker_data,
+            cycle_months,
+            scale,
+        ))
+    }
+
+    fn pfs_months_with_scale(
+        start_date: Time,
+        end_date: Time,
+        ticker_data: &TickerData,
+        cycle_months: u32,
+        scale: PriceScale,
     ) -> Vec<PFS> {
         let mut monthly_pfs = Vec::<PFS>::new();
 
@@ -119,7 +452,7 @@ impl PlotPFS {
         let time_period = start_date.time_period(&end_date);
         for date in time_period.iter() {
             // PFS for this date
-            let mut pfs = (100.0, 1);
+            let mut pfs = vec![100.0];
             // iterate possible cycles in candle history
             for cycle in 1..num_cycles + 1 {
                 // find candle X cycles back
@@ -145,17 +478,15 @@ impl PlotPFS {
                     }
                     // found candle X cycles back
                     if prev_candle.date < cycle_date && candle.date >= cycle_date {
-                        let change = candle.percent_change(prev_candle.close);
-                        pfs = (pfs.0 + change, pfs.1 + 1);
+                        let change = candle.scaled_percent_change(prev_candle.close, scale);
+                        pfs.push(change);
                         break;
                     }
                 }
             }
-            monthly_pfs.push(PFS {
-                date: *date,
-                value: pfs.0 / pfs.1 as f64,
-                cycle: cycle_months,
-            });
+            let pfs_mean = pfs.iter().sum::<f64>() / pfs.len() as f64;
+            // the band is of the changes alone, the 100 seed is not a sample
+            monthly_pfs.push(PFS::new(*date, pfs_mean, cycle_months).with_band(&pfs[1..]));
         }
         monthly_pfs
     }
@@ -168,6 +499,30 @@ impl PlotPFS {
         ticker_data: &TickerData,
         cycle_days: u32,
     ) -> PFSResult<Vec<PFS>> {
+        Self::pfs_days_scaled(
+            start_date,
+            end_date,
+            ticker_data,
+            cycle_days,
+            PriceScale::Linear,
+            None,
+        )
+    }
+
+    /// Compute PFS based on daily cycles with percent changes measured on `scale`,
+    /// optionally on the series with its trend removed
+    pub fn pfs_days_scaled(
+        start_date: Time,
+        end_date: Time,
+        ticker_data: &TickerData,
+        cycle_days: u32,
+        scale: PriceScale,
+        detrend: Option<DetrendMethod>,
+    ) -> PFSResult<Vec<PFS>> {
+        let ticker_data = &*Self::detrend_series(ticker_data, detrend)?;
+        ticker_data
+            .validate_price_scale(scale)
+            .map_err(PFSError::TickerDataError)?;
         let mut daily_pfs = Vec::<PFS>::new();
 
         // compute number of cycles possible in candle history
@@ -208,23 +563,99 @@ impl PlotPFS {
                         .expect("Failed to get previous candle");
                     // found candle X cycles back
                     if prev_candle.date < cycle_date && candle.date >= cycle_date {
-                        let change = candle.percent_change(prev_candle.close);
+                        let change = candle.scaled_percent_change(prev_candle.close, scale);
                         pfs.push(change);
                         break;
                     }
                 }
             }
             let pfs_mean = pfs.iter().sum::<f64>() / pfs.len() as f64;
-            daily_pfs.push(PFS {
-                date: *date,
-                value: pfs_mean,
-                cycle: cycle_days,
-            });
+            daily_pfs.push(PFS::new(*date, pfs_mean, cycle_days).with_band(&pfs[1..]));
         }
         daily_pfs.dedup_by(|a, b| a.date == b.date);
         Ok(daily_pfs)
     }
 
+    /// Compute PFS based on weekly cycles, with candles aggregated into Monday to Sunday weeks
+    /// e.g. PFS 20 is the average percent change in price every 20 weeks into the past
+    pub fn pfs_weeks(
+        start_date: Time,
+        end_date: Time,
+        ticker_data: &TickerData,
+        cycle_weeks: u32,
+    ) -> PFSResult<Vec<PFS>> {
+        Self::pfs_buckets(
+            start_date,
+            end_date,
+            ticker_data,
+            cycle_weeks,
+            PFSTimeframe::Week,
+        )
+    }
+
+    /// Compute PFS based on hourly cycles, with candles aggregated into hours
+    /// e.g. PFS 20 is the average percent change in price every 20 hours into the past
+    pub fn pfs_hours(
+        start_date: Time,
+        end_date: Time,
+        ticker_data: &TickerData,
+        cycle_hours: u32,
+    ) -> PFSResult<Vec<PFS>> {
+        Self::pfs_buckets(
+            start_date,
+            end_date,
+            ticker_data,
+            cycle_hours,
+            PFSTimeframe::Hour,
+        )
+    }
+
+    /// PFS of each fixed length bucket of the timeframe, averaged like `pfs_days`
+    fn pfs_buckets(
+        start_date: Time,
+        end_date: Time,
+        ticker_data: &TickerData,
+        cycle: u32,
+        timeframe: PFSTimeframe,
+    ) -> PFSResult<Vec<PFS>> {
+        let ticker_data = &*timeframe.bucketed(ticker_data)?;
+        let secs = timeframe
+            .bucket()
+            .and_then(|bucket| bucket.to_secs())
+            .ok_or(PFSError::UnsupportedTimeframe(timeframe))?;
+        if cycle == 0 {
+            return Err(PFSError::ZeroCycle);
+        }
+        let mut bucket_pfs = Vec::<PFS>::new();
+        if ticker_data.candles.is_empty() {
+            return Ok(bucket_pfs);
+        }
+        let earliest = ticker_data.earliest_date().to_unix();
+        for date in timeframe.dates(&start_date, &end_date).iter() {
+            // PFS for this date
+            let mut pfs = vec![100.0];
+            // compute number of cycles possible in candle history
+            let num_cycles = (date.to_unix() - earliest) / (secs * cycle as i64);
+            // iterate possible cycles in candle history
+            for cycles in 1..num_cycles + 1 {
+                // candle X cycles back
+                let cycle_date = date.to_unix() - secs * cycle as i64 * cycles;
+                // find candle X cycles back
+                let index = ticker_data
+                    .candles
+                    .iter()
+                    .position(|candle| candle.date.to_unix() >= cycle_date);
+                if let Some(index) = index.filter(|index| *index > 0) {
+                    let prev_candle = &ticker_data.candles[index - 1];
+                    pfs.push(ticker_data.candles[index].percent_change(prev_candle.close));
+                }
+            }
+            let pfs_mean = pfs.iter().sum::<f64>() / pfs.len() as f64;
+            bucket_pfs.push(PFS::new(*date, pfs_mean, cycle).with_band(&pfs[1..]));
+        }
+        Ok(bucket_pfs)
+    }
+
     /// Compute PFS based on yearly cycles,
     /// e.g. PFS 20 is the average percent change in price every 20 years into the past
     pub fn pfs_years(
@@ -232,8 +663,75 @@ impl PlotPFS {
         end_date: Time,
         ticker_data: &TickerData,
         cycle_years: u32,
-    ) -> Vec<PFS> {
+    ) -> PFSResult<Vec<PFS>> {
+        Self::pfs_years_anchored(
+            start_date,
+            end_date,
+            ticker_data,
+            cycle_years,
+            start_date.year,
+        )
+    }
+
+    /// Compute PFS based on yearly cycles with the cycle phase aligned to `anchor_year`,
+    /// e.g. a major low, rather than to `start_date`.
+    /// Each date looks back to the nearest year in phase with the anchor, then every `cycle_years` before that.
+    pub fn pfs_years_anchored(
+        start_date: Time,
+        end_date: Time,
+        ticker_data: &TickerData,
+        cycle_years: u32,
+        anchor_year: i32,
+    ) -> PFSResult<Vec<PFS>> {
+        Self::pfs_years_with_scale(
+            start_date,
+            end_date,
+            ticker_data,
+            cycle_years,
+            anchor_year,
+            PriceScale::Linear,
+        )
+    }
+
+    /// Compute PFS based on yearly cycles in phase with `anchor_year` like [`Self::pfs_years_anchored`],
+    /// with percent changes measured on `scale`, optionally on the series with its trend removed
+    pub fn pfs_years_scaled(
+        start_date: Time,
+        end_date: Time,
+        ticker_data: &TickerData,
+        cycle_years: u32,
+        anchor_year: i32,
+        scale: PriceScale,
+        detrend: Option<DetrendMethod>,
+    ) -> PFSResult<Vec<PFS>> {
+        let ticker_data = &*Self::detrend_series(ticker_data, detrend)?;
+        ticker_data
+            .validate_price_scale(scale)
+            .map_err(PFSError::TickerDataError)?;
+        Self::pfs_years_with_scale(
+            start_date,
+            end_date,
+            ticker_data,
+            cycle_years,
+            anchor_year,
+            scale,
+        )
+    }
+
+    fn pfs_years_with_scale(
+        start_date: Time,
+        end_date: Time,
+        ticker_data: &TickerData,
+        cycle_years: u32,
+        anchor_year: i32,
+        scale: PriceScale,
+    ) -> PFSResult<Vec<PFS>> {
+        if cycle_years == 0 {
+            return Err(PFSError::ZeroCycle);
+        }
         let mut yearly_pfs = Vec::<PFS>::new();
+        // years between start date and the last year in phase with the anchor
+        let phase_offset = (start_date.year - anchor_year).rem_euclid(cycle_years as i32);
 
         // compute number of cycles possible in candle history
         let earliest_candle_year = ticker_data.earliest_date().year;
@@ -243,7 +741,7 @@ impl PlotPFS {
         let time_period = start_date.time_period(&end_date);
         for date in time_period.iter() {
             // PFS for this date
-            let mut pfs = (100.0, 1);
+            let mut pfs = vec![100.0];
             // iterate possible cycles in candle history
             for cycle in 1..num_cycles + 1 {
                 // find candle X cycles back
@@ -257,11 +755,12 @@ impl PlotPFS {
                         .get(index - 1)
                         .expect("Failed to get previous candle");
                     // candle X cycles back
-                    if date.year < candle.date.year - cycle_years as i32 * cycle {
+                    let year = date.year - phase_offset;
+                    if year < candle.date.year - cycle_years as i32 * cycle {
                         continue;
                     }
                     let cycle_date = Time::new(
-                        date.year - cycle_years as i32 * cycle,
+                        year - cycle_years as i32 * cycle,
                         &date.month,
                         &date.day,
                         None,
@@ -276,19 +775,16 @@ impl PlotPFS {
                     }
                     // found candle X cycles back
                     if prev_candle.date < cycle_date && candle.date >= cycle_date {
-                        let change = candle.percent_change(prev_candle.close);
-                        pfs = (pfs.0 + change, pfs.1 + 1);
+                        let change = candle.scaled_percent_change(prev_candle.close, scale);
+                        pfs.push(change);
                         break;
                     }
                 }
             }
-            yearly_pfs.push(PFS {
-                date: *date,
-                value: pfs.0 / pfs.1 as f64,
-                cycle: cycle_years,
-            });
+            let pfs_mean = pfs.iter().sum::<f64>() / pfs.len() as f64;
+            yearly_pfs.push(PFS::new(*date, pfs_mean, cycle_years).with_band(&pfs[1..]));
         }
-        yearly_pfs
+        Ok(yearly_pfs)
     }
 
     fn find_confluent_pfs_reversal(
@@ -392,7 +888,7 @@ impl PlotPFS {
                     "Failed to find index for confluent PFS direction target date: {}",
                     target_date.to_string()
                 );
-                return None;
+                None
             }
             Some(target_index) => {
                 if target_index == 0 {
@@ -529,6 +1025,7 @@ impl PlotPFS {
         ticker_data: &TickerData,
         pfs_cycles: Vec<Vec<PFS>>,
         cycles: &[u32],
+        timeframe: PFSTimeframe,
     ) -> ConfluentPFSCorrelation {
         // iterate each date in time period
         // find previous candle and current candle and determine % change is position or negative
@@ -536,7 +1033,7 @@ impl PlotPFS {
         let mut total_count = 0;
         let mut events = Vec::<ConfluentPFSEvent>::new();
 
-        let time_period = start_date.time_period(&end_date);
+        let time_period = timeframe.dates(&start_date, &end_date);
         for (index, date) in time_period.iter().enumerate() {
             if index == 0 {
                 continue;
@@ -544,8 +1041,14 @@ impl PlotPFS {
             let prev_date = time_period
                 .get(index - 1)
                 .expect("Failed to get previous date");
-            let prev_candle = ticker_data.candles.iter().find(|c| &c.date == prev_date);
-            let current_candle = ticker_data.candles.iter().find(|c| &c.date == date);
+            let prev_candle = ticker_data
+                .candles
+                .iter()
+                .find(|c| timeframe.same_date(&c.date, prev_date));
+            let current_candle = ticker_data
+                .candles
+                .iter()
+                .find(|c| timeframe.same_date(&c.date, date));
 
             let mut candle_direction: Option<Direction> = None;
             let mut pfs_direction = Vec::<Option<Direction>>::new();
@@ -562,8 +1065,8 @@ impl PlotPFS {
 
                 for pfs in pfs_cycles.iter() {
                     // find PFS for this cycle for current candle and previous candle and determine if PFS is positive or negative
-                    let prev_pfs = pfs.iter().find(|p| &p.date == prev_date);
-                    let curr_pfs = pfs.iter().find(|p| &p.date == date);
+                    let prev_pfs = pfs.iter().find(|p| timeframe.same_date(&p.date, prev_date));
+                    let curr_pfs = pfs.iter().find(|p| timeframe.same_date(&p.date, date));
                     if let (Some(prev_pfs), Some(curr_pfs)) = (prev_pfs, curr_pfs) {
                         if prev_pfs.value < curr_pfs.value {
                             pfs_direction.push(Some(Direction::Up));
@@ -634,6 +1137,7 @@ impl PlotPFS {
         end_date: Time,
         pfs_cycles: Vec<Vec<PFS>>,
         cycles: &[u32],
+        timeframe: PFSTimeframe,
     ) -> ConfluentPFSCorrelation {
         // iterate each date in time period
         // find previous candle and current candle and determine % change is position or negative
@@ -641,7 +1145,7 @@ impl PlotPFS {
         let mut total_count = 0;
         let mut events = Vec::<ConfluentPFSEvent>::new();
 
-        let time_period = start_date.time_period(&end_date);
+        let time_period = timeframe.dates(&start_date, &end_date);
         for (index, date) in time_period.iter().enumerate() {
             if index == 0 || index == time_period.len() - 1 {
                 continue;
@@ -656,9 +1160,9 @@ impl PlotPFS {
 
             // determine if all PFS have a reversal on this date
             for pfs in pfs_cycles.iter() {
-                let prev_pfs = pfs.iter().find(|p| &p.date == prev_date);
-                let curr_pfs = pfs.iter().find(|p| &p.date == date);
-                let next_pfs = pfs.iter().find(|p| &p.date == next_date);
+                let prev_pfs = pfs.iter().find(|p| timeframe.same_date(&p.date, prev_date));
+                let curr_pfs = pfs.iter().find(|p| timeframe.same_date(&p.date, date));
+                let next_pfs = pfs.iter().find(|p| timeframe.same_date(&p.date, next_date));
                 if let (Some(prev_pfs), Some(curr_pfs), Some(next_pfs)) =
                     (prev_pfs, curr_pfs, next_pfs)
                 {
@@ -752,8 +1256,24 @@ impl PlotPFS {
             PFSTimeframe::Minute => {
                 vec![]
             }
-            PFSTimeframe::Hour => {
-                vec![]
+            PFSTimeframe::Hour | PFSTimeframe::Week => {
+                let mut pfs_cycles = vec![];
+                let mut threads = vec![];
+                for cycle in cycles.iter() {
+                    let ticker = ticker_data.clone();
+                    let cycle = *cycle;
+                    let start_date = self.start_date;
+                    let end_date = self.end_date;
+                    let thread = std::thread::spawn(move || {
+                        Self::pfs_buckets(start_date, end_date, &ticker, cycle, timeframe)
+                    });
+                    threads.push(thread);
+                }
+                for thread in threads {
+                    let res: Vec<PFS> = thread.join().expect("Failed to join PFS cycle thread")?;
+                    pfs_cycles.push(res);
+                }
+                pfs_cycles
             }
             PFSTimeframe::Day => {
                 let mut pfs_cycles = vec![];
@@ -807,73 +1327,125 @@ impl PlotPFS {
                     threads.push(thread);
                 }
                 for thread in threads {
-                    let res: Vec<PFS> = thread.join().expect("Failed to join PFS cycle thread");
+                    let res: Vec<PFS> = thread.join().expect("Failed to join PFS cycle thread")?;
                     pfs_cycles.push(res);
                 }
                 pfs_cycles
             }
         };
-        Ok(pfs_cycles)
+        pfs_cycles.iter().map(|pfs| self.smoothed(pfs)).collect()
     }
 
+    /// PFS of `cycle` on a single date, e.g. tomorrow, as computed by the bulk function of the timeframe
+    /// before smoothing. Yearly cycles are in phase with `self.start_date` like the bulk projection.
+    pub fn pfs_on_date(
+        &self,
+        ticker_data: &TickerData,
+        cycle: u32,
+        timeframe: PFSTimeframe,
+        date: &Time,
+    ) -> PFSResult<f64> {
+        let pfs = match timeframe {
+            PFSTimeframe::Minute => return Err(PFSError::UnsupportedTimeframe(timeframe)),
+            PFSTimeframe::Hour => Self::pfs_hours(*date, *date, ticker_data, cycle)?,
+            PFSTimeframe::Day => Self::pfs_days(*date, *date, ticker_data, cycle)?,
+            PFSTimeframe::Week => Self::pfs_weeks(*date, *date, ticker_data, cycle)?,
+            PFSTimeframe::Month => Self::pfs_months(*date, *date, ticker_data, cycle),
+            PFSTimeframe::Year => {
+                Self::pfs_years_anchored(*date, *date, ticker_data, cycle, self.start_date.year)?
+            }
+        };
+        let pfs = pfs.first().ok_or_else(|| {
+            PFSError::CustomError(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("No PFS computed on {}", date.to_string()),
+            ))
+        })?;
+        Ok(pfs.value)
+    }
+
+    /// Rank correlations by highest `pct_correlation`, ties going to the larger sample `total`
+    fn sort_correlations(correlations: &mut [ConfluentPFSCorrelation]) {
+        correlations.sort_by(|a, b| {
+            b.pct_correlation
+                .partial_cmp(&a.pct_correlation)
+                .unwrap_or(Ordering::Equal)
+                .then(b.total.cmp(&a.total))
+        });
+    }
+
+    /// Drop correlations measured over fewer than `min_total` events, 0 keeps all
+    fn retain_min_total(correlations: &mut Vec<ConfluentPFSCorrelation>, min_total: usize) {
+        correlations.retain(|c| c.total as usize >= min_total);
+    }
+
+    /// Every combination of `cycles`, from single cycles up to all of them
+    fn all_pfs_combinations(cycles: &[u32]) -> Vec<Vec<u32>> {
+        (1..=cycles.len())
+            .flat_map(|k| Self::pfs_combinations(cycles, k))
+            .collect()
+    }
+
+    /// PFS series of each cycle in the combination
+    fn comb_pfs_cycles(pfs_cycles: &[Vec<PFS>], comb: &[u32]) -> Vec<Vec<PFS>> {
+        comb.iter()
+            .map(|c| {
+                let pfs = pfs_cycles.iter().find(|pfs| &pfs[0].cycle == c).unwrap();
+                pfs.to_vec()
+            })
+            .collect()
+    }
+
+    /// Find the direction correlation with price for each combination of PFS cycles.
+    /// Combinations with a correlation below `min_correlation` are dropped, 0.0 keeps all.
+    /// Combinations measured over fewer than `min_total` events are dropped, 0 keeps all.
+    /// Returned highest correlation first, and of equal correlations the larger sample first.
+    /// Combinations are independent and computed in parallel on the rayon thread pool,
+    /// so the search runs roughly as many times faster as there are cores.
     pub fn confluent_pfs_direction(
         &self,
         ticker_data: &TickerData,
         cycles: &[u32],
         timeframe: PFSTimeframe,
         out_file: &str,
+        min_correlation: f64,
+        min_total: usize,
     ) -> PFSResult<Vec<ConfluentPFSCorrelation>> {
         let pfs_cycles = self.pfs_cycles_for_timeframe(ticker_data, cycles, timeframe)?;
-
-        let mut correlations = Vec::<ConfluentPFSCorrelation>::new();
-        for k in 1..=cycles.len() {
-            let combs = Self::pfs_combinations(cycles, k);
-            let mut comb_threads = vec![];
-            for comb in combs.into_iter() {
-                // find PFS for each cycle in combination
-                let pfs_comb_cycles = comb
-                    .iter()
-                    .map(|c| {
-                        let pfs = pfs_cycles.iter().find(|pfs| &pfs[0].cycle == c).unwrap();
-                        pfs.to_vec()
-                    })
-                    .collect::<Vec<Vec<PFS>>>();
-                let start_date = self.start_date;
-                let end_date = self.end_date;
-                let ticker_data = ticker_data.clone();
-                let comb_thread = std::thread::spawn(move || {
-                    Self::confluent_pfs_direction_inner(
-                        start_date,
-                        end_date,
-                        &ticker_data,
-                        pfs_comb_cycles,
-                        &comb,
-                    )
-                });
-                comb_threads.push(comb_thread);
-            }
-            for comb_thread in comb_threads {
-                let res: ConfluentPFSCorrelation = comb_thread
-                    .join()
-                    .expect("Failed to join confluent PFS direction thread");
-                correlations.push(res);
-            }
-        }
-        // remove correlations that have no hits
-        correlations.retain(|c| c.hits > 0);
-        // sort correlations by highest correlation
-        correlations.sort_by(|a, b| b.pct_correlation.partial_cmp(&a.pct_correlation).unwrap());
-        self.write_pfs_confluence_csv(correlations.to_vec(), out_file)
-            .expect("Failed to write PFS confluence CSV");
+        // price direction is measured on candles of the timeframe
+        let ticker_data = &*timeframe.bucketed(ticker_data)?;
+
+        let mut correlations = Self::all_pfs_combinations(cycles)
+            .par_iter()
+            .map(|comb| {
+                Self::confluent_pfs_direction_inner(
+                    self.start_date,
+                    self.end_date,
+                    ticker_data,
+                    Self::comb_pfs_cycles(&pfs_cycles, comb),
+                    comb,
+                    timeframe,
+                )
+            })
+            .collect::<Vec<ConfluentPFSCorrelation>>();
+        // remove correlations that have no hits or are below the threshold
+        correlations.retain(|c| c.hits > 0 && c.pct_correlation >= min_correlation);
+        Self::retain_min_total(&mut correlations, min_total);
+        Self::sort_correlations(&mut correlations);
+        self.write_pfs_confluence_csv(correlations.to_vec(), out_file)?;
         Ok(correlations)
     }
 
+    /// Find the reversal correlation for each combination of PFS cycles.
+    /// Combinations measured over fewer than `min_total` events are dropped, 0 keeps all.
+    /// Returned highest correlation first, and of equal correlations the larger sample first.
     pub fn confluent_pfs_reversal(
         &self,
         ticker_data: &TickerData,
         cycles: &[u32],
         timeframe: PFSTimeframe,
         out_file: &str,
+        min_total: usize,
     ) -> PFSResult<Vec<ConfluentPFSCorrelation>> {
         let pfs_cycles = self.pfs_cycles_for_timeframe(ticker_data, cycles, timeframe)?;
 
@@ -893,7 +1465,9 @@ impl PlotPFS {
                 let start_date = self.start_date;
                 let end_date = self.end_date;
                 let thread = std::thread::spawn(move || {
-                    Self::confluent_pfs_reversal_inner(start_date, end_date, pfs_cycles, &comb)
+                    Self::confluent_pfs_reversal_inner(
+                        start_date, end_date, pfs_cycles, &comb, timeframe,
+                    )
                 });
                 threads.push(thread);
             }
@@ -905,54 +1479,211 @@ impl PlotPFS {
                 correlations.push(correlation);
             }
         }
-        // sort correlations by highest correlation
-        if correlations.len() > 1 {
-            correlations.sort_by(|a, b| b.pct_correlation.partial_cmp(&a.pct_correlation).unwrap());
-        }
+        Self::retain_min_total(&mut correlations, min_total);
+        Self::sort_correlations(&mut correlations);
         // eliminate if correlation.cycles.len() == 1
         //let correlations = correlations.into_iter().filter(|c| c.cycles.len() > 1).collect::<Vec<ConfluentPFSCorrelation>>();
-        self.write_pfs_confluence_csv(correlations.to_vec(), out_file)
-            .expect("Failed to write PFS confluence CSV");
+        self.write_pfs_confluence_csv(correlations.to_vec(), out_file)?;
         Ok(correlations)
     }
 
-    fn trade_quantity(&self, capital: f64, price: f64) -> f64 {
-        let quantity = capital / price;
-        (quantity * 1000000.0).round() / 1000000.0
+    /// Step a backtest forward one candle, filling at the candle close or at its open and stops
+    /// as the `fill` mode sets.
+    /// `bars_held` counts bars since the open trade's last signal, and the trade exits at the close
+    /// once its hold window expires.
+    /// A new trade is sized by the backtest's sizing policy on its current equity,
+    /// and every fill crosses the backtest's spread.
+    #[allow(clippy::too_many_arguments)]
+    fn backtest_candle(
+        open_trade: &mut Option<Trade>,
+        bars_held: &mut usize,
+        backtest: &mut Backtest,
+        candle: &Candle,
+        signal: Option<Order>,
+        trailing_stop_type: TrailingStopType,
+        trailing_stop: f64,
+        stop_loss_pct: f64,
+        fill: FillMode,
+        hold: HoldPolicy,
+    ) -> PFSResult<()> {
+        let stops = (trailing_stop_type, trailing_stop, stop_loss_pct);
+        match fill {
+            FillMode::Close => Self::backtest_candle_close(
+                open_trade, bars_held, backtest, candle, signal, stops, hold,
+            ),
+            FillMode::OpenIntrabar => Self::backtest_candle_intrabar(
+                open_trade, bars_held, backtest, candle, signal, stops, hold,
+            ),
+        }
     }
 
-    fn stop_triggered(
-        order: &Order,
-        trailing_stop: &Option<f64>,
-        stop_loss: &Option<f64>,
+    /// Every fill at the candle close.
+    /// A signal exits an opposing trade, or one whose stop the close is through,
+    /// and enters the signal direction, replacing an open trade in the same direction.
+    /// Otherwise an open trade exits if its stop is hit, or its trailing stop is updated.
+    fn backtest_candle_close(
+        open_trade: &mut Option<Trade>,
+        bars_held: &mut usize,
+        backtest: &mut Backtest,
         candle: &Candle,
-    ) -> bool {
-        match order {
-            Order::Long => {
-                if trailing_stop.is_some() && stop_loss.is_some() {
-                    candle.close < trailing_stop.unwrap() || candle.close < stop_loss.unwrap()
-                } else if trailing_stop.is_some() && stop_loss.is_none() {
-                    candle.close < trailing_stop.unwrap()
-                } else if trailing_stop.is_none() && stop_loss.is_some() {
-                    candle.close < stop_loss.unwrap()
-                } else {
-                    false
+        signal: Option<Order>,
+        stops: (TrailingStopType, f64, f64),
+        hold: HoldPolicy,
+    ) -> PFSResult<()> {
+        let date = candle.date;
+        let spread_bps = backtest.spread_bps;
+        match signal {
+            Some(order) => {
+                if let Some(mut trade) = open_trade.take() {
+                    if trade.order != order || trade.stop_triggered(candle) {
+                        trade.exit(
+                            date,
+                            Trade::exit_fill(&trade.order, candle.close, spread_bps),
+                        );
+                        backtest.add_trade(trade).map_err(PFSError::BacktestError)?;
+                    }
                 }
+                *bars_held = 0;
+                *open_trade = Some(Self::backtest_entry(
+                    backtest,
+                    date,
+                    order,
+                    candle.close,
+                    stops,
+                ));
             }
-            Order::Short => {
-                if trailing_stop.is_some() && stop_loss.is_some() {
-                    candle.close > trailing_stop.unwrap() || candle.close > stop_loss.unwrap()
-                } else if trailing_stop.is_some() && stop_loss.is_none() {
-                    candle.close > trailing_stop.unwrap()
-                } else if trailing_stop.is_none() && stop_loss.is_some() {
-                    candle.close > stop_loss.unwrap()
-                } else {
-                    false
+            None => {
+                if let Some(mut trade) = open_trade.take() {
+                    *bars_held += 1;
+                    if trade.stop_triggered(candle) || hold.hold_expired(*bars_held) {
+                        trade.exit(
+                            date,
+                            Trade::exit_fill(&trade.order, candle.close, spread_bps),
+                        );
+                        backtest.add_trade(trade).map_err(PFSError::BacktestError)?;
+                    } else {
+                        let (trailing_stop_type, trailing_stop, _) = stops;
+                        trade.trailing_stop = Some(Trade::calc_trailing_stop(
+                            trade.order.clone(),
+                            candle.close,
+                            trailing_stop_type,
+                            trailing_stop,
+                        ));
+                        *open_trade = Some(trade);
+                    }
                 }
             }
         }
+        Ok(())
     }
 
+    /// Entries at the candle open and stops checked against its high and low, exiting at the stop price.
+    /// A signal exits an opposing trade at the open and enters a trade in the signal direction if none is open,
+    /// which the rest of the candle can stop out.
+    /// Otherwise an open trade exits if its stop is hit, or its trailing stop is updated.
+    fn backtest_candle_intrabar(
+        open_trade: &mut Option<Trade>,
+        bars_held: &mut usize,
+        backtest: &mut Backtest,
+        candle: &Candle,
+        signal: Option<Order>,
+        stops: (TrailingStopType, f64, f64),
+        hold: HoldPolicy,
+    ) -> PFSResult<()> {
+        let fill = FillMode::OpenIntrabar;
+        let date = candle.date;
+        let entry_price = fill.entry_price(candle);
+        let spread_bps = backtest.spread_bps;
+        // signal exits an opposing trade at the price the new trade enters
+        if let (Some(trade), Some(order)) = (open_trade.as_ref(), signal.as_ref()) {
+            if &trade.order != order {
+                let mut trade = trade.clone();
+                trade.exit(
+                    date,
+                    Trade::exit_fill(&trade.order, entry_price, spread_bps),
+                );
+                backtest.add_trade(trade).map_err(PFSError::BacktestError)?;
+                *open_trade = None;
+            }
+        }
+        match (open_trade.take(), signal) {
+            (Some(mut trade), signal) => match fill.stop_exit_price(&trade, candle) {
+                Some(exit_price) => {
+                    trade.exit(date, Trade::exit_fill(&trade.order, exit_price, spread_bps));
+                    backtest.add_trade(trade).map_err(PFSError::BacktestError)?;
+                }
+                None => {
+                    // a fresh signal in the trade direction restarts the hold window
+                    *bars_held = match signal {
+                        Some(_) => 0,
+                        None => *bars_held + 1,
+                    };
+                    if hold.hold_expired(*bars_held) {
+                        trade.exit(
+                            date,
+                            Trade::exit_fill(&trade.order, candle.close, spread_bps),
+                        );
+                        backtest.add_trade(trade).map_err(PFSError::BacktestError)?;
+                        return Ok(());
+                    }
+                    let (trailing_stop_type, trailing_stop, _) = stops;
+                    trade.trailing_stop = Some(Trade::calc_trailing_stop(
+                        trade.order.clone(),
+                        candle.close,
+                        trailing_stop_type,
+                        trailing_stop,
+                    ));
+                    *open_trade = Some(trade);
+                }
+            },
+            (None, Some(order)) => {
+                *bars_held = 0;
+                let mut trade = Self::backtest_entry(backtest, date, order, entry_price, stops);
+                // rest of the candle after an entry at the open can hit the stop
+                match fill.stop_exit_price(&trade, candle) {
+                    Some(exit_price) => {
+                        trade.exit(date, Trade::exit_fill(&trade.order, exit_price, spread_bps));
+                        backtest.add_trade(trade).map_err(PFSError::BacktestError)?;
+                    }
+                    None => *open_trade = Some(trade),
+                }
+            }
+            (None, None) => (),
+        }
+        Ok(())
+    }
+
+    /// Trade entered at `entry_price` across the spread, with stops of `stops` from the entry price
+    fn backtest_entry(
+        backtest: &Backtest,
+        date: Time,
+        order: Order,
+        entry_price: f64,
+        stops: (TrailingStopType, f64, f64),
+    ) -> Trade {
+        let (trailing_stop_type, trailing_stop, stop_loss_pct) = stops;
+        let fill_price = Trade::entry_fill(&order, entry_price, backtest.spread_bps);
+        let stop_loss = Trade::calc_stop_loss(order.clone(), entry_price, stop_loss_pct);
+        let capital = backtest.trade_capital(fill_price, Some(stop_loss));
+        Trade::new(
+            date,
+            order.clone(),
+            Trade::trade_quantity(capital, fill_price),
+            fill_price,
+            capital,
+            Some(Trade::calc_trailing_stop(
+                order,
+                entry_price,
+                trailing_stop_type,
+                trailing_stop,
+            )),
+            Some(stop_loss),
+        )
+    }
+
+    /// Backtest each combination of PFS cycles that reverses in confluence,
+    /// returned with the most profitable backtest first.
+    /// Combinations measured over fewer than `min_total` events are not backtested, 0 keeps all.
     #[allow(clippy::too_many_arguments)]
     pub fn backtest_confluent_pfs_reversal(
         &self,
@@ -960,12 +1691,18 @@ impl PlotPFS {
         cycles: &[u32],
         timeframe: PFSTimeframe,
         out_file: &str,
-        capital: f64,
+        min_total: usize,
+        starting_equity: f64,
+        sizing: PositionSizing,
+        spread_bps: f64,
         trailing_stop_type: TrailingStopType,
         trailing_stop: f64,
         stop_loss_pct: f64,
+        fill: FillMode,
+        hold: HoldPolicy,
     ) -> PFSResult<Vec<Backtest>> {
-        let rev_corr = self.confluent_pfs_reversal(ticker_data, cycles, timeframe, out_file)?;
+        let rev_corr =
+            self.confluent_pfs_reversal(ticker_data, cycles, timeframe, out_file, min_total)?;
 
         let mut all_backtests = Vec::<Backtest>::new();
         for corr in rev_corr.iter() {
@@ -973,8 +1710,11 @@ impl PlotPFS {
             let cycles = corr.cycles.clone();
             let pfs_cycles = self.pfs_cycles_for_timeframe(ticker_data, &cycles, timeframe)?;
 
-            let open_trade_mutex: Arc<Mutex<Option<Trade>>> = Arc::new(Mutex::new(None));
-            let mut backtest = Backtest::new(capital);
+            let mut open_trade: Option<Trade> = None;
+            let mut bars_held = 0;
+            let mut backtest = Backtest::new(starting_equity);
+            backtest.sizing = sizing;
+            backtest.spread_bps = spread_bps;
 
             // isolate ticker candles from self.start_date to self.end_date
             let ticker_candles = ticker_data
@@ -986,165 +1726,27 @@ impl PlotPFS {
             for candle in ticker_candles {
                 let date = &candle.date;
                 // get PFS confluent reversal event for each date
-                let event =
-                    self.find_confluent_pfs_reversal(&pfs_cycles, &cycles, ticker_data, date);
-                let mut open_trade = open_trade_mutex
-                    .lock()
-                    .expect("Failed to lock open_trade mutex");
-
-                match event {
-                    // if event, exit Long and enter Short if reversal is High
-                    // if event, exit Short and enter Long if reversal is Low
-                    Some(event) => {
-                        if let Some(reversal) = event.reversal {
-                            match reversal {
-                                ReversalType::High => {
-                                    debug!("PFS reversal High: {}", date.to_string_daily());
-                                    // exit Long if price below trailing stop, or open trade is Long
-                                    if let Some(trade) = &*open_trade {
-                                        // clone is ok because value is overwritten after this block
-                                        let mut trade = trade.clone();
-                                        // let mut trade = trade.clone();
-                                        if trade.order == Order::Long
-                                            || Self::stop_triggered(
-                                                &trade.order,
-                                                &trade.trailing_stop,
-                                                &trade.stop_loss,
-                                                candle,
-                                            )
-                                        {
-                                            trade.exit(*date, candle.close);
-                                            backtest.add_trade(trade);
-                                            *open_trade = None;
-                                        }
-                                    }
-                                    // enter short
-                                    let qty = self.trade_quantity(capital, candle.close);
-                                    let trailing_stop = Trade::calc_trailing_stop(
-                                        Order::Short,
-                                        candle.close,
-                                        trailing_stop_type,
-                                        trailing_stop,
-                                    );
-                                    let stop_loss = Trade::calc_stop_loss(
-                                        Order::Short,
-                                        candle.close,
-                                        stop_loss_pct,
-                                    );
-                                    *open_trade = Some(Trade::new(
-                                        *date,
-                                        Order::Short,
-                                        qty,
-                                        candle.close,
-                                        capital,
-                                        Some(trailing_stop),
-                                        Some(stop_loss),
-                                    ));
-                                }
-                                ReversalType::Low => {
-                                    debug!("PFS Low: {}", date.to_string_daily());
-                                    // exit short
-                                    if let Some(trade) = &*open_trade {
-                                        // clone is ok because value is overwritten after this block
-                                        let mut trade = trade.clone();
-                                        if trade.order == Order::Short
-                                            || Self::stop_triggered(
-                                                &trade.order,
-                                                &trade.trailing_stop,
-                                                &trade.stop_loss,
-                                                candle,
-                                            )
-                                        {
-                                            trade.exit(*date, candle.close);
-                                            backtest.add_trade(trade);
-                                            *open_trade = None;
-                                        }
-                                    }
-                                    // enter long
-                                    let qty = Trade::trade_quantity(capital, candle.close);
-                                    let trailing_stop = Trade::calc_trailing_stop(
-                                        Order::Long,
-                                        candle.close,
-                                        trailing_stop_type,
-                                        trailing_stop,
-                                    );
-                                    let stop_loss = Trade::calc_stop_loss(
-                                        Order::Long,
-                                        candle.close,
-                                        stop_loss_pct,
-                                    );
-                                    *open_trade = Some(Trade::new(
-                                        *date,
-                                        Order::Long,
-                                        qty,
-                                        candle.close,
-                                        capital,
-                                        Some(trailing_stop),
-                                        Some(stop_loss),
-                                    ));
-                                }
-                            }
-                        }
-                    }
-                    // if no event, check trailing stop
-                    // if trailing stop is hit, exit trade
-                    // otherwise update trailing stop
-                    None => {
-                        debug!("No PFS Reversal: {}", date.to_string_daily());
-                        if let Some(trade) = &*open_trade {
-                            match trade.order {
-                                Order::Long => {
-                                    // Long trailing stop is hit, exit trade
-                                    if Self::stop_triggered(
-                                        &trade.order,
-                                        &trade.trailing_stop,
-                                        &trade.stop_loss,
-                                        candle,
-                                    ) {
-                                        let mut trade = trade.clone();
-                                        trade.exit(*date, candle.close);
-                                        backtest.add_trade(trade);
-                                    }
-                                    // Long trailing stop is not hit, update trailing stop
-                                    else {
-                                        let mut trade = trade.clone();
-                                        trade.trailing_stop = Some(Trade::calc_trailing_stop(
-                                            Order::Long,
-                                            candle.close,
-                                            trailing_stop_type,
-                                            trailing_stop,
-                                        ));
-                                        *open_trade = Some(trade);
-                                    }
-                                }
-                                Order::Short => {
-                                    // Short trailing stop is hit, exit trade
-                                    if Self::stop_triggered(
-                                        &trade.order,
-                                        &trade.trailing_stop,
-                                        &trade.stop_loss,
-                                        candle,
-                                    ) {
-                                        let mut trade = trade.clone();
-                                        trade.exit(*date, candle.close);
-                                        backtest.add_trade(trade);
-                                    }
-                                    // Short trailing stop is not hit, update trailing stop
-                                    else {
-                                        let mut trade = trade.clone();
-                                        trade.trailing_stop = Some(Trade::calc_trailing_stop(
-                                            Order::Short,
-                                            candle.close,
-                                            trailing_stop_type,
-                                            trailing_stop,
-                                        ));
-                                        *open_trade = Some(trade);
-                                    }
-                                }
-                            }
-                        }
-                    }
+                // reversal High enters Short, reversal Low enters Long
+                let signal = self
+                    .find_confluent_pfs_reversal(&pfs_cycles, &cycles, ticker_data, date)
+                    .and_then(|event| event.reversal)
+                    .map(Order::from);
+                match &signal {
+                    Some(order) => debug!("PFS reversal {:?}: {}", order, date.to_string_daily()),
+                    None => debug!("No PFS Reversal: {}", date.to_string_daily()),
                 }
+                Self::backtest_candle(
+                    &mut open_trade,
+                    &mut bars_held,
+                    &mut backtest,
+                    candle,
+                    signal,
+                    trailing_stop_type,
+                    trailing_stop,
+                    stop_loss_pct,
+                    fill,
+                    hold,
+                )?;
             }
             backtest.summarize();
             all_backtests.push(backtest);
@@ -1172,49 +1774,40 @@ impl PlotPFS {
             if backtest.trades.is_empty() {
                 continue;
             }
-            let start_date = backtest
-                .start_date
-                .expect("No start date found")
-                .to_string_daily();
-            let end_date = backtest
-                .end_date
-                .expect("No end date found")
-                .to_string_daily();
-            let pnl = backtest.pnl.unwrap_or(0.0);
-            let avg_trade = backtest.avg_trade_pnl.unwrap_or(0.0);
-            let avg_win = backtest.avg_win_trade_pnl.unwrap_or(0.0);
-            let avg_loss = backtest.avg_loss_trade_pnl.unwrap_or(0.0);
-            let win_trades = backtest.num_win_trades();
-            let loss_trades = backtest.num_loss_trades();
-            let trades = backtest.trades.len();
+            let report = backtest.report();
             writeln!(
                 file,
                 "{},{},{},{},{},{},{},{},{}",
-                start_date,
-                end_date,
-                pnl,
-                avg_trade,
-                avg_win,
-                avg_loss,
-                win_trades,
-                loss_trades,
-                trades
+                report.start_date,
+                report.end_date,
+                report.pnl,
+                report.avg_trade_pnl,
+                report.avg_win_trade_pnl,
+                report.avg_loss_trade_pnl,
+                report.win_trades,
+                report.loss_trades,
+                report.trades
             )?;
         }
         Ok(())
     }
 
+    /// Errors if no correlations are left to write, e.g. all fell below a minimum correlation or total.
     fn write_pfs_confluence_csv(
         &self,
         correlations: Vec<ConfluentPFSCorrelation>,
         out_file: &str,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> PFSResult<()> {
         if correlations.is_empty() {
-            return Err("No correlations found".into());
+            return Err(PFSError::CustomError(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "No correlations found",
+            )));
         }
-        let mut file = File::create(out_file)?;
+        let mut file = File::create(out_file).map_err(PFSError::CustomError)?;
 
-        writeln!(file, "start_date,end_date,correlation,hits,total,cycles")?;
+        writeln!(file, "start_date,end_date,correlation,hits,total,cycles")
+            .map_err(PFSError::CustomError)?;
         // format Vec<u32> into format that implements Display
         for corr in correlations.iter() {
             let cycles = corr
@@ -1232,7 +1825,31 @@ impl PlotPFS {
                 corr.hits,
                 corr.total,
                 cycles
-            )?;
+            )
+            .map_err(PFSError::CustomError)?;
+        }
+        Ok(())
+    }
+
+    /// Write PFS projection to CSV, one row per PFS date.
+    /// Band columns are empty if the PFS has no confidence band.
+    pub fn write_pfs_csv(&self, pfs: &[PFS], out_file: &str) -> PFSResult<()> {
+        let mut file = File::create(out_file).map_err(PFSError::CustomError)?;
+        writeln!(file, "date,projection,band_low,band_high").map_err(PFSError::CustomError)?;
+        for point in pfs.iter() {
+            let (band_low, band_high) = match point.band {
+                Some((low, high)) => (low.to_string(), high.to_string()),
+                None => (String::new(), String::new()),
+            };
+            writeln!(
+                file,
+                "{},{},{},{}",
+                point.date.to_string_daily(),
+                point.value,
+                band_low,
+                band_high
+            )
+            .map_err(PFSError::CustomError)?;
         }
         Ok(())
     }
@@ -1243,7 +1860,8 @@ impl PlotPFS {
         out_file: &str,
         plot_title: &str,
         plot_color: &RGBColor,
-    ) {
+    ) -> PFSResult<()> {
+        let daily_pfs = &self.smoothed(daily_pfs)?;
         // get daily PFS data
         let data = self.get_data(daily_pfs);
         // draw chart
@@ -1298,6 +1916,7 @@ impl PlotPFS {
         // To avoid the IO failure being ignored silently, we manually call the present function
         root.present().expect("Unable to write result to file, please make sure 'plotters-doc-data' dir exists under current dir");
         println!("Result has been saved to {}", out_file);
+        Ok(())
     }
 
     fn get_data(&self, daily_pfs: &[PFS]) -> Vec<(String, f32)> {
@@ -1337,3 +1956,718 @@ impl PlotPFS {
             .date_naive()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Day, Month};
+
+    /// Temp path of `file` for this process, so concurrent test runs do not write the same file
+    fn temp_path(file: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("{}_{}", std::process::id(), file))
+    }
+
+    /// Candle that opens, trades and closes at `close`
+    fn candle(date: Time, close: f64) -> Candle {
+        Candle {
+            date,
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: None,
+        }
+    }
+
+    /// Daily candles from `start_date` closing at each of `closes`
+    fn daily_series(
+        start_date: Time,
+        closes: impl IntoIterator<Item = f64>,
+    ) -> PFSResult<TickerData> {
+        let candles = closes
+            .into_iter()
+            .enumerate()
+            .map(|(day, close)| candle(start_date.delta_date(day as i64), close))
+            .collect::<Vec<Candle>>();
+        let mut ticker_data = TickerData::new();
+        ticker_data
+            .add_series(candles)
+            .map_err(PFSError::TickerDataError)?;
+        Ok(ticker_data)
+    }
+
+    /// Confluent correlation of `cycles` that hit `hits` of `total` events
+    fn correlation(cycles: Vec<u32>, hits: u32, total: u32) -> ConfluentPFSCorrelation {
+        ConfluentPFSCorrelation {
+            cycles,
+            events: vec![],
+            hits,
+            total,
+            pct_correlation: hits as f64 / total as f64,
+        }
+    }
+
+    #[test]
+    fn confluent_direction_min_correlation() -> PFSResult<()> {
+        let start_date = Time::new(2022, &Month::from_num(1), &Day::from_num(1), None, None);
+        let ticker_data = daily_series(
+            start_date,
+            (0..120).map(|i| 100.0 + 10.0 * (i as f64 / 5.0).sin() + ((i * 7) % 11) as f64),
+        )?;
+
+        let pfs = PlotPFS::new(start_date.delta_date(90), start_date.delta_date(119));
+        let out_file = temp_path("pfs_min_correlation_test.csv");
+        let out_file = out_file.to_str().expect("Invalid temp file path");
+        let cycles = [3, 5, 7, 11];
+        let all = pfs.confluent_pfs_direction(
+            &ticker_data,
+            &cycles,
+            PFSTimeframe::Day,
+            out_file,
+            0.0,
+            0,
+        )?;
+        assert!(!all.is_empty());
+
+        let min_correlation = all[all.len() / 2].pct_correlation;
+        let filtered = pfs.confluent_pfs_direction(
+            &ticker_data,
+            &cycles,
+            PFSTimeframe::Day,
+            out_file,
+            min_correlation,
+            0,
+        )?;
+        assert!(!filtered.is_empty());
+        assert!(filtered
+            .iter()
+            .all(|c| c.pct_correlation >= min_correlation));
+        let expected = all
+            .iter()
+            .filter(|c| c.pct_correlation >= min_correlation)
+            .count();
+        assert_eq!(filtered.len(), expected);
+
+        // a threshold above every correlation leaves nothing to write
+        let none = pfs.confluent_pfs_direction(
+            &ticker_data,
+            &cycles,
+            PFSTimeframe::Day,
+            out_file,
+            all[0].pct_correlation + 1.0,
+            0,
+        );
+        assert!(matches!(none, Err(PFSError::CustomError(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn confluent_reversal_backtest_min_total() -> PFSResult<()> {
+        let start_date = Time::new(2022, &Month::from_num(1), &Day::from_num(1), None, None);
+        let ticker_data = daily_series(
+            start_date,
+            (0..120).map(|i| 100.0 + 10.0 * (i as f64 / 5.0).sin() + ((i * 7) % 11) as f64),
+        )?;
+
+        let pfs = PlotPFS::new(start_date.delta_date(90), start_date.delta_date(119));
+        let out_file = temp_path("pfs_reversal_min_total_test.csv");
+        let out_file = out_file.to_str().expect("Invalid temp file path");
+        let cycles = [3, 5, 7, 11];
+        let all =
+            pfs.confluent_pfs_reversal(&ticker_data, &cycles, PFSTimeframe::Day, out_file, 0)?;
+        // every combination is measured over the same days
+        let total = all[0].total as usize;
+        assert!(all.iter().all(|c| c.total as usize == total));
+
+        let backtest = |min_total: usize| {
+            pfs.backtest_confluent_pfs_reversal(
+                &ticker_data,
+                &cycles,
+                PFSTimeframe::Day,
+                out_file,
+                min_total,
+                1000.0,
+                PositionSizing::Full,
+                0.0,
+                TrailingStopType::Percent,
+                0.01,
+                0.01,
+                FillMode::Close,
+                HoldPolicy::AlwaysInMarket,
+            )
+        };
+        assert_eq!(backtest(total)?.len(), all.len());
+        // no combination meets a larger sample, so none is backtested
+        assert!(matches!(backtest(total + 1), Err(PFSError::CustomError(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn pfs_on_date_matches_series() -> PFSResult<()> {
+        let start_date = Time::new(2022, &Month::from_num(1), &Day::from_num(1), None, None);
+        let ticker_data = daily_series(
+            start_date,
+            (0..120).map(|i| 100.0 + 10.0 * (i as f64 / 5.0).sin() + ((i * 7) % 11) as f64),
+        )?;
+
+        // through the day after the last candle
+        let plot = PlotPFS::new(start_date.delta_date(90), start_date.delta_date(120));
+        let series = PlotPFS::pfs_days(plot.start_date, plot.end_date, &ticker_data, 7)?;
+        for pfs in series.iter() {
+            let value = plot.pfs_on_date(&ticker_data, 7, PFSTimeframe::Day, &pfs.date)?;
+            assert_eq!(value, pfs.value);
+        }
+        assert!(matches!(
+            plot.pfs_on_date(&ticker_data, 7, PFSTimeframe::Minute, &plot.end_date),
+            Err(PFSError::UnsupportedTimeframe(PFSTimeframe::Minute))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn flat_series_pfs_is_baseline() -> PFSResult<()> {
+        let start_date = Time::new(2020, &Month::from_num(1), &Day::from_num(1), None, None);
+        let ticker_data = daily_series(start_date, (0..800).map(|_| 250.0))?;
+
+        let mut plot = PlotPFS::new(start_date.delta_date(700), start_date.delta_date(830));
+        for (baseline, lookback, timeframe, cycle) in [
+            (100.0, 1, PFSTimeframe::Day, 7),
+            (0.0, 5, PFSTimeframe::Day, 30),
+            (50.0, 1, PFSTimeframe::Month, 2),
+            (100.0, 1, PFSTimeframe::Year, 1),
+        ] {
+            plot.baseline = baseline;
+            plot.change_lookback = lookback;
+            let pfs = plot.pfs_normalized(&ticker_data, cycle, timeframe)?;
+            assert_eq!(pfs.len(), 131);
+            assert!(pfs.iter().all(|pfs| pfs.value == baseline));
+        }
+
+        // a rising series projects above the baseline
+        let rising = daily_series(start_date, (0..800).map(|i| 100.0 + i as f64))?;
+        plot.baseline = 100.0;
+        plot.change_lookback = 1;
+        let pfs = plot.pfs_normalized(&rising, 7, PFSTimeframe::Day)?;
+        assert!(pfs.iter().all(|pfs| pfs.value > 100.0));
+
+        // a zero cycle never moves back through the history
+        assert!(matches!(
+            plot.pfs_normalized(&ticker_data, 0, PFSTimeframe::Day),
+            Err(PFSError::ZeroCycle)
+        ));
+        assert!(matches!(
+            PlotPFS::pfs_weeks(plot.start_date, plot.end_date, &ticker_data, 0),
+            Err(PFSError::ZeroCycle)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn daily_pfs_measures_leap_day() -> PFSResult<()> {
+        let start_date = Time::new(2020, &Month::from_num(1), &Day::from_num(1), None, None);
+        let leap_day = Time::new(2020, &Month::from_num(2), &Day::from_num(29), None, None);
+        // flat except for a 10% jump into the leap day
+        let ticker_data = daily_series(
+            start_date,
+            (0..120).map(|i| match start_date.delta_date(i) < leap_day {
+                true => 100.0,
+                false => 110.0,
+            }),
+        )?;
+
+        let date = leap_day.delta_date(7);
+        let plot = PlotPFS::new(date, date);
+        let pfs = plot.pfs_normalized(&ticker_data, 7, PFSTimeframe::Day)?;
+        // the jump is one of the 9 weekly cycle dates back to the first candle
+        assert!((pfs[0].value - (100.0 + 10.0 / 9.0)).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn pfs_weeks_one_per_week() -> PFSResult<()> {
+        // Saturday
+        let start_date = Time::new(2022, &Month::from_num(1), &Day::from_num(1), None, None);
+        let ticker_data = daily_series(
+            start_date,
+            (0..400).map(|i| 100.0 + 10.0 * (i as f64 / 9.0).sin()),
+        )?;
+
+        // Wednesday through the Friday 9 weeks later
+        let from = Time::new(2022, &Month::from_num(11), &Day::from_num(2), None, None);
+        let to = from.delta_date(65);
+        let weekly = PlotPFS::pfs_weeks(from, to, &ticker_data, 4)?;
+        assert_eq!(weekly.len(), 10);
+        assert!(weekly
+            .iter()
+            .all(|pfs| pfs.date.to_naive_date().weekday() == chrono::Weekday::Mon));
+        assert!(weekly
+            .windows(2)
+            .all(|pair| pair[1].date.to_unix() - pair[0].date.to_unix() == 7 * 24 * 60 * 60));
+
+        // weekly candles are used as is
+        let resampled = ticker_data
+            .resample(Timeframe::Week)
+            .map_err(PFSError::TickerDataError)?;
+        let from_resampled = PlotPFS::pfs_weeks(from, to, &resampled, 4)?;
+        assert_eq!(
+            weekly.iter().map(|pfs| pfs.value).collect::<Vec<f64>>(),
+            from_resampled
+                .iter()
+                .map(|pfs| pfs.value)
+                .collect::<Vec<f64>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parallel_direction_matches_serial() -> PFSResult<()> {
+        let start_date = Time::new(2022, &Month::from_num(1), &Day::from_num(1), None, None);
+        let ticker_data = daily_series(
+            start_date,
+            (0..120).map(|i| 100.0 + 10.0 * (i as f64 / 5.0).sin() + ((i * 7) % 11) as f64),
+        )?;
+
+        let pfs = PlotPFS::new(start_date.delta_date(90), start_date.delta_date(119));
+        let out_file = temp_path("pfs_parallel_direction_test.csv");
+        let out_file = out_file.to_str().expect("Invalid temp file path");
+        let cycles = [3, 5, 7, 11];
+        let parallel = pfs.confluent_pfs_direction(
+            &ticker_data,
+            &cycles,
+            PFSTimeframe::Day,
+            out_file,
+            0.0,
+            0,
+        )?;
+
+        let pfs_cycles = pfs.pfs_cycles_for_timeframe(&ticker_data, &cycles, PFSTimeframe::Day)?;
+        let mut serial = PlotPFS::all_pfs_combinations(&cycles)
+            .iter()
+            .map(|comb| {
+                PlotPFS::confluent_pfs_direction_inner(
+                    pfs.start_date,
+                    pfs.end_date,
+                    &ticker_data,
+                    PlotPFS::comb_pfs_cycles(&pfs_cycles, comb),
+                    comb,
+                    PFSTimeframe::Day,
+                )
+            })
+            .collect::<Vec<ConfluentPFSCorrelation>>();
+        serial.retain(|c| c.hits > 0);
+        PlotPFS::sort_correlations(&mut serial);
+
+        let summary = |correlations: &[ConfluentPFSCorrelation]| {
+            correlations
+                .iter()
+                .map(|c| (c.cycles.clone(), c.hits, c.total))
+                .collect::<Vec<(Vec<u32>, u32, u32)>>()
+        };
+        assert!(!parallel.is_empty());
+        assert_eq!(summary(&parallel), summary(&serial));
+        Ok(())
+    }
+
+    #[test]
+    fn min_total_drops_small_samples() {
+        let mut correlations = vec![
+            correlation(vec![3], 2, 2),
+            correlation(vec![5], 10, 10),
+            correlation(vec![3, 5], 9, 9),
+            correlation(vec![7], 25, 25),
+        ];
+        PlotPFS::retain_min_total(&mut correlations, 10);
+        let kept = correlations
+            .iter()
+            .map(|c| c.cycles.clone())
+            .collect::<Vec<Vec<u32>>>();
+        assert_eq!(kept, vec![vec![5], vec![7]]);
+
+        PlotPFS::retain_min_total(&mut correlations, 0);
+        assert_eq!(correlations.len(), 2);
+    }
+
+    #[test]
+    fn equal_correlation_ranks_larger_sample_first() {
+        let mut correlations = vec![
+            correlation(vec![3], 3, 4),
+            correlation(vec![5], 30, 40),
+            correlation(vec![3, 5], 9, 10),
+            correlation(vec![7], 30, 60),
+        ];
+        PlotPFS::sort_correlations(&mut correlations);
+        let ranked = correlations
+            .iter()
+            .map(|c| c.cycles.clone())
+            .collect::<Vec<Vec<u32>>>();
+        assert_eq!(ranked, vec![vec![3, 5], vec![5], vec![3], vec![7]]);
+    }
+
+    #[test]
+    fn pfs_band_is_standard_error() -> PFSResult<()> {
+        let start_date = Time::new(2023, &Month::from_num(1), &Day::from_num(1), None, None);
+        let ticker_data = daily_series(
+            start_date,
+            (0..30).map(|i| if i % 2 == 0 { 100.0 } else { 110.0 }),
+        )?;
+
+        // day 15 averages the seed with the changes into day 10 and day 5, day 0 has no candle before it
+        let date = start_date.delta_date(15);
+        let pfs = PlotPFS::pfs_days(date, date, &ticker_data, 5)?;
+        let down = (100.0 - 110.0) / 110.0 * 100.0;
+        let up = (110.0 - 100.0) / 100.0 * 100.0;
+        assert!((pfs[0].value - (100.0 + down + up) / 3.0).abs() < 1e-9);
+        // the standard error of the two changes is half their spread, the seed is not a sample
+        let (low, high) = pfs[0].band.expect("Two changes have a band");
+        assert!((high - low - (up - down)).abs() < 1e-9);
+        assert!((low - (pfs[0].value - (up - down) / 2.0)).abs() < 1e-9);
+
+        // day 20 has one change into day 10, which alone has no band
+        let date = start_date.delta_date(20);
+        let pfs = PlotPFS::pfs_days(date, date, &ticker_data, 10)?;
+        assert!(pfs[0].band.is_none());
+
+        // no cycle fits before day 5, so the seed alone has no band
+        let date = start_date.delta_date(5);
+        let pfs = PlotPFS::pfs_days(date, date, &ticker_data, 10)?;
+        assert!(pfs[0].band.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn pfs_csv() -> PFSResult<()> {
+        let start_date = Time::new(2023, &Month::from_num(1), &Day::from_num(1), None, None);
+        let end_date = start_date.delta_date(1);
+        let mut second = PFS::new(end_date, 99.5, 7);
+        second.band = Some((98.0, 101.0));
+        let pfs = vec![PFS::new(start_date, 100.25, 7), second];
+
+        let out_file = temp_path("pfs_csv_test.csv");
+        let out_file = out_file.to_str().expect("Invalid temp file path");
+        PlotPFS::new(start_date, end_date).write_pfs_csv(&pfs, out_file)?;
+        let csv = std::fs::read_to_string(out_file).map_err(PFSError::CustomError)?;
+        let rows = csv.lines().collect::<Vec<&str>>();
+        assert_eq!(
+            rows,
+            vec![
+                "date,projection,band_low,band_high",
+                &format!("{},100.25,,", start_date.to_string_daily()),
+                &format!("{},99.5,98,101", end_date.to_string_daily()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn intrabar_entry_and_stop() -> PFSResult<()> {
+        let candle = Candle {
+            date: Time::new(2023, &Month::from_num(1), &Day::from_num(2), None, None),
+            open: 100.0,
+            high: 101.0,
+            low: 97.0,
+            close: 100.5,
+            volume: None,
+        };
+        let mut open_trade = None;
+        let mut backtest = Backtest::new(1000.0);
+        PlotPFS::backtest_candle(
+            &mut open_trade,
+            &mut 0,
+            &mut backtest,
+            &candle,
+            Some(Order::Long),
+            TrailingStopType::Percent,
+            0.05,
+            0.02,
+            FillMode::OpenIntrabar,
+            HoldPolicy::AlwaysInMarket,
+        )?;
+        assert!(open_trade.is_none());
+        assert_eq!(backtest.num_trades(), 1);
+        let trade = &backtest.trades[0];
+        assert_eq!(trade.entry_price, candle.open);
+        assert_eq!(trade.exit_price, Some(98.0));
+        Ok(())
+    }
+
+    #[test]
+    fn close_fill_reenters_on_each_signal() -> PFSResult<()> {
+        let start_date = Time::new(2023, &Month::from_num(1), &Day::from_num(2), None, None);
+        let mut open_trade = None;
+        let mut backtest = Backtest::new(1000.0);
+        // Long signals on every bar, the last closing through the stop loss
+        for (i, close) in [100.0, 105.0, 90.0].into_iter().enumerate() {
+            let candle = candle(start_date.delta_date(i as i64), close);
+            PlotPFS::backtest_candle(
+                &mut open_trade,
+                &mut 0,
+                &mut backtest,
+                &candle,
+                Some(Order::Long),
+                TrailingStopType::Percent,
+                0.5,
+                0.05,
+                FillMode::Close,
+                HoldPolicy::AlwaysInMarket,
+            )?;
+        }
+        // the second signal replaced the first trade, and the stopped out trade re-entered at the close
+        assert_eq!(backtest.num_trades(), 1);
+        assert_eq!(backtest.trades[0].entry_price, 105.0);
+        assert_eq!(backtest.trades[0].exit_price, Some(90.0));
+        assert_eq!(open_trade.map(|trade| trade.entry_price), Some(90.0));
+        Ok(())
+    }
+
+    #[test]
+    fn flat_between_signals_exits_after_hold_bars() -> PFSResult<()> {
+        let start_date = Time::new(2023, &Month::from_num(1), &Day::from_num(2), None, None);
+        let mut open_trade = None;
+        let mut bars_held = 0;
+        let mut backtest = Backtest::new(1000.0);
+        let hold = HoldPolicy::FlatBetweenSignals { hold_bars: 3 };
+        for i in 0..6 {
+            let candle = candle(start_date.delta_date(i), 100.0 + i as f64);
+            let signal = (i == 0).then_some(Order::Long);
+            PlotPFS::backtest_candle(
+                &mut open_trade,
+                &mut bars_held,
+                &mut backtest,
+                &candle,
+                signal,
+                TrailingStopType::Percent,
+                0.05,
+                0.05,
+                FillMode::Close,
+                hold,
+            )?;
+            // held through the window, exits on the third bar after entry
+            assert_eq!(open_trade.is_some(), i < 3);
+        }
+        assert_eq!(backtest.num_trades(), 1);
+        let trade = &backtest.trades[0];
+        assert_eq!(trade.entry_date, start_date);
+        assert_eq!(trade.exit_date, Some(start_date.delta_date(3)));
+        assert_eq!(trade.exit_price, Some(103.0));
+        Ok(())
+    }
+
+    #[test]
+    fn spread_costs_round_trip() -> PFSResult<()> {
+        let start_date = Time::new(2023, &Month::from_num(1), &Day::from_num(2), None, None);
+        // enter Long on a flat price and exit the next bar
+        let round_trip = |spread_bps: f64| -> PFSResult<Backtest> {
+            let mut open_trade = None;
+            let mut bars_held = 0;
+            let mut backtest = Backtest::new(1000.0);
+            backtest.spread_bps = spread_bps;
+            for i in 0..2 {
+                let candle = candle(start_date.delta_date(i), 100.0);
+                PlotPFS::backtest_candle(
+                    &mut open_trade,
+                    &mut bars_held,
+                    &mut backtest,
+                    &candle,
+                    (i == 0).then_some(Order::Long),
+                    TrailingStopType::Percent,
+                    0.05,
+                    0.05,
+                    FillMode::Close,
+                    HoldPolicy::FlatBetweenSignals { hold_bars: 1 },
+                )?;
+            }
+            Ok(backtest)
+        };
+        let free = round_trip(0.0)?;
+        let spread = round_trip(5.0)?;
+
+        let (free, spread) = (&free.trades[0], &spread.trades[0]);
+        assert_eq!(free.pnl, Some(0.0));
+        // bought at the ask and sold at the bid
+        assert_eq!(spread.entry_price, 100.025);
+        assert_eq!(spread.exit_price, Some(99.975));
+        // 5 bips is 0.05 percent of the position
+        let cost = free.pnl.unwrap() - spread.pnl.unwrap();
+        assert!((cost - 0.05).abs() < 1e-4);
+        Ok(())
+    }
+
+    #[test]
+    fn detrended_pfs_removes_drift() -> PFSResult<()> {
+        let first_date = Time::new(2021, &Month::from_num(1), &Day::from_num(1), None, None);
+        // daily candles rising one dollar a day, which a line fits
+        let ticker_data = daily_series(first_date, (0..120).map(|day| 100.0 + day as f64))?;
+        // and rising one percent a day, which closes stay a fixed ratio above their trailing average of
+        let compounding = daily_series(first_date, (0..120).map(|day| 100.0 * 1.01_f64.powi(day)))?;
+
+        for (ticker_data, method) in [
+            (&ticker_data, DetrendMethod::Linear),
+            (&compounding, DetrendMethod::MovingAverage(5)),
+        ] {
+            let raw = ticker_data
+                .returns(PriceScale::Linear)
+                .map_err(PFSError::TickerDataError)?;
+            assert!(raw.iter().all(|(_, change)| *change > 0.0));
+            let detrended = ticker_data
+                .detrended(method)
+                .map_err(PFSError::TickerDataError)?
+                .returns(PriceScale::Linear)
+                .map_err(PFSError::TickerDataError)?;
+            assert!(detrended.iter().all(|(_, change)| change.abs() < 1e-9));
+        }
+
+        let start_date = first_date.delta_date(101);
+        let end_date = first_date.delta_date(109);
+        let pfs = |detrend: Option<DetrendMethod>| {
+            PlotPFS::pfs_days_scaled(
+                start_date,
+                end_date,
+                &ticker_data,
+                10,
+                PriceScale::Linear,
+                detrend,
+            )
+        };
+        let raw = pfs(None)?;
+        let detrended = pfs(Some(DetrendMethod::Linear))?;
+        assert_eq!(raw.len(), detrended.len());
+        // only the trend moves the raw PFS, so the detrended PFS is flat
+        let flat = detrended[0].value;
+        for (raw, detrended) in raw.iter().zip(detrended.iter()) {
+            assert!(raw.value > detrended.value);
+            assert!((detrended.value - flat).abs() < 1e-9);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn pfs_years_anchor_shifts_phase() -> PFSResult<()> {
+        // monthly candles from 2000 through 2021
+        let candles = (0..(22 * 12))
+            .map(|i| {
+                let date = Time::new(
+                    2000 + i / 12,
+                    &Month::from_num(i as u32 % 12 + 1),
+                    &Day::from_num(1),
+                    None,
+                    None,
+                );
+                candle(date, 100.0 + ((i * 7) % 13) as f64)
+            })
+            .collect::<Vec<Candle>>();
+        let mut ticker_data = TickerData::new();
+        ticker_data
+            .add_series(candles)
+            .map_err(PFSError::TickerDataError)?;
+
+        let date = |year: i32, day: u32| {
+            Time::new(year, &Month::from_num(1), &Day::from_num(day), None, None)
+        };
+        let values = |pfs: Vec<PFS>| pfs.iter().map(|p| p.value).collect::<Vec<f64>>();
+        let cycle_years = 4;
+
+        // anchoring to the start year is the default behavior
+        let default = values(PlotPFS::pfs_years(
+            date(2021, 2),
+            date(2021, 10),
+            &ticker_data,
+            cycle_years,
+        )?);
+        let anchored_to_start = values(PlotPFS::pfs_years_anchored(
+            date(2021, 2),
+            date(2021, 10),
+            &ticker_data,
+            cycle_years,
+            2021 - cycle_years as i32,
+        )?);
+        assert_eq!(default, anchored_to_start);
+
+        // anchoring one year earlier shifts the cycle phase back by one year
+        let anchored = values(PlotPFS::pfs_years_anchored(
+            date(2021, 2),
+            date(2021, 10),
+            &ticker_data,
+            cycle_years,
+            2020,
+        )?);
+        let one_year_back = values(PlotPFS::pfs_years(
+            date(2020, 2),
+            date(2020, 10),
+            &ticker_data,
+            cycle_years,
+        )?);
+        assert_eq!(anchored, one_year_back);
+        assert_ne!(anchored, default);
+        // the scaled projection keeps the anchor
+        let scaled = values(PlotPFS::pfs_years_scaled(
+            date(2021, 2),
+            date(2021, 10),
+            &ticker_data,
+            cycle_years,
+            2020,
+            PriceScale::Linear,
+            None,
+        )?);
+        assert_eq!(scaled, anchored);
+
+        // a zero year cycle is rejected rather than dividing by zero
+        assert!(matches!(
+            PlotPFS::pfs_years(date(2021, 2), date(2021, 10), &ticker_data, 0),
+            Err(PFSError::ZeroCycle)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn smoothing_reduces_jaggedness_not_phase() -> PFSResult<()> {
+        let start_date = Time::new(2022, &Month::from_num(1), &Day::from_num(1), None, None);
+        // one 60 day cycle peaking on day 15, with jagged noise
+        let noisy = (0..60)
+            .map(|i| {
+                let cycle = 10.0 * (2.0 * std::f64::consts::PI * i as f64 / 60.0).sin();
+                let noise = ((i * 7) % 5) as f64 - 2.0;
+                PFS::new(start_date.delta_date(i), 100.0 + cycle + noise, 60)
+            })
+            .collect::<Vec<PFS>>();
+        let jaggedness = |pfs: &[PFS]| {
+            pfs.windows(2)
+                .map(|w| (w[1].value - w[0].value).powi(2))
+                .sum::<f64>()
+                / (pfs.len() - 1) as f64
+        };
+        let peak = |pfs: &[PFS]| {
+            pfs.iter()
+                .max_by(|a, b| a.value.partial_cmp(&b.value).unwrap())
+                .unwrap()
+                .date
+        };
+
+        let mut plot = PlotPFS::new(start_date, start_date.delta_date(59));
+        assert_eq!(jaggedness(&plot.smoothed(&noisy)?), jaggedness(&noisy));
+        for smoothing in [Smoothing::MovingAverage(5), Smoothing::Gaussian(2.0)] {
+            plot.smoothing = Some(smoothing);
+            let smoothed = plot.smoothed(&noisy)?;
+            assert_eq!(smoothed.len(), noisy.len());
+            assert!(jaggedness(&smoothed) < jaggedness(&noisy) / 4.0);
+            let shift = peak(&smoothed)
+                .diff_days(&start_date.delta_date(15))
+                .expect("Failed to diff peak dates");
+            assert!(
+                shift.abs() <= 2,
+                "{:?} shifted peak {} days",
+                smoothing,
+                shift
+            );
+        }
+
+        // a Gaussian without a positive deviation has no weights to smooth with
+        for sigma in [0.0, -1.0, f64::NAN] {
+            plot.smoothing = Some(Smoothing::Gaussian(sigma));
+            assert!(matches!(
+                plot.smoothed(&noisy),
+                Err(PFSError::InvalidSmoothing(_))
+            ));
+        }
+        Ok(())
+    }
+}
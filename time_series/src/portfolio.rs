@@ -0,0 +1,298 @@
+use crate::{Candle, Order, Time, Trade, TrailingStopType};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Open position of a backtest, held as one or more units that each keep their own entry and stops.
+/// With `max_units` above one, repeated signals in the held direction scale into the position,
+/// and the units overlap in time, so a backtest of them allows `max_units` open trades.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioState {
+    /// Open units in the order they were entered
+    pub units: Vec<Trade>,
+    /// Most units held at once, at least one
+    pub max_units: usize,
+    /// Longest a unit is held before exiting at the candle close
+    #[serde(default)]
+    pub max_hold: Option<Duration>,
+}
+
+impl Default for PortfolioState {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl PortfolioState {
+    pub fn new(max_units: usize) -> Self {
+        Self {
+            units: Vec::new(),
+            max_units: max_units.max(1),
+            max_hold: None,
+        }
+    }
+
+    pub fn with_max_hold(mut self, max_hold: Option<Duration>) -> Self {
+        self.max_hold = max_hold;
+        self
+    }
+
+    pub fn is_flat(&self) -> bool {
+        self.units.is_empty()
+    }
+
+    /// Direction of the open units, `None` if flat.
+    pub fn order(&self) -> Option<&Order> {
+        self.units.first().map(|unit| &unit.order)
+    }
+
+    /// Base asset held across all units.
+    pub fn contracts(&self) -> f64 {
+        self.units.iter().map(|unit| unit.contracts).sum()
+    }
+
+    /// Entry price of the position weighted by each unit's contracts, `None` if flat.
+    pub fn avg_entry_price(&self) -> Option<f64> {
+        let contracts = self.contracts();
+        if contracts == 0.0 {
+            return None;
+        }
+        let notional = self
+            .units
+            .iter()
+            .map(|unit| unit.entry_price * unit.contracts)
+            .sum::<f64>();
+        Some(notional / contracts)
+    }
+
+    /// Room to open another unit in the held direction.
+    pub fn can_add(&self) -> bool {
+        self.units.len() < self.max_units
+    }
+
+    /// Opens `unit` if flat, or adds it to units in the same direction while under `max_units`.
+    /// Returns false if the unit was not opened.
+    pub fn open_unit(&mut self, unit: Trade) -> bool {
+        match self.order() {
+            Some(order) if order != &unit.order => false,
+            _ if !self.can_add() => false,
+            _ => {
+                self.units.push(unit);
+                true
+            }
+        }
+    }
+
+    /// Exit every unit at `exit_price`, returning the closed trades in entry order.
+    pub fn close_all(&mut self, date: Time, exit_price: f64) -> Vec<Trade> {
+        self.units
+            .drain(..)
+            .map(|mut unit| {
+                unit.exit(date, exit_price);
+                unit
+            })
+            .collect()
+    }
+
+    /// Exit units whose trailing stop or stop loss the candle closed through at the candle close.
+    pub fn close_stopped(&mut self, candle: &Candle) -> Vec<Trade> {
+        let (stopped, open): (Vec<Trade>, Vec<Trade>) = self
+            .units
+            .drain(..)
+            .partition(|unit| unit.stop_triggered(candle));
+        self.units = open;
+        stopped
+            .into_iter()
+            .map(|mut unit| {
+                unit.exit(candle.date, candle.close);
+                unit
+            })
+            .collect()
+    }
+
+    /// Exit units held longer than their max hold at the candle close.
+    pub fn close_expired(&mut self, candle: &Candle) -> Vec<Trade> {
+        let (expired, open): (Vec<Trade>, Vec<Trade>) = self
+            .units
+            .drain(..)
+            .partition(|unit| unit.hold_exceeded(&candle.date));
+        self.units = open;
+        expired
+            .into_iter()
+            .map(|mut unit| {
+                unit.exit(candle.date, candle.close);
+                unit
+            })
+            .collect()
+    }
+
+    /// Trail every unit's stop behind `price`.
+    pub fn update_trailing_stops(
+        &mut self,
+        price: f64,
+        trailing_stop_type: TrailingStopType,
+        trailing_stop: f64,
+    ) {
+        for unit in self.units.iter_mut() {
+            unit.trailing_stop = Some(Trade::calc_trailing_stop(
+                unit.order.clone(),
+                price,
+                trailing_stop_type,
+                trailing_stop,
+            ));
+        }
+    }
+
+    /// Step the position forward one candle, filling at the candle close.
+    /// Units whose stop is hit or that were held past `max_hold` exit first. An opposing signal then exits every unit and enters
+    /// the signal direction, and a signal in the held direction adds a unit up to `max_units`.
+    /// A single unit is instead replaced by a signal in its direction, re-entering at the close.
+    /// Without a signal the remaining units trail their stops.
+    /// A new unit is sized to `capital` of quote asset.
+    /// Returns the trades that exited on this candle.
+    pub fn step(
+        &mut self,
+        candle: &Candle,
+        signal: Option<Order>,
+        capital: f64,
+        trailing_stop_type: TrailingStopType,
+        trailing_stop: f64,
+        stop_loss_pct: f64,
+    ) -> Vec<Trade> {
+        let mut closed = self.close_stopped(candle);
+        closed.extend(self.close_expired(candle));
+        match signal {
+            Some(order) => {
+                match self.order() {
+                    Some(held) if held != &order => {
+                        closed.extend(self.close_all(candle.date, candle.close))
+                    }
+                    Some(_) if self.max_units == 1 => self.units.clear(),
+                    _ => (),
+                }
+                let mut unit = Trade::new(
+                    candle.date,
+                    order.clone(),
+                    Trade::trade_quantity(capital, candle.close),
+                    candle.close,
+                    capital,
+                    Some(Trade::calc_trailing_stop(
+                        order.clone(),
+                        candle.close,
+                        trailing_stop_type,
+                        trailing_stop,
+                    )),
+                    Some(Trade::calc_stop_loss(order, candle.close, stop_loss_pct)),
+                );
+                unit.max_hold = self.max_hold;
+                self.open_unit(unit);
+            }
+            None => self.update_trailing_stops(candle.close, trailing_stop_type, trailing_stop),
+        }
+        closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Day, Month};
+
+    fn candle(day: u32, close: f64) -> Candle {
+        Candle {
+            date: Time::new(2023, &Month::from_num(1), &Day::from_num(day), None, None),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: None,
+        }
+    }
+
+    #[test]
+    fn pyramid_consecutive_signals() {
+        let mut portfolio = PortfolioState::new(3);
+        let step = |portfolio: &mut PortfolioState, candle: &Candle, signal: Option<Order>| {
+            portfolio.step(candle, signal, 1000.0, TrailingStopType::Percent, 0.5, 0.5)
+        };
+        for (day, close) in [(2, 100.0), (3, 125.0), (4, 200.0)] {
+            assert!(step(&mut portfolio, &candle(day, close), Some(Order::Long)).is_empty());
+        }
+        // $1,000 per unit buys 10, 8 and 5 contracts
+        assert_eq!(portfolio.units.len(), 3);
+        assert_eq!(portfolio.contracts(), 23.0);
+        let avg = portfolio.avg_entry_price().unwrap();
+        assert!((avg - 3000.0 / 23.0).abs() < 1e-9);
+
+        // a fourth signal in the same direction is over the unit cap
+        step(&mut portfolio, &candle(5, 200.0), Some(Order::Long));
+        assert_eq!(portfolio.units.len(), 3);
+
+        // an opposing signal exits every unit and enters the other way
+        let closed = step(&mut portfolio, &candle(6, 180.0), Some(Order::Short));
+        assert_eq!(closed.len(), 3);
+        assert!(closed.iter().all(|unit| unit.exit_price == Some(180.0)));
+        assert_eq!(portfolio.units.len(), 1);
+        assert_eq!(portfolio.order(), Some(&Order::Short));
+    }
+
+    #[test]
+    fn max_hold_exits_without_signal() {
+        let mut portfolio =
+            PortfolioState::default().with_max_hold(Some(Duration::from_secs(3 * 86_400)));
+        let step = |portfolio: &mut PortfolioState, day: u32, signal: Option<Order>| {
+            // wide stops so only the hold limit can exit
+            portfolio.step(
+                &candle(day, 100.0 + day as f64),
+                signal,
+                1000.0,
+                TrailingStopType::Percent,
+                50.0,
+                50.0,
+            )
+        };
+        assert!(step(&mut portfolio, 2, Some(Order::Long)).is_empty());
+        // held exactly the limit stays open
+        for day in 3..=5 {
+            assert!(step(&mut portfolio, day, None).is_empty());
+        }
+        let closed = step(&mut portfolio, 6, None);
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].exit_price, Some(106.0));
+        assert_eq!(closed[0].exit_date, Some(candle(6, 106.0).date));
+        assert!(portfolio.is_flat());
+    }
+
+    #[test]
+    fn single_unit_replaced_by_repeat_signal() {
+        let mut portfolio = PortfolioState::default();
+        portfolio.step(
+            &candle(2, 100.0),
+            Some(Order::Long),
+            1000.0,
+            TrailingStopType::Percent,
+            0.5,
+            0.5,
+        );
+        portfolio.step(
+            &candle(3, 110.0),
+            Some(Order::Long),
+            1000.0,
+            TrailingStopType::Percent,
+            0.5,
+            0.5,
+        );
+        assert_eq!(portfolio.units.len(), 1);
+        assert_eq!(portfolio.avg_entry_price(), Some(110.0));
+        // closing through the stop loss exits
+        let closed = portfolio.step(
+            &candle(4, 40.0),
+            None,
+            1000.0,
+            TrailingStopType::Percent,
+            0.5,
+            0.5,
+        );
+        assert_eq!(closed.len(), 1);
+        assert!(portfolio.is_flat());
+    }
+}
@@ -1,9 +1,57 @@
-#[macro_export]
-macro_rules! precise_round {
-    ($num:expr, $decimals:expr) => {{
-        let factor = 10.0_f64.powi($decimals);
-        ($num * factor).round() / factor
-    }};
+/// How a value is rounded to a number of decimal places.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoundDir {
+    /// Nearest, with ties away from zero, e.g. 2.665 to 2.67 and -2.665 to -2.67
+    #[default]
+    HalfUp,
+    /// Nearest, with ties to the even digit, e.g. 2.665 to 2.66 and 2.675 to 2.68.
+    /// Sums of many rounded values are not biased away from zero.
+    HalfEven,
+    /// Toward negative infinity
+    Down,
+    /// Toward positive infinity
+    Up,
+}
+
+/// Round `value` to `decimals` places, with ties away from zero.
+/// See [`precise_round_dir`].
+pub fn precise_round(value: f64, decimals: u32) -> f64 {
+    precise_round_dir(value, decimals, RoundDir::HalfUp)
+}
+
+/// Round `value` to `decimals` places in direction `dir`.
+///
+/// Rounding is of the decimal `value` is written as rather than its binary representation.
+/// e.g. 2.675 and 1.005 are stored just below the tie, as 2.67499999... and 1.00499999...,
+/// and naive `(1.005 * 100.0).round() / 100.0` rounds to 1.0,
+/// but both are ties here so 2.675 rounds to 2.68 and 1.005 to 1.01 with [`RoundDir::HalfUp`].
+pub fn precise_round_dir(value: f64, decimals: u32, dir: RoundDir) -> f64 {
+    if !value.is_finite() {
+        return value;
+    }
+    let factor = 10.0_f64.powi(decimals as i32);
+    let mut scaled = value * factor;
+    // error of the binary representation and of scaling by the factor
+    let tolerance = scaled.abs().max(1.0) * f64::EPSILON * 8.0;
+    if (scaled - scaled.round()).abs() <= tolerance {
+        scaled = scaled.round();
+    }
+    let floor = scaled.floor();
+    let is_tie = (scaled - floor - 0.5).abs() <= tolerance;
+    let rounded = match dir {
+        RoundDir::Down => floor,
+        RoundDir::Up => scaled.ceil(),
+        RoundDir::HalfUp if is_tie => match scaled > 0.0 {
+            true => floor + 1.0,
+            false => floor,
+        },
+        RoundDir::HalfEven if is_tie => match floor % 2.0 == 0.0 {
+            true => floor,
+            false => floor + 1.0,
+        },
+        RoundDir::HalfUp | RoundDir::HalfEven => scaled.round(),
+    };
+    rounded / factor
 }
 
 #[macro_export]
@@ -42,3 +90,48 @@ macro_rules! num_unwrap {
         }
     }};
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_decimal_places() {
+        assert_eq!(precise_round(20123.456789, 0), 20123.0);
+        assert_eq!(precise_round(20123.456789, 2), 20123.46);
+        assert_eq!(precise_round(0.123456789, 5), 0.12346);
+        assert_eq!(precise_round(0.1234567891, 9), 0.123456789);
+        assert_eq!(precise_round(-1.005, 2), -1.01);
+        assert!(precise_round(f64::NAN, 2).is_nan());
+    }
+
+    #[test]
+    fn round_ties() {
+        // naive rounding of the binary value misses the tie
+        assert_eq!((1.005 * 100.0_f64).round() / 100.0, 1.0);
+        assert_eq!(precise_round_dir(1.005, 2, RoundDir::HalfUp), 1.01);
+        assert_eq!(precise_round_dir(1.005, 2, RoundDir::HalfEven), 1.0);
+        assert_eq!(precise_round_dir(2.675, 2, RoundDir::HalfUp), 2.68);
+        assert_eq!(precise_round_dir(2.675, 2, RoundDir::HalfEven), 2.68);
+        assert_eq!(precise_round_dir(2.665, 2, RoundDir::HalfUp), 2.67);
+        assert_eq!(precise_round_dir(2.665, 2, RoundDir::HalfEven), 2.66);
+        assert_eq!(precise_round_dir(-2.665, 2, RoundDir::HalfUp), -2.67);
+        assert_eq!(precise_round_dir(-2.665, 2, RoundDir::HalfEven), -2.66);
+        assert_eq!(precise_round_dir(0.5, 0, RoundDir::HalfEven), 0.0);
+        assert_eq!(precise_round_dir(1.5, 0, RoundDir::HalfEven), 2.0);
+        // not a tie
+        assert_eq!(precise_round_dir(2.6751, 2, RoundDir::HalfEven), 2.68);
+        assert_eq!(precise_round_dir(2.6649, 2, RoundDir::HalfUp), 2.66);
+    }
+
+    #[test]
+    fn round_directed() {
+        // 0.29 * 100 is 28.999999999999996
+        assert_eq!(precise_round_dir(0.29, 2, RoundDir::Down), 0.29);
+        assert_eq!(precise_round_dir(0.29, 2, RoundDir::Up), 0.29);
+        assert_eq!(precise_round_dir(1.23456, 3, RoundDir::Down), 1.234);
+        assert_eq!(precise_round_dir(1.23416, 3, RoundDir::Up), 1.235);
+        assert_eq!(precise_round_dir(-1.23456, 3, RoundDir::Down), -1.235);
+        assert_eq!(precise_round_dir(-1.23456, 3, RoundDir::Up), -1.234);
+    }
+}
@@ -4,7 +4,7 @@ use csv;
 use csv::WriterBuilder;
 use log::debug;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::fs::File;
@@ -12,20 +12,87 @@ use std::io::Error;
 use std::path::PathBuf;
 use std::str::FromStr;
 
+/// Ticker CSV that has no candles to load, or does not match the expected columns
+#[derive(Debug)]
+pub enum LoadError {
+    EmptyFile(PathBuf),
+    MissingHeader(PathBuf),
+    NoValidRows(PathBuf),
+    /// Header lacks a required column
+    MissingColumn(PathBuf, String),
+    /// Row that failed to parse, with its line number in the file
+    BadRow {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::EmptyFile(path) => write!(f, "Empty ticker CSV: {}", path.display()),
+            LoadError::MissingHeader(path) => {
+                write!(f, "Ticker CSV missing header row: {}", path.display())
+            }
+            LoadError::NoValidRows(path) => {
+                write!(f, "Ticker CSV has no valid candle rows: {}", path.display())
+            }
+            LoadError::MissingColumn(path, column) => {
+                write!(
+                    f,
+                    "Ticker CSV missing {} column: {}",
+                    column,
+                    path.display()
+                )
+            }
+            LoadError::BadRow { path, line, reason } => {
+                write!(
+                    f,
+                    "Ticker CSV bad row on line {}: {}: {}",
+                    line,
+                    reason,
+                    path.display()
+                )
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum TickerDataError {
+    LoadError(LoadError),
     NoCandleForDate(Time),
     NoCandleForIndex(usize),
+    NonPositivePrice(Time),
+    /// Pushed candle is not after the last candle
+    OutOfOrderCandle(Time),
+    /// Resample target is not longer than the interval between candles
+    ResampleTimeframe(Timeframe),
     CustomError(std::io::Error),
 }
 
 impl Display for TickerDataError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
+            TickerDataError::LoadError(e) => write!(f, "Load error: {}", e),
             TickerDataError::NoCandleForDate(date) => {
                 write!(f, "No candle for date: {}", date.to_string())
             }
             TickerDataError::NoCandleForIndex(index) => write!(f, "No candle for index: {}", index),
+            TickerDataError::NonPositivePrice(date) => {
+                write!(f, "Non-positive price for date: {}", date.to_string())
+            }
+            TickerDataError::OutOfOrderCandle(date) => {
+                write!(f, "Candle not after the last candle: {}", date.to_string())
+            }
+            TickerDataError::ResampleTimeframe(timeframe) => {
+                write!(
+                    f,
+                    "Cannot resample to {}, candles are not shorter",
+                    timeframe
+                )
+            }
             TickerDataError::CustomError(msg) => write!(f, "{}", msg),
         }
     }
@@ -45,6 +112,134 @@ pub enum FirstMove {
     EngulfingLow,
 }
 
+/// Which candle to keep when two series have a candle on the same date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeOverlap {
+    KeepSelf,
+    KeepOther,
+}
+
+/// How candle dates are matched when aligning a series to other dates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateMatch {
+    /// Candle timestamp equals the date
+    Exact,
+    /// Candle falls on the same calendar day as the date
+    Day,
+}
+
+/// Trend removed from a series to isolate its cyclical component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetrendMethod {
+    /// Least squares line fit to closes over time
+    Linear,
+    /// Trailing simple moving average of this many closes
+    MovingAverage(usize),
+}
+
+/// Profile of a loaded series to catch a wrong or empty file before analysis.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeriesStats {
+    pub count: usize,
+    /// First and last candle dates, None if the series is empty
+    pub date_range: Option<(Time, Time)>,
+    pub min_close: Option<f64>,
+    pub max_close: Option<f64>,
+    pub mean_close: Option<f64>,
+    /// Timeframe matching the median spacing of candles, None if no timeframe matches
+    pub timeframe: Option<Timeframe>,
+}
+
+impl Display for SeriesStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let range = match &self.date_range {
+            Some((start, end)) => format!("{} to {}", start.to_string(), end.to_string()),
+            None => "none".to_string(),
+        };
+        let price = |price: Option<f64>| match price {
+            Some(price) => price.to_string(),
+            None => "none".to_string(),
+        };
+        let timeframe = match &self.timeframe {
+            Some(timeframe) => timeframe.to_string(),
+            None => "unknown".to_string(),
+        };
+        write!(
+            f,
+            "{} candles, {}, close min: {}, max: {}, mean: {}, timeframe: {}",
+            self.count,
+            range,
+            price(self.min_close),
+            price(self.max_close),
+            price(self.mean_close),
+            timeframe
+        )
+    }
+}
+
+/// Indices of the candle fields in a ticker CSV, found by header name
+struct CsvColumns {
+    date: usize,
+    open: usize,
+    high: usize,
+    low: usize,
+    close: usize,
+    volume: Option<usize>,
+}
+
+impl CsvColumns {
+    /// Columns of the header, or the name of the first required column it lacks
+    fn from_headers(headers: &csv::StringRecord) -> Result<Self, String> {
+        let find = |names: &[&str]| {
+            headers
+                .iter()
+                .position(|header| names.contains(&header.trim().to_lowercase().as_str()))
+        };
+        let required = |name: &str| find(&[name]).ok_or_else(|| name.to_string());
+        Ok(Self {
+            date: find(&["date", "time"]).ok_or_else(|| "date".to_string())?,
+            open: required("open")?,
+            high: required("high")?,
+            low: required("low")?,
+            close: required("close")?,
+            volume: find(&["volume"]),
+        })
+    }
+
+    /// Candle of the row, or the reason it could not be parsed
+    fn parse(&self, record: &csv::StringRecord) -> Result<Candle, String> {
+        let field = |index: usize, name: &str| {
+            record
+                .get(index)
+                .map(str::trim)
+                .ok_or_else(|| format!("missing {}", name))
+        };
+        let price = |index: usize, name: &str| {
+            let value = field(index, name)?;
+            f64::from_str(value).map_err(|_| format!("invalid {} \"{}\"", name, value))
+        };
+        let date = field(self.date, "date")?;
+        let date = date
+            .parse::<i64>()
+            .map_err(|_| format!("invalid date \"{}\"", date))?;
+        let volume = match self.volume {
+            Some(index) => match record.get(index).map(str::trim) {
+                Some("NaN") | Some("") | None => None,
+                Some(_) => Some(price(index, "volume")?),
+            },
+            None => None,
+        };
+        Ok(Candle {
+            date: Time::from_unix(date),
+            open: price(self.open, "open")?,
+            high: price(self.high, "high")?,
+            low: price(self.low, "low")?,
+            close: price(self.close, "close")?,
+            volume,
+        })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct TickerData {
     /// Candlestick history of a ticker.
@@ -69,47 +264,58 @@ impl TickerData {
     }
 
     /// Read candles from CSV file.
-    /// Handles duplicate candles and sorts candles by date.
+    /// Handles duplicate candles and sorts candles by date,
+    /// so newest-first exports load in chronological order.
     /// Expects date of candle to be in UNIX timestamp format.
-    /// CSV format: date,open,high,low,close,volume
+    /// Columns are found by header name, `date` (or `time`), `open`, `high`, `low`, `close`,
+    /// and an optional `volume`. Other columns are ignored.
+    /// A missing column or a row that fails to parse is a `LoadError`.
     pub fn add_csv_series(&mut self, csv_path: &PathBuf) -> TickerDataResult<()> {
         let file_buffer = File::open(csv_path).map_err(TickerDataError::CustomError)?;
         let mut csv = csv::Reader::from_reader(file_buffer);
+        let load_error =
+            |e: fn(PathBuf) -> LoadError| TickerDataError::LoadError(e(csv_path.clone()));
 
-        let mut headers = Vec::new();
-        if let Ok(result) = csv.headers() {
-            for header in result {
-                headers.push(String::from(header));
+        let headers = csv
+            .headers()
+            .map_err(|_| load_error(LoadError::EmptyFile))?
+            .clone();
+        match headers.get(0) {
+            None => return Err(load_error(LoadError::EmptyFile)),
+            // first row is a candle rather than column names
+            Some(first) if first.trim().parse::<f64>().is_ok() => {
+                return Err(load_error(LoadError::MissingHeader))
             }
+            _ => (),
         }
+        let columns = CsvColumns::from_headers(&headers).map_err(|column| {
+            TickerDataError::LoadError(LoadError::MissingColumn(csv_path.clone(), column))
+        })?;
 
-        for record in csv.records().flatten() {
-            let date = Time::from_unix(
-                record[0]
-                    .parse::<i64>()
-                    .expect("failed to parse candle UNIX timestamp into i64"),
-            );
-            let volume = match record.get(5) {
-                Some(vol) => {
-                    if vol == "NaN" {
-                        None
-                    } else {
-                        let vol =
-                            f64::from_str(vol).expect("failed to parse candle volume into f64");
-                        Some(vol)
-                    }
+        let mut valid_rows = 0;
+        for record in csv.records() {
+            let candle = record
+                .map_err(|e| (e.position().map_or(0, |p| p.line() as usize), e.to_string()))
+                .and_then(|record| {
+                    let line = record.position().map_or(0, |p| p.line() as usize);
+                    columns.parse(&record).map_err(|reason| (line, reason))
+                });
+            match candle {
+                Ok(candle) => {
+                    self.append_candle(&candle);
+                    valid_rows += 1;
                 }
-                None => None,
-            };
-            let candle = Candle {
-                date,
-                open: f64::from_str(&record[1]).expect("failed to parse candle volume into f64"),
-                high: f64::from_str(&record[2]).expect("failed to parse candle volume into f64"),
-                low: f64::from_str(&record[3]).expect("failed to parse candle volume into f64"),
-                close: f64::from_str(&record[4]).expect("failed to parse candle volume into f64"),
-                volume,
-            };
-            self.append_candle(&candle);
+                Err((line, reason)) => {
+                    return Err(TickerDataError::LoadError(LoadError::BadRow {
+                        path: csv_path.clone(),
+                        line,
+                        reason,
+                    }))
+                }
+            }
+        }
+        if valid_rows == 0 {
+            return Err(load_error(LoadError::NoValidRows));
         }
         Ok(())
     }
@@ -123,6 +329,39 @@ impl TickerData {
         Ok(())
     }
 
+    /// Union candles from another series, keeping existing candles on overlapping dates.
+    pub fn merge(&mut self, other: TickerData) {
+        self.merge_with(other, MergeOverlap::KeepSelf)
+    }
+
+    /// Union candles from another series and sort candles by date.
+    /// Candles on the same date are resolved by `overlap`.
+    pub fn merge_with(&mut self, other: TickerData, overlap: MergeOverlap) {
+        for candle in other.candles.into_iter() {
+            let key = self.hasher.hash_candle(&candle);
+            match self.hashmap.entry(key) {
+                Entry::Vacant(e) => {
+                    e.insert(candle.clone());
+                    self.candles.push(candle);
+                }
+                Entry::Occupied(mut e) => {
+                    if overlap == MergeOverlap::KeepOther {
+                        e.insert(candle.clone());
+                        if let Some(existing) = self
+                            .candles
+                            .iter_mut()
+                            .find(|c| c.unix_date() == candle.unix_date())
+                        {
+                            *existing = candle;
+                        }
+                    }
+                }
+            }
+        }
+        self.candles
+            .sort_by(|a, b| a.date.partial_cmp(&b.date).unwrap());
+    }
+
     /// Use historical ticker data from a CSV and fetch the latest candles from RapidAPI
     pub async fn build_series(
         &mut self,
@@ -171,6 +410,30 @@ impl TickerData {
         }
     }
 
+    /// Append a live candle, which must be dated after the last candle.
+    pub fn push_candle(&mut self, candle: Candle) -> TickerDataResult<()> {
+        if let Some(last) = self.candles.last() {
+            if candle.date.to_unix() <= last.date.to_unix() {
+                return Err(TickerDataError::OutOfOrderCandle(candle.date));
+            }
+        }
+        let key = self.hasher.hash_candle(&candle);
+        self.hashmap.insert(key, candle.clone());
+        self.candles.push(candle);
+        Ok(())
+    }
+
+    /// Same as `push_candle`, then evict the oldest candles to keep at most `max_len`.
+    pub fn push_candle_capped(&mut self, candle: Candle, max_len: usize) -> TickerDataResult<()> {
+        self.push_candle(candle)?;
+        let excess = self.candles.len().saturating_sub(max_len);
+        for evicted in self.candles.drain(..excess) {
+            let key = self.hasher.hash_candle(&evicted);
+            self.hashmap.remove(&key);
+        }
+        Ok(())
+    }
+
     /// If candle does not exist in self.candles, append candle to self.candles.
     /// Sort candles by date.
     fn append_candle(&mut self, candle: &Candle) {
@@ -197,6 +460,355 @@ impl TickerData {
         Ok(())
     }
 
+    /// Check every price can be placed on `scale`.
+    /// Log scale is only defined for positive prices.
+    pub fn validate_price_scale(&self, scale: PriceScale) -> TickerDataResult<()> {
+        if scale == PriceScale::Log {
+            if let Some(candle) = self
+                .candles
+                .iter()
+                .find(|c| [c.open, c.high, c.low, c.close].iter().any(|p| *p <= 0.0))
+            {
+                return Err(TickerDataError::NonPositivePrice(candle.date));
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy of the series with open, high, low and close transformed onto `scale`.
+    pub fn price_scaled(&self, scale: PriceScale) -> TickerDataResult<TickerData> {
+        self.validate_price_scale(scale)?;
+        let mut scaled = self.clone();
+        for candle in scaled.candles.iter_mut() {
+            candle.open = scale.scale_price(candle.open);
+            candle.high = scale.scale_price(candle.high);
+            candle.low = scale.scale_price(candle.low);
+            candle.close = scale.scale_price(candle.close);
+        }
+        for candle in scaled.hashmap.values_mut() {
+            candle.open = scale.scale_price(candle.open);
+            candle.high = scale.scale_price(candle.high);
+            candle.low = scale.scale_price(candle.low);
+            candle.close = scale.scale_price(candle.close);
+        }
+        Ok(scaled)
+    }
+
+    /// Copy of the series with open, high, low and close divided by the trend at each candle,
+    /// so prices oscillate around 1.0 and percent changes exclude the drift of the trend.
+    /// A moving average drops the leading candles without a full window.
+    pub fn detrended(&self, method: DetrendMethod) -> TickerDataResult<TickerData> {
+        let trend = match method {
+            DetrendMethod::Linear => {
+                let first = match self.candles.first() {
+                    Some(candle) => candle.date.to_unix(),
+                    None => return Ok(TickerData::new()),
+                };
+                // days since the first candle
+                let xs = self
+                    .candles
+                    .iter()
+                    .map(|c| (c.date.to_unix() - first) as f64 / 86400.0)
+                    .collect::<Vec<f64>>();
+                let n = xs.len() as f64;
+                let x_mean = xs.iter().sum::<f64>() / n;
+                let y_mean = self.candles.iter().map(|c| c.close).sum::<f64>() / n;
+                let covariance = xs
+                    .iter()
+                    .zip(self.candles.iter())
+                    .map(|(x, c)| (x - x_mean) * (c.close - y_mean))
+                    .sum::<f64>();
+                let variance = xs.iter().map(|x| (x - x_mean).powi(2)).sum::<f64>();
+                let slope = match variance {
+                    v if v > 0.0 => covariance / v,
+                    _ => 0.0,
+                };
+                xs.iter()
+                    .map(|x| Some(y_mean + slope * (x - x_mean)))
+                    .collect::<Vec<Option<f64>>>()
+            }
+            DetrendMethod::MovingAverage(window) => {
+                let window = window.max(1);
+                (0..self.candles.len())
+                    .map(|i| {
+                        (i + 1 >= window).then(|| {
+                            self.candles[i + 1 - window..=i]
+                                .iter()
+                                .map(|c| c.close)
+                                .sum::<f64>()
+                                / window as f64
+                        })
+                    })
+                    .collect::<Vec<Option<f64>>>()
+            }
+        };
+        let mut candles = Vec::<Candle>::new();
+        for (candle, trend) in self.candles.iter().zip(trend) {
+            let trend = match trend {
+                Some(trend) => trend,
+                None => continue,
+            };
+            if trend <= 0.0 {
+                return Err(TickerDataError::NonPositivePrice(candle.date));
+            }
+            candles.push(Candle {
+                date: candle.date,
+                open: candle.open / trend,
+                high: candle.high / trend,
+                low: candle.low / trend,
+                close: candle.close / trend,
+                volume: candle.volume,
+            });
+        }
+        let mut detrended = TickerData::new();
+        detrended.add_series(candles)?;
+        Ok(detrended)
+    }
+
+    /// The `top_n` strongest cycles of the close prices as (period in candles, power) pairs, strongest first.
+    /// Closes are linearly detrended, centered on zero and zero padded to a power of two
+    /// for an FFT periodogram, and each cycle is a local peak of the periodogram.
+    pub fn dominant_cycles(&self, top_n: usize) -> CycleResult<Vec<(f64, f64)>> {
+        if self.candles.len() < 4 {
+            return Err(CycleError::EmptySeries);
+        }
+        let detrended = self
+            .detrended(DetrendMethod::Linear)
+            .map_err(CycleError::TickerDataError)?;
+        let closes = detrended
+            .candles
+            .iter()
+            .map(|c| c.close)
+            .collect::<Vec<f64>>();
+        let close_mean = closes.iter().sum::<f64>() / closes.len() as f64;
+
+        let n = closes.len().next_power_of_two();
+        let mut re = closes
+            .iter()
+            .map(|close| close - close_mean)
+            .collect::<Vec<f64>>();
+        re.resize(n, 0.0);
+        let mut im = vec![0.0; n];
+        fft(&mut re, &mut im);
+        let power = (0..=n / 2)
+            .map(|k| (re[k] * re[k] + im[k] * im[k]) / n as f64)
+            .collect::<Vec<f64>>();
+
+        let mut peaks = (1..n / 2)
+            .filter(|&k| power[k] > power[k - 1] && power[k] >= power[k + 1])
+            .map(|k| (n as f64 / k as f64, power[k]))
+            .collect::<Vec<(f64, f64)>>();
+        peaks.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        peaks.truncate(top_n);
+        Ok(peaks)
+    }
+
+    /// Hurst exponent of the close prices by rescaled range (R/S) analysis of returns
+    /// over windows doubling from 8 returns up to `max_lag`, see [`hurst`].
+    /// Above 0.5 the series is trending, below 0.5 it is mean reverting, and a random walk is near 0.5.
+    /// Short windows bias the estimate up slightly, so a random walk measures around 0.55.
+    pub fn hurst_exponent(&self, max_lag: usize) -> CycleResult<f64> {
+        let closes = self.candles.iter().map(|c| c.close).collect::<Vec<f64>>();
+        hurst(&closes, max_lag as u32).map_err(CycleError::HurstError)
+    }
+
+    /// Heikin-Ashi candles of the series, which smooth out noise for reading trends and swings.
+    /// Close is the average of the raw OHLC, open is the midpoint of the previous HA candle body,
+    /// and high and low extend to include the HA open and close.
+    /// The first HA open is seeded from the midpoint of the first raw candle body.
+    pub fn heikin_ashi(&self) -> TickerData {
+        let mut heikin_ashi = TickerData::new();
+        let mut prev: Option<Candle> = None;
+        for candle in self.candles.iter() {
+            let close = (candle.open + candle.high + candle.low + candle.close) / 4.0;
+            let open = match &prev {
+                Some(prev) => (prev.open + prev.close) / 2.0,
+                None => (candle.open + candle.close) / 2.0,
+            };
+            let ha_candle = Candle {
+                date: candle.date,
+                open,
+                high: candle.high.max(open).max(close),
+                low: candle.low.min(open).min(close),
+                close,
+                volume: candle.volume,
+            };
+            heikin_ashi.append_candle(&ha_candle);
+            prev = Some(ha_candle);
+        }
+        heikin_ashi
+    }
+
+    /// Aggregate candles into `target` candles, each dated at the start of its bucket.
+    /// Buckets align to calendar units, e.g. the top of the hour for `Hour`, midnight UTC for `Day`
+    /// and Monday for `Week`.
+    /// Open is from the first candle, close from the last, high and low are the extremes,
+    /// and volume is summed if every candle has volume.
+    /// Candles are assumed dated at their open, and an incomplete trailing bucket is dropped.
+    pub fn resample(&self, target: Timeframe) -> TickerDataResult<TickerData> {
+        let mut resampled = TickerData::new();
+        let interval = match self
+            .candles
+            .windows(2)
+            .map(|pair| pair[1].date.to_unix() - pair[0].date.to_unix())
+            .filter(|secs| *secs > 0)
+            .min()
+        {
+            Some(interval) => interval,
+            None => return Ok(resampled),
+        };
+        if target.to_secs().map_or(false, |secs| secs <= interval) {
+            return Err(TickerDataError::ResampleTimeframe(target));
+        }
+        let mut buckets: Vec<((i64, i64), Candle)> = Vec::new();
+        for candle in self.candles.iter() {
+            let bounds = Self::resample_bucket(&candle.date, &target);
+            match buckets.last_mut() {
+                Some((last_bounds, bar)) if *last_bounds == bounds => {
+                    bar.high = bar.high.max(candle.high);
+                    bar.low = bar.low.min(candle.low);
+                    bar.close = candle.close;
+                    bar.volume = bar.volume.zip(candle.volume).map(|(a, b)| a + b);
+                }
+                _ => buckets.push((
+                    bounds,
+                    Candle {
+                        date: Time::from_unix(bounds.0),
+                        ..candle.clone()
+                    },
+                )),
+            }
+        }
+        // the last bucket is complete once its last candle closes at the bucket end
+        if let (Some(((_, end), _)), Some(last)) = (buckets.last(), self.candles.last()) {
+            if last.date.to_unix() + interval < *end {
+                buckets.pop();
+            }
+        }
+        for (_, candle) in buckets.iter() {
+            resampled.append_candle(candle);
+        }
+        Ok(resampled)
+    }
+
+    /// Start and end unix seconds of the `target` bucket containing `date`
+    fn resample_bucket(date: &Time, target: &Timeframe) -> (i64, i64) {
+        match target.to_secs() {
+            // unix epoch is a Thursday, so weeks are offset 4 days to start on Monday
+            Some(secs) if *target == Timeframe::Week => {
+                let monday = 4 * 24 * 60 * 60;
+                let start = (date.to_unix() - monday).div_euclid(secs) * secs + monday;
+                (start, start + secs)
+            }
+            Some(secs) => {
+                let start = date.to_unix().div_euclid(secs) * secs;
+                (start, start + secs)
+            }
+            None => {
+                let start = Time::new(date.year, &date.month, &Day::One, None, None);
+                let end = match date.month {
+                    Month::December => {
+                        Time::new(date.year + 1, &Month::January, &Day::One, None, None)
+                    }
+                    _ => Time::new(
+                        date.year,
+                        &Month::from_num(date.month.to_num() + 1),
+                        &Day::One,
+                        None,
+                        None,
+                    ),
+                };
+                (start.to_unix(), end.to_unix())
+            }
+        }
+    }
+
+    /// Inner join the series to `dates` by exact timestamp, dropping candles with no matching date.
+    pub fn align_to(&self, dates: &[Time]) -> TickerData {
+        self.align_to_with(dates, DateMatch::Exact)
+    }
+
+    /// Inner join the series to `dates`, dropping candles with no matching date.
+    /// With `DateMatch::Day` every candle on a matching day is kept, so an intraday series
+    /// aligned to daily dates keeps all of its candles on those days.
+    pub fn align_to_with(&self, dates: &[Time], matching: DateMatch) -> TickerData {
+        let keys: HashSet<i64> = dates.iter().map(|date| date_key(date, matching)).collect();
+        let mut aligned = TickerData::new();
+        for candle in self.candles.iter() {
+            if keys.contains(&date_key(&candle.date, matching)) {
+                aligned.append_candle(candle);
+            }
+        }
+        aligned
+    }
+
+    /// Percent change of each close from the previous close, measured on `scale`.
+    pub fn returns(&self, scale: PriceScale) -> TickerDataResult<Vec<(Time, f64)>> {
+        self.validate_price_scale(scale)?;
+        Ok(self
+            .candles
+            .windows(2)
+            .map(|pair| {
+                (
+                    pair[1].date,
+                    pair[1].scaled_percent_change(pair[0].close, scale),
+                )
+            })
+            .collect())
+    }
+
+    /// Candle count, date range, close price range and mean, and detected timeframe.
+    pub fn stats(&self) -> SeriesStats {
+        let closes = self.candles.iter().map(|c| c.close);
+        let count = self.candles.len();
+        let mean_close = match count {
+            0 => None,
+            _ => Some(closes.clone().sum::<f64>() / count as f64),
+        };
+        SeriesStats {
+            count,
+            date_range: self
+                .candles
+                .first()
+                .zip(self.candles.last())
+                .map(|(first, last)| (first.date, last.date)),
+            min_close: closes.clone().reduce(f64::min),
+            max_close: closes.reduce(f64::max),
+            mean_close,
+            timeframe: self.detect_timeframe(),
+        }
+    }
+
+    /// Timeframe of the median spacing between candles.
+    /// Months are 28 to 31 days apart, other timeframes must match exactly.
+    fn detect_timeframe(&self) -> Option<Timeframe> {
+        let mut gaps = self
+            .candles
+            .windows(2)
+            .map(|w| w[1].date.to_unix() - w[0].date.to_unix())
+            .collect::<Vec<i64>>();
+        if gaps.is_empty() {
+            return None;
+        }
+        gaps.sort();
+        let median = gaps[gaps.len() / 2];
+        let day = 24 * 60 * 60;
+        if (28 * day..=31 * day).contains(&median) {
+            return Some(Timeframe::Month);
+        }
+        [
+            Timeframe::Min1,
+            Timeframe::Min5,
+            Timeframe::Min15,
+            Timeframe::Hour,
+            Timeframe::Hour4,
+            Timeframe::Day,
+            Timeframe::Week,
+        ]
+        .into_iter()
+        .find(|timeframe| timeframe.to_secs() == Some(median))
+    }
+
     /// Get reference to `Vec<Candle>` from `TickerData`.
     pub fn get_candles(&self) -> &Vec<Candle> {
         &self.candles
@@ -210,6 +822,38 @@ impl TickerData {
         &self.get_candles()[self.candles.len() - 1].date
     }
 
+    /// Apply `f` to each moving window of `window` candles.
+    /// Each result is dated by the last candle in the window,
+    /// so the first `window - 1` candles have no result.
+    pub fn rolling<T>(&self, window: usize, f: impl Fn(&[Candle]) -> T) -> Vec<(Time, T)> {
+        if window == 0 {
+            return Vec::new();
+        }
+        self.candles
+            .windows(window)
+            .map(|candles| (candles[candles.len() - 1].date, f(candles)))
+            .collect()
+    }
+
+    /// Volume weighted average of the typical price `(high + low + close) / 3` over the last `lookback` candles.
+    /// None if there are fewer candles, any lacks volume, or the total volume is zero.
+    pub fn vwap(&self, lookback: usize) -> Option<f64> {
+        if lookback == 0 || lookback > self.candles.len() {
+            return None;
+        }
+        let mut price_volume = 0.0;
+        let mut total_volume = 0.0;
+        for candle in &self.candles[self.candles.len() - lookback..] {
+            let volume = candle.volume?;
+            price_volume += (candle.high + candle.low + candle.close) / 3.0 * volume;
+            total_volume += volume;
+        }
+        match total_volume > 0.0 {
+            true => Some(price_volume / total_volume),
+            false => None,
+        }
+    }
+
     /// Find price extreme (highs) in a given range of candles +/- the extreme candle.
     pub fn pivot_highs(&self, left_bars: usize, right_bars: usize) -> Vec<Candle> {
         // identify a daily reversal by checking maximum/minimum for period (day - candle_range)..(day + candle_range)
@@ -595,3 +1239,430 @@ impl TickerData {
         signals
     }
 }
+
+fn date_key(date: &Time, matching: DateMatch) -> i64 {
+    match matching {
+        DateMatch::Exact => date.to_unix(),
+        DateMatch::Day => Time::new(date.year, &date.month, &date.day, None, None).to_unix(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Temp path of `file` for this process, so concurrent test runs do not write the same file
+    fn temp_path(file: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("{}_{}", std::process::id(), file))
+    }
+
+    fn candle(day: u32, close: f64) -> Candle {
+        Candle {
+            date: Time::new(2023, &Month::from_num(1), &Day::from_num(day), None, None),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: None,
+        }
+    }
+
+    fn series(first_day: u32, closes: &[f64]) -> TickerDataResult<TickerData> {
+        let mut ticker_data = TickerData::new();
+        ticker_data.add_series(
+            closes
+                .iter()
+                .enumerate()
+                .map(|(i, close)| candle(first_day + i as u32, *close))
+                .collect(),
+        )?;
+        Ok(ticker_data)
+    }
+
+    #[test]
+    fn series_stats() -> TickerDataResult<()> {
+        let ticker_data = series(1, &[100.0, 104.0, 98.0, 102.0])?;
+        let stats = ticker_data.stats();
+        assert_eq!(stats.count, 4);
+        let (start, end) = stats.date_range.expect("expected date range");
+        assert_eq!(start, candle(1, 0.0).date);
+        assert_eq!(end, candle(4, 0.0).date);
+        assert_eq!(stats.min_close, Some(98.0));
+        assert_eq!(stats.max_close, Some(104.0));
+        assert_eq!(stats.mean_close, Some(101.0));
+        assert_eq!(stats.timeframe, Some(Timeframe::Day));
+
+        let stats = TickerData::new().stats();
+        assert_eq!(stats.count, 0);
+        assert!(stats.date_range.is_none());
+        assert!(stats.mean_close.is_none());
+        assert!(stats.timeframe.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn log_scaled_returns() -> TickerDataResult<()> {
+        let ticker_data = series(1, &[100.0, 200.0, 100.0])?;
+
+        let linear = ticker_data.returns(PriceScale::Linear)?;
+        assert_eq!(linear[0].1, 100.0);
+        assert_eq!(linear[1].1, -50.0);
+
+        // doubling and halving are the same size move on a log scale
+        let log = ticker_data.returns(PriceScale::Log)?;
+        assert_eq!(log.len(), 2);
+        assert!((log[0].1 - 100.0 * 2_f64.ln()).abs() < 1e-9);
+        assert!((log[0].1 + log[1].1).abs() < 1e-9);
+
+        let scaled = ticker_data.price_scaled(PriceScale::Log)?;
+        assert!((scaled.candles[1].close - 200_f64.ln()).abs() < 1e-9);
+
+        // log scale is undefined for non-positive prices
+        let ticker_data = series(1, &[100.0, 0.0, 50.0])?;
+        assert!(ticker_data.returns(PriceScale::Linear).is_ok());
+        match ticker_data.returns(PriceScale::Log) {
+            Err(TickerDataError::NonPositivePrice(date)) => assert_eq!(date, candle(2, 0.0).date),
+            _ => panic!("expected non-positive price error"),
+        }
+        assert!(PlotPFS::pfs_days_scaled(
+            *ticker_data.earliest_date(),
+            *ticker_data.latest_date(),
+            &ticker_data,
+            1,
+            PriceScale::Log,
+            None
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn merge_overlapping_series() -> TickerDataResult<()> {
+        // days 1-5 and days 4-8 overlap on days 4 and 5
+        let first = series(1, &[1.0, 2.0, 3.0, 4.0, 5.0])?;
+        let second = series(4, &[40.0, 50.0, 6.0, 7.0, 8.0])?;
+
+        let mut keep_self = first.clone();
+        keep_self.merge(second.clone());
+        let mut keep_other = first;
+        keep_other.merge_with(second, MergeOverlap::KeepOther);
+
+        for merged in [&keep_self, &keep_other] {
+            assert_eq!(merged.candles.len(), 8);
+            for pair in merged.candles.windows(2) {
+                assert!(pair[0].date < pair[1].date);
+            }
+        }
+        let closes = |ticker_data: &TickerData| {
+            ticker_data
+                .candles
+                .iter()
+                .map(|c| c.close)
+                .collect::<Vec<f64>>()
+        };
+        assert_eq!(
+            closes(&keep_self),
+            vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]
+        );
+        assert_eq!(
+            closes(&keep_other),
+            vec![1.0, 2.0, 3.0, 40.0, 50.0, 6.0, 7.0, 8.0]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rolling_mean_of_closes() -> TickerDataResult<()> {
+        let closes = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let ticker_data = series(1, &closes)?;
+
+        let means = ticker_data.rolling(3, |candles| {
+            candles.iter().map(|c| c.close).sum::<f64>() / candles.len() as f64
+        });
+        assert_eq!(means.len(), closes.len() - 2);
+        // first two candles have no full window
+        assert_eq!(means[0].0, ticker_data.candles[2].date);
+        for (i, (date, mean)) in means.iter().enumerate() {
+            let expected = (closes[i] + closes[i + 1] + closes[i + 2]) / 3.0;
+            assert_eq!(*date, ticker_data.candles[i + 2].date);
+            assert_eq!(*mean, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn heikin_ashi_candles() -> TickerDataResult<()> {
+        // (open, high, low, close)
+        let raw = [
+            (10.0, 12.0, 9.0, 11.0),
+            (11.0, 13.0, 10.0, 12.0),
+            (12.0, 12.5, 8.0, 9.0),
+        ];
+        let mut ticker_data = TickerData::new();
+        ticker_data.add_series(
+            raw.iter()
+                .enumerate()
+                .map(|(i, (open, high, low, close))| Candle {
+                    open: *open,
+                    high: *high,
+                    low: *low,
+                    close: *close,
+                    ..candle(i as u32 + 1, 0.0)
+                })
+                .collect(),
+        )?;
+        let heikin_ashi = ticker_data.heikin_ashi();
+        let field = |f: fn(&Candle) -> f64| heikin_ashi.candles.iter().map(f).collect::<Vec<_>>();
+
+        // HA close = (O + H + L + C) / 4
+        assert_eq!(field(|c| c.close), vec![10.5, 11.5, 10.375]);
+        // HA open seeded by (O1 + C1) / 2, then (prev HA open + prev HA close) / 2
+        assert_eq!(field(|c| c.open), vec![10.5, 10.5, 11.0]);
+        // HA high = max(H, HA open, HA close), HA low = min(L, HA open, HA close)
+        assert_eq!(field(|c| c.high), vec![12.0, 13.0, 12.5]);
+        assert_eq!(field(|c| c.low), vec![9.0, 10.0, 8.0]);
+        for (ha, raw) in heikin_ashi.candles.iter().zip(ticker_data.candles.iter()) {
+            assert_eq!(ha.date, raw.date);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn align_to_subset_of_dates() -> TickerDataResult<()> {
+        let ticker_data = series(1, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0])?;
+        // day 9 has no candle
+        let dates = [
+            candle(2, 0.0).date,
+            candle(4, 0.0).date,
+            candle(9, 0.0).date,
+        ];
+        let aligned = ticker_data.align_to(&dates);
+        let closes = aligned.candles.iter().map(|c| c.close).collect::<Vec<_>>();
+        assert_eq!(closes, vec![2.0, 4.0]);
+
+        // midday on day 3 only matches the daily candle at day granularity
+        let midday = [Time::new(
+            2023,
+            &Month::from_num(1),
+            &Day::from_num(3),
+            Some(12),
+            Some(0),
+        )];
+        assert!(ticker_data.align_to(&midday).candles.is_empty());
+        let aligned = ticker_data.align_to_with(&midday, DateMatch::Day);
+        assert_eq!(aligned.candles.len(), 1);
+        assert_eq!(aligned.candles[0].close, 3.0);
+        Ok(())
+    }
+
+    #[test]
+    fn csv_without_candles() -> TickerDataResult<()> {
+        let load = |name: &str, contents: &str| {
+            let path = temp_path(name);
+            std::fs::write(&path, contents).map_err(TickerDataError::CustomError)?;
+            let res = TickerData::new().add_csv_series(&path);
+            std::fs::remove_file(&path).map_err(TickerDataError::CustomError)?;
+            Ok::<_, TickerDataError>((path, res))
+        };
+
+        let (path, res) = load("ticker_empty_test.csv", "")?;
+        match res {
+            Err(TickerDataError::LoadError(LoadError::EmptyFile(p))) => assert_eq!(p, path),
+            _ => panic!("expected empty file error"),
+        }
+        let (path, res) = load("ticker_header_only_test.csv", "time,open,high,low,close\n")?;
+        match res {
+            Err(TickerDataError::LoadError(LoadError::NoValidRows(p))) => assert_eq!(p, path),
+            _ => panic!("expected no valid rows error"),
+        }
+        let (_, res) = load(
+            "ticker_headerless_test.csv",
+            "1609459200,1,2,0.5,1.5\n1609545600,1.5,2,1,1.8\n",
+        )?;
+        assert!(matches!(
+            res,
+            Err(TickerDataError::LoadError(LoadError::MissingHeader(_)))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn csv_descending_dates() -> TickerDataResult<()> {
+        let path = temp_path("ticker_descending_test.csv");
+        std::fs::write(
+            &path,
+            "time,open,high,low,close\n1609632000,3,3,3,3\n1609545600,2,2,2,2\n1609459200,1,1,1,1\n",
+        )
+        .map_err(TickerDataError::CustomError)?;
+        let mut ticker_data = TickerData::new();
+        let res = ticker_data.add_csv_series(&path);
+        std::fs::remove_file(&path).map_err(TickerDataError::CustomError)?;
+        res?;
+
+        assert!(ticker_data.earliest_date() < ticker_data.latest_date());
+        let closes = ticker_data
+            .candles
+            .iter()
+            .map(|candle| candle.close)
+            .collect::<Vec<f64>>();
+        assert_eq!(closes, vec![1.0, 2.0, 3.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn push_candles_capped() -> TickerDataResult<()> {
+        let mut ticker_data = TickerData::new();
+        let start = Time::new(2023, &Month::January, &Day::One, Some(0), Some(0)).to_unix();
+        let candle = |i: i64| Candle {
+            date: Time::from_unix(start + i * 60),
+            ..candle(1, i as f64)
+        };
+        for i in 0..1000 {
+            ticker_data.push_candle_capped(candle(i), 500)?;
+        }
+        assert_eq!(ticker_data.candles.len(), 500);
+        assert_eq!(ticker_data.hashmap.len(), 500);
+        assert_eq!(ticker_data.candles[0].close, 500.0);
+        assert_eq!(ticker_data.candles[499].close, 999.0);
+        assert!(ticker_data
+            .candles
+            .windows(2)
+            .all(|pair| pair[0].date < pair[1].date));
+
+        // a repeat of the last candle or an earlier one is rejected
+        for i in [999, 998] {
+            assert!(matches!(
+                ticker_data.push_candle(candle(i)),
+                Err(TickerDataError::OutOfOrderCandle(_))
+            ));
+        }
+        assert_eq!(ticker_data.candles.len(), 500);
+        Ok(())
+    }
+
+    #[test]
+    fn csv_schema_validation() -> TickerDataResult<()> {
+        let load = |name: &str, contents: &str| {
+            let path = temp_path(name);
+            std::fs::write(&path, contents).map_err(TickerDataError::CustomError)?;
+            let mut ticker_data = TickerData::new();
+            let res = ticker_data.add_csv_series(&path);
+            std::fs::remove_file(&path).map_err(TickerDataError::CustomError)?;
+            Ok::<_, TickerDataError>((ticker_data, res))
+        };
+
+        // columns are found by name, in any order and alongside extra columns
+        let (ticker_data, res) = load(
+            "ticker_schema_good_test.csv",
+            "time,close,open,high,low,Fisher\n1609459200,1.5,1,2,0.5,0.1\n1609545600,1.8,1.5,2,1,0.2\n",
+        )?;
+        res?;
+        assert_eq!(ticker_data.candles.len(), 2);
+        assert_eq!(ticker_data.candles[0].open, 1.0);
+        assert_eq!(ticker_data.candles[0].close, 1.5);
+        assert_eq!(ticker_data.candles[0].volume, None);
+        let (ticker_data, res) = load(
+            "ticker_schema_volume_test.csv",
+            "date,open,high,low,close,volume\n1609459200,1,2,0.5,1.5,42\n",
+        )?;
+        res?;
+        assert_eq!(ticker_data.candles[0].volume, Some(42.0));
+
+        let (_, res) = load(
+            "ticker_schema_missing_high_test.csv",
+            "date,open,low,close\n1609459200,1,0.5,1.5\n",
+        )?;
+        match res {
+            Err(TickerDataError::LoadError(LoadError::MissingColumn(_, column))) => {
+                assert_eq!(column, "high")
+            }
+            _ => panic!("expected missing column error"),
+        }
+
+        // header is line 1, so the 41st candle is on line 42
+        let mut csv = "date,open,high,low,close\n".to_string();
+        for i in 0..50 {
+            let close = if i == 40 { "n/a" } else { "1.5" };
+            csv += &format!("{},1,2,0.5,{}\n", 1609459200 + i * 86400, close);
+        }
+        let (_, res) = load("ticker_schema_bad_close_test.csv", &csv)?;
+        match res {
+            Err(TickerDataError::LoadError(LoadError::BadRow { line, reason, .. })) => {
+                assert_eq!(line, 42);
+                assert_eq!(reason, "invalid close \"n/a\"");
+            }
+            _ => panic!("expected bad row error"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn vwap_over_lookback() -> TickerDataResult<()> {
+        let mut ticker_data = TickerData::new();
+        let bars = [
+            (1, 90.0, 80.0, 85.0, 5.0),
+            (2, 110.0, 100.0, 105.0, 2.0),
+            (3, 103.0, 97.0, 100.0, 1.0),
+            (4, 124.0, 116.0, 120.0, 3.0),
+        ];
+        ticker_data.add_series(
+            bars.iter()
+                .map(|(day, high, low, close, volume)| Candle {
+                    volume: Some(*volume),
+                    high: *high,
+                    low: *low,
+                    ..candle(*day, *close)
+                })
+                .collect(),
+        )?;
+        // typical prices 105, 100, 120 weighted by volumes 2, 1, 3: (210 + 100 + 360) / 6
+        let vwap = ticker_data.vwap(3).unwrap();
+        assert!((vwap - 670.0 / 6.0).abs() < 1e-9);
+        assert!(ticker_data.vwap(5).is_none());
+        assert!(ticker_data.vwap(0).is_none());
+
+        // one candle without volume invalidates any window that includes it
+        ticker_data.candles[1].volume = None;
+        assert!(ticker_data.vwap(3).is_none());
+        assert!(ticker_data.vwap(2).is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn resample_to_hour() -> TickerDataResult<()> {
+        let mut ticker_data = TickerData::new();
+        let start = Time::new(2023, &Month::January, &Day::One, Some(0), Some(0)).to_unix();
+        // twelve 5 minute candles fill the hour, the 13th starts an incomplete hour
+        let candles = (0..13)
+            .map(|i| {
+                let close = 100.0 + i as f64;
+                Candle {
+                    date: Time::from_unix(start + i * 300),
+                    open: close - 0.5,
+                    high: close + if i == 4 { 10.0 } else { 1.0 },
+                    low: close - if i == 7 { 10.0 } else { 1.0 },
+                    close,
+                    volume: Some(2.0),
+                }
+            })
+            .collect();
+        ticker_data.add_series(candles)?;
+
+        let hourly = ticker_data.resample(Timeframe::Hour)?;
+        assert_eq!(hourly.candles.len(), 1);
+        let candle = &hourly.candles[0];
+        assert_eq!(candle.date.to_unix(), start);
+        assert_eq!(candle.open, 99.5);
+        assert_eq!(candle.high, 114.0);
+        assert_eq!(candle.low, 97.0);
+        assert_eq!(candle.close, 111.0);
+        assert_eq!(candle.volume, Some(24.0));
+
+        assert!(matches!(
+            ticker_data.resample(Timeframe::Min5),
+            Err(TickerDataError::ResampleTimeframe(Timeframe::Min5))
+        ));
+        Ok(())
+    }
+}
@@ -78,28 +78,44 @@ impl Time {
         weekday == Weekday::Sat || weekday == Weekday::Sun
     }
 
+    /// Calendar quarter of the year, 1 through 4
+    pub fn quarter(&self) -> u8 {
+        ((self.month.to_num() - 1) / 3 + 1) as u8
+    }
+
+    /// ISO 8601 week, 1 through 53.
+    /// Days in early January can belong to the last week of the previous year,
+    /// and days in late December to week 1 of the next year.
+    pub fn week_of_year(&self) -> u8 {
+        self.to_naive_date().iso_week().week() as u8
+    }
+
     pub fn from_eclipse_date_format(date: &str) -> Self {
-        let end_year_index = date.find(' ').unwrap();
-        let year = date[..end_year_index].parse::<i32>().unwrap();
-        let start_month_index = end_year_index + 1;
-        let end_month_index = date[start_month_index..].find(' ').unwrap() + start_month_index;
-        let month = Month::from_name(
-            date[start_month_index..end_month_index]
-                .parse::<String>()
-                .unwrap()
-                .as_str(),
-        );
-        let start_day_index = end_month_index + 1;
-        let end_day_index = date.len();
-        let day = Day::from_num(date[start_day_index..end_day_index].parse::<u32>().unwrap());
+        Self::try_from_eclipse_date_format(date).unwrap()
+    }
 
-        Self {
+    /// Parse an eclipse catalog date such as `-1999 June 12`, or error if it is malformed
+    pub fn try_from_eclipse_date_format(date: &str) -> TimeResult<Self> {
+        let invalid = || TimeError::InvalidDate(date.to_string());
+        let mut parts = date.split(' ');
+        let (Some(year), Some(month), Some(day), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let year = year.parse::<i32>().map_err(|_| invalid())?;
+        let month = Month::try_from_name(month)?;
+        let day = day.parse::<u32>().map_err(|_| invalid())?;
+        if NaiveDate::from_ymd_opt(year, month.to_num(), day).is_none() {
+            return Err(invalid());
+        }
+        Ok(Self {
             year,
             month,
-            day,
+            day: Day::from_num(day),
             hour: None,
             minute: None,
-        }
+        })
     }
 
     pub fn from_api_format(date: &str) -> Self {
@@ -116,6 +132,7 @@ impl Time {
         }
     }
 
+    #[allow(clippy::inherent_to_string)]
     pub fn to_string(&self) -> String {
         format!(
             "{}-{}-{}.{}.{}",
@@ -160,6 +177,26 @@ impl Time {
         }
     }
 
+    /// Parse an RFC 3339 timestamp such as `2023-07-01T00:00:00Z`, converted to UTC.
+    /// Seconds and sub-second precision are dropped.
+    pub fn from_rfc3339(s: &str) -> TimeResult<Self> {
+        let dt = DateTime::parse_from_rfc3339(s)
+            .map_err(|e| TimeError::InvalidDate(format!("{}: {}", s, e)))?;
+        Ok(Self::from_datetime(dt.with_timezone(&Utc)))
+    }
+
+    /// RFC 3339 timestamp in UTC, with a missing hour or minute as zero
+    pub fn to_rfc3339(&self) -> String {
+        format!(
+            "{:04}-{}-{}T{:02}:{:02}:00Z",
+            self.year,
+            self.month.to_string(),
+            self.day.to_string(),
+            self.hour.unwrap_or(0),
+            self.minute.unwrap_or(0)
+        )
+    }
+
     /// Start time for 'Horizon API'
     pub fn start_time(&self) -> String {
         format!("&START_TIME='{}'", self.to_string())
@@ -366,20 +403,27 @@ impl Month {
     }
 
     pub fn from_name(month: &str) -> Self {
+        match Self::try_from_name(month) {
+            Ok(month) => month,
+            Err(_) => panic!("Invalid month: {}", month),
+        }
+    }
+
+    pub fn try_from_name(month: &str) -> TimeResult<Self> {
         match month {
-            "January" => Month::January,
-            "February" => Month::February,
-            "March" => Month::March,
-            "April" => Month::April,
-            "May" => Month::May,
-            "June" => Month::June,
-            "July" => Month::July,
-            "August" => Month::August,
-            "September" => Month::September,
-            "October" => Month::October,
-            "November" => Month::November,
-            "December" => Month::December,
-            _ => panic!("Invalid month: {}", month),
+            "January" => Ok(Month::January),
+            "February" => Ok(Month::February),
+            "March" => Ok(Month::March),
+            "April" => Ok(Month::April),
+            "May" => Ok(Month::May),
+            "June" => Ok(Month::June),
+            "July" => Ok(Month::July),
+            "August" => Ok(Month::August),
+            "September" => Ok(Month::September),
+            "October" => Ok(Month::October),
+            "November" => Ok(Month::November),
+            "December" => Ok(Month::December),
+            _ => Err(TimeError::InvalidDate(format!("Invalid month: {}", month))),
         }
     }
 
@@ -618,3 +662,66 @@ impl Day {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> Time {
+        Time::new(
+            year,
+            &Month::from_num(month),
+            &Day::from_num(day),
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn quarter_and_iso_week() {
+        assert_eq!(date(2023, 1, 1).quarter(), 1);
+        assert_eq!(date(2023, 3, 31).quarter(), 1);
+        assert_eq!(date(2023, 4, 1).quarter(), 2);
+        assert_eq!(date(2023, 9, 30).quarter(), 3);
+        assert_eq!(date(2023, 12, 31).quarter(), 4);
+
+        assert_eq!(date(2023, 1, 2).week_of_year(), 1);
+        assert_eq!(date(2023, 6, 15).week_of_year(), 24);
+        // Sunday Jan 1 2023 is in the last ISO week of 2022
+        assert_eq!(date(2023, 1, 1).week_of_year(), 52);
+        // Friday Jan 1 2021 is in week 53 of 2020
+        assert_eq!(date(2021, 1, 1).week_of_year(), 53);
+        // Monday Dec 30 2024 is in week 1 of 2025
+        assert_eq!(date(2024, 12, 30).week_of_year(), 1);
+    }
+
+    #[test]
+    fn rfc3339_round_trip() -> TimeResult<()> {
+        let time = Time::from_rfc3339("2024-02-29T13:45:00Z")?;
+        assert_eq!(
+            time.to_unix_ms(),
+            Time::new(2024, &Month::February, &Day::TwentyNine, Some(13), Some(45)).to_unix_ms()
+        );
+        assert_eq!(time.to_rfc3339(), "2024-02-29T13:45:00Z");
+        assert_eq!(
+            Time::from_rfc3339(&time.to_rfc3339())?.to_unix_ms(),
+            time.to_unix_ms()
+        );
+
+        // sub-second precision and offsets are accepted
+        let time = Time::from_rfc3339("2023-07-01T02:30:15.250+02:00")?;
+        assert_eq!(time.to_rfc3339(), "2023-07-01T00:30:00Z");
+        assert_eq!(
+            Time::from_rfc3339(&time.to_rfc3339())?.to_unix_ms(),
+            time.to_unix_ms()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rfc3339_rejects_invalid_dates() {
+        assert!(Time::from_rfc3339("2023-04-31T00:00:00Z").is_err());
+        assert!(Time::from_rfc3339("2023-02-29T00:00:00Z").is_err());
+        assert!(Time::from_rfc3339("2023-07-01").is_err());
+    }
+}